@@ -3,6 +3,15 @@
 
 use futures::future::Future;
 use ::apl::AddrAndEp;
+use ::apl::device_profile::{BindingTableListRecord, Status};
+use ::apl::endpoints::{IndicationFilter, IndicationHandler, IndicationStream};
+use ::apl::framework::SimpleDescriptor;
+use ::mac::MacStatus;
+use ::nwk::NwkError;
+use ::security::SecurityError;
+use ::types::{ClusterId, Endpoint, ExtendedPanId, GroupId, IeeeAddress, ProfileId};
+use std::error;
+use std::fmt;
 use std::borrow::Cow;
 
 pub const MAX_DESCRIPTOR_SIZE: usize = 64;
@@ -12,6 +21,7 @@ pub const MIN_HEADER_OVERHEAD: usize = 0x0C;
 
 mod frame_format{
     use std::borrow::Cow;
+    use types::{ClusterId, Endpoint, GroupId, ProfileId};
     
     #[derive(Serialize, Deserialize)]
     pub enum FrameType {
@@ -36,11 +46,11 @@ mod frame_format{
     }
 
     pub struct AddressingFields {
-        pub destination_endpoint: Option<u8>, // only for broadcast or normal unicast
-        pub group_address: Option<u16>, // only for group addressing
-        pub cluster_identifier: Option<u16>, // only for data or ack frames
-        pub profile_identifier: Option<u16>, // only for data or ack frames
-        pub source_endpoint: u8 
+        pub destination_endpoint: Option<Endpoint>, // only for broadcast or normal unicast
+        pub group_address: Option<GroupId>, // only for group addressing
+        pub cluster_identifier: Option<ClusterId>, // only for data or ack frames
+        pub profile_identifier: Option<ProfileId>, // only for data or ack frames
+        pub source_endpoint: Endpoint 
     }
 
     pub enum FragmentationField {
@@ -68,19 +78,94 @@ mod frame_format{
     }
 }
 
+status_codes! {
+    /// The status codes of the APS primitives.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ApsStatus {
+        Success = 0x00,
+        AsduTooLong = 0xA0,
+        DefragDeferred = 0xA1,
+        DefragUnsupported = 0xA2,
+        IllegalRequest = 0xA3,
+        InvalidBinding = 0xA4,
+        InvalidGroup = 0xA5,
+        InvalidParameter = 0xA6,
+        NoAck = 0xA7,
+        NoBoundDevice = 0xA8,
+        NoShortAddress = 0xA9,
+        NotSupported = 0xAA,
+        SecuredLinkKey = 0xAB,
+        SecuredNwkKey = 0xAC,
+        SecurityFail = 0xAD,
+        TableFull = 0xAE,
+        Unsecured = 0xAF,
+        UnsupportedAttribute = 0xB0,
+    }
+}
+
+/// The failure of an APS request: an APS status, or the failure of the NWK
+/// layer or of the security processing that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApsError {
+    Status(ApsStatus),
+    Nwk(NwkError),
+    Security(SecurityError),
+}
+
+impl fmt::Display for ApsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            ApsError::Status(ref status) => write!(f, "{}", status),
+            ApsError::Nwk(ref error) => write!(f, "NWK: {}", error),
+            ApsError::Security(ref error) => write!(f, "security: {}", error),
+        }
+    }
+}
+
+impl error::Error for ApsError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            ApsError::Status(_) => None,
+            ApsError::Nwk(ref error) => Some(error),
+            ApsError::Security(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<ApsStatus> for ApsError {
+    fn from(status: ApsStatus) -> Self {
+        ApsError::Status(status)
+    }
+}
+
+impl From<NwkError> for ApsError {
+    fn from(error: NwkError) -> Self {
+        ApsError::Nwk(error)
+    }
+}
+
+impl From<SecurityError> for ApsError {
+    fn from(error: SecurityError) -> Self {
+        ApsError::Security(error)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SecurityStatus {
     Unsecured,
     SecuredNwkKey,
     SecuredLinkKey
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum IndicationStatus {
     Success,
     DefragUnupported,
     DefragDeferred,
-    SecurityProcessingError/*(Error)*/// error 
+    SecurityProcessingError(SecurityError)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct TxOptions {
     pub security_enabled: bool,
     pub use_nwk_key: bool,
@@ -93,11 +178,12 @@ pub struct TxOptions {
 /// new frame is received. This is transmitted to the NHLE issuing the
 /// APSDE-DATA.indication primitive, calling the function provided on
 /// the registration of the interested endpoint.
+#[derive(Clone)]
 pub struct DataIndication<'a> {
     pub dst_addr_ep: AddrAndEp,
     pub src_addr_ep: AddrAndEp,
-    pub profile_id: u16,
-    pub cluster_id: u16,
+    pub profile_id: ProfileId,
+    pub cluster_id: ClusterId,
     pub asdu: Cow<'a, [u8]>,
     pub status: IndicationStatus,
     pub security_status: SecurityStatus,
@@ -105,7 +191,25 @@ pub struct DataIndication<'a> {
     pub rx_time: ::Duration
 }
 
-#[derive(Debug)]
+impl<'a> DataIndication<'a> {
+    /// Copy the ASDU if borrowed, so that the indication can outlive the
+    /// received frame.
+    pub fn into_owned(self) -> DataIndication<'static> {
+        DataIndication {
+            dst_addr_ep: self.dst_addr_ep,
+            src_addr_ep: self.src_addr_ep,
+            profile_id: self.profile_id,
+            cluster_id: self.cluster_id,
+            asdu: Cow::Owned(self.asdu.into_owned()),
+            status: self.status,
+            security_status: self.security_status,
+            link_quality: self.link_quality,
+            rx_time: self.rx_time
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DataConfirmStatus {
     Success,
     NoShortAddress,
@@ -113,14 +217,43 @@ pub enum DataConfirmStatus {
     SecurityFail,
     NoAck,
     AsduTooLong, //Application service data unit, specification contains a typo
-    NldeError/*(Error)*/
+    NldeError(NwkError)
+}
+
+impl DataConfirmStatus {
+    /// `Ok` on success, otherwise the error with its cause.
+    pub fn into_result(self) -> Result<(), ApsError> {
+        match self {
+            DataConfirmStatus::Success => Ok(()),
+            DataConfirmStatus::NoShortAddress => Err(ApsError::Status(ApsStatus::NoShortAddress)),
+            DataConfirmStatus::NoBoundDevice => Err(ApsError::Status(ApsStatus::NoBoundDevice)),
+            DataConfirmStatus::SecurityFail => Err(ApsError::Status(ApsStatus::SecurityFail)),
+            DataConfirmStatus::NoAck => Err(ApsError::Status(ApsStatus::NoAck)),
+            DataConfirmStatus::AsduTooLong => Err(ApsError::Status(ApsStatus::AsduTooLong)),
+            DataConfirmStatus::NldeError(error) => Err(ApsError::Nwk(error)),
+        }
+    }
+}
+
+impl From<MacStatus> for DataConfirmStatus {
+    /// Report the failure of a frame sent to a neighbor, including the
+    /// frames dropped from the indirect transmission queue.
+    fn from(status: MacStatus) -> Self {
+        match status {
+            MacStatus::Success => DataConfirmStatus::Success,
+            MacStatus::NoAck => DataConfirmStatus::NoAck,
+            MacStatus::NoShortAddress => DataConfirmStatus::NoShortAddress,
+            MacStatus::FrameTooLong => DataConfirmStatus::AsduTooLong,
+            _ => DataConfirmStatus::NldeError(NwkError::Mac(status)),
+        }
+    }
 }
 
 /// The data contained in the APSDE-DATA.confirm primitive that is issued by the
 /// APS to the Next Higher Layer Entity in response to a APSDE-DATA.request
 pub struct DataConfirm {
     pub dst: AddrAndEp,
-    pub src_endpoint: u8,
+    pub src_endpoint: Endpoint,
     pub status: DataConfirmStatus,
     pub tx_time: ::Duration
 }
@@ -128,18 +261,34 @@ pub struct DataConfirm {
 /// The arguments needed to issue the APSDE-DATA.request primitive
 pub struct DataRequest<'a> {
     pub dst: AddrAndEp,
-    pub profile_id: u16,
-    pub cluster_id: u16,
-    pub src_endpoint: u8,
+    pub profile_id: ProfileId,
+    pub cluster_id: ClusterId,
+    pub src_endpoint: Endpoint,
     pub asdu: Cow<'a, [u8]>,
     pub options: TxOptions,
     pub radius: u8
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RegistrationError {
-    
+    /// The endpoint is reserved: the ZDO, the Green Power, the broadcast
+    /// endpoint or one of the others outside the application range.
+    ReservedEndpoint,
+    /// An application object is already registered on the endpoint.
+    EndpointInUse,
+}
+
+impl fmt::Display for RegistrationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RegistrationError::ReservedEndpoint => write!(f, "reserved endpoint"),
+            RegistrationError::EndpointInUse => write!(f, "endpoint already in use"),
+        }
+    }
 }
 
+impl error::Error for RegistrationError {}
+
 /// This trait is implemented by all these structs that implements an
 /// Application Support sub-layer Data Entity in order to provide the
 /// functionalities required by the specification.
@@ -147,17 +296,33 @@ pub enum RegistrationError {
 /// Crates that need the functionalities of a zigbee APSDE can rely on this trait.
 pub trait ApsdeSap{
     fn data_request(&self, request: DataRequest<'a>) -> Box<Future<Output=DataConfirm>>;
-    fn register_application_object(&self, endpoint: u8, indication_callback: Fn(DataIndication)) -> Result<(), RegistrationError>;
-    fn deregister_application_object(&self, endpoint: u8);
+    /// Register an application object on the endpoint of its simple
+    /// descriptor. The received frames addressed to it are passed to
+    /// `handler`.
+    fn register_application_object(&self, descriptor: SimpleDescriptor<'static, 'static>,
+                                   handler: IndicationHandler) -> Result<(), RegistrationError>;
+    fn deregister_application_object(&self, endpoint: Endpoint);
+    /// Register an application object whose indications that match
+    /// `filter` are received from the returned stream, buffering up to
+    /// `buffer` of them. See `endpoints` for what happens when the buffer
+    /// is full.
+    fn indication_stream(&self, descriptor: SimpleDescriptor<'static, 'static>,
+                         filter: IndicationFilter, buffer: usize)
+                         -> Result<IndicationStream, RegistrationError> {
+        let (handler, stream) = IndicationHandler::stream(filter, buffer);
+        self.register_application_object(descriptor, handler)?;
+        Ok(stream)
+    }
 }
 
 pub struct BindRequest {
-    pub src_addr: u64,
-    pub src_endpoint: u8,
-    pub cluster_id: u16,
+    pub src_addr: IeeeAddress,
+    pub src_endpoint: Endpoint,
+    pub cluster_id: ClusterId,
     pub dst_addr: AddrAndEp
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BindStatus {
     Success,
     IllegalRequest,
@@ -167,14 +332,15 @@ pub enum BindStatus {
 
 pub struct BindConfirm {
     pub status: BindStatus,
-    pub src_addr: u64,
-    pub src_endpoint: u8,
-    pub cluster_id: u16,
+    pub src_addr: IeeeAddress,
+    pub src_endpoint: Endpoint,
+    pub cluster_id: ClusterId,
     pub dst_addr: AddrAndEp
 }
 
 type UnbindRequest = BindRequest;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum UnbindStatus {
     Success,
     IllegalRequest,
@@ -183,24 +349,236 @@ pub enum UnbindStatus {
 
 pub struct UnbindConfirm {
     pub status: UnbindStatus,
-    pub src_addr: u64,
-    pub src_endpoint: u8,
-    pub cluster_id: u16,
+    pub src_addr: IeeeAddress,
+    pub src_endpoint: Endpoint,
+    pub cluster_id: ClusterId,
     pub dst_addr: AddrAndEp
 }
 
-// TODO: Fill structs
+/// An entry of the binding table. The destination is either a group
+/// (`AddrAndEp::ShortAddressNoEp`) or an endpoint of a device
+/// (`AddrAndEp::LongAddress`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BindingTableEntry {
+    pub src_addr: IeeeAddress,
+    pub src_endpoint: Endpoint,
+    pub cluster_id: ClusterId,
+    pub dst_addr: AddrAndEp
+}
 
+/// The binding table (apsBindingTable).
+#[derive(Clone)]
 pub struct BindingTable {
+    entries: Vec<BindingTableEntry>,
+    capacity: usize
 }
+
+impl BindingTable {
+    pub fn new(capacity: usize) -> Self {
+        BindingTable { entries: Vec::with_capacity(capacity), capacity }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &BindingTableEntry> {
+        self.entries.iter()
+    }
+
+    /// Add the binding described by an APSME-BIND.request.
+    pub fn bind(&mut self, request: &BindRequest) -> BindStatus {
+        let entry = BindingTableEntry {
+            src_addr: request.src_addr,
+            src_endpoint: request.src_endpoint,
+            cluster_id: request.cluster_id,
+            dst_addr: request.dst_addr
+        };
+        match entry.dst_addr {
+            AddrAndEp::ShortAddressNoEp(_) | AddrAndEp::LongAddress(_, _) => {}
+            _ => return BindStatus::IllegalRequest
+        }
+        if self.entries.contains(&entry) {
+            return BindStatus::Success;
+        }
+        if self.entries.len() >= self.capacity {
+            return BindStatus::TableFull;
+        }
+        self.entries.push(entry);
+        BindStatus::Success
+    }
+
+    /// Remove the binding described by an APSME-UNBIND.request.
+    pub fn unbind(&mut self, request: &UnbindRequest) -> UnbindStatus {
+        let before = self.entries.len();
+        self.entries.retain(|e| !(e.src_addr == request.src_addr
+                                  && e.src_endpoint == request.src_endpoint
+                                  && e.cluster_id == request.cluster_id
+                                  && e.dst_addr == request.dst_addr));
+        if self.entries.len() < before { UnbindStatus::Success } else { UnbindStatus::InvalidBinding }
+    }
+
+    /// The destinations bound to an endpoint and cluster of this device.
+    pub fn destinations<'a>(&'a self, src_addr: IeeeAddress, src_endpoint: Endpoint, cluster_id: ClusterId)
+                            -> impl Iterator<Item = AddrAndEp> + 'a {
+        self.entries.iter()
+            .filter(move |e| e.src_addr == src_addr && e.src_endpoint == src_endpoint
+                    && e.cluster_id == cluster_id)
+            .map(|e| e.dst_addr)
+    }
+
+    /// Remove all the bindings from or to a device, e.g. after it left the
+    /// network. Return the number of entries removed.
+    pub fn remove_device(&mut self, ieee_address: IeeeAddress) -> usize {
+        let before = self.entries.len();
+        self.entries.retain(|e| e.src_addr != ieee_address && match e.dst_addr {
+            AddrAndEp::LongAddress(address, _) => address != ieee_address,
+            _ => true
+        });
+        before - self.entries.len()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// The content of the binding table in the format used by the
+    /// Mgmt_Bind_rsp command.
+    pub fn records(&self) -> Vec<BindingTableListRecord> {
+        self.entries.iter().map(|e| BindingTableListRecord {
+            src_addr: e.src_addr,
+            src_endpoint: e.src_endpoint,
+            cluster_id: e.cluster_id,
+            dst_addr: e.dst_addr
+        }).collect()
+    }
+}
+
+// TODO: Fill structs
+
 pub struct ChannelMask {
 }
-pub struct GroupTable<'a> {
-    entries: &'a Iterator<Item=(u16, Iterator<Item = u8>)>
+
+/// The group table (apsGroupTable): the endpoints of this device that are
+/// members of each group.
+#[derive(Clone)]
+pub struct GroupTable {
+    entries: Vec<(GroupId, Endpoint)>,
+    capacity: usize
+}
+
+/// The kinds of commands whose issuers are restricted by the permissions
+/// configuration.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionCategory {
+    /// Changes of the permissions configuration itself.
+    ModifyPermissionsConfiguration,
+    /// The ZDO requests that change the network settings, such as
+    /// Mgmt_Leave_req, Mgmt_Permit_Joining_req and Mgmt_NWK_Update_req.
+    NetworkSettings,
+    /// The ZDO requests that change the application settings, such as
+    /// Bind_req and Unbind_req.
+    ApplicationSettings,
+    /// The ZDO requests that change the security settings.
+    SecuritySettings,
+    /// The commands of the application profiles.
+    ApplicationCommands,
+    /// The APS commands that manage the keys.
+    SecurityCommands,
+}
+
+impl PermissionCategory {
+    /// The category of a ZDO request, `None` if any device may issue it.
+    pub fn for_zdo_request(cluster_id: ClusterId) -> Option<Self> {
+        match cluster_id.0 {
+            // End_Device_Bind_req to Recover_Source_Bind_req, including
+            // Replace_Device_req (0x0024) that rewrites the binding table.
+            0x0020..=0x002A => Some(PermissionCategory::ApplicationSettings),
+            // Mgmt_Leave_req, Mgmt_Direct_Join_req, Mgmt_Permit_Joining_req,
+            // Mgmt_NWK_Update_req.
+            0x0034..=0x0036 | 0x0038 => Some(PermissionCategory::NetworkSettings),
+            // Security_Set_Configuration_req, Security_Start_Key_Update_req,
+            // Security_Decommission_req.
+            0x0043 | 0x0045 | 0x0046 => Some(PermissionCategory::SecuritySettings),
+            _ => None,
+        }
+    }
+}
+
+/// The permissions of one device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PermissionsDescriptor {
+    pub device_address: IeeeAddress,
+    pub modify_permissions_configuration: bool,
+    pub network_settings: bool,
+    pub application_settings: bool,
+    pub security_settings: bool,
+    pub application_commands: bool,
+    pub security_commands: bool,
+    /// Whether the commands of the device must be secured with a link key.
+    pub link_key_required: bool,
+}
+
+impl PermissionsDescriptor {
+    pub fn permits(&self, category: PermissionCategory) -> bool {
+        match category {
+            PermissionCategory::ModifyPermissionsConfiguration => self.modify_permissions_configuration,
+            PermissionCategory::NetworkSettings => self.network_settings,
+            PermissionCategory::ApplicationSettings => self.application_settings,
+            PermissionCategory::SecuritySettings => self.security_settings,
+            PermissionCategory::ApplicationCommands => self.application_commands,
+            PermissionCategory::SecurityCommands => self.security_commands,
+        }
+    }
 }
+
+/// The devices allowed to issue the restricted commands
+/// (apsPermissionsConfiguration). An empty configuration allows every
+/// device; otherwise, the devices not listed are not allowed to issue any
+/// of them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct PermissionsConfiguration {
+    pub descriptors: Vec<PermissionsDescriptor>,
+}
+
+impl PermissionsConfiguration {
+    pub fn descriptor(&self, device_address: IeeeAddress) -> Option<&PermissionsDescriptor> {
+        self.descriptors.iter().find(|d| d.device_address == device_address)
+    }
+
+    /// Check that `source` may issue a command of `category`. The handlers
+    /// of the ZDO and APS commands reject the command with `NotAuthorized`
+    /// otherwise.
+    pub fn authorize(&self, source: IeeeAddress, category: PermissionCategory, link_key_secured: bool)
+                     -> Result<(), Status> {
+        if self.descriptors.is_empty() {
+            return Ok(());
+        }
+        match self.descriptor(source) {
+            Some(descriptor) if descriptor.permits(category)
+                && (link_key_secured || !descriptor.link_key_required) => Ok(()),
+            _ => Err(Status::NotAuthorized),
+        }
+    }
+
+    /// Replace the configuration on behalf of `source`, or of the
+    /// application itself if `None`. A device must be allowed to modify
+    /// the configuration by the current one.
+    pub fn modify(&mut self, configuration: PermissionsConfiguration, source: Option<IeeeAddress>,
+                  link_key_secured: bool) -> Result<(), Status> {
+        if let Some(source) = source {
+            self.authorize(source, PermissionCategory::ModifyPermissionsConfiguration, link_key_secured)?;
+        }
+        *self = configuration;
+        Ok(())
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddGroupStatus {
     Success,
     InvalidParameter,
@@ -208,10 +586,11 @@ pub enum AddGroupStatus {
 }
 pub struct AddGroupConfirm{
     pub status: AddGroupStatus,
-    pub group_address: u16,
-    pub endpoint: u8
+    pub group_address: GroupId,
+    pub endpoint: Endpoint
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RemoveGroupStatus {
     Success,
     InvalidGroup,
@@ -219,17 +598,70 @@ pub enum RemoveGroupStatus {
 }
 pub struct RemoveGroupConfirm{
     pub status: RemoveGroupStatus,
-    pub group_address: u16,
-    pub endpoint: u8
+    pub group_address: GroupId,
+    pub endpoint: Endpoint
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum RemoveAllGroupsStatus {
     Success,
     InvalidParameter
 }
 pub struct RemoveAllGroupsConfirm{
     pub status: RemoveAllGroupsStatus,
-    pub endpoint: u8
+    pub endpoint: Endpoint
+}
+
+impl GroupTable {
+    pub fn new(capacity: usize) -> Self {
+        GroupTable { entries: Vec::with_capacity(capacity), capacity }
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (GroupId, Endpoint)> + 'a {
+        self.entries.iter().cloned()
+    }
+
+    /// The endpoints that are members of a group.
+    pub fn endpoints<'a>(&'a self, group_address: GroupId) -> impl Iterator<Item = Endpoint> + 'a {
+        self.entries.iter()
+            .filter(move |&&(group, _)| group == group_address)
+            .map(|&(_, endpoint)| endpoint)
+    }
+
+    pub fn add(&mut self, group_address: GroupId, endpoint: Endpoint) -> AddGroupStatus {
+        if !endpoint.is_application() {
+            return AddGroupStatus::InvalidParameter;
+        }
+        if self.entries.contains(&(group_address, endpoint)) {
+            return AddGroupStatus::Success;
+        }
+        if self.entries.len() >= self.capacity {
+            return AddGroupStatus::TableFull;
+        }
+        self.entries.push((group_address, endpoint));
+        AddGroupStatus::Success
+    }
+
+    pub fn remove(&mut self, group_address: GroupId, endpoint: Endpoint) -> RemoveGroupStatus {
+        if !endpoint.is_application() {
+            return RemoveGroupStatus::InvalidParameter;
+        }
+        let before = self.entries.len();
+        self.entries.retain(|&entry| entry != (group_address, endpoint));
+        if self.entries.len() < before { RemoveGroupStatus::Success } else { RemoveGroupStatus::InvalidGroup }
+    }
+
+    pub fn remove_all(&mut self, endpoint: Endpoint) -> RemoveAllGroupsStatus {
+        if !endpoint.is_application() {
+            return RemoveAllGroupsStatus::InvalidParameter;
+        }
+        self.entries.retain(|&(_, e)| e != endpoint);
+        RemoveAllGroupsStatus::Success
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
 }
 
 pub trait ApsmeSap{
@@ -238,7 +670,7 @@ pub trait ApsmeSap{
     fn binding_table(&self) -> Option<BindingTable> { None }
     fn designated_coordinator(&self) -> bool { false }
     fn channel_mask(&self) -> Option<ChannelMask>;
-    fn use_extended_pan_id(&self) -> u64 { 0 }
+    fn use_extended_pan_id(&self) -> ExtendedPanId { ExtendedPanId::ANY }
     fn group_table(&self) -> Option<GroupTable> { None }
     fn nonmember_radius(&self) -> u8 { 2 }
     fn permissions_configuration(&self) -> Option<PermissionsConfiguration>{
@@ -250,20 +682,28 @@ pub trait ApsmeSap{
     fn last_channel_failure_rate(&self) -> Option<f32>;
     fn channel_timer(&self) -> Option<f32>;
     fn max_window_size(&self) -> Option<u8>;
-    fn set_binding_table(&self, BindingTable) -> Result<(), ()>;
-    fn set_designated_coordinator(&self, bool) -> Result<(), ()>;
-    fn set_channel_mask(&self, ChannelMask) -> Result<(), ()>;
-    fn set_extended_pan_id(&self, u64) -> Result<(), ()>;
-    fn set_group_table(&self, GroupTable) -> Result<(), ()>;
-    fn set_nonmember_radius(&self, u8) -> Result<(), ()>;
-    fn set_permissions_configuration(&self, PermissionsConfiguration) -> Result<(), ()>;
-    fn set_insecure_join(&self, bool) -> Result<(), ()>;
-    fn set_interframe_delay(&self, u8) -> Result<(), ()>;
-    fn set_last_channel_energy(&self, u8) -> Result<(), ()>;
-    fn set_last_channel_failure_rate(&self, f32) -> Result<(), ()>;
-    fn set_channel_timer(&self, f32) -> Result<(), ()>;
-    fn set_max_window_size(&self, u8) -> Result<(), ()>;
-    fn add_group_request(&self, group_adderss: u16, endpoint: u8) -> AddGroupConfirm;
-    fn remove_group_request(&self, group_adderss: u16, endpoint: u8) -> RemoveGroupConfirm;
-    fn remove_all_groups_request(&self, endpoint: u8) -> RemoveAllGroupsConfirm;
+    fn set_binding_table(&self, BindingTable) -> Result<(), ApsStatus>;
+    fn set_designated_coordinator(&self, bool) -> Result<(), ApsStatus>;
+    fn set_channel_mask(&self, ChannelMask) -> Result<(), ApsStatus>;
+    fn set_extended_pan_id(&self, extended_pan_id: ExtendedPanId) -> Result<(), ApsStatus>;
+    fn set_group_table(&self, GroupTable) -> Result<(), ApsStatus>;
+    fn set_nonmember_radius(&self, u8) -> Result<(), ApsStatus>;
+    /// Replace the permissions configuration on behalf of `source`, as
+    /// `PermissionsConfiguration::modify`, and store it.
+    fn set_permissions_configuration(&self, configuration: PermissionsConfiguration, source: Option<IeeeAddress>,
+                                     link_key_secured: bool) -> Result<(), ApsStatus> {
+        let mut current = self.permissions_configuration().unwrap_or_default();
+        current.modify(configuration, source, link_key_secured).map_err(|_| ApsStatus::IllegalRequest)?;
+        self.store_permissions_configuration(current)
+    }
+    fn store_permissions_configuration(&self, PermissionsConfiguration) -> Result<(), ApsStatus>;
+    fn set_insecure_join(&self, bool) -> Result<(), ApsStatus>;
+    fn set_interframe_delay(&self, u8) -> Result<(), ApsStatus>;
+    fn set_last_channel_energy(&self, u8) -> Result<(), ApsStatus>;
+    fn set_last_channel_failure_rate(&self, f32) -> Result<(), ApsStatus>;
+    fn set_channel_timer(&self, f32) -> Result<(), ApsStatus>;
+    fn set_max_window_size(&self, u8) -> Result<(), ApsStatus>;
+    fn add_group_request(&self, group_adderss: GroupId, endpoint: Endpoint) -> AddGroupConfirm;
+    fn remove_group_request(&self, group_adderss: GroupId, endpoint: Endpoint) -> RemoveGroupConfirm;
+    fn remove_all_groups_request(&self, endpoint: Endpoint) -> RemoveAllGroupsConfirm;
 }
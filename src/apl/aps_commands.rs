@@ -0,0 +1,610 @@
+//! The APS command frames, described in section 4.4.10 of the
+//! specification, that the trust center and the devices exchange to
+//! manage the keys.
+//!
+//! Each command knows how to encode and decode its payload, that is what
+//! follows the command identifier. A command frame has no addressing
+//! fields: the header is the frame control and the APS counter, secured
+//! with the link keys of `ApsSecurity` when needed.
+
+use apl::aps_security::ApsSecurity;
+use codec::{DecodeError, Reader, write_u16, write_u64};
+use security::{Key, KeyIdentifier, SecurityError, KEY_SIZE};
+use security::mmo::verify_key_hash;
+use types::{IeeeAddress, NwkAddress};
+
+/// The command frame type, in the frame control field.
+const COMMAND_FRAME: u8 = 0x01;
+const BROADCAST_DELIVERY: u8 = 0x02 << 2;
+const SECURITY: u8 = 1 << 5;
+const ACK_REQUEST: u8 = 1 << 6;
+
+fn read_key(reader: &mut Reader) -> Result<Key, DecodeError> {
+    let mut key = [0; KEY_SIZE];
+    key.copy_from_slice(reader.read_bytes(KEY_SIZE)?);
+    Ok(key)
+}
+
+/// The type of the keys transported, verified or confirmed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    NetworkKey = 0x01,
+    ApplicationLinkKey = 0x03,
+    TrustCenterLinkKey = 0x04,
+}
+
+impl KeyType {
+    pub fn from_u8(value: u8) -> Result<Self, DecodeError> {
+        match value {
+            0x01 => Ok(KeyType::NetworkKey),
+            0x03 => Ok(KeyType::ApplicationLinkKey),
+            0x04 => Ok(KeyType::TrustCenterLinkKey),
+            _ => Err(DecodeError::InvalidValue("key type")),
+        }
+    }
+}
+
+/// The fields of the transport key command that depend on the key type.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKeyData {
+    TrustCenterLinkKey { destination_address: IeeeAddress, source_address: IeeeAddress },
+    NetworkKey { sequence_number: u8, destination_address: IeeeAddress, source_address: IeeeAddress },
+    ApplicationLinkKey { partner_address: IeeeAddress, initiator: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TransportKey {
+    pub key: Key,
+    pub data: TransportKeyData,
+}
+
+impl TransportKey {
+    pub const ID: u8 = 0x05;
+
+    pub fn key_type(&self) -> KeyType {
+        match self.data {
+            TransportKeyData::TrustCenterLinkKey { .. } => KeyType::TrustCenterLinkKey,
+            TransportKeyData::NetworkKey { .. } => KeyType::NetworkKey,
+            TransportKeyData::ApplicationLinkKey { .. } => KeyType::ApplicationLinkKey,
+        }
+    }
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.key_type() as u8);
+        out.extend_from_slice(&self.key);
+        match self.data {
+            TransportKeyData::TrustCenterLinkKey { destination_address, source_address } => {
+                write_u64(out, destination_address.0);
+                write_u64(out, source_address.0);
+            }
+            TransportKeyData::NetworkKey { sequence_number, destination_address, source_address } => {
+                out.push(sequence_number);
+                write_u64(out, destination_address.0);
+                write_u64(out, source_address.0);
+            }
+            TransportKeyData::ApplicationLinkKey { partner_address, initiator } => {
+                write_u64(out, partner_address.0);
+                out.push(initiator as u8);
+            }
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let key_type = KeyType::from_u8(reader.read_u8()?)?;
+        let key = read_key(reader)?;
+        let data = match key_type {
+            KeyType::TrustCenterLinkKey => TransportKeyData::TrustCenterLinkKey {
+                destination_address: IeeeAddress(reader.read_u64()?),
+                source_address: IeeeAddress(reader.read_u64()?),
+            },
+            KeyType::NetworkKey => TransportKeyData::NetworkKey {
+                sequence_number: reader.read_u8()?,
+                destination_address: IeeeAddress(reader.read_u64()?),
+                source_address: IeeeAddress(reader.read_u64()?),
+            },
+            KeyType::ApplicationLinkKey => TransportKeyData::ApplicationLinkKey {
+                partner_address: IeeeAddress(reader.read_u64()?),
+                initiator: reader.read_u8()? != 0,
+            },
+        };
+        Ok(TransportKey { key, data })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateDeviceStatus {
+    StandardSecuredRejoin = 0x00,
+    StandardUnsecuredJoin = 0x01,
+    DeviceLeft = 0x02,
+    StandardTrustCenterRejoin = 0x03,
+}
+
+/// Sent by a router to the trust center when a device joins, rejoins or
+/// leaves through it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UpdateDevice {
+    pub device_address: IeeeAddress,
+    pub device_short_address: NwkAddress,
+    pub status: UpdateDeviceStatus,
+}
+
+impl UpdateDevice {
+    pub const ID: u8 = 0x06;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        write_u64(out, self.device_address.0);
+        write_u16(out, self.device_short_address.0);
+        out.push(self.status as u8);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let device_address = IeeeAddress(reader.read_u64()?);
+        let device_short_address = NwkAddress(reader.read_u16()?);
+        let status = match reader.read_u8()? {
+            0x00 => UpdateDeviceStatus::StandardSecuredRejoin,
+            0x01 => UpdateDeviceStatus::StandardUnsecuredJoin,
+            0x02 => UpdateDeviceStatus::DeviceLeft,
+            0x03 => UpdateDeviceStatus::StandardTrustCenterRejoin,
+            _ => return Err(DecodeError::InvalidValue("update device status")),
+        };
+        Ok(UpdateDevice { device_address, device_short_address, status })
+    }
+}
+
+/// Sent by the trust center to the parent of a device that must leave the
+/// network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RemoveDevice {
+    pub target_address: IeeeAddress,
+}
+
+impl RemoveDevice {
+    pub const ID: u8 = 0x07;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        write_u64(out, self.target_address.0);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(RemoveDevice { target_address: IeeeAddress(reader.read_u64()?) })
+    }
+}
+
+/// Sent to the trust center to obtain a link key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestKey {
+    /// A link key to share with the partner.
+    ApplicationLinkKey { partner_address: IeeeAddress },
+    /// A new link key to share with the trust center.
+    TrustCenterLinkKey,
+}
+
+impl RequestKey {
+    pub const ID: u8 = 0x08;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        match *self {
+            RequestKey::ApplicationLinkKey { partner_address } => {
+                out.push(0x02);
+                write_u64(out, partner_address.0);
+            }
+            RequestKey::TrustCenterLinkKey => out.push(KeyType::TrustCenterLinkKey as u8),
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.read_u8()? {
+            0x02 => Ok(RequestKey::ApplicationLinkKey { partner_address: IeeeAddress(reader.read_u64()?) }),
+            0x04 => Ok(RequestKey::TrustCenterLinkKey),
+            _ => Err(DecodeError::InvalidValue("request key type")),
+        }
+    }
+}
+
+/// Sent by the trust center to make the network key with the given
+/// sequence number the active one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SwitchKey {
+    pub sequence_number: u8,
+}
+
+impl SwitchKey {
+    pub const ID: u8 = 0x09;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.sequence_number);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(SwitchKey { sequence_number: reader.read_u8()? })
+    }
+}
+
+/// Sent by the trust center to the parent of a device that does not have
+/// the network key yet. The parent forwards the tunneled frame, a whole
+/// APS command frame secured with the link key of the device, to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tunnel {
+    pub destination_address: IeeeAddress,
+    pub frame: Vec<u8>,
+}
+
+impl Tunnel {
+    pub const ID: u8 = 0x0E;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        write_u64(out, self.destination_address.0);
+        out.extend_from_slice(&self.frame);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let destination_address = IeeeAddress(reader.read_u64()?);
+        Ok(Tunnel { destination_address, frame: reader.read_to_end().to_vec() })
+    }
+}
+
+/// Sent by a device to the trust center to prove that it knows the link
+/// key they share.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VerifyKey {
+    pub key_type: KeyType,
+    pub source_address: IeeeAddress,
+    pub hash: Key,
+}
+
+impl VerifyKey {
+    pub const ID: u8 = 0x0F;
+
+    /// The command that proves the knowledge of `link_key`.
+    pub fn new(key_type: KeyType, source_address: IeeeAddress, link_key: &Key) -> Self {
+        VerifyKey { key_type, source_address, hash: verify_key_hash(link_key) }
+    }
+
+    /// Whether the hash matches `link_key`.
+    pub fn verify(&self, link_key: &Key) -> bool {
+        self.hash == verify_key_hash(link_key)
+    }
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.key_type as u8);
+        write_u64(out, self.source_address.0);
+        out.extend_from_slice(&self.hash);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let key_type = KeyType::from_u8(reader.read_u8()?)?;
+        let source_address = IeeeAddress(reader.read_u64()?);
+        let hash = read_key(reader)?;
+        Ok(VerifyKey { key_type, source_address, hash })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfirmKeyStatus {
+    Success = 0x00,
+    SecurityFailure = 0xAD,
+}
+
+/// The answer of the trust center to a verify key command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConfirmKey {
+    pub status: ConfirmKeyStatus,
+    pub key_type: KeyType,
+    pub destination_address: IeeeAddress,
+}
+
+impl ConfirmKey {
+    pub const ID: u8 = 0x10;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.status as u8);
+        out.push(self.key_type as u8);
+        write_u64(out, self.destination_address.0);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let status = match reader.read_u8()? {
+            0x00 => ConfirmKeyStatus::Success,
+            0xAD => ConfirmKeyStatus::SecurityFailure,
+            _ => return Err(DecodeError::InvalidValue("confirm key status")),
+        };
+        let key_type = KeyType::from_u8(reader.read_u8()?)?;
+        let destination_address = IeeeAddress(reader.read_u64()?);
+        Ok(ConfirmKey { status, key_type, destination_address })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApsCommand {
+    TransportKey(TransportKey),
+    UpdateDevice(UpdateDevice),
+    RemoveDevice(RemoveDevice),
+    RequestKey(RequestKey),
+    SwitchKey(SwitchKey),
+    Tunnel(Tunnel),
+    VerifyKey(VerifyKey),
+    ConfirmKey(ConfirmKey),
+}
+
+impl ApsCommand {
+    pub fn id(&self) -> u8 {
+        match *self {
+            ApsCommand::TransportKey(_) => TransportKey::ID,
+            ApsCommand::UpdateDevice(_) => UpdateDevice::ID,
+            ApsCommand::RemoveDevice(_) => RemoveDevice::ID,
+            ApsCommand::RequestKey(_) => RequestKey::ID,
+            ApsCommand::SwitchKey(_) => SwitchKey::ID,
+            ApsCommand::Tunnel(_) => Tunnel::ID,
+            ApsCommand::VerifyKey(_) => VerifyKey::ID,
+            ApsCommand::ConfirmKey(_) => ConfirmKey::ID,
+        }
+    }
+
+    /// The key that secures the command when it is sent to a single
+    /// device. The network key is transported with the key-transport key
+    /// and the link keys with the key-load key; the tunnel and verify key
+    /// commands are only secured by the NWK layer.
+    pub fn key_identifier(&self) -> Option<KeyIdentifier> {
+        match *self {
+            ApsCommand::TransportKey(ref command) => match command.key_type() {
+                KeyType::NetworkKey => Some(KeyIdentifier::KeyTransport),
+                _ => Some(KeyIdentifier::KeyLoad),
+            },
+            ApsCommand::Tunnel(_) | ApsCommand::VerifyKey(_) => None,
+            _ => Some(KeyIdentifier::Data),
+        }
+    }
+
+    /// Encode the command identifier followed by the payload.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.id());
+        match *self {
+            ApsCommand::TransportKey(ref command) => command.encode_payload(out),
+            ApsCommand::UpdateDevice(ref command) => command.encode_payload(out),
+            ApsCommand::RemoveDevice(ref command) => command.encode_payload(out),
+            ApsCommand::RequestKey(ref command) => command.encode_payload(out),
+            ApsCommand::SwitchKey(ref command) => command.encode_payload(out),
+            ApsCommand::Tunnel(ref command) => command.encode_payload(out),
+            ApsCommand::VerifyKey(ref command) => command.encode_payload(out),
+            ApsCommand::ConfirmKey(ref command) => command.encode_payload(out),
+        }
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match reader.read_u8()? {
+            TransportKey::ID => ApsCommand::TransportKey(TransportKey::decode_payload(reader)?),
+            UpdateDevice::ID => ApsCommand::UpdateDevice(UpdateDevice::decode_payload(reader)?),
+            RemoveDevice::ID => ApsCommand::RemoveDevice(RemoveDevice::decode_payload(reader)?),
+            RequestKey::ID => ApsCommand::RequestKey(RequestKey::decode_payload(reader)?),
+            SwitchKey::ID => ApsCommand::SwitchKey(SwitchKey::decode_payload(reader)?),
+            Tunnel::ID => ApsCommand::Tunnel(Tunnel::decode_payload(reader)?),
+            VerifyKey::ID => ApsCommand::VerifyKey(VerifyKey::decode_payload(reader)?),
+            ConfirmKey::ID => ApsCommand::ConfirmKey(ConfirmKey::decode_payload(reader)?),
+            _ => return Err(DecodeError::InvalidValue("command identifier")),
+        })
+    }
+}
+
+/// An APS command frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ApsCommandFrame {
+    pub broadcast: bool,
+    pub ack_request: bool,
+    pub aps_counter: u8,
+    pub command: ApsCommand,
+}
+
+impl ApsCommandFrame {
+    fn header(&self, security: bool) -> [u8; 2] {
+        let mut frame_control = COMMAND_FRAME;
+        if self.broadcast { frame_control |= BROADCAST_DELIVERY; }
+        if security { frame_control |= SECURITY; }
+        if self.ack_request { frame_control |= ACK_REQUEST; }
+        [frame_control, self.aps_counter]
+    }
+
+    /// Encode the frame without APS security.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.header(false));
+        self.command.encode(out);
+    }
+
+    /// Encode the frame secured with the link key shared with
+    /// `destination`, or without APS security if `key_identifier` is
+    /// `None`.
+    pub fn encode_secured(&self, security: &mut ApsSecurity, destination: IeeeAddress,
+                          key_identifier: Option<KeyIdentifier>, extended_nonce: bool)
+                          -> Result<Vec<u8>, SecurityError> {
+        match key_identifier {
+            Some(key_identifier) => {
+                let mut payload = Vec::new();
+                self.command.encode(&mut payload);
+                security.secure(&self.header(true), &payload, destination, key_identifier, extended_nonce)
+            }
+            None => {
+                let mut frame = Vec::new();
+                self.encode(&mut frame);
+                Ok(frame)
+            }
+        }
+    }
+
+    /// Decode a received command frame, removing its APS security if any.
+    /// The source address, known from the NWK layer, is used when the frame
+    /// does not carry it.
+    pub fn decode(frame: &[u8], security: &mut ApsSecurity, source_address: Option<IeeeAddress>)
+                  -> Result<CommandIndication, SecurityError> {
+        let mut reader = Reader::new(frame);
+        let frame_control = reader.read_u8()?;
+        let aps_counter = reader.read_u8()?;
+        if frame_control & 0x03 != COMMAND_FRAME {
+            return Err(DecodeError::InvalidValue("frame type").into());
+        }
+        let (command, source_address, key_identifier) = if frame_control & SECURITY != 0 {
+            let unsecured = security.unsecure(&frame[..2], reader.remaining(), source_address)?;
+            let command = ApsCommand::decode(&mut Reader::new(&unsecured.payload))?;
+            (command, Some(unsecured.source_address), Some(unsecured.key_identifier))
+        } else {
+            (ApsCommand::decode(&mut reader)?, source_address, None)
+        };
+        Ok(CommandIndication {
+            source_address,
+            key_identifier,
+            frame: ApsCommandFrame {
+                broadcast: frame_control & (0x03 << 2) == BROADCAST_DELIVERY,
+                ack_request: frame_control & ACK_REQUEST != 0,
+                aps_counter,
+                command,
+            },
+        })
+    }
+}
+
+/// A command frame received by the APSME, reported to the upper layer
+/// with the matching indication primitive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandIndication {
+    pub source_address: Option<IeeeAddress>,
+    /// The key that secured the frame, `None` if it was only secured by
+    /// the NWK layer.
+    pub key_identifier: Option<KeyIdentifier>,
+    pub frame: ApsCommandFrame,
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apl::aps_security::LinkKeyTable;
+
+    const ALICE: IeeeAddress = IeeeAddress(0x0011_2233_4455_6677);
+    const BOB: IeeeAddress = IeeeAddress(0x8899_AABB_CCDD_EEFF);
+    const KEY: Key = *b"ZigBeeAlliance09";
+    const ALICE_BYTES: [u8; 8] = [0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00];
+    const BOB_BYTES: [u8; 8] = [0xFF, 0xEE, 0xDD, 0xCC, 0xBB, 0xAA, 0x99, 0x88];
+
+    /// Check that `command` encodes to the identifier and `payload`, and
+    /// decodes back.
+    fn round_trip(command: ApsCommand, payload: &[u8]) {
+        let mut out = Vec::new();
+        command.encode(&mut out);
+        assert_eq!(out[0], command.id());
+        assert_eq!(&out[1..], payload);
+        let mut reader = Reader::new(&out);
+        assert_eq!(ApsCommand::decode(&mut reader), Ok(command));
+        assert!(reader.remaining().is_empty());
+    }
+
+    fn concat(parts: &[&[u8]]) -> Vec<u8> {
+        parts.concat()
+    }
+
+    fn security() -> ApsSecurity {
+        let mut link_keys = LinkKeyTable::new(1);
+        link_keys.set_key(BOB, KEY).unwrap();
+        ApsSecurity::new(ALICE, link_keys)
+    }
+
+    #[test]
+    fn transport_key() {
+        let command = TransportKey {
+            key: KEY,
+            data: TransportKeyData::TrustCenterLinkKey { destination_address: ALICE, source_address: BOB },
+        };
+        round_trip(ApsCommand::TransportKey(command), &concat(&[&[0x04], &KEY, &ALICE_BYTES, &BOB_BYTES]));
+        let command = TransportKey {
+            key: KEY,
+            data: TransportKeyData::NetworkKey { sequence_number: 7, destination_address: ALICE, source_address: BOB },
+        };
+        round_trip(ApsCommand::TransportKey(command), &concat(&[&[0x01], &KEY, &[7], &ALICE_BYTES, &BOB_BYTES]));
+        let command = TransportKey {
+            key: KEY,
+            data: TransportKeyData::ApplicationLinkKey { partner_address: BOB, initiator: true },
+        };
+        round_trip(ApsCommand::TransportKey(command), &concat(&[&[0x03], &KEY, &BOB_BYTES, &[1]]));
+    }
+
+    #[test]
+    fn device_management() {
+        let command = UpdateDevice {
+            device_address: ALICE,
+            device_short_address: NwkAddress(0x1234),
+            status: UpdateDeviceStatus::StandardTrustCenterRejoin,
+        };
+        round_trip(ApsCommand::UpdateDevice(command), &concat(&[&ALICE_BYTES, &[0x34, 0x12, 0x03]]));
+        round_trip(ApsCommand::RemoveDevice(RemoveDevice { target_address: BOB }), &BOB_BYTES);
+    }
+
+    #[test]
+    fn request_and_switch_key() {
+        round_trip(ApsCommand::RequestKey(RequestKey::ApplicationLinkKey { partner_address: BOB }),
+                   &concat(&[&[0x02], &BOB_BYTES]));
+        round_trip(ApsCommand::RequestKey(RequestKey::TrustCenterLinkKey), &[0x04]);
+        round_trip(ApsCommand::SwitchKey(SwitchKey { sequence_number: 0xFE }), &[0xFE]);
+    }
+
+    #[test]
+    fn verify_and_confirm_key() {
+        let command = VerifyKey::new(KeyType::TrustCenterLinkKey, ALICE, &KEY);
+        assert!(command.verify(&KEY));
+        assert!(!command.verify(&[0; KEY_SIZE]));
+        round_trip(ApsCommand::VerifyKey(command), &concat(&[&[0x04], &ALICE_BYTES, &command.hash]));
+        let command = ConfirmKey {
+            status: ConfirmKeyStatus::SecurityFailure,
+            key_type: KeyType::TrustCenterLinkKey,
+            destination_address: ALICE,
+        };
+        round_trip(ApsCommand::ConfirmKey(command), &concat(&[&[0xAD, 0x04], &ALICE_BYTES]));
+    }
+
+    #[test]
+    fn invalid_commands() {
+        let decode = |bytes: &[u8]| ApsCommand::decode(&mut Reader::new(bytes));
+        assert_eq!(decode(&[]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&[0x01]), Err(DecodeError::InvalidValue("command identifier")));
+        assert_eq!(decode(&concat(&[&[0x05, 0x02], &KEY])), Err(DecodeError::InvalidValue("key type")));
+        assert_eq!(decode(&concat(&[&[0x0F, 0x00], &ALICE_BYTES, &KEY])), Err(DecodeError::InvalidValue("key type")));
+        assert_eq!(decode(&[0x08, 0x01]), Err(DecodeError::InvalidValue("request key type")));
+        assert_eq!(decode(&concat(&[&[0x06], &ALICE_BYTES, &[0x00, 0x00, 0x04]])),
+                   Err(DecodeError::InvalidValue("update device status")));
+        assert_eq!(decode(&concat(&[&[0x10, 0x01, 0x04], &ALICE_BYTES])),
+                   Err(DecodeError::InvalidValue("confirm key status")));
+        // Truncated key, address and sequence number.
+        assert_eq!(decode(&[0x05, 0x04, 0x00]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&concat(&[&[0x05, 0x04], &KEY, &ALICE_BYTES])), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&[0x07, 0x00, 0x00]), Err(DecodeError::UnexpectedEnd));
+        assert_eq!(decode(&[0x09]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn unsecured_frame() {
+        let frame = ApsCommandFrame {
+            broadcast: true,
+            ack_request: false,
+            aps_counter: 0x42,
+            command: ApsCommand::SwitchKey(SwitchKey { sequence_number: 3 }),
+        };
+        let mut out = Vec::new();
+        frame.encode(&mut out);
+        assert_eq!(out, [0x09, 0x42, 0x09, 0x03]);
+        let indication = ApsCommandFrame::decode(&out, &mut security(), Some(BOB)).unwrap();
+        assert_eq!(indication, CommandIndication { source_address: Some(BOB), key_identifier: None, frame });
+    }
+
+    #[test]
+    fn invalid_frames() {
+        let mut security = security();
+        assert_eq!(ApsCommandFrame::decode(&[0x01], &mut security, None),
+                   Err(SecurityError::Decode(DecodeError::UnexpectedEnd)));
+        assert_eq!(ApsCommandFrame::decode(&[0x01, 0x00], &mut security, None),
+                   Err(SecurityError::Decode(DecodeError::UnexpectedEnd)));
+        assert_eq!(ApsCommandFrame::decode(&[0x00, 0x00, 0x09, 0x00], &mut security, None),
+                   Err(SecurityError::Decode(DecodeError::InvalidValue("frame type"))));
+        assert_eq!(ApsCommandFrame::decode(&[0x01, 0x00, 0x42], &mut security, None),
+                   Err(SecurityError::Decode(DecodeError::InvalidValue("command identifier"))));
+        assert_eq!(ApsCommandFrame::decode(&[0x01, 0x00, 0x07, 0x00], &mut security, None),
+                   Err(SecurityError::Decode(DecodeError::UnexpectedEnd)));
+        // A secured frame whose auxiliary header is cut short.
+        assert!(ApsCommandFrame::decode(&[0x21, 0x00, 0x05], &mut security, Some(BOB)).is_err());
+    }
+}
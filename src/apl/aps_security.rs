@@ -0,0 +1,340 @@
+//! The protection of APS frames with link keys, as described in section
+//! 4.4.1 of the specification.
+//!
+//! Each device shares a link key with the trust center and, possibly, with
+//! other devices it talks to. Data frames are secured directly with the
+//! link key; the commands that transport keys use keys derived from it
+//! with the keyed hash function, so that a key is never encrypted with
+//! itself. Every link key has its own pair of frame counters.
+
+use apl::aps::{DataConfirmStatus, IndicationStatus, SecurityStatus};
+use codec::Reader;
+use security::{ccm, AuxiliaryHeader, Key, KeyIdentifier, SecurityError, SecurityLevel,
+               ZIGBEE_SECURITY_LEVEL};
+use security::key_store::{FrameCounter, FrameCounterPersistence, KeyStore};
+use security::mmo::{key_load_key, key_transport_key};
+use types::IeeeAddress;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkKeyEntry {
+    pub partner_address: IeeeAddress,
+    pub key: Key,
+    pub outgoing_frame_counter: u32,
+    /// The last frame counter received from the partner, if any.
+    pub incoming_frame_counter: Option<u32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkKeyTableFull;
+
+/// The link keys shared with other devices (apsDeviceKeyPairSet).
+pub struct LinkKeyTable {
+    entries: Vec<LinkKeyEntry>,
+    capacity: usize,
+}
+
+impl LinkKeyTable {
+    pub fn new(capacity: usize) -> Self {
+        LinkKeyTable { entries: Vec::with_capacity(capacity), capacity }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &LinkKeyEntry> {
+        self.entries.iter()
+    }
+
+    pub fn get(&self, partner_address: IeeeAddress) -> Option<&LinkKeyEntry> {
+        self.entries.iter().find(|e| e.partner_address == partner_address)
+    }
+
+    pub fn get_mut(&mut self, partner_address: IeeeAddress) -> Option<&mut LinkKeyEntry> {
+        self.entries.iter_mut().find(|e| e.partner_address == partner_address)
+    }
+
+    /// Set the key shared with `partner_address`. The frame counters
+    /// restart from zero when the key changes.
+    pub fn set_key(&mut self, partner_address: IeeeAddress, key: Key) -> Result<(), LinkKeyTableFull> {
+        if let Some(entry) = self.get_mut(partner_address) {
+            if entry.key != key {
+                *entry = LinkKeyEntry { partner_address, key, outgoing_frame_counter: 0,
+                                        incoming_frame_counter: None };
+            }
+            return Ok(());
+        }
+        if self.entries.len() >= self.capacity {
+            return Err(LinkKeyTableFull);
+        }
+        self.entries.push(LinkKeyEntry { partner_address, key, outgoing_frame_counter: 0,
+                                         incoming_frame_counter: None });
+        Ok(())
+    }
+
+    pub fn remove(&mut self, partner_address: IeeeAddress) -> Option<LinkKeyEntry> {
+        self.entries.iter()
+            .position(|e| e.partner_address == partner_address)
+            .map(|i| self.entries.swap_remove(i))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+}
+
+/// The key actually used by CCM* for a given key identifier.
+fn derive_key(link_key: &Key, key_identifier: KeyIdentifier) -> Result<Key, SecurityError> {
+    match key_identifier {
+        KeyIdentifier::Data => Ok(*link_key),
+        KeyIdentifier::KeyTransport => Ok(key_transport_key(link_key)),
+        KeyIdentifier::KeyLoad => Ok(key_load_key(link_key)),
+        // Frames secured with the network key at the APS layer are not
+        // handled by the link key table.
+        KeyIdentifier::Network => Err(SecurityError::UnknownKey),
+    }
+}
+
+/// An APS frame whose security was successfully removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsecuredApsFrame {
+    pub source_address: IeeeAddress,
+    pub key_identifier: KeyIdentifier,
+    pub payload: Vec<u8>,
+}
+
+impl UnsecuredApsFrame {
+    /// The security status reported in the APSDE-DATA.indication.
+    pub fn security_status(&self) -> SecurityStatus {
+        match self.key_identifier {
+            KeyIdentifier::Network => SecurityStatus::SecuredNwkKey,
+            _ => SecurityStatus::SecuredLinkKey,
+        }
+    }
+}
+
+/// The APS security material of a device.
+pub struct ApsSecurity {
+    extended_address: IeeeAddress,
+    security_level: SecurityLevel,
+    link_keys: LinkKeyTable,
+}
+
+impl ApsSecurity {
+    pub fn new(extended_address: IeeeAddress, link_keys: LinkKeyTable) -> Self {
+        ApsSecurity { extended_address, security_level: ZIGBEE_SECURITY_LEVEL, link_keys }
+    }
+
+    pub fn link_keys(&self) -> &LinkKeyTable {
+        &self.link_keys
+    }
+
+    pub fn link_keys_mut(&mut self) -> &mut LinkKeyTable {
+        &mut self.link_keys
+    }
+
+    /// Secure the payload of an APS frame sent to `destination` with the
+    /// link key shared with it. `header` is the encoded APS header, with the
+    /// security flag already set. Return the header followed by the
+    /// auxiliary header, the encrypted payload and the message integrity
+    /// code.
+    pub fn secure(&mut self, header: &[u8], payload: &[u8], destination: IeeeAddress,
+                  key_identifier: KeyIdentifier, extended_nonce: bool)
+                  -> Result<Vec<u8>, SecurityError> {
+        let entry = self.link_keys.get_mut(destination).ok_or(SecurityError::UnknownKey)?;
+        let key = derive_key(&entry.key, key_identifier)?;
+        if entry.outgoing_frame_counter == u32::MAX {
+            return Err(SecurityError::FrameCounterExhausted);
+        }
+        let aux = AuxiliaryHeader {
+            security_level: self.security_level,
+            key_identifier,
+            frame_counter: entry.outgoing_frame_counter,
+            source_address: if extended_nonce { Some(self.extended_address.0) } else { None },
+            key_sequence_number: None,
+        };
+        entry.outgoing_frame_counter += 1;
+
+        let mut frame = header.to_vec();
+        let mut a = frame.clone();
+        aux.encode_with_level(&mut a);
+        aux.encode(&mut frame);
+        let mut m = payload.to_vec();
+        ccm::encrypt(&key, &aux.nonce(self.extended_address.0), self.security_level, &a, &mut m);
+        frame.extend_from_slice(&m);
+        Ok(frame)
+    }
+
+    /// Secure the payload like `secure`, after storing a value of the
+    /// outgoing frame counter of the link key ahead of the one used, when
+    /// `persistence` requires it.
+    #[allow(clippy::too_many_arguments)]
+    pub fn secure_persistent<S: KeyStore>(&mut self, header: &[u8], payload: &[u8], destination: IeeeAddress,
+                                          key_identifier: KeyIdentifier, extended_nonce: bool, store: &mut S,
+                                          persistence: &FrameCounterPersistence)
+                                          -> Result<Vec<u8>, SecurityError> {
+        if let Some(entry) = self.link_keys.get(destination) {
+            persistence.update(store, FrameCounter::Link(destination.0), entry.outgoing_frame_counter)
+                .map_err(|_| SecurityError::FrameCounterNotStored)?;
+        }
+        self.secure(header, payload, destination, key_identifier, extended_nonce)
+    }
+
+    /// Check and remove the security of a received APS frame. `header` is
+    /// the encoded APS header and `secured` what follows it. The source
+    /// address, known from the NWK layer, is used when the frame does not
+    /// carry it.
+    pub fn unsecure(&mut self, header: &[u8], secured: &[u8], source_address: Option<IeeeAddress>)
+                    -> Result<UnsecuredApsFrame, SecurityError> {
+        let mut reader = Reader::new(secured);
+        let aux = AuxiliaryHeader::decode(&mut reader, self.security_level)?;
+        let source_address = aux.source_address.map(IeeeAddress).or(source_address)
+            .ok_or(SecurityError::MissingSourceAddress)?;
+        let entry = self.link_keys.get_mut(source_address).ok_or(SecurityError::UnknownKey)?;
+        let key = derive_key(&entry.key, aux.key_identifier)?;
+        if entry.incoming_frame_counter.is_some_and(|last| aux.frame_counter <= last) {
+            return Err(SecurityError::BadFrameCounter);
+        }
+
+        let mut a = header.to_vec();
+        aux.encode_with_level(&mut a);
+        let mut payload = reader.read_to_end().to_vec();
+        ccm::decrypt(&key, &aux.nonce(source_address.0), self.security_level, &a, &mut payload)?;
+        entry.incoming_frame_counter = Some(aux.frame_counter);
+        Ok(UnsecuredApsFrame { source_address, key_identifier: aux.key_identifier, payload })
+    }
+}
+
+impl From<SecurityError> for DataConfirmStatus {
+    fn from(_: SecurityError) -> Self {
+        DataConfirmStatus::SecurityFail
+    }
+}
+
+impl From<SecurityError> for IndicationStatus {
+    fn from(error: SecurityError) -> Self {
+        IndicationStatus::SecurityProcessingError(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use security::mmo::key_transport_key;
+
+    const ALICE: IeeeAddress = IeeeAddress(0x0011_2233_4455_6677);
+    const BOB: IeeeAddress = IeeeAddress(0x8899_AABB_CCDD_EEFF);
+    const KEY: Key = *b"ZigBeeAlliance09";
+    /// An APS data frame header with the security flag set.
+    const HEADER: [u8; 8] = [0x20, 0x01, 0x06, 0x00, 0x04, 0x01, 0x01, 0x05];
+
+    fn security(address: IeeeAddress, partner: IeeeAddress) -> ApsSecurity {
+        let mut link_keys = LinkKeyTable::new(2);
+        link_keys.set_key(partner, KEY).unwrap();
+        ApsSecurity::new(address, link_keys)
+    }
+
+    #[test]
+    fn data_frame_round_trip() {
+        let mut alice = security(ALICE, BOB);
+        let mut bob = security(BOB, ALICE);
+        let frame = alice.secure(&HEADER, b"payload", BOB, KeyIdentifier::Data, true).unwrap();
+        assert_eq!(&frame[..HEADER.len()], &HEADER);
+        // Security control with the extended nonce, frame counter and
+        // source address, then the payload and a 4 byte MIC.
+        assert_eq!(frame.len(), HEADER.len() + 1 + 4 + 8 + 7 + 4);
+        let unsecured = bob.unsecure(&HEADER, &frame[HEADER.len()..], None).unwrap();
+        assert_eq!(unsecured.source_address, ALICE);
+        assert_eq!(unsecured.payload, b"payload");
+        assert_eq!(unsecured.security_status(), SecurityStatus::SecuredLinkKey);
+        assert_eq!(alice.link_keys().get(BOB).unwrap().outgoing_frame_counter, 1);
+        assert_eq!(bob.link_keys().get(ALICE).unwrap().incoming_frame_counter, Some(0));
+    }
+
+    #[test]
+    fn source_address_from_the_nwk_layer() {
+        let mut alice = security(ALICE, BOB);
+        let mut bob = security(BOB, ALICE);
+        let frame = alice.secure(&HEADER, b"x", BOB, KeyIdentifier::Data, false).unwrap();
+        let secured = &frame[HEADER.len()..];
+        assert_eq!(bob.unsecure(&HEADER, secured, None), Err(SecurityError::MissingSourceAddress));
+        assert_eq!(bob.unsecure(&HEADER, secured, Some(ALICE)).unwrap().payload, b"x");
+    }
+
+    #[test]
+    fn key_transport_uses_the_derived_key() {
+        let mut alice = security(ALICE, BOB);
+        let mut bob = security(BOB, ALICE);
+        let frame = alice.secure(&HEADER, b"key", BOB, KeyIdentifier::KeyTransport, true).unwrap();
+        let mut reader = Reader::new(&frame[HEADER.len()..]);
+        let aux = AuxiliaryHeader::decode(&mut reader, ZIGBEE_SECURITY_LEVEL).unwrap();
+        let mut a = HEADER.to_vec();
+        aux.encode_with_level(&mut a);
+        let nonce = aux.nonce(ALICE.0);
+        let mut c = reader.read_to_end().to_vec();
+        assert!(ccm::decrypt(&KEY, &nonce, ZIGBEE_SECURITY_LEVEL, &a, &mut c.clone()).is_err());
+        ccm::decrypt(&key_transport_key(&KEY), &nonce, ZIGBEE_SECURITY_LEVEL, &a, &mut c).unwrap();
+        assert_eq!(c, b"key");
+        let unsecured = bob.unsecure(&HEADER, &frame[HEADER.len()..], None).unwrap();
+        assert_eq!(unsecured.key_identifier, KeyIdentifier::KeyTransport);
+    }
+
+    #[test]
+    fn replayed_frames_are_rejected() {
+        let mut alice = security(ALICE, BOB);
+        let mut bob = security(BOB, ALICE);
+        let first = alice.secure(&HEADER, b"1", BOB, KeyIdentifier::Data, true).unwrap();
+        let second = alice.secure(&HEADER, b"2", BOB, KeyIdentifier::Data, true).unwrap();
+        assert!(bob.unsecure(&HEADER, &second[HEADER.len()..], None).is_ok());
+        assert_eq!(bob.unsecure(&HEADER, &second[HEADER.len()..], None), Err(SecurityError::BadFrameCounter));
+        assert_eq!(bob.unsecure(&HEADER, &first[HEADER.len()..], None), Err(SecurityError::BadFrameCounter));
+    }
+
+    #[test]
+    fn unknown_keys() {
+        let mut alice = security(ALICE, BOB);
+        assert_eq!(alice.secure(&HEADER, b"", ALICE, KeyIdentifier::Data, true), Err(SecurityError::UnknownKey));
+        assert_eq!(alice.secure(&HEADER, b"", BOB, KeyIdentifier::Network, true), Err(SecurityError::UnknownKey));
+        let mut eve = security(BOB, BOB);
+        let frame = alice.secure(&HEADER, b"", BOB, KeyIdentifier::Data, true).unwrap();
+        assert_eq!(eve.unsecure(&HEADER, &frame[HEADER.len()..], None), Err(SecurityError::UnknownKey));
+    }
+
+    #[test]
+    fn link_key_table() {
+        let mut table = LinkKeyTable::new(1);
+        table.set_key(ALICE, KEY).unwrap();
+        table.get_mut(ALICE).unwrap().outgoing_frame_counter = 5;
+        table.set_key(ALICE, KEY).unwrap();
+        assert_eq!(table.get(ALICE).unwrap().outgoing_frame_counter, 5);
+        table.set_key(ALICE, [0; 16]).unwrap();
+        assert_eq!(table.get(ALICE).unwrap().outgoing_frame_counter, 0);
+        assert_eq!(table.set_key(BOB, KEY), Err(LinkKeyTableFull));
+        assert_eq!(table.remove(ALICE).map(|entry| entry.partner_address), Some(ALICE));
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn outgoing_frame_counter_is_stored_ahead() {
+        use security::key_store::MemoryKeyStore;
+
+        let persistence = FrameCounterPersistence { interval: 4 };
+        let mut store = MemoryKeyStore::new();
+        let mut alice = security(ALICE, BOB);
+        for _ in 0..5 {
+            alice.secure_persistent(&HEADER, b"x", BOB, KeyIdentifier::Data, true, &mut store, &persistence)
+                .unwrap();
+            let counter = alice.link_keys().get(BOB).unwrap().outgoing_frame_counter;
+            assert!(store.outgoing_frame_counter(FrameCounter::Link(BOB.0)) >= counter);
+        }
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Link(BOB.0)), 8);
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), 0);
+        assert_eq!(alice.secure_persistent(&HEADER, b"x", ALICE, KeyIdentifier::Data, true, &mut store,
+                                           &persistence),
+                   Err(SecurityError::UnknownKey));
+    }
+}
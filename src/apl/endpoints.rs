@@ -0,0 +1,835 @@
+//! The application objects registered on the endpoints of the device.
+//!
+//! The registry owns the simple descriptor of each application object,
+//! that the ZDO reports in the Active_EP_rsp and Simple_Desc_rsp, and the
+//! handler to which the APSDE-DATA.indications addressed to the endpoint
+//! are delivered, if their source may issue application commands. It also
+//! answers the ZDO requests that change the bindings, the network and the
+//! security settings, once the permissions configuration allows their
+//! source to issue them.
+//!
+//! The indications can also be received as an `IndicationStream`, with a
+//! bounded buffer: when the buffer is full, the APS does not wait for the
+//! application and the new indications are dropped, as if the frames were
+//! lost, until the stream is polled again. The stream counts the dropped
+//! indications.
+//!
+//! An endpoint that implements ZCL clusters is registered with their
+//! `ClusterSet`: the frames it receives are handled by the clusters and
+//! their responses are queued as APSDE-DATA.requests back to the source.
+//! The reports of their attributes are queued by `poll_reports`, sent
+//! indirectly to the devices bound to the cluster with the transmission
+//! options of the endpoint. The queued requests are taken with
+//! `take_requests`.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use futures::channel::mpsc::{self, Receiver, Sender, UnboundedSender};
+use futures::stream::Stream;
+use futures::task::{Context, Poll};
+use apl::AddrAndEp;
+use apl::aps::{BindRequest, BindStatus, BindingTable, DataIndication, DataRequest, GroupTable,
+               PermissionCategory, PermissionsConfiguration, RegistrationError, SecurityStatus, TxOptions,
+               UnbindStatus};
+use apl::device_profile::{ActiveEpRsp, BindReq, MgmtNwkUpdateReq, SimpleDescRsp, Status};
+use apl::framework::SimpleDescriptor;
+use types::{ClusterId, Endpoint, IeeeAddress, NwkAddress, ProfileId};
+use zcl::cluster::ClusterSet;
+
+/// Selects the indications of an endpoint passed to a stream. A `None`
+/// field matches any value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IndicationFilter {
+    pub profile_id: Option<ProfileId>,
+    pub cluster_id: Option<ClusterId>,
+}
+
+impl IndicationFilter {
+    pub fn matches(&self, indication: &DataIndication) -> bool {
+        self.profile_id.is_none_or(|profile_id| profile_id == indication.profile_id)
+            && self.cluster_id.is_none_or(|cluster_id| cluster_id == indication.cluster_id)
+    }
+}
+
+/// The indications received on an endpoint, as an asynchronous stream.
+/// It ends when the application object is deregistered.
+pub struct IndicationStream {
+    receiver: Receiver<DataIndication<'static>>,
+    dropped: Arc<AtomicUsize>,
+}
+
+impl IndicationStream {
+    /// The number of indications dropped so far because the buffer was
+    /// full.
+    pub fn dropped(&self) -> usize {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Stream for IndicationStream {
+    type Item = DataIndication<'static>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+/// Where the indications received on an endpoint go.
+pub enum IndicationHandler {
+    /// Called with each indication.
+    Callback(Box<dyn FnMut(&DataIndication) + Send>),
+    /// Each indication is sent on the channel, with its own copy of the
+    /// ASDU.
+    Channel(UnboundedSender<DataIndication<'static>>),
+    /// The indications that match the filter are buffered for an
+    /// `IndicationStream`.
+    Stream {
+        filter: IndicationFilter,
+        sender: Sender<DataIndication<'static>>,
+        dropped: Arc<AtomicUsize>,
+    },
+    /// The frames are handled by the ZCL clusters of the endpoint.
+    Clusters(ClusterSet),
+}
+
+impl IndicationHandler {
+    /// A handler that feeds a stream, buffering up to `buffer`
+    /// indications.
+    pub fn stream(filter: IndicationFilter, buffer: usize) -> (Self, IndicationStream) {
+        // The channel has an additional slot for each sender.
+        let (sender, receiver) = mpsc::channel(buffer.saturating_sub(1));
+        let dropped = Arc::new(AtomicUsize::new(0));
+        let handler = IndicationHandler::Stream { filter, sender, dropped: dropped.clone() };
+        (handler, IndicationStream { receiver, dropped })
+    }
+
+    /// Deliver the indication received on the endpoint, adding the replies
+    /// of the clusters to `requests`. Return false if it was not
+    /// delivered: it did not match the filter, the buffer was full or the
+    /// receiving end was dropped.
+    fn deliver(&mut self, endpoint: Endpoint, indication: &DataIndication,
+               requests: &mut Vec<DataRequest<'static>>) -> bool {
+        match *self {
+            IndicationHandler::Callback(ref mut callback) => {
+                callback(indication);
+                true
+            }
+            IndicationHandler::Channel(ref sender) => {
+                sender.unbounded_send(indication.clone().into_owned()).is_ok()
+            }
+            IndicationHandler::Stream { ref filter, ref mut sender, ref dropped } => {
+                if !filter.matches(indication) {
+                    return false;
+                }
+                match sender.try_send(indication.clone().into_owned()) {
+                    Ok(()) => true,
+                    Err(ref error) if error.is_full() => {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                        false
+                    }
+                    Err(_) => false,
+                }
+            }
+            IndicationHandler::Clusters(ref mut clusters) => {
+                if let Some(asdu) = clusters.handle_frame(indication.cluster_id, &indication.asdu) {
+                    requests.push(DataRequest {
+                        dst: indication.src_addr_ep,
+                        profile_id: indication.profile_id,
+                        cluster_id: indication.cluster_id,
+                        src_endpoint: endpoint,
+                        asdu: Cow::Owned(asdu),
+                        // The response is secured as the request was.
+                        options: TxOptions {
+                            security_enabled: indication.security_status != SecurityStatus::Unsecured,
+                            use_nwk_key: indication.security_status == SecurityStatus::SecuredNwkKey,
+                            acknowledged_transmission: true,
+                            fragmentation_permitted: false,
+                            include_extended_nonce_in_aps_security_frame: false,
+                        },
+                        radius: 0,
+                    });
+                }
+                true
+            }
+        }
+    }
+}
+
+/// A ZDO request that changes the settings of the device, restricted by
+/// the permissions configuration.
+pub enum ZdoRequest<'a> {
+    Bind(&'a BindReq),
+    Unbind(&'a BindReq),
+    MgmtLeave {
+        device_address: IeeeAddress,
+        remove_children: bool,
+        rejoin: bool,
+    },
+    MgmtDirectJoin {
+        device_address: IeeeAddress,
+        capability_information: u8,
+    },
+    MgmtPermitJoining {
+        permit_duration: u8,
+        tc_significance: bool,
+    },
+    MgmtNwkUpdate(&'a MgmtNwkUpdateReq),
+    SecuritySetConfiguration,
+    SecurityStartKeyUpdate {
+        device_address: IeeeAddress,
+    },
+    SecurityDecommission {
+        device_address: IeeeAddress,
+    },
+}
+
+impl<'a> ZdoRequest<'a> {
+    /// The cluster identifier of the request.
+    pub fn cluster_id(&self) -> ClusterId {
+        ClusterId(match *self {
+            ZdoRequest::Bind(_) => 0x0021,
+            ZdoRequest::Unbind(_) => 0x0022,
+            ZdoRequest::MgmtLeave { .. } => 0x0034,
+            ZdoRequest::MgmtDirectJoin { .. } => 0x0035,
+            ZdoRequest::MgmtPermitJoining { .. } => 0x0036,
+            ZdoRequest::MgmtNwkUpdate(_) => 0x0038,
+            ZdoRequest::SecuritySetConfiguration => 0x0043,
+            ZdoRequest::SecurityStartKeyUpdate { .. } => 0x0045,
+            ZdoRequest::SecurityDecommission { .. } => 0x0046,
+        })
+    }
+}
+
+/// The transmission options of the reports of an endpoint that did not
+/// set its own: secured with the link key shared with the destination.
+pub const DEFAULT_REPORT_OPTIONS: TxOptions = TxOptions {
+    security_enabled: true,
+    use_nwk_key: false,
+    acknowledged_transmission: true,
+    fragmentation_permitted: false,
+    include_extended_nonce_in_aps_security_frame: false,
+};
+
+struct ApplicationObject {
+    descriptor: SimpleDescriptor<'static, 'static>,
+    handler: IndicationHandler,
+    /// The transmission options of the reports of the clusters.
+    report_options: TxOptions,
+}
+
+#[derive(Default)]
+pub struct EndpointRegistry {
+    objects: BTreeMap<Endpoint, ApplicationObject>,
+    requests: Vec<DataRequest<'static>>,
+}
+
+impl EndpointRegistry {
+    pub fn new() -> Self {
+        EndpointRegistry { objects: BTreeMap::new(), requests: Vec::new() }
+    }
+
+    /// Register an application object on the endpoint of its descriptor.
+    pub fn register(&mut self, descriptor: SimpleDescriptor<'static, 'static>, handler: IndicationHandler)
+                    -> Result<(), RegistrationError> {
+        if !descriptor.endpoint.is_application() {
+            return Err(RegistrationError::ReservedEndpoint);
+        }
+        if self.objects.contains_key(&descriptor.endpoint) {
+            return Err(RegistrationError::EndpointInUse);
+        }
+        self.objects.insert(descriptor.endpoint, ApplicationObject {
+            descriptor,
+            handler,
+            report_options: DEFAULT_REPORT_OPTIONS,
+        });
+        Ok(())
+    }
+
+    /// Set the transmission options of the reports sent by the clusters of
+    /// the endpoint. Return false if the endpoint is not registered.
+    pub fn set_report_options(&mut self, endpoint: Endpoint, options: TxOptions) -> bool {
+        match self.objects.get_mut(&endpoint) {
+            Some(object) => {
+                object.report_options = options;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the application object registered on the endpoint, returning
+    /// its descriptor.
+    pub fn deregister(&mut self, endpoint: Endpoint) -> Option<SimpleDescriptor<'static, 'static>> {
+        self.objects.remove(&endpoint).map(|object| object.descriptor)
+    }
+
+    pub fn is_registered(&self, endpoint: Endpoint) -> bool {
+        self.objects.contains_key(&endpoint)
+    }
+
+    /// The registered endpoints, in increasing order.
+    pub fn active_endpoints(&self) -> Vec<Endpoint> {
+        self.objects.keys().cloned().collect()
+    }
+
+    pub fn simple_descriptor(&self, endpoint: Endpoint) -> Option<&SimpleDescriptor<'static, 'static>> {
+        self.objects.get(&endpoint).map(|object| &object.descriptor)
+    }
+
+    /// The clusters of the endpoint, if it was registered with them.
+    pub fn clusters(&self, endpoint: Endpoint) -> Option<&ClusterSet> {
+        match self.objects.get(&endpoint).map(|object| &object.handler) {
+            Some(IndicationHandler::Clusters(clusters)) => Some(clusters),
+            _ => None,
+        }
+    }
+
+    pub fn clusters_mut(&mut self, endpoint: Endpoint) -> Option<&mut ClusterSet> {
+        match self.objects.get_mut(&endpoint).map(|object| &mut object.handler) {
+            Some(&mut IndicationHandler::Clusters(ref mut clusters)) => Some(clusters),
+            _ => None,
+        }
+    }
+
+    /// The reply of the ZDO to an Active_EP_req for this device.
+    pub fn active_ep_rsp(&self, nwk_addr_of_interest: NwkAddress) -> ActiveEpRsp<'static> {
+        ActiveEpRsp {
+            status: Status::Success,
+            nwk_addr_of_interest,
+            active_ep_list: Cow::Owned(self.active_endpoints()),
+        }
+    }
+
+    /// The reply of the ZDO to a Simple_Desc_req for this device.
+    pub fn simple_desc_rsp<'a>(&'a self, nwk_addr_of_interest: NwkAddress, endpoint: Endpoint)
+                               -> SimpleDescRsp<'a, 'a> {
+        let (status, desc) = if endpoint == Endpoint::ZDO || endpoint == Endpoint::BROADCAST {
+            (Status::InvalidEp, None)
+        } else {
+            match self.simple_descriptor(endpoint) {
+                Some(descriptor) => (Status::Success, Some(SimpleDescriptor {
+                    endpoint: descriptor.endpoint,
+                    appl_prof_id: descriptor.appl_prof_id,
+                    appl_dev_id: descriptor.appl_dev_id,
+                    appl_dev_vers: descriptor.appl_dev_vers,
+                    appl_input_clusters: Cow::Borrowed(&descriptor.appl_input_clusters),
+                    appl_output_clusters: Cow::Borrowed(&descriptor.appl_output_clusters),
+                })),
+                None => (Status::NotActive, None),
+            }
+        };
+        SimpleDescRsp { status, nwk_addr_of_interest, desc }
+    }
+
+    /// Answer a ZDO request sent by `source`, that secured it with a link
+    /// key if `link_key_secured`. The request is rejected with
+    /// `NotAuthorized` unless the permissions configuration allows the
+    /// source to issue it. The Bind_req and Unbind_req are applied to the
+    /// binding table; the management requests are only authorized, and
+    /// carried out by the NWK layer when the status is `Success`.
+    pub fn handle_zdo_request(&self, request: &ZdoRequest, source: IeeeAddress, link_key_secured: bool,
+                              permissions: &PermissionsConfiguration, bindings: &mut BindingTable) -> Status {
+        if let Some(category) = PermissionCategory::for_zdo_request(request.cluster_id()) {
+            if let Err(status) = permissions.authorize(source, category, link_key_secured) {
+                return status;
+            }
+        }
+        let (bind, request) = match *request {
+            ZdoRequest::Bind(request) => (true, request),
+            ZdoRequest::Unbind(request) => (false, request),
+            _ => return Status::Success,
+        };
+        if !self.is_registered(request.src_endp) {
+            return Status::InvalidEp;
+        }
+        let request = BindRequest {
+            src_addr: request.src_add,
+            src_endpoint: request.src_endp,
+            cluster_id: request.cluster_id,
+            dst_addr: request.dst_addr,
+        };
+        if bind {
+            match bindings.bind(&request) {
+                BindStatus::Success => Status::Success,
+                BindStatus::TableFull => Status::TableFull,
+                BindStatus::IllegalRequest | BindStatus::NotSupported => Status::NotSupported,
+            }
+        } else {
+            match bindings.unbind(&request) {
+                UnbindStatus::Success => Status::Success,
+                UnbindStatus::InvalidBinding => Status::NoEntry,
+                UnbindStatus::IllegalRequest => Status::NotSupported,
+            }
+        }
+    }
+
+    /// Deliver a received frame to the application objects it is addressed
+    /// to: the destination endpoint, all the endpoints of the profile for
+    /// the broadcast endpoint, or the members of the group in `groups` for
+    /// a group address. Return the endpoints that received it.
+    ///
+    /// The frame is dropped unless the permissions configuration allows
+    /// `source`, the IEEE address of the sender if known, to issue
+    /// application commands.
+    pub fn dispatch(&mut self, indication: &DataIndication, groups: Option<&GroupTable>, source: Option<IeeeAddress>,
+                    permissions: &PermissionsConfiguration) -> Vec<Endpoint> {
+        let link_key_secured = indication.security_status == SecurityStatus::SecuredLinkKey;
+        let authorized = match source {
+            Some(source) => {
+                permissions.authorize(source, PermissionCategory::ApplicationCommands, link_key_secured).is_ok()
+            }
+            None => permissions.descriptors.is_empty(),
+        };
+        if !authorized {
+            return Vec::new();
+        }
+        let endpoints: Vec<Endpoint> = match indication.dst_addr_ep {
+            AddrAndEp::ShortAddressWithEp(_, Endpoint::BROADCAST)
+            | AddrAndEp::LongAddress(_, Endpoint::BROADCAST) => {
+                let profile_id = indication.profile_id;
+                self.objects.values()
+                    .filter(|object| profile_id == ProfileId::WILDCARD
+                            || object.descriptor.appl_prof_id == profile_id)
+                    .map(|object| object.descriptor.endpoint)
+                    .collect()
+            }
+            AddrAndEp::ShortAddressWithEp(_, endpoint)
+            | AddrAndEp::LongAddress(_, endpoint) => vec![endpoint],
+            AddrAndEp::ShortAddressNoEp(group) => {
+                groups.map_or_else(Vec::new, |groups| groups.endpoints(group).collect())
+            }
+            AddrAndEp::None => Vec::new(),
+        };
+        let objects = &mut self.objects;
+        let requests = &mut self.requests;
+        endpoints.into_iter()
+            .filter(|&endpoint| {
+                objects.get_mut(&endpoint)
+                    .is_some_and(|object| object.handler.deliver(endpoint, indication, requests))
+            })
+            .collect()
+    }
+
+    /// Queue the reports of the attributes of the clusters due at time
+    /// `now`.
+    pub fn poll_reports(&mut self, now: Duration) {
+        for (&endpoint, object) in &mut self.objects {
+            let clusters = match object.handler {
+                IndicationHandler::Clusters(ref mut clusters) => clusters,
+                _ => continue,
+            };
+            for (cluster_id, asdu) in clusters.poll_reports(now) {
+                self.requests.push(DataRequest {
+                    dst: AddrAndEp::None,
+                    profile_id: object.descriptor.appl_prof_id,
+                    cluster_id,
+                    src_endpoint: endpoint,
+                    asdu: Cow::Owned(asdu),
+                    options: object.report_options,
+                    radius: 0,
+                });
+            }
+        }
+    }
+
+    /// The time of the next report of the clusters, when `poll_reports`
+    /// must be called again.
+    pub fn next_report(&self) -> Option<Duration> {
+        self.objects.values()
+            .filter_map(|object| match object.handler {
+                IndicationHandler::Clusters(ref clusters) => clusters.next_report(),
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Take the requests queued so far, the responses of the clusters and
+    /// their reports, to be sent with APSDE-DATA.request.
+    pub fn take_requests(&mut self) -> Vec<DataRequest<'static>> {
+        ::std::mem::take(&mut self.requests)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::stream::StreamExt;
+    use apl::aps::{IndicationStatus, PermissionsDescriptor};
+    use types::{AttributeId, GroupId};
+    use zcl::cluster::{AttributeDefinition, AttributeTable, ClusterSide, GenericCluster};
+    use zcl::data_type::{AttributeValue, DataType};
+    use zcl::general::{AccessControl, ReportingConfiguration};
+
+    fn descriptor(endpoint: u8, profile_id: ProfileId) -> SimpleDescriptor<'static, 'static> {
+        SimpleDescriptor {
+            endpoint: Endpoint(endpoint),
+            appl_prof_id: profile_id,
+            appl_dev_id: 0x0100,
+            appl_dev_vers: 1,
+            appl_input_clusters: Cow::Owned(vec![ClusterId(0x0006)]),
+            appl_output_clusters: Cow::Owned(Vec::new()),
+        }
+    }
+
+    fn registry() -> EndpointRegistry {
+        let mut registry = EndpointRegistry::new();
+        let handler = IndicationHandler::Callback(Box::new(|_: &DataIndication| {}));
+        registry.register(descriptor(1, ProfileId::HOME_AUTOMATION), handler).unwrap();
+        registry
+    }
+
+    const INSTALLER: IeeeAddress = IeeeAddress(0x0011_2233_4455_6677);
+    const INTRUDER: IeeeAddress = IeeeAddress(0x8899_AABB_CCDD_EEFF);
+
+    fn permissions() -> PermissionsConfiguration {
+        PermissionsConfiguration {
+            descriptors: vec![PermissionsDescriptor {
+                device_address: INSTALLER,
+                modify_permissions_configuration: false,
+                network_settings: true,
+                application_settings: true,
+                security_settings: false,
+                application_commands: true,
+                security_commands: false,
+                link_key_required: true,
+            }],
+        }
+    }
+
+    fn bind_req(src_endp: u8) -> BindReq {
+        BindReq {
+            src_add: IeeeAddress(1),
+            src_endp: Endpoint(src_endp),
+            cluster_id: ClusterId(0x0006),
+            dst_addr: AddrAndEp::LongAddress(IeeeAddress(2), Endpoint(1)),
+        }
+    }
+
+    fn indication(dst_addr_ep: AddrAndEp, profile_id: ProfileId, cluster_id: u16) -> DataIndication<'static> {
+        DataIndication {
+            dst_addr_ep,
+            src_addr_ep: AddrAndEp::ShortAddressWithEp(NwkAddress(0x1234), Endpoint(5)),
+            profile_id,
+            cluster_id: ClusterId(cluster_id),
+            asdu: Cow::Owned(vec![0x00, 0x01, 0x00, 0x00, 0x00]),
+            status: IndicationStatus::Success,
+            security_status: SecurityStatus::SecuredLinkKey,
+            link_quality: 255,
+            rx_time: Duration::from_secs(0),
+        }
+    }
+
+    fn to_endpoint(endpoint: u8) -> AddrAndEp {
+        AddrAndEp::ShortAddressWithEp(NwkAddress(0x0000), Endpoint(endpoint))
+    }
+
+    /// A handler that counts the indications it receives.
+    fn counter() -> (IndicationHandler, Arc<AtomicUsize>) {
+        let count = Arc::new(AtomicUsize::new(0));
+        let handler_count = count.clone();
+        let handler = IndicationHandler::Callback(Box::new(move |_: &DataIndication| {
+            handler_count.fetch_add(1, Ordering::Relaxed);
+        }));
+        (handler, count)
+    }
+
+    #[test]
+    fn registration() {
+        let mut registry = registry();
+        for &endpoint in &[0x00, 0xF1, 0xF2, 0xFF] {
+            assert_eq!(registry.register(descriptor(endpoint, ProfileId::HOME_AUTOMATION), counter().0),
+                       Err(RegistrationError::ReservedEndpoint));
+        }
+        assert_eq!(registry.register(descriptor(1, ProfileId(0xC05E)), counter().0),
+                   Err(RegistrationError::EndpointInUse));
+        registry.register(descriptor(0xF0, ProfileId(0xC05E)), counter().0).unwrap();
+        assert_eq!(registry.active_endpoints(), vec![Endpoint(1), Endpoint(0xF0)]);
+        assert_eq!(&registry.active_ep_rsp(NwkAddress(0x1234)).active_ep_list[..], &[Endpoint(1), Endpoint(0xF0)]);
+
+        let response = registry.simple_desc_rsp(NwkAddress(0x1234), Endpoint(0xF0));
+        assert_eq!(response.status, Status::Success);
+        assert_eq!(response.desc.unwrap().appl_prof_id, ProfileId(0xC05E));
+        assert_eq!(registry.simple_desc_rsp(NwkAddress(0x1234), Endpoint(2)).status, Status::NotActive);
+        assert_eq!(registry.simple_desc_rsp(NwkAddress(0x1234), Endpoint::ZDO).status, Status::InvalidEp);
+
+        assert_eq!(registry.deregister(Endpoint(1)).map(|d| d.endpoint), Some(Endpoint(1)));
+        assert!(!registry.is_registered(Endpoint(1)));
+        assert!(registry.deregister(Endpoint(1)).is_none());
+        assert!(registry.clusters(Endpoint(0xF0)).is_none());
+    }
+
+    #[test]
+    fn dispatch_to_the_addressed_endpoints() {
+        let mut registry = EndpointRegistry::new();
+        let (handler, first) = counter();
+        registry.register(descriptor(1, ProfileId::HOME_AUTOMATION), handler).unwrap();
+        let (handler, second) = counter();
+        registry.register(descriptor(2, ProfileId::HOME_AUTOMATION), handler).unwrap();
+        let (sender, mut receiver) = mpsc::unbounded();
+        registry.register(descriptor(3, ProfileId(0xC05E)), IndicationHandler::Channel(sender)).unwrap();
+
+        let open = PermissionsConfiguration::default();
+        let ha = ProfileId::HOME_AUTOMATION;
+        assert_eq!(registry.dispatch(&indication(to_endpoint(2), ha, 6), None, None, &open), vec![Endpoint(2)]);
+        assert_eq!(registry.dispatch(&indication(to_endpoint(4), ha, 6), None, None, &open), vec![]);
+        let broadcast = AddrAndEp::LongAddress(IeeeAddress(1), Endpoint::BROADCAST);
+        assert_eq!(registry.dispatch(&indication(broadcast, ha, 6), None, None, &open),
+                   vec![Endpoint(1), Endpoint(2)]);
+        assert_eq!(registry.dispatch(&indication(broadcast, ProfileId::WILDCARD, 6), None, None, &open),
+                   vec![Endpoint(1), Endpoint(2), Endpoint(3)]);
+        assert_eq!((first.load(Ordering::Relaxed), second.load(Ordering::Relaxed)), (2, 3));
+        assert_eq!(receiver.try_next().unwrap().unwrap().profile_id, ProfileId::WILDCARD);
+
+        let mut groups = GroupTable::new(4);
+        groups.add(GroupId(0x0001), Endpoint(1));
+        groups.add(GroupId(0x0001), Endpoint(3));
+        let group = AddrAndEp::ShortAddressNoEp(GroupId(0x0001));
+        assert_eq!(registry.dispatch(&indication(group, ha, 6), Some(&groups), None, &open),
+                   vec![Endpoint(1), Endpoint(3)]);
+        assert_eq!(registry.dispatch(&indication(group, ha, 6), None, None, &open), vec![]);
+        assert_eq!(registry.dispatch(&indication(AddrAndEp::None, ha, 6), None, None, &open), vec![]);
+
+        // A channel whose receiver was dropped no longer receives.
+        drop(receiver);
+        assert_eq!(registry.dispatch(&indication(to_endpoint(3), ha, 6), None, None, &open), vec![]);
+    }
+
+    #[test]
+    fn indication_filters() {
+        let ha = ProfileId::HOME_AUTOMATION;
+        let received = indication(to_endpoint(1), ha, 6);
+        assert!(IndicationFilter::default().matches(&received));
+        assert!(IndicationFilter { profile_id: Some(ha), cluster_id: Some(ClusterId(6)) }.matches(&received));
+        assert!(!IndicationFilter { profile_id: Some(ProfileId(0xC05E)), cluster_id: None }.matches(&received));
+        assert!(!IndicationFilter { profile_id: None, cluster_id: Some(ClusterId(8)) }.matches(&received));
+    }
+
+    #[test]
+    fn stream_drops_the_indications_when_full() {
+        let filter = IndicationFilter { profile_id: None, cluster_id: Some(ClusterId(6)) };
+        let (handler, mut stream) = IndicationHandler::stream(filter, 2);
+        let mut registry = EndpointRegistry::new();
+        registry.register(descriptor(1, ProfileId::HOME_AUTOMATION), handler).unwrap();
+
+        let open = PermissionsConfiguration::default();
+        let ha = ProfileId::HOME_AUTOMATION;
+        assert_eq!(registry.dispatch(&indication(to_endpoint(1), ha, 8), None, None, &open), vec![]);
+        for _ in 0..2 {
+            assert_eq!(registry.dispatch(&indication(to_endpoint(1), ha, 6), None, None, &open), vec![Endpoint(1)]);
+        }
+        assert_eq!(registry.dispatch(&indication(to_endpoint(1), ha, 6), None, None, &open), vec![]);
+        assert_eq!(stream.dropped(), 1);
+
+        let first = block_on(stream.next()).unwrap();
+        assert_eq!(first.cluster_id, ClusterId(6));
+        assert_eq!(&first.asdu[..], &indication(to_endpoint(1), ha, 6).asdu[..]);
+        // There is room again once the stream is polled.
+        assert_eq!(registry.dispatch(&indication(to_endpoint(1), ha, 6), None, None, &open), vec![Endpoint(1)]);
+        assert_eq!(stream.dropped(), 1);
+
+        // The stream ends with the buffered indications when the endpoint
+        // is deregistered.
+        registry.deregister(Endpoint(1));
+        assert_eq!(block_on(stream.collect::<Vec<_>>()).len(), 2);
+    }
+
+    #[test]
+    fn clusters_answer_the_frames() {
+        let mut clusters = ClusterSet::new();
+        clusters.add(Box::new(GenericCluster {
+            id: ClusterId(0x0006),
+            side: ClusterSide::Server,
+            attributes: AttributeTable::new(),
+        })).unwrap();
+        let mut registry = EndpointRegistry::new();
+        registry.register(descriptor(1, ProfileId::HOME_AUTOMATION), IndicationHandler::Clusters(clusters))
+            .unwrap();
+        assert!(registry.clusters(Endpoint(1)).is_some());
+
+        let mut received = indication(to_endpoint(1), ProfileId::HOME_AUTOMATION, 0x0006);
+        received.security_status = SecurityStatus::SecuredNwkKey;
+        assert_eq!(registry.dispatch(&received, None, None, &PermissionsConfiguration::default()), vec![Endpoint(1)]);
+        let requests = registry.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].dst, received.src_addr_ep);
+        assert_eq!((requests[0].src_endpoint, requests[0].cluster_id), (Endpoint(1), ClusterId(0x0006)));
+        // The response is secured as the request was.
+        assert!(requests[0].options.security_enabled && requests[0].options.use_nwk_key);
+        assert!(registry.take_requests().is_empty());
+    }
+
+    #[test]
+    fn zdo_requests_are_authorized() {
+        let registry = registry();
+        let permissions = permissions();
+        let mut bindings = BindingTable::new(4);
+        let request = bind_req(1);
+        let bind = ZdoRequest::Bind(&request);
+        assert_eq!(registry.handle_zdo_request(&bind, INTRUDER, true, &permissions, &mut bindings),
+                   Status::NotAuthorized);
+        assert_eq!(registry.handle_zdo_request(&bind, INSTALLER, false, &permissions, &mut bindings),
+                   Status::NotAuthorized);
+        assert!(bindings.is_empty());
+        assert_eq!(registry.handle_zdo_request(&bind, INSTALLER, true, &permissions, &mut bindings),
+                   Status::Success);
+        assert_eq!(bindings.len(), 1);
+        let unbind = ZdoRequest::Unbind(&request);
+        assert_eq!(registry.handle_zdo_request(&unbind, INTRUDER, true, &permissions, &mut bindings),
+                   Status::NotAuthorized);
+        assert_eq!(registry.handle_zdo_request(&unbind, INSTALLER, true, &permissions, &mut bindings),
+                   Status::Success);
+        assert_eq!(registry.handle_zdo_request(&unbind, INSTALLER, true, &permissions, &mut bindings),
+                   Status::NoEntry);
+        let leave = ZdoRequest::MgmtLeave { device_address: INSTALLER, remove_children: false, rejoin: false };
+        assert_eq!(registry.handle_zdo_request(&leave, INTRUDER, true, &permissions, &mut bindings),
+                   Status::NotAuthorized);
+        let join = ZdoRequest::MgmtDirectJoin { device_address: INTRUDER, capability_information: 0 };
+        assert_eq!(registry.handle_zdo_request(&join, INTRUDER, true, &permissions, &mut bindings),
+                   Status::NotAuthorized);
+        assert_eq!(registry.handle_zdo_request(&join, INSTALLER, true, &permissions, &mut bindings),
+                   Status::Success);
+    }
+
+    #[test]
+    fn security_settings_are_authorized() {
+        let registry = registry();
+        let permissions = permissions();
+        let mut bindings = BindingTable::new(4);
+        let requests = [
+            ZdoRequest::SecuritySetConfiguration,
+            ZdoRequest::SecurityStartKeyUpdate { device_address: INTRUDER },
+            ZdoRequest::SecurityDecommission { device_address: INTRUDER },
+        ];
+        for request in &requests {
+            // The installer may change the application and network
+            // settings, but not the security settings.
+            assert_eq!(registry.handle_zdo_request(request, INSTALLER, true, &permissions, &mut bindings),
+                       Status::NotAuthorized);
+            assert_eq!(registry.handle_zdo_request(request, INTRUDER, true, &permissions, &mut bindings),
+                       Status::NotAuthorized);
+        }
+        let mut permissions = permissions;
+        permissions.descriptors[0].security_settings = true;
+        for request in &requests {
+            assert_eq!(registry.handle_zdo_request(request, INSTALLER, true, &permissions, &mut bindings),
+                       Status::Success);
+        }
+    }
+
+    #[test]
+    fn application_commands_are_authorized() {
+        let mut registry = EndpointRegistry::new();
+        let (handler, count) = counter();
+        registry.register(descriptor(1, ProfileId::HOME_AUTOMATION), handler).unwrap();
+        let permissions = permissions();
+        let mut received = indication(to_endpoint(1), ProfileId::HOME_AUTOMATION, 6);
+        assert_eq!(registry.dispatch(&received, None, Some(INTRUDER), &permissions), vec![]);
+        // The sender is unknown.
+        assert_eq!(registry.dispatch(&received, None, None, &permissions), vec![]);
+        // The installer must secure its commands with a link key.
+        received.security_status = SecurityStatus::SecuredNwkKey;
+        assert_eq!(registry.dispatch(&received, None, Some(INSTALLER), &permissions), vec![]);
+        assert_eq!(count.load(Ordering::Relaxed), 0);
+
+        received.security_status = SecurityStatus::SecuredLinkKey;
+        assert_eq!(registry.dispatch(&received, None, Some(INSTALLER), &permissions), vec![Endpoint(1)]);
+        let mut denied = permissions;
+        denied.descriptors[0].application_commands = false;
+        assert_eq!(registry.dispatch(&received, None, Some(INSTALLER), &denied), vec![]);
+        assert_eq!(count.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn permissions_configuration_is_modified_when_authorized() {
+        let mut configuration = permissions();
+        let replacement = PermissionsConfiguration::default();
+        assert_eq!(configuration.modify(replacement.clone(), Some(INTRUDER), true), Err(Status::NotAuthorized));
+        // The installer may not modify the configuration.
+        assert_eq!(configuration.modify(replacement.clone(), Some(INSTALLER), true), Err(Status::NotAuthorized));
+        assert_eq!(configuration, permissions());
+
+        configuration.descriptors[0].modify_permissions_configuration = true;
+        assert_eq!(configuration.modify(replacement.clone(), Some(INSTALLER), false), Err(Status::NotAuthorized));
+        assert_eq!(configuration.modify(replacement.clone(), Some(INSTALLER), true), Ok(()));
+        assert_eq!(configuration, replacement);
+
+        // The application itself is always allowed.
+        let mut configuration = permissions();
+        assert_eq!(configuration.modify(replacement.clone(), None, false), Ok(()));
+        assert_eq!(configuration, replacement);
+    }
+
+    #[test]
+    fn bind_checks_the_source_endpoint() {
+        let registry = registry();
+        let mut bindings = BindingTable::new(1);
+        let open = PermissionsConfiguration::default();
+        let request = bind_req(2);
+        assert_eq!(registry.handle_zdo_request(&ZdoRequest::Bind(&request), INTRUDER, false, &open, &mut bindings),
+                   Status::InvalidEp);
+        let request = bind_req(1);
+        assert_eq!(registry.handle_zdo_request(&ZdoRequest::Bind(&request), INTRUDER, false, &open, &mut bindings),
+                   Status::Success);
+        let mut other = bind_req(1);
+        other.cluster_id = ClusterId(0x0008);
+        assert_eq!(registry.handle_zdo_request(&ZdoRequest::Bind(&other), INTRUDER, false, &open, &mut bindings),
+                   Status::TableFull);
+    }
+
+    #[test]
+    fn zdo_permission_categories() {
+        assert_eq!(PermissionCategory::for_zdo_request(ClusterId(0x0024)),
+                   Some(PermissionCategory::ApplicationSettings));
+        assert_eq!(PermissionCategory::for_zdo_request(ClusterId(0x0035)),
+                   Some(PermissionCategory::NetworkSettings));
+        assert_eq!(PermissionCategory::for_zdo_request(ClusterId(0x0046)),
+                   Some(PermissionCategory::SecuritySettings));
+        assert_eq!(PermissionCategory::for_zdo_request(ClusterId(0x0005)), None);
+    }
+
+    #[test]
+    fn reports_use_the_options_of_the_endpoint() {
+        let mut attributes = AttributeTable::new();
+        attributes.add(AttributeDefinition {
+            id: AttributeId(0x0000),
+            manufacturer_code: None,
+            data_type: DataType::Boolean,
+            access: AccessControl { readable: true, writable: false, reportable: true },
+            default: AttributeValue::Boolean(Some(false)),
+        }).unwrap();
+        attributes.configure_reporting(None, &ReportingConfiguration::Reported {
+            attribute_id: AttributeId(0x0000),
+            data_type: DataType::Boolean,
+            min_interval: 0,
+            max_interval: 10,
+            reportable_change: None,
+        }).unwrap();
+        let mut clusters = ClusterSet::new();
+        clusters.add(Box::new(GenericCluster { id: ClusterId(0x0006), side: ClusterSide::Server, attributes }))
+            .unwrap();
+        let mut registry = EndpointRegistry::new();
+        registry.register(descriptor(1, ProfileId::HOME_AUTOMATION), IndicationHandler::Clusters(clusters))
+            .unwrap();
+
+        registry.poll_reports(Duration::from_secs(0));
+        let requests = registry.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].dst, AddrAndEp::None);
+        assert_eq!(requests[0].src_endpoint, Endpoint(1));
+        assert_eq!(requests[0].options, DEFAULT_REPORT_OPTIONS);
+
+        let options = TxOptions { use_nwk_key: true, ..DEFAULT_REPORT_OPTIONS };
+        assert!(registry.set_report_options(Endpoint(1), options));
+        assert!(!registry.set_report_options(Endpoint(2), options));
+        assert_eq!(registry.next_report(), Some(Duration::from_secs(10)));
+        registry.poll_reports(Duration::from_secs(10));
+        let requests = registry.take_requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].options, options);
+    }
+}
@@ -1,6 +1,7 @@
 ///The framework is 
 
 use std::borrow::Cow;
+use types::{ClusterId, Endpoint, ProfileId};
 
 pub struct ComplexDescriptor<'a> {
     desc: Cow<'a, [ComplexDescriptorField]>
@@ -19,6 +20,7 @@ pub enum ComplexDescriptorField{
     IconUrl(String)
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum LogicalType{
     Coordinator,
     Router,
@@ -34,6 +36,7 @@ pub enum FrequencyBand{
     High = 3,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MacCapability{
     pub alternate_pan_coordinator: bool,
     pub device_type: bool,
@@ -123,12 +126,12 @@ bitfield!{
 }
 
 pub struct SimpleDescriptor<'a, 'b> {
-    pub endpoint: u8,
-    pub appl_prof_id: u16,
+    pub endpoint: Endpoint,
+    pub appl_prof_id: ProfileId,
     pub appl_dev_id: u16,
     pub appl_dev_vers: u8,
-    pub appl_input_clusters: Cow<'a, [u16]>,
-    pub appl_output_clusters: Cow<'b, [u16]>
+    pub appl_input_clusters: Cow<'a, [ClusterId]>,
+    pub appl_output_clusters: Cow<'b, [ClusterId]>
 }
 
 pub struct UserDescriptor{
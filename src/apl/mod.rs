@@ -1,11 +1,18 @@
-#[derive(Clone, Copy)]
+use types::{Endpoint, GroupId, IeeeAddress, NwkAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AddrAndEp{
     None,
-    ShortAddressNoEp(u16),
-    ShortAddressWithEp(u16, u8),
-    LongAddress(u64, u8)
+    /// A group address.
+    ShortAddressNoEp(GroupId),
+    ShortAddressWithEp(NwkAddress, Endpoint),
+    LongAddress(IeeeAddress, Endpoint)
 }
 
 pub mod aps;
 pub mod framework;
 pub mod device_profile;
+pub mod aps_security;
+pub mod aps_commands;
+pub mod trust_center;
+pub mod endpoints;
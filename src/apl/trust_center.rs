@@ -0,0 +1,728 @@
+//! The centralized trust center, described in section 4.6.3 of the
+//! specification.
+//!
+//! The trust center authorizes the devices that join the network and sends
+//! them the network key, secured with the link key they share with it:
+//! pre-configured, derived from an install code or, if allowed, the
+//! default one. A device that joined through a router receives the key
+//! tunneled through its parent. Afterwards, the devices can request a new
+//! trust center link key and prove they know it, or a link key to talk to
+//! another device. The trust center also distributes new network keys and
+//! removes devices from the network.
+
+use std::error;
+use std::fmt;
+use rand::Rng;
+use apl::aps_commands::{ApsCommand, ApsCommandFrame, CommandIndication, ConfirmKey,
+                        ConfirmKeyStatus, KeyType, RemoveDevice, RequestKey, SwitchKey,
+                        TransportKey, TransportKeyData, Tunnel, UpdateDeviceStatus};
+use apl::aps::{PermissionCategory, PermissionsConfiguration};
+use apl::aps_security::{ApsSecurity, LinkKeyTable};
+use nwk::security::NetworkKey;
+use security::{Key, KeyIdentifier, SecurityError, KEY_SIZE};
+use security::install_code::{self, InstallCodeError};
+use types::{IeeeAddress, NwkAddress};
+
+/// The well known trust center link key, "ZigBeeAlliance09", used by the
+/// devices that have no other link key.
+pub const DEFAULT_TRUST_CENTER_LINK_KEY: Key = *b"ZigBeeAlliance09";
+
+pub struct TrustCenterConfig {
+    /// Whether new devices may join the network.
+    pub allow_joins: bool,
+    /// Whether the devices without a pre-configured link key may join with
+    /// the default trust center link key.
+    pub allow_default_link_key: bool,
+    /// The maximum number of devices in the network.
+    pub max_devices: usize,
+    /// The devices allowed to send security commands to the trust center.
+    pub permissions: PermissionsConfiguration,
+}
+
+impl Default for TrustCenterConfig {
+    fn default() -> Self {
+        TrustCenterConfig {
+            allow_joins: true,
+            allow_default_link_key: true,
+            max_devices: 32,
+            permissions: PermissionsConfiguration::default(),
+        }
+    }
+}
+
+/// A device authorized by the trust center.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeviceRecord {
+    pub address: IeeeAddress,
+    pub short_address: NwkAddress,
+    pub parent_address: IeeeAddress,
+    pub parent_short_address: NwkAddress,
+    /// Whether the device proved it knows its trust center link key.
+    pub link_key_verified: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TrustCenterAction {
+    /// An APS command frame to send to a device of the network.
+    Send { destination: NwkAddress, frame: Vec<u8> },
+    /// An APS command frame to broadcast to all the devices.
+    Broadcast { frame: Vec<u8> },
+    /// A new network key, to store in the NWK security of the trust center.
+    SetNetworkKey(NetworkKey),
+    /// The network key with this sequence number becomes the active one.
+    SwitchNetworkKey(u8),
+    DeviceAuthorized(IeeeAddress),
+    /// The device is not allowed in the network; its parent was asked to
+    /// remove it, unless the trust center is the parent.
+    DeviceRejected(IeeeAddress),
+    /// The device was removed; its parent was asked to make it leave,
+    /// unless the trust center is the parent.
+    DeviceRemoved(IeeeAddress),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrustCenterError {
+    InvalidInstallCode(InstallCodeError),
+    TableFull,
+    UnknownDevice,
+    /// The source of the command is not allowed to issue it.
+    NotAuthorized,
+    Security(SecurityError),
+}
+
+impl fmt::Display for TrustCenterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TrustCenterError::InvalidInstallCode(ref error) => write!(f, "{}", error),
+            TrustCenterError::TableFull => write!(f, "link key table full"),
+            TrustCenterError::UnknownDevice => write!(f, "unknown device"),
+            TrustCenterError::NotAuthorized => write!(f, "not authorized"),
+            TrustCenterError::Security(ref error) => write!(f, "security: {}", error),
+        }
+    }
+}
+
+impl error::Error for TrustCenterError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            TrustCenterError::InvalidInstallCode(ref error) => Some(error),
+            TrustCenterError::Security(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<InstallCodeError> for TrustCenterError {
+    fn from(error: InstallCodeError) -> Self {
+        TrustCenterError::InvalidInstallCode(error)
+    }
+}
+
+impl From<SecurityError> for TrustCenterError {
+    fn from(error: SecurityError) -> Self {
+        TrustCenterError::Security(error)
+    }
+}
+
+/// The trust center. Generic over the random number generator that
+/// generates the keys.
+pub struct TrustCenter<R> {
+    rng: R,
+    extended_address: IeeeAddress,
+    config: TrustCenterConfig,
+    security: ApsSecurity,
+    network_key: NetworkKey,
+    /// The network key distributed but not yet active.
+    next_network_key: Option<NetworkKey>,
+    devices: Vec<DeviceRecord>,
+    aps_counter: u8,
+}
+
+impl<R: Rng> TrustCenter<R> {
+    pub fn new(rng: R, extended_address: IeeeAddress, config: TrustCenterConfig, link_keys: LinkKeyTable,
+               network_key: NetworkKey) -> Self {
+        TrustCenter {
+            rng,
+            extended_address,
+            config,
+            security: ApsSecurity::new(extended_address, link_keys),
+            network_key,
+            next_network_key: None,
+            devices: Vec::new(),
+            aps_counter: 0,
+        }
+    }
+
+    pub fn network_key(&self) -> NetworkKey {
+        self.network_key
+    }
+
+    pub fn devices(&self) -> &[DeviceRecord] {
+        &self.devices
+    }
+
+    pub fn device(&self, address: IeeeAddress) -> Option<&DeviceRecord> {
+        self.devices.iter().find(|d| d.address == address)
+    }
+
+    pub fn set_allow_joins(&mut self, allow_joins: bool) {
+        self.config.allow_joins = allow_joins;
+    }
+
+    pub fn set_permissions(&mut self, permissions: PermissionsConfiguration) {
+        self.config.permissions = permissions;
+    }
+
+    /// The link keys shared with the devices, used to secure the commands
+    /// they send to the trust center.
+    pub fn security_mut(&mut self) -> &mut ApsSecurity {
+        &mut self.security
+    }
+
+    /// Pre-configure the link key of a device that will join the network.
+    pub fn add_link_key(&mut self, address: IeeeAddress, key: Key) -> Result<(), TrustCenterError> {
+        self.security.link_keys_mut().set_key(address, key).map_err(|_| TrustCenterError::TableFull)
+    }
+
+    /// Pre-configure the link key of a device from its install code.
+    pub fn add_install_code(&mut self, address: IeeeAddress, install_code: &[u8]) -> Result<(), TrustCenterError> {
+        let key = install_code::link_key(install_code)?;
+        self.add_link_key(address, key)
+    }
+
+    fn random_key(&mut self) -> Key {
+        let mut key = [0; KEY_SIZE];
+        self.rng.fill(&mut key);
+        key
+    }
+
+    /// Encode a command sent to `destination`, secured with the link key
+    /// they share if `key_identifier` is not `None`.
+    fn frame(&mut self, command: ApsCommand, destination: IeeeAddress, key_identifier: Option<KeyIdentifier>)
+             -> Result<Vec<u8>, SecurityError> {
+        let frame = ApsCommandFrame {
+            broadcast: false,
+            ack_request: false,
+            aps_counter: self.aps_counter,
+            command,
+        };
+        self.aps_counter = self.aps_counter.wrapping_add(1);
+        frame.encode_secured(&mut self.security, destination, key_identifier, true)
+    }
+
+    fn transport_network_key(&mut self, destination: IeeeAddress, key: NetworkKey) -> Result<Vec<u8>, SecurityError> {
+        let command = ApsCommand::TransportKey(TransportKey {
+            key: key.key,
+            data: TransportKeyData::NetworkKey {
+                sequence_number: key.sequence_number,
+                destination_address: destination,
+                source_address: self.extended_address,
+            },
+        });
+        self.frame(command, destination, Some(KeyIdentifier::KeyTransport))
+    }
+
+    /// Send a command to a device, tunneled through its parent if the
+    /// trust center is not the parent.
+    fn send_to_device(&mut self, frame: Vec<u8>, device: &DeviceRecord) -> Result<TrustCenterAction, SecurityError> {
+        if device.parent_address == self.extended_address {
+            return Ok(TrustCenterAction::Send { destination: device.short_address, frame });
+        }
+        let tunnel = ApsCommand::Tunnel(Tunnel { destination_address: device.address, frame });
+        let frame = self.frame(tunnel, device.parent_address, None)?;
+        Ok(TrustCenterAction::Send { destination: device.parent_short_address, frame })
+    }
+
+    fn reject(&mut self, device: IeeeAddress, parent: IeeeAddress, parent_short_address: NwkAddress)
+              -> Vec<TrustCenterAction> {
+        let mut actions = Vec::new();
+        if parent != self.extended_address {
+            let command = ApsCommand::RemoveDevice(RemoveDevice { target_address: device });
+            // A parent that does not share a link key with the trust center
+            // cannot be asked to remove the device.
+            if let Ok(frame) = self.frame(command, parent, Some(KeyIdentifier::Data)) {
+                actions.push(TrustCenterAction::Send { destination: parent_short_address, frame });
+            }
+        }
+        actions.push(TrustCenterAction::DeviceRejected(device));
+        actions
+    }
+
+    /// Handle a device that joined, rejoined or left the network, either
+    /// directly on the trust center or through a router that sent an update
+    /// device command.
+    pub fn handle_join(&mut self, device: IeeeAddress, short_address: NwkAddress, parent: IeeeAddress,
+                       parent_short_address: NwkAddress, status: UpdateDeviceStatus)
+                       -> Result<Vec<TrustCenterAction>, TrustCenterError> {
+        match status {
+            UpdateDeviceStatus::DeviceLeft => {
+                self.devices.retain(|d| d.address != device);
+                Ok(Vec::new())
+            }
+            UpdateDeviceStatus::StandardSecuredRejoin => {
+                // The device already has the network key.
+                if let Some(record) = self.devices.iter_mut().find(|d| d.address == device) {
+                    record.short_address = short_address;
+                    record.parent_address = parent;
+                    record.parent_short_address = parent_short_address;
+                }
+                Ok(Vec::new())
+            }
+            UpdateDeviceStatus::StandardUnsecuredJoin | UpdateDeviceStatus::StandardTrustCenterRejoin => {
+                // A device unknown to the trust center is a new one, whether
+                // it joins or claims to rejoin.
+                let known = self.device(device).is_some();
+                if !known && !self.config.allow_joins {
+                    return Ok(self.reject(device, parent, parent_short_address));
+                }
+                if self.security.link_keys().get(device).is_none()
+                    && (!self.config.allow_default_link_key
+                        || self.add_link_key(device, DEFAULT_TRUST_CENTER_LINK_KEY).is_err()) {
+                    return Ok(self.reject(device, parent, parent_short_address));
+                }
+                if !known && self.devices.len() >= self.config.max_devices {
+                    return Ok(self.reject(device, parent, parent_short_address));
+                }
+
+                let record = match self.devices.iter_mut().find(|d| d.address == device) {
+                    Some(record) => {
+                        record.short_address = short_address;
+                        record.parent_address = parent;
+                        record.parent_short_address = parent_short_address;
+                        *record
+                    }
+                    None => {
+                        let record = DeviceRecord {
+                            address: device,
+                            short_address,
+                            parent_address: parent,
+                            parent_short_address,
+                            link_key_verified: false,
+                        };
+                        self.devices.push(record);
+                        record
+                    }
+                };
+                let network_key = self.network_key;
+                let frame = self.transport_network_key(device, network_key)?;
+                let send = self.send_to_device(frame, &record)?;
+                Ok(vec![send, TrustCenterAction::DeviceAuthorized(device)])
+            }
+        }
+    }
+
+    /// Handle an APS command sent to the trust center by the device whose
+    /// network address is `source_short_address`.
+    pub fn handle_command(&mut self, indication: &CommandIndication, source_short_address: NwkAddress)
+                          -> Result<Vec<TrustCenterAction>, TrustCenterError> {
+        let source = indication.source_address.ok_or(TrustCenterError::UnknownDevice)?;
+        let secured = indication.key_identifier == Some(KeyIdentifier::Data);
+        // The keys other than the network key are the link key shared
+        // with the source or derived from it.
+        let link_key_secured = match indication.key_identifier {
+            Some(KeyIdentifier::Network) | None => false,
+            Some(_) => true,
+        };
+        self.config.permissions
+            .authorize(source, PermissionCategory::SecurityCommands, link_key_secured)
+            .map_err(|_| TrustCenterError::NotAuthorized)?;
+        match indication.frame.command {
+            ApsCommand::UpdateDevice(ref update) if secured => {
+                self.handle_join(update.device_address, update.device_short_address, source,
+                                 source_short_address, update.status)
+            }
+            ApsCommand::RequestKey(RequestKey::TrustCenterLinkKey) if secured => {
+                let key = self.random_key();
+                let command = ApsCommand::TransportKey(TransportKey {
+                    key,
+                    data: TransportKeyData::TrustCenterLinkKey {
+                        destination_address: source,
+                        source_address: self.extended_address,
+                    },
+                });
+                let frame = self.frame(command, source, Some(KeyIdentifier::KeyLoad))?;
+                self.add_link_key(source, key)?;
+                if let Some(record) = self.devices.iter_mut().find(|d| d.address == source) {
+                    record.link_key_verified = false;
+                }
+                Ok(vec![TrustCenterAction::Send { destination: source_short_address, frame }])
+            }
+            ApsCommand::RequestKey(RequestKey::ApplicationLinkKey { partner_address }) if secured => {
+                let partner = *self.device(partner_address).ok_or(TrustCenterError::UnknownDevice)?;
+                let key = self.random_key();
+                let mut actions = Vec::with_capacity(2);
+                for &(destination, short_address, other, initiator) in
+                    &[(source, source_short_address, partner_address, true),
+                      (partner_address, partner.short_address, source, false)] {
+                    let command = ApsCommand::TransportKey(TransportKey {
+                        key,
+                        data: TransportKeyData::ApplicationLinkKey { partner_address: other, initiator },
+                    });
+                    let frame = self.frame(command, destination, Some(KeyIdentifier::KeyLoad))?;
+                    actions.push(TrustCenterAction::Send { destination: short_address, frame });
+                }
+                Ok(actions)
+            }
+            ApsCommand::VerifyKey(ref verify) if verify.key_type == KeyType::TrustCenterLinkKey => {
+                let verified = self.security.link_keys().get(verify.source_address)
+                    .is_some_and(|entry| verify.source_address == source && verify.verify(&entry.key));
+                let (status, key_identifier) = if verified {
+                    (ConfirmKeyStatus::Success, Some(KeyIdentifier::Data))
+                } else {
+                    (ConfirmKeyStatus::SecurityFailure, None)
+                };
+                let command = ApsCommand::ConfirmKey(ConfirmKey {
+                    status,
+                    key_type: verify.key_type,
+                    destination_address: source,
+                });
+                let frame = self.frame(command, source, key_identifier)?;
+                if verified {
+                    if let Some(record) = self.devices.iter_mut().find(|d| d.address == source) {
+                        record.link_key_verified = true;
+                    }
+                }
+                Ok(vec![TrustCenterAction::Send { destination: source_short_address, frame }])
+            }
+            _ => Ok(Vec::new()),
+        }
+    }
+
+    /// Generate a new network key and send it to every device. It becomes
+    /// active with `switch_network_key`.
+    pub fn rotate_network_key(&mut self) -> Result<Vec<TrustCenterAction>, TrustCenterError> {
+        let key = NetworkKey {
+            sequence_number: self.network_key.sequence_number.wrapping_add(1),
+            key: self.random_key(),
+        };
+        self.next_network_key = Some(key);
+        let mut actions = vec![TrustCenterAction::SetNetworkKey(key)];
+        for device in self.devices.clone() {
+            let frame = self.transport_network_key(device.address, key)?;
+            actions.push(self.send_to_device(frame, &device)?);
+        }
+        Ok(actions)
+    }
+
+    /// Make the network key distributed by `rotate_network_key` the active
+    /// one, and ask all the devices to switch to it.
+    pub fn switch_network_key(&mut self) -> Option<Vec<TrustCenterAction>> {
+        let key = self.next_network_key.take()?;
+        self.network_key = key;
+        let mut frame = Vec::new();
+        ApsCommandFrame {
+            broadcast: true,
+            ack_request: false,
+            aps_counter: self.aps_counter,
+            command: ApsCommand::SwitchKey(SwitchKey { sequence_number: key.sequence_number }),
+        }.encode(&mut frame);
+        self.aps_counter = self.aps_counter.wrapping_add(1);
+        Some(vec![TrustCenterAction::Broadcast { frame },
+                  TrustCenterAction::SwitchNetworkKey(key.sequence_number)])
+    }
+
+    /// Remove a device from the network and forget its link key.
+    pub fn remove_device(&mut self, address: IeeeAddress) -> Result<Vec<TrustCenterAction>, TrustCenterError> {
+        let device = *self.device(address).ok_or(TrustCenterError::UnknownDevice)?;
+        // The device is forgotten only once its parent can be asked to
+        // remove it.
+        let mut actions = Vec::new();
+        if device.parent_address != self.extended_address {
+            let command = ApsCommand::RemoveDevice(RemoveDevice { target_address: address });
+            let frame = self.frame(command, device.parent_address, Some(KeyIdentifier::Data))?;
+            actions.push(TrustCenterAction::Send { destination: device.parent_short_address, frame });
+        }
+        self.devices.retain(|d| d.address != address);
+        self.security.link_keys_mut().remove(address);
+        actions.push(TrustCenterAction::DeviceRemoved(address));
+        Ok(actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+    use apl::aps::PermissionsDescriptor;
+    use apl::aps_commands::VerifyKey;
+
+    const TRUST_CENTER: IeeeAddress = IeeeAddress(0x0011_2233_4455_6677);
+    const ROUTER: IeeeAddress = IeeeAddress(0x8899_AABB_CCDD_EEFF);
+    const DEVICE: IeeeAddress = IeeeAddress(0x0102_0304_0506_0708);
+    const NETWORK_KEY: NetworkKey = NetworkKey { sequence_number: 0, key: [0xAB; KEY_SIZE] };
+    const INSTALL_CODE: [u8; 18] = [0x83, 0xFE, 0xD3, 0x40, 0x7A, 0x93, 0x97, 0x23, 0xA5, 0xC6,
+                                    0x39, 0xB2, 0x69, 0x16, 0xD5, 0x05, 0xC3, 0xB5];
+
+    fn trust_center(config: TrustCenterConfig) -> TrustCenter<StepRng> {
+        TrustCenter::new(StepRng::new(1, 1), TRUST_CENTER, config, LinkKeyTable::new(4), NETWORK_KEY)
+    }
+
+    /// The APS security of a device sharing `key` with the trust center.
+    fn device_security(address: IeeeAddress, key: Key) -> ApsSecurity {
+        let mut link_keys = LinkKeyTable::new(1);
+        link_keys.set_key(TRUST_CENTER, key).unwrap();
+        ApsSecurity::new(address, link_keys)
+    }
+
+    fn sent_frame(action: &TrustCenterAction) -> &[u8] {
+        match *action {
+            TrustCenterAction::Send { ref frame, .. } => frame,
+            ref action => panic!("unexpected action {:?}", action),
+        }
+    }
+
+    #[test]
+    fn join_with_an_install_code() {
+        let mut tc = trust_center(TrustCenterConfig { allow_default_link_key: false, ..Default::default() });
+        tc.add_install_code(DEVICE, &INSTALL_CODE).unwrap();
+        let actions = tc.handle_join(DEVICE, NwkAddress(0x1234), TRUST_CENTER, NwkAddress(0x0000),
+                                     UpdateDeviceStatus::StandardUnsecuredJoin).unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[1], TrustCenterAction::DeviceAuthorized(DEVICE));
+        match actions[0] {
+            TrustCenterAction::Send { destination, .. } => assert_eq!(destination, NwkAddress(0x1234)),
+            ref action => panic!("unexpected action {:?}", action),
+        }
+
+        let key = install_code::link_key(&INSTALL_CODE).unwrap();
+        let mut security = device_security(DEVICE, key);
+        let indication = ApsCommandFrame::decode(sent_frame(&actions[0]), &mut security, None).unwrap();
+        assert_eq!(indication.source_address, Some(TRUST_CENTER));
+        assert_eq!(indication.key_identifier, Some(KeyIdentifier::KeyTransport));
+        match indication.frame.command {
+            ApsCommand::TransportKey(ref transport) => assert_eq!(transport.key, NETWORK_KEY.key),
+            ref command => panic!("unexpected command {:?}", command),
+        }
+        assert!(tc.device(DEVICE).is_some());
+    }
+
+    #[test]
+    fn invalid_install_code() {
+        let mut tc = trust_center(TrustCenterConfig::default());
+        let mut code = INSTALL_CODE;
+        code[17] ^= 0x01;
+        assert_eq!(tc.add_install_code(DEVICE, &code),
+                   Err(TrustCenterError::InvalidInstallCode(InstallCodeError::InvalidCrc)));
+        assert!(tc.security_mut().link_keys().get(DEVICE).is_none());
+    }
+
+    #[test]
+    fn device_without_link_key_is_rejected() {
+        let mut tc = trust_center(TrustCenterConfig { allow_default_link_key: false, ..Default::default() });
+        tc.add_link_key(ROUTER, DEFAULT_TRUST_CENTER_LINK_KEY).unwrap();
+        let actions = tc.handle_join(DEVICE, NwkAddress(0x1234), ROUTER, NwkAddress(0x0001),
+                                     UpdateDeviceStatus::StandardUnsecuredJoin).unwrap();
+        assert_eq!(actions.len(), 2);
+        assert_eq!(actions[1], TrustCenterAction::DeviceRejected(DEVICE));
+        let mut security = device_security(ROUTER, DEFAULT_TRUST_CENTER_LINK_KEY);
+        let indication = ApsCommandFrame::decode(sent_frame(&actions[0]), &mut security, None).unwrap();
+        assert_eq!(indication.frame.command, ApsCommand::RemoveDevice(RemoveDevice { target_address: DEVICE }));
+        assert!(tc.device(DEVICE).is_none());
+    }
+
+    #[test]
+    fn join_through_a_router_is_tunneled() {
+        let mut tc = trust_center(TrustCenterConfig::default());
+        tc.add_link_key(ROUTER, [0x11; KEY_SIZE]).unwrap();
+        let actions = tc.handle_join(DEVICE, NwkAddress(0x1234), ROUTER, NwkAddress(0x0001),
+                                     UpdateDeviceStatus::StandardUnsecuredJoin).unwrap();
+        match actions[0] {
+            TrustCenterAction::Send { destination, .. } => assert_eq!(destination, NwkAddress(0x0001)),
+            ref action => panic!("unexpected action {:?}", action),
+        }
+        let mut router = device_security(ROUTER, [0x11; KEY_SIZE]);
+        let indication = ApsCommandFrame::decode(sent_frame(&actions[0]), &mut router, None).unwrap();
+        let tunneled = match indication.frame.command {
+            ApsCommand::Tunnel(ref tunnel) => {
+                assert_eq!(tunnel.destination_address, DEVICE);
+                tunnel.frame.clone()
+            }
+            ref command => panic!("unexpected command {:?}", command),
+        };
+        let mut device = device_security(DEVICE, DEFAULT_TRUST_CENTER_LINK_KEY);
+        let indication = ApsCommandFrame::decode(&tunneled, &mut device, None).unwrap();
+        match indication.frame.command {
+            ApsCommand::TransportKey(ref transport) => assert_eq!(transport.key, NETWORK_KEY.key),
+            ref command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    #[test]
+    fn commands_secured_with_the_network_key_need_permission() {
+        let descriptor = PermissionsDescriptor {
+            device_address: DEVICE,
+            modify_permissions_configuration: false,
+            network_settings: false,
+            application_settings: false,
+            security_settings: false,
+            application_commands: false,
+            security_commands: true,
+            link_key_required: true,
+        };
+        let mut tc = trust_center(TrustCenterConfig {
+            permissions: PermissionsConfiguration { descriptors: vec![descriptor] },
+            ..Default::default()
+        });
+        tc.add_link_key(DEVICE, DEFAULT_TRUST_CENTER_LINK_KEY).unwrap();
+        let mut indication = CommandIndication {
+            source_address: Some(DEVICE),
+            key_identifier: Some(KeyIdentifier::Network),
+            frame: ApsCommandFrame {
+                broadcast: false,
+                ack_request: false,
+                aps_counter: 0,
+                command: ApsCommand::RequestKey(RequestKey::TrustCenterLinkKey),
+            },
+        };
+        assert_eq!(tc.handle_command(&indication, NwkAddress(0x1234)), Err(TrustCenterError::NotAuthorized));
+        indication.key_identifier = Some(KeyIdentifier::Data);
+        let actions = tc.handle_command(&indication, NwkAddress(0x1234)).unwrap();
+        assert_eq!(actions.len(), 1);
+        indication.source_address = Some(ROUTER);
+        assert_eq!(tc.handle_command(&indication, NwkAddress(0x0001)), Err(TrustCenterError::NotAuthorized));
+    }
+
+    #[test]
+    fn unknown_devices_cannot_rejoin_while_joins_are_disabled() {
+        let mut tc = trust_center(TrustCenterConfig { allow_joins: false, ..Default::default() });
+        for &status in &[UpdateDeviceStatus::StandardUnsecuredJoin, UpdateDeviceStatus::StandardTrustCenterRejoin] {
+            assert_eq!(tc.handle_join(DEVICE, NwkAddress(0x1234), TRUST_CENTER, NwkAddress(0x0000), status),
+                       Ok(vec![TrustCenterAction::DeviceRejected(DEVICE)]));
+        }
+        assert!(tc.device(DEVICE).is_none());
+        assert!(tc.security_mut().link_keys().get(DEVICE).is_none());
+
+        // A device that joined before can still rejoin.
+        tc.set_allow_joins(true);
+        join(&mut tc, DEVICE, NwkAddress(0x1234));
+        tc.set_allow_joins(false);
+        let actions = tc.handle_join(DEVICE, NwkAddress(0x5678), TRUST_CENTER, NwkAddress(0x0000),
+                                     UpdateDeviceStatus::StandardTrustCenterRejoin).unwrap();
+        assert_eq!(actions[1], TrustCenterAction::DeviceAuthorized(DEVICE));
+        assert_eq!(tc.device(DEVICE).map(|d| d.short_address), Some(NwkAddress(0x5678)));
+    }
+
+    /// Make `device` join the trust center directly, with the default link
+    /// key.
+    fn join(tc: &mut TrustCenter<StepRng>, device: IeeeAddress, short_address: NwkAddress) {
+        let actions = tc.handle_join(device, short_address, TRUST_CENTER, NwkAddress(0x0000),
+                                     UpdateDeviceStatus::StandardUnsecuredJoin).unwrap();
+        assert_eq!(actions[1], TrustCenterAction::DeviceAuthorized(device));
+    }
+
+    fn command_indication(source: IeeeAddress, command: ApsCommand) -> CommandIndication {
+        CommandIndication {
+            source_address: Some(source),
+            key_identifier: None,
+            frame: ApsCommandFrame { broadcast: false, ack_request: false, aps_counter: 0, command },
+        }
+    }
+
+    #[test]
+    fn verify_and_confirm_the_link_key() {
+        let mut tc = trust_center(TrustCenterConfig::default());
+        join(&mut tc, DEVICE, NwkAddress(0x1234));
+        let mut device = device_security(DEVICE, DEFAULT_TRUST_CENTER_LINK_KEY);
+
+        // A hash of another key, or of the key of another device, fails.
+        for &(address, key) in &[(DEVICE, [0x55; KEY_SIZE]), (ROUTER, DEFAULT_TRUST_CENTER_LINK_KEY)] {
+            let verify = VerifyKey::new(KeyType::TrustCenterLinkKey, address, &key);
+            let actions = tc.handle_command(&command_indication(DEVICE, ApsCommand::VerifyKey(verify)),
+                                            NwkAddress(0x1234)).unwrap();
+            let indication = ApsCommandFrame::decode(sent_frame(&actions[0]), &mut device, Some(TRUST_CENTER))
+                .unwrap();
+            assert_eq!(indication.key_identifier, None);
+            assert_eq!(indication.frame.command, ApsCommand::ConfirmKey(ConfirmKey {
+                status: ConfirmKeyStatus::SecurityFailure,
+                key_type: KeyType::TrustCenterLinkKey,
+                destination_address: DEVICE,
+            }));
+            assert!(!tc.device(DEVICE).unwrap().link_key_verified);
+        }
+
+        let verify = VerifyKey::new(KeyType::TrustCenterLinkKey, DEVICE, &DEFAULT_TRUST_CENTER_LINK_KEY);
+        let actions = tc.handle_command(&command_indication(DEVICE, ApsCommand::VerifyKey(verify)),
+                                        NwkAddress(0x1234)).unwrap();
+        let indication = ApsCommandFrame::decode(sent_frame(&actions[0]), &mut device, None).unwrap();
+        assert_eq!(indication.key_identifier, Some(KeyIdentifier::Data));
+        assert_eq!(indication.frame.command, ApsCommand::ConfirmKey(ConfirmKey {
+            status: ConfirmKeyStatus::Success,
+            key_type: KeyType::TrustCenterLinkKey,
+            destination_address: DEVICE,
+        }));
+        assert!(tc.device(DEVICE).unwrap().link_key_verified);
+    }
+
+    #[test]
+    fn rotate_and_switch_the_network_key() {
+        let mut tc = trust_center(TrustCenterConfig::default());
+        assert_eq!(tc.switch_network_key(), None);
+        join(&mut tc, DEVICE, NwkAddress(0x1234));
+        let actions = tc.rotate_network_key().unwrap();
+        assert_eq!(actions.len(), 2);
+        let key = match actions[0] {
+            TrustCenterAction::SetNetworkKey(key) => key,
+            ref action => panic!("unexpected action {:?}", action),
+        };
+        assert_eq!(key.sequence_number, 1);
+        assert_ne!(key.key, NETWORK_KEY.key);
+        // The active key does not change until the switch.
+        assert_eq!(tc.network_key(), NETWORK_KEY);
+
+        let mut device = device_security(DEVICE, DEFAULT_TRUST_CENTER_LINK_KEY);
+        let indication = ApsCommandFrame::decode(sent_frame(&actions[1]), &mut device, None).unwrap();
+        match indication.frame.command {
+            ApsCommand::TransportKey(TransportKey {
+                key: transported,
+                data: TransportKeyData::NetworkKey { sequence_number: 1, destination_address: DEVICE, .. },
+            }) => assert_eq!(transported, key.key),
+            ref command => panic!("unexpected command {:?}", command),
+        }
+
+        let actions = tc.switch_network_key().unwrap();
+        assert_eq!(actions[1], TrustCenterAction::SwitchNetworkKey(1));
+        let frame = match actions[0] {
+            TrustCenterAction::Broadcast { ref frame } => frame.clone(),
+            ref action => panic!("unexpected action {:?}", action),
+        };
+        let indication = ApsCommandFrame::decode(&frame, &mut device, Some(TRUST_CENTER)).unwrap();
+        assert!(indication.frame.broadcast);
+        assert_eq!(indication.frame.command, ApsCommand::SwitchKey(SwitchKey { sequence_number: 1 }));
+        assert_eq!(tc.network_key(), key);
+        assert_eq!(tc.switch_network_key(), None);
+    }
+
+    #[test]
+    fn remove_a_device() {
+        let mut tc = trust_center(TrustCenterConfig::default());
+        assert_eq!(tc.remove_device(DEVICE), Err(TrustCenterError::UnknownDevice));
+        join(&mut tc, DEVICE, NwkAddress(0x1234));
+        assert_eq!(tc.remove_device(DEVICE), Ok(vec![TrustCenterAction::DeviceRemoved(DEVICE)]));
+        assert!(tc.device(DEVICE).is_none());
+        assert!(tc.security_mut().link_keys().get(DEVICE).is_none());
+
+        // A device that joined through a router is removed by it.
+        tc.add_link_key(ROUTER, [0x11; KEY_SIZE]).unwrap();
+        tc.handle_join(DEVICE, NwkAddress(0x1234), ROUTER, NwkAddress(0x0001),
+                       UpdateDeviceStatus::StandardUnsecuredJoin).unwrap();
+        let actions = tc.remove_device(DEVICE).unwrap();
+        assert_eq!(actions[1], TrustCenterAction::DeviceRemoved(DEVICE));
+        let mut router = device_security(ROUTER, [0x11; KEY_SIZE]);
+        let indication = ApsCommandFrame::decode(sent_frame(&actions[0]), &mut router, None).unwrap();
+        assert_eq!(indication.frame.command, ApsCommand::RemoveDevice(RemoveDevice { target_address: DEVICE }));
+        assert!(tc.device(DEVICE).is_none());
+    }
+
+    #[test]
+    fn failed_removal_keeps_the_device() {
+        let mut tc = trust_center(TrustCenterConfig::default());
+        tc.add_link_key(ROUTER, [0x11; KEY_SIZE]).unwrap();
+        tc.handle_join(DEVICE, NwkAddress(0x1234), ROUTER, NwkAddress(0x0001),
+                       UpdateDeviceStatus::StandardUnsecuredJoin).unwrap();
+        // The parent can no longer be asked to remove the device.
+        tc.security_mut().link_keys_mut().remove(ROUTER);
+        assert_eq!(tc.remove_device(DEVICE), Err(TrustCenterError::Security(SecurityError::UnknownKey)));
+        assert!(tc.device(DEVICE).is_some());
+        assert!(tc.security_mut().link_keys().get(DEVICE).is_some());
+    }
+}
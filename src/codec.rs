@@ -0,0 +1,101 @@
+//! Helpers shared by the encoders and decoders of the frames of the
+//! different layers of the stack.
+//!
+//! All the multi-octet fields defined by the Zigbee specification are
+//! transmitted in little endian order.
+
+use std::error;
+use std::fmt;
+
+/// The reasons why a frame could not be decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The input ended before the end of the frame.
+    UnexpectedEnd,
+    /// A field holds a reserved value or a value that is not valid in
+    /// its context. The name of the field is carried along.
+    InvalidValue(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::UnexpectedEnd => write!(f, "unexpected end of frame"),
+            DecodeError::InvalidValue(field) => write!(f, "invalid value of the {} field", field),
+        }
+    }
+}
+
+impl error::Error for DecodeError {}
+
+/// A cursor over a received frame, used to read its fields in order.
+pub struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Reader { data }
+    }
+
+    /// The octets that have not been read yet.
+    pub fn remaining(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.data.len() < len {
+            return Err(DecodeError::UnexpectedEnd);
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        let b = self.read_bytes(2)?;
+        Ok(u16::from(b[0]) | u16::from(b[1]) << 8)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        let low = u32::from(self.read_u16()?);
+        let high = u32::from(self.read_u16()?);
+        Ok(low | high << 16)
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, DecodeError> {
+        let low = u64::from(self.read_u32()?);
+        let high = u64::from(self.read_u32()?);
+        Ok(low | high << 32)
+    }
+
+    /// Read everything that is left in the frame.
+    pub fn read_to_end(&mut self) -> &'a [u8] {
+        let rest = self.data;
+        self.data = &[];
+        rest
+    }
+}
+
+pub fn write_u16(out: &mut Vec<u8>, value: u16) {
+    out.push(value as u8);
+    out.push((value >> 8) as u8);
+}
+
+pub fn write_u32(out: &mut Vec<u8>, value: u32) {
+    write_u16(out, value as u16);
+    write_u16(out, (value >> 16) as u16);
+}
+
+pub fn write_u64(out: &mut Vec<u8>, value: u64) {
+    write_u32(out, value as u32);
+    write_u32(out, (value >> 32) as u32);
+}
@@ -0,0 +1,228 @@
+//! The errors of the stack.
+//!
+//! Every layer reports its failures with the status codes defined by its
+//! specification: `MacStatus`, `NwkStatus`, `ApsStatus`, the ZDP `Status`
+//! and `ZclStatus`. They convert from and to their numeric value. The
+//! errors of a layer carry the error of the layer below that caused them,
+//! so that `Error::source` walks down the stack to the root cause.
+
+use std::error;
+use std::fmt;
+use apl::aps::ApsError;
+use apl::device_profile::Status;
+use codec::DecodeError;
+use mac::MacStatus;
+use nwk::NwkError;
+use security::SecurityError;
+use zcl::ZclStatus;
+
+/// Define an enumeration of status codes whose discriminants are the
+/// values defined by the specification, and implement the conversions
+/// from and to `u8`, `Display` and `Error` for it from the same list of
+/// variants.
+macro_rules! status_codes {
+    ($(#[$attr:meta])* pub enum $status:ident {
+        $($(#[$variant_attr:meta])* $variant:ident = $value:literal),* $(,)*
+    }) => {
+        $(#[$attr])*
+        pub enum $status {
+            $($(#[$variant_attr])* $variant = $value,)*
+        }
+
+        impl ::std::convert::TryFrom<u8> for $status {
+            type Error = ::error::UnknownStatus;
+
+            fn try_from(value: u8) -> Result<Self, ::error::UnknownStatus> {
+                match value {
+                    $($value => Ok($status::$variant),)*
+                    _ => Err(::error::UnknownStatus(value)),
+                }
+            }
+        }
+
+        impl From<$status> for u8 {
+            fn from(status: $status) -> u8 {
+                status as u8
+            }
+        }
+
+        impl ::std::fmt::Display for $status {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                write!(f, "{:?} (0x{:02X})", self, *self as u8)
+            }
+        }
+
+        impl ::std::error::Error for $status {}
+    };
+}
+
+/// A numeric value that is not a status code of the expected layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnknownStatus(pub u8);
+
+impl fmt::Display for UnknownStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown status code 0x{:02X}", self.0)
+    }
+}
+
+impl error::Error for UnknownStatus {}
+
+/// An error of any layer of the stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Error {
+    Mac(MacStatus),
+    Nwk(NwkError),
+    Aps(ApsError),
+    Zdp(Status),
+    Zcl(ZclStatus),
+    Security(SecurityError),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Mac(ref status) => write!(f, "MAC: {}", status),
+            Error::Nwk(ref error) => write!(f, "NWK: {}", error),
+            Error::Aps(ref error) => write!(f, "APS: {}", error),
+            Error::Zdp(ref status) => write!(f, "ZDP: {}", status),
+            Error::Zcl(ref status) => write!(f, "ZCL: {}", status),
+            Error::Security(ref error) => write!(f, "security: {}", error),
+            Error::Decode(ref error) => write!(f, "decode: {}", error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Mac(ref status) => Some(status),
+            Error::Nwk(ref error) => Some(error),
+            Error::Aps(ref error) => Some(error),
+            Error::Zdp(ref status) => Some(status),
+            Error::Zcl(ref status) => Some(status),
+            Error::Security(ref error) => Some(error),
+            Error::Decode(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<MacStatus> for Error {
+    fn from(status: MacStatus) -> Self {
+        Error::Mac(status)
+    }
+}
+
+impl From<NwkError> for Error {
+    fn from(error: NwkError) -> Self {
+        Error::Nwk(error)
+    }
+}
+
+impl From<ApsError> for Error {
+    fn from(error: ApsError) -> Self {
+        Error::Aps(error)
+    }
+}
+
+impl From<Status> for Error {
+    fn from(status: Status) -> Self {
+        Error::Zdp(status)
+    }
+}
+
+impl From<ZclStatus> for Error {
+    fn from(status: ZclStatus) -> Self {
+        Error::Zcl(status)
+    }
+}
+
+impl From<SecurityError> for Error {
+    fn from(error: SecurityError) -> Self {
+        Error::Security(error)
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(error: DecodeError) -> Self {
+        Error::Decode(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::convert::TryFrom;
+    use std::error::Error as StdError;
+    use apl::aps::ApsStatus;
+    use nwk::NwkStatus;
+
+    /// Check that every code known to `T` converts back to itself, and
+    /// return how many there are.
+    fn round_trip<T: TryFrom<u8, Error = UnknownStatus> + Copy + Into<u8>>() -> usize {
+        let mut known = 0;
+        for code in 0..=u8::MAX {
+            match T::try_from(code) {
+                Ok(status) => {
+                    assert_eq!(status.into(), code);
+                    known += 1;
+                }
+                Err(error) => assert_eq!(error, UnknownStatus(code)),
+            }
+        }
+        known
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        assert_eq!(round_trip::<MacStatus>(), 36);
+        assert_eq!(round_trip::<NwkStatus>(), 18);
+        assert_eq!(round_trip::<ApsStatus>(), 18);
+        assert_eq!(round_trip::<Status>(), 15);
+        assert_eq!(round_trip::<ZclStatus>(), 36);
+    }
+
+    #[test]
+    fn unknown_codes() {
+        assert_eq!(MacStatus::try_from(0x01), Err(UnknownStatus(0x01)));
+        assert_eq!(NwkStatus::try_from(0xCB), Err(UnknownStatus(0xCB)));
+        assert_eq!(ApsStatus::try_from(0xB1), Err(UnknownStatus(0xB1)));
+        assert_eq!(Status::try_from(0x87), Err(UnknownStatus(0x87)));
+        assert_eq!(ZclStatus::try_from(0xFF), Err(UnknownStatus(0xFF)));
+        assert_eq!(UnknownStatus(0xCB).to_string(), "unknown status code 0xCB");
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(MacStatus::NoAck.to_string(), "NoAck (0xE9)");
+        assert_eq!(NwkStatus::StartupFailure.to_string(), "StartupFailure (0xC4)");
+        assert_eq!(ApsStatus::Success.to_string(), "Success (0x00)");
+        assert_eq!(Status::NotAuthorized.to_string(), "NotAuthorized (0x8D)");
+        assert_eq!(ZclStatus::InvalidValue.to_string(), "InvalidValue (0x87)");
+        assert_eq!(Error::from(ZclStatus::InvalidValue).to_string(), "ZCL: InvalidValue (0x87)");
+    }
+
+    #[test]
+    fn source_chain() {
+        let error = Error::from(ApsError::Nwk(NwkError::Mac(MacStatus::NoAck)));
+        assert_eq!(error.to_string(), "APS: NWK: MAC: NoAck (0xE9)");
+        let mut chain = Vec::new();
+        let mut source = error.source();
+        while let Some(error) = source {
+            chain.push(error.to_string());
+            source = error.source();
+        }
+        assert_eq!(chain, ["NWK: MAC: NoAck (0xE9)", "MAC: NoAck (0xE9)", "NoAck (0xE9)"]);
+
+        let error = Error::from(ApsError::Security(SecurityError::Decode(DecodeError::UnexpectedEnd)));
+        let root = error.source().and_then(|e| e.source()).and_then(|e| e.source()).unwrap();
+        assert_eq!(root.to_string(), DecodeError::UnexpectedEnd.to_string());
+        assert!(root.source().is_none());
+
+        // A status of the layer itself is the root cause.
+        let error = Error::from(NwkError::Status(NwkStatus::NoNetworks));
+        assert!(error.source().unwrap().source().is_none());
+        assert_eq!(Error::from(ZclStatus::Failure).source().unwrap().to_string(), "Failure (0x01)");
+    }
+}
@@ -11,13 +11,23 @@
 extern crate futures;
 #[macro_use] extern crate serde_derive;
 #[macro_use] extern crate bitfield;
+extern crate rand;
+extern crate aes;
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Unknownable<T> {
     Known(T),
     Unknown
 }
 
 use std::time::Duration;
+#[macro_use]
+pub mod error;
 /// This module focus on the application layer.
 pub mod apl;
 pub mod nwk;
+pub mod codec;
+pub mod mac;
+pub mod security;
+pub mod types;
+pub mod zcl;
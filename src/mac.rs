@@ -0,0 +1,332 @@
+//! The primitives of the IEEE 802.15.4 MAC sub-layer that the network
+//! layer relies on.
+//!
+//! The MAC is driven through requests; the matching confirms and
+//! indications are delivered to the network layer by the crate that
+//! implements the MAC, calling the `handle_*` methods of the NWK
+//! components.
+
+use std::borrow::Cow;
+use apl::framework::MacCapability;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MacAddress {
+    Short(u16),
+    Extended(u64),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressMode {
+    Short,
+    Extended,
+}
+
+status_codes! {
+    /// The status values of the MAC confirm primitives.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum MacStatus {
+        Success = 0x00,
+        CounterError = 0xDB,
+        ImproperKeyType = 0xDC,
+        ImproperSecurityLevel = 0xDD,
+        UnsupportedLegacy = 0xDE,
+        UnsupportedSecurity = 0xDF,
+        BeaconLoss = 0xE0,
+        ChannelAccessFailure = 0xE1,
+        Denied = 0xE2,
+        DisableTrxFailure = 0xE3,
+        SecurityError = 0xE4,
+        FrameTooLong = 0xE5,
+        InvalidGts = 0xE6,
+        InvalidHandle = 0xE7,
+        InvalidParameter = 0xE8,
+        NoAck = 0xE9,
+        NoBeacon = 0xEA,
+        NoData = 0xEB,
+        NoShortAddress = 0xEC,
+        OutOfCap = 0xED,
+        PanIdConflict = 0xEE,
+        Realignment = 0xEF,
+        TransactionExpired = 0xF0,
+        TransactionOverflow = 0xF1,
+        TxActive = 0xF2,
+        UnavailableKey = 0xF3,
+        UnsupportedAttribute = 0xF4,
+        InvalidAddress = 0xF5,
+        OnTimeTooLong = 0xF6,
+        PastTime = 0xF7,
+        TrackingOff = 0xF8,
+        InvalidIndex = 0xF9,
+        LimitReached = 0xFA,
+        ReadOnly = 0xFB,
+        ScanInProgress = 0xFC,
+        SuperframeOverlap = 0xFD,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanType {
+    EnergyDetect,
+    Active,
+    Passive,
+    Orphan,
+}
+
+/// The association status returned by a coordinator to a device that
+/// requested to associate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssociationStatus {
+    Success = 0x00,
+    PanAtCapacity = 0x01,
+    PanAccessDenied = 0x02,
+}
+
+/// The description of a PAN, built from a received beacon.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PanDescriptor {
+    pub coord_address: MacAddress,
+    pub coord_pan_id: u16,
+    pub logical_channel: u8,
+    pub pan_coordinator: bool,
+    pub association_permit: bool,
+    pub link_quality: u8,
+    /// The beacon payload, that for Zigbee networks holds the NWK beacon
+    /// payload.
+    pub beacon_payload: Vec<u8>,
+}
+
+/// The result of an MLME-SCAN.request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanConfirm {
+    pub status: MacStatus,
+    pub scan_type: ScanType,
+    pub unscanned_channels: u32,
+    /// The energy measured on each channel, for energy detection scans.
+    pub energy_detect_list: Vec<(u8, u8)>,
+    /// The PANs found, for active and passive scans.
+    pub pan_descriptors: Vec<PanDescriptor>,
+}
+
+/// The result of an MLME-ASSOCIATE.request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssociateConfirm {
+    Success { short_address: u16 },
+    Refused(AssociationStatus),
+    Failed(MacStatus),
+}
+
+/// MLME-ASSOCIATE.indication: a device requested to associate to this
+/// one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AssociateIndication {
+    pub device_address: u64,
+    pub capability_information: MacCapability,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct McpsDataRequest<'a> {
+    pub src_addr_mode: AddressMode,
+    pub dst_pan_id: u16,
+    pub dst_address: MacAddress,
+    pub msdu: Cow<'a, [u8]>,
+    pub msdu_handle: u8,
+    pub ack_tx: bool,
+    pub indirect_tx: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct McpsDataConfirm {
+    pub msdu_handle: u8,
+    pub status: MacStatus,
+}
+
+pub struct McpsDataIndication<'a> {
+    pub src_address: MacAddress,
+    pub src_pan_id: u16,
+    pub dst_address: MacAddress,
+    pub msdu: Cow<'a, [u8]>,
+    pub link_quality: u8,
+}
+
+/// The MAC sub-layer management entity.
+pub trait MlmeSap {
+    fn extended_address(&self) -> u64;
+    fn scan_request(&self, scan_type: ScanType, scan_channels: u32, scan_duration: u8);
+    fn start_request(&self, pan_id: u16, logical_channel: u8, pan_coordinator: bool);
+    fn associate_request(&self, logical_channel: u8, coord_pan_id: u16,
+                         coord_address: MacAddress, capability_information: MacCapability);
+    fn associate_response(&self, device_address: u64, short_address: u16, status: AssociationStatus);
+    fn poll_request(&self, coord_address: MacAddress, coord_pan_id: u16);
+    fn set_short_address(&self, short_address: u16);
+    fn set_pan_id(&self, pan_id: u16);
+    fn set_logical_channel(&self, logical_channel: u8);
+    fn set_rx_on_when_idle(&self, rx_on_when_idle: bool);
+    fn set_association_permit(&self, association_permit: bool);
+}
+
+/// The MAC common part sub-layer data service.
+pub trait McpsSap {
+    fn data_request(&self, request: McpsDataRequest);
+}
+
+impl<T: MlmeSap> MlmeSap for &T {
+    fn extended_address(&self) -> u64 {
+        (**self).extended_address()
+    }
+    fn scan_request(&self, scan_type: ScanType, scan_channels: u32, scan_duration: u8) {
+        (**self).scan_request(scan_type, scan_channels, scan_duration)
+    }
+    fn start_request(&self, pan_id: u16, logical_channel: u8, pan_coordinator: bool) {
+        (**self).start_request(pan_id, logical_channel, pan_coordinator)
+    }
+    fn associate_request(&self, logical_channel: u8, coord_pan_id: u16,
+                         coord_address: MacAddress, capability_information: MacCapability) {
+        (**self).associate_request(logical_channel, coord_pan_id, coord_address, capability_information)
+    }
+    fn associate_response(&self, device_address: u64, short_address: u16, status: AssociationStatus) {
+        (**self).associate_response(device_address, short_address, status)
+    }
+    fn poll_request(&self, coord_address: MacAddress, coord_pan_id: u16) {
+        (**self).poll_request(coord_address, coord_pan_id)
+    }
+    fn set_short_address(&self, short_address: u16) {
+        (**self).set_short_address(short_address)
+    }
+    fn set_pan_id(&self, pan_id: u16) {
+        (**self).set_pan_id(pan_id)
+    }
+    fn set_logical_channel(&self, logical_channel: u8) {
+        (**self).set_logical_channel(logical_channel)
+    }
+    fn set_rx_on_when_idle(&self, rx_on_when_idle: bool) {
+        (**self).set_rx_on_when_idle(rx_on_when_idle)
+    }
+    fn set_association_permit(&self, association_permit: bool) {
+        (**self).set_association_permit(association_permit)
+    }
+}
+
+impl<T: McpsSap> McpsSap for &T {
+    fn data_request(&self, request: McpsDataRequest) {
+        (**self).data_request(request)
+    }
+}
+
+impl MacCapability {
+    /// Encode the capability information field, as carried by association
+    /// and rejoin requests.
+    pub fn to_u8(&self) -> u8 {
+        (self.alternate_pan_coordinator as u8)
+            | (self.device_type as u8) << 1
+            | (self.power_source as u8) << 2
+            | (self.receiver_on_when_idle as u8) << 3
+            | (self.security as u8) << 6
+            | (self.allocate_address as u8) << 7
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        MacCapability {
+            alternate_pan_coordinator: value & 0x01 != 0,
+            device_type: value & 0x02 != 0,
+            power_source: value & 0x04 != 0,
+            receiver_on_when_idle: value & 0x08 != 0,
+            security: value & 0x40 != 0,
+            allocate_address: value & 0x80 != 0,
+        }
+    }
+}
+
+/// A MAC that records the requests it receives, to test the components
+/// that drive it.
+#[cfg(test)]
+pub mod mock {
+    use std::cell::RefCell;
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub enum Request {
+        Scan { scan_type: ScanType, scan_channels: u32, scan_duration: u8 },
+        Start { pan_id: u16, logical_channel: u8, pan_coordinator: bool },
+        Associate { logical_channel: u8, coord_pan_id: u16, coord_address: MacAddress,
+                    capability_information: MacCapability },
+        AssociateResponse { device_address: u64, short_address: u16, status: AssociationStatus },
+        Poll { coord_address: MacAddress, coord_pan_id: u16 },
+        SetShortAddress(u16),
+        SetPanId(u16),
+        SetLogicalChannel(u8),
+        SetRxOnWhenIdle(bool),
+        SetAssociationPermit(bool),
+        Data(McpsDataRequest<'static>),
+    }
+
+    pub struct MockMac {
+        extended_address: u64,
+        requests: RefCell<Vec<Request>>,
+    }
+
+    impl MockMac {
+        pub fn new(extended_address: u64) -> Self {
+            MockMac { extended_address, requests: RefCell::new(Vec::new()) }
+        }
+
+        /// The requests received since the last call.
+        pub fn take_requests(&self) -> Vec<Request> {
+            self.requests.borrow_mut().drain(..).collect()
+        }
+
+        fn push(&self, request: Request) {
+            self.requests.borrow_mut().push(request);
+        }
+    }
+
+    impl MlmeSap for MockMac {
+        fn extended_address(&self) -> u64 {
+            self.extended_address
+        }
+        fn scan_request(&self, scan_type: ScanType, scan_channels: u32, scan_duration: u8) {
+            self.push(Request::Scan { scan_type, scan_channels, scan_duration });
+        }
+        fn start_request(&self, pan_id: u16, logical_channel: u8, pan_coordinator: bool) {
+            self.push(Request::Start { pan_id, logical_channel, pan_coordinator });
+        }
+        fn associate_request(&self, logical_channel: u8, coord_pan_id: u16,
+                             coord_address: MacAddress, capability_information: MacCapability) {
+            self.push(Request::Associate { logical_channel, coord_pan_id, coord_address, capability_information });
+        }
+        fn associate_response(&self, device_address: u64, short_address: u16, status: AssociationStatus) {
+            self.push(Request::AssociateResponse { device_address, short_address, status });
+        }
+        fn poll_request(&self, coord_address: MacAddress, coord_pan_id: u16) {
+            self.push(Request::Poll { coord_address, coord_pan_id });
+        }
+        fn set_short_address(&self, short_address: u16) {
+            self.push(Request::SetShortAddress(short_address));
+        }
+        fn set_pan_id(&self, pan_id: u16) {
+            self.push(Request::SetPanId(pan_id));
+        }
+        fn set_logical_channel(&self, logical_channel: u8) {
+            self.push(Request::SetLogicalChannel(logical_channel));
+        }
+        fn set_rx_on_when_idle(&self, rx_on_when_idle: bool) {
+            self.push(Request::SetRxOnWhenIdle(rx_on_when_idle));
+        }
+        fn set_association_permit(&self, association_permit: bool) {
+            self.push(Request::SetAssociationPermit(association_permit));
+        }
+    }
+
+    impl McpsSap for MockMac {
+        fn data_request(&self, request: McpsDataRequest) {
+            self.push(Request::Data(McpsDataRequest {
+                src_addr_mode: request.src_addr_mode,
+                dst_pan_id: request.dst_pan_id,
+                dst_address: request.dst_address,
+                msdu: Cow::Owned(request.msdu.into_owned()),
+                msdu_handle: request.msdu_handle,
+                ack_tx: request.ack_tx,
+                indirect_tx: request.indirect_tx,
+            }));
+        }
+    }
+}
@@ -0,0 +1,533 @@
+//! Network address assignment and address conflict resolution, as
+//! described in section 3.6.1.6 and 3.6.1.9 of the specification.
+//!
+//! Addresses can be assigned with the distributed (tree) mechanism, where
+//! each router owns a block of addresses computed from the Cskip function,
+//! or with the stochastic mechanism, where addresses are chosen at random
+//! and conflicts are detected and resolved afterwards.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use rand::Rng;
+use apl::device_profile::{IeeeAddrRsp, NwkAddrRsp, Status};
+use nwk::commands::{NetworkStatus, NetworkStatusCode};
+use types::{IeeeAddress, NwkAddress};
+
+/// The highest address that can be assigned to a device. The ones above
+/// are reserved for broadcasts.
+pub const MAX_NETWORK_ADDRESS: u16 = 0xFFF7;
+
+/// The parameters of the distributed address assignment mechanism.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TreeParameters {
+    /// nwkMaxChildren (Cm)
+    pub max_children: u8,
+    /// nwkMaxRouters (Rm)
+    pub max_routers: u8,
+    /// nwkMaxDepth (Lm)
+    pub max_depth: u8,
+}
+
+impl TreeParameters {
+    /// The size of the address sub-block assigned to each router child of
+    /// a parent at `depth`. Zero means the parent can not accept children,
+    /// also when the parameters need more addresses than there are.
+    pub fn cskip(&self, depth: u8) -> u16 {
+        if depth >= self.max_depth {
+            return 0;
+        }
+        let cm = i64::from(self.max_children);
+        let rm = i64::from(self.max_routers);
+        let exponent = u32::from(self.max_depth - depth - 1);
+        let skip = if rm == 1 {
+            Some(1 + cm * i64::from(exponent))
+        } else {
+            rm.checked_pow(exponent)
+                .and_then(|power| cm.checked_mul(power))
+                .and_then(|block| (1 + cm - rm).checked_sub(block))
+                .map(|skip| skip / (1 - rm))
+        };
+        match skip {
+            Some(skip) if (0..=i64::from(MAX_NETWORK_ADDRESS)).contains(&skip) => skip as u16,
+            _ => 0,
+        }
+    }
+}
+
+/// Hands out the addresses of the block owned by a router, with the
+/// distributed address assignment mechanism.
+pub struct TreeAddressAllocator {
+    params: TreeParameters,
+    address: u16,
+    depth: u8,
+    routers: u8,
+    end_devices: u8,
+}
+
+impl TreeAddressAllocator {
+    pub fn new(params: TreeParameters, address: u16, depth: u8) -> Self {
+        TreeAddressAllocator { params, address, depth, routers: 0, end_devices: 0 }
+    }
+
+    pub fn allocate(&mut self, router: bool) -> Option<u16> {
+        let cskip = self.params.cskip(self.depth);
+        if cskip == 0 {
+            return None;
+        }
+        if router {
+            if self.routers >= self.params.max_routers {
+                return None;
+            }
+            let address = u32::from(self.address) + u32::from(cskip) * u32::from(self.routers) + 1;
+            self.routers += 1;
+            Some(address as u16)
+        } else {
+            let max_end_devices = self.params.max_children.saturating_sub(self.params.max_routers);
+            if self.end_devices >= max_end_devices {
+                return None;
+            }
+            self.end_devices += 1;
+            let address = u32::from(self.address)
+                + u32::from(cskip) * u32::from(self.params.max_routers)
+                + u32::from(self.end_devices);
+            Some(address as u16)
+        }
+    }
+}
+
+/// The number of random addresses tried before giving up, when the
+/// address space is nearly full.
+pub const MAX_ALLOCATION_ATTEMPTS: usize = 1024;
+
+/// Chooses addresses at random, with the stochastic address assignment
+/// mechanism.
+pub struct StochasticAddressAllocator<R> {
+    rng: R,
+}
+
+impl<R: Rng> StochasticAddressAllocator<R> {
+    pub fn new(rng: R) -> Self {
+        StochasticAddressAllocator { rng }
+    }
+
+    /// Choose a random address among the ones for which `in_use` returns
+    /// `false`. `None` is returned when `MAX_ALLOCATION_ATTEMPTS` addresses
+    /// in a row were in use.
+    pub fn allocate<F: Fn(u16) -> bool>(&mut self, in_use: F) -> Option<u16> {
+        (0..MAX_ALLOCATION_ATTEMPTS)
+            .map(|_| self.rng.gen_range(0x0001..=MAX_NETWORK_ADDRESS))
+            .find(|&address| !in_use(address))
+    }
+}
+
+pub enum AddressAllocator<R> {
+    Tree(TreeAddressAllocator),
+    Stochastic(StochasticAddressAllocator<R>),
+}
+
+/// The nwkAddressMap: the known associations between IEEE and network
+/// addresses.
+#[derive(Default)]
+pub struct AddressMap {
+    entries: HashMap<u64, u16>,
+}
+
+impl AddressMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Store an association. Any previous association of either address is
+    /// replaced.
+    pub fn insert(&mut self, ieee_address: u64, network_address: u16) {
+        self.remove_network_address(network_address);
+        self.entries.insert(ieee_address, network_address);
+    }
+
+    pub fn network_address(&self, ieee_address: u64) -> Option<u16> {
+        self.entries.get(&ieee_address).cloned()
+    }
+
+    pub fn ieee_address(&self, network_address: u16) -> Option<u64> {
+        self.entries.iter()
+            .find(|&(_, &nwk)| nwk == network_address)
+            .map(|(&ieee, _)| ieee)
+    }
+
+    pub fn remove_ieee_address(&mut self, ieee_address: u64) -> Option<u16> {
+        self.entries.remove(&ieee_address)
+    }
+
+    pub fn remove_network_address(&mut self, network_address: u16) -> Option<u64> {
+        let ieee = self.ieee_address(network_address)?;
+        self.entries.remove(&ieee);
+        Some(ieee)
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (u64, u16)> + 'a {
+        self.entries.iter().map(|(&ieee, &nwk)| (ieee, nwk))
+    }
+}
+
+/// What must be done after an address conflict has been detected or
+/// reported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictAction {
+    /// Broadcast to 0xFFFD the given network status command.
+    Report(NetworkStatus),
+    /// The address of this device was in conflict and it has been replaced
+    /// with the given one, that must be announced with a Device_annce.
+    AddressChanged(u16),
+    /// The address of an end device child was in conflict and it has been
+    /// replaced. The child must be informed with an unsolicited rejoin
+    /// response.
+    ChildAddressChanged { ieee_address: u64, network_address: u16 },
+}
+
+/// Assigns network addresses to this device children, keeps the address
+/// map and resolves the address conflicts.
+pub struct AddressManager<R> {
+    ieee_address: u64,
+    network_address: u16,
+    allocator: AddressAllocator<R>,
+    address_map: AddressMap,
+    /// The IEEE addresses of the children this device assigned an address
+    /// to, each with a flag telling whether it is a router.
+    children: Vec<(u64, bool)>,
+}
+
+impl<R: Rng> AddressManager<R> {
+    pub fn new(ieee_address: u64, network_address: u16, allocator: AddressAllocator<R>) -> Self {
+        AddressManager {
+            ieee_address,
+            network_address,
+            allocator,
+            address_map: AddressMap::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn ieee_address(&self) -> u64 {
+        self.ieee_address
+    }
+
+    pub fn network_address(&self) -> u16 {
+        self.network_address
+    }
+
+    pub fn set_network_address(&mut self, network_address: u16) {
+        self.network_address = network_address;
+    }
+
+    pub fn address_map(&self) -> &AddressMap {
+        &self.address_map
+    }
+
+    pub fn address_map_mut(&mut self) -> &mut AddressMap {
+        &mut self.address_map
+    }
+
+    /// Assign an address to a device that is joining through this one and
+    /// record it in the address map. A device that is already known keeps
+    /// its address.
+    pub fn allocate_child_address(&mut self, ieee_address: u64, router: bool) -> Option<u16> {
+        if let Some(address) = self.address_map.network_address(ieee_address) {
+            if !self.children.iter().any(|&(child, _)| child == ieee_address) {
+                self.children.push((ieee_address, router));
+            }
+            return Some(address);
+        }
+        let address = match self.allocator {
+            AddressAllocator::Tree(ref mut tree) => tree.allocate(router)?,
+            AddressAllocator::Stochastic(ref mut stochastic) => {
+                let network_address = self.network_address;
+                let map = &self.address_map;
+                stochastic.allocate(|a| a == network_address || map.ieee_address(a).is_some())?
+            }
+        };
+        self.address_map.insert(ieee_address, address);
+        self.children.push((ieee_address, router));
+        Some(address)
+    }
+
+    /// Assign an address to a device that is rejoining through this one
+    /// and that was using `current_address`. With stochastic addressing the
+    /// device keeps its address, unless it is used by another device.
+    pub fn rejoin_child_address(&mut self, ieee_address: u64, current_address: u16, router: bool)
+                                -> Option<u16> {
+        let keep = match self.allocator {
+            AddressAllocator::Stochastic(_) => current_address != self.network_address
+                && current_address <= MAX_NETWORK_ADDRESS
+                && self.address_map.ieee_address(current_address)
+                    .is_none_or(|ieee| ieee == ieee_address),
+            AddressAllocator::Tree(_) => false,
+        };
+        if !keep {
+            return self.allocate_child_address(ieee_address, router);
+        }
+        self.address_map.insert(ieee_address, current_address);
+        if !self.children.iter().any(|&(child, _)| child == ieee_address) {
+            self.children.push((ieee_address, router));
+        }
+        Some(current_address)
+    }
+
+    /// Forget the children and the address map, e.g. after leaving the
+    /// network.
+    pub fn clear(&mut self) {
+        self.children.clear();
+        self.address_map = AddressMap::new();
+    }
+
+    /// Forget a child that left the network.
+    pub fn remove_child(&mut self, ieee_address: u64) {
+        self.children.retain(|&(child, _)| child != ieee_address);
+        self.address_map.remove_ieee_address(ieee_address);
+    }
+
+    fn report(address: u16) -> ConflictAction {
+        ConflictAction::Report(NetworkStatus {
+            status: NetworkStatusCode::AddressConflict,
+            destination_address: address,
+        })
+    }
+
+    /// Check an association between a network and an IEEE address learned
+    /// from a received frame, e.g. a device announcement or a frame with
+    /// the source IEEE address, and update the address map.
+    pub fn process_address_pair(&mut self, network_address: u16, ieee_address: u64)
+                                -> Option<ConflictAction> {
+        if ieee_address == self.ieee_address {
+            return None;
+        }
+        if network_address == self.network_address {
+            return Some(Self::report(network_address));
+        }
+        match self.address_map.ieee_address(network_address) {
+            Some(known) if known != ieee_address => Some(Self::report(network_address)),
+            _ => {
+                self.address_map.insert(ieee_address, network_address);
+                None
+            }
+        }
+    }
+
+    /// Handle a received network status command reporting an address
+    /// conflict. If the address is the one of this device or of one of its
+    /// end device children, a new one is chosen with the stochastic
+    /// mechanism.
+    pub fn handle_address_conflict(&mut self, status: &NetworkStatus) -> Option<ConflictAction> {
+        if status.status != NetworkStatusCode::AddressConflict {
+            return None;
+        }
+        let address = status.destination_address;
+        if address == self.network_address {
+            let new_address = self.new_stochastic_address()?;
+            self.network_address = new_address;
+            return Some(ConflictAction::AddressChanged(new_address));
+        }
+        let child = self.address_map.ieee_address(address)
+            .filter(|&ieee| self.is_end_device_child(ieee))?;
+        let new_address = self.new_stochastic_address()?;
+        self.address_map.insert(child, new_address);
+        Some(ConflictAction::ChildAddressChanged { ieee_address: child, network_address: new_address })
+    }
+
+    fn new_stochastic_address(&mut self) -> Option<u16> {
+        let in_use: Vec<u16> = self.address_map.iter().map(|(_, nwk)| nwk).collect();
+        let own = self.network_address;
+        match self.allocator {
+            AddressAllocator::Stochastic(ref mut stochastic) =>
+                stochastic.allocate(|a| a == own || in_use.contains(&a)),
+            AddressAllocator::Tree(_) => None,
+        }
+    }
+
+    fn is_end_device_child(&self, ieee_address: u64) -> bool {
+        self.children.iter().any(|&(child, router)| child == ieee_address && !router)
+    }
+
+    fn associated_devices(&self) -> Vec<u16> {
+        self.children.iter()
+            .filter_map(|&(ieee, _)| self.address_map.network_address(ieee))
+            .collect()
+    }
+
+    /// Build the NWK_addr_rsp for a NWK_addr_req about `ieee_addr_of_interest`.
+    /// `None` is returned when the request is not about this device nor
+    /// about one of its end device children, so no response must be sent.
+    pub fn nwk_addr_rsp(&self, ieee_addr_of_interest: u64, request_type: u8, start_index: u8)
+                        -> Option<NwkAddrRsp<'static>> {
+        let (nwk_address, own) = if ieee_addr_of_interest == self.ieee_address {
+            (self.network_address, true)
+        } else if self.is_end_device_child(ieee_addr_of_interest) {
+            (self.address_map.network_address(ieee_addr_of_interest)?, false)
+        } else {
+            return None;
+        };
+        let (status, list) = self.address_response_list(own, request_type, start_index);
+        Some(NwkAddrRsp {
+            status,
+            ieee_addr_remote_dev: IeeeAddress(ieee_addr_of_interest),
+            nwk_addr_remote_dev: NwkAddress(nwk_address),
+            start_index,
+            nwk_addr_assoc_dev_list: Cow::Owned(list),
+        })
+    }
+
+    /// Build the IEEE_addr_rsp for an IEEE_addr_req about
+    /// `nwk_addr_of_interest`, with the same rules of `nwk_addr_rsp`.
+    pub fn ieee_addr_rsp(&self, nwk_addr_of_interest: u16, request_type: u8, start_index: u8)
+                         -> Option<IeeeAddrRsp<'static>> {
+        let (ieee_address, own) = if nwk_addr_of_interest == self.network_address {
+            (self.ieee_address, true)
+        } else {
+            let ieee = self.address_map.ieee_address(nwk_addr_of_interest)
+                .filter(|&ieee| self.is_end_device_child(ieee))?;
+            (ieee, false)
+        };
+        let (status, list) = self.address_response_list(own, request_type, start_index);
+        Some(IeeeAddrRsp {
+            status,
+            ieee_addr_remote_dev: IeeeAddress(ieee_address),
+            nwk_addr_remote_dev: NwkAddress(nwk_addr_of_interest),
+            start_index,
+            nwk_addr_assoc_dev_list: Cow::Owned(list),
+        })
+    }
+
+    /// The associated device list is included only in the extended
+    /// response (request type 1) about this device.
+    fn address_response_list(&self, own: bool, request_type: u8, start_index: u8)
+                             -> (Status, Vec<NwkAddress>) {
+        match request_type {
+            0 => (Status::Success, Vec::new()),
+            1 if own => (Status::Success,
+                         self.associated_devices().into_iter().skip(start_index as usize)
+                             .map(NwkAddress).collect()),
+            1 => (Status::Success, Vec::new()),
+            _ => (Status::InvRequestType, Vec::new()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    fn tree(max_children: u8, max_routers: u8, max_depth: u8) -> TreeParameters {
+        TreeParameters { max_children, max_routers, max_depth }
+    }
+
+    #[test]
+    fn cskip_of_the_specification_example() {
+        // Table 3.49 of the specification: Cm = 8, Rm = 4, Lm = 3.
+        let params = tree(8, 4, 3);
+        assert_eq!(params.cskip(0), 41);
+        assert_eq!(params.cskip(1), 9);
+        assert_eq!(params.cskip(2), 1);
+        assert_eq!(params.cskip(3), 0);
+        // A single router per parent makes a chain.
+        assert_eq!(tree(4, 1, 5).cskip(0), 17);
+    }
+
+    #[test]
+    fn cskip_overflow() {
+        assert_eq!(tree(20, 6, 15).cskip(0), 0);
+        assert_eq!(tree(255, 255, 255).cskip(0), 0);
+        assert_eq!(tree(1, 255, 255).cskip(0), 0);
+        assert_eq!(tree(255, 255, 255).cskip(254), 1);
+    }
+
+    #[test]
+    fn tree_allocation() {
+        let mut coordinator = TreeAddressAllocator::new(tree(8, 4, 3), 0x0000, 0);
+        let routers: Vec<_> = (0..5).map(|_| coordinator.allocate(true)).collect();
+        assert_eq!(routers, vec![Some(1), Some(42), Some(83), Some(124), None]);
+        let end_devices: Vec<_> = (0..5).map(|_| coordinator.allocate(false)).collect();
+        assert_eq!(end_devices, vec![Some(165), Some(166), Some(167), Some(168), None]);
+
+        let mut router = TreeAddressAllocator::new(tree(8, 4, 3), 42, 1);
+        assert_eq!(router.allocate(true), Some(43));
+        assert_eq!(router.allocate(false), Some(42 + 9 * 4 + 1));
+        let mut leaf = TreeAddressAllocator::new(tree(8, 4, 3), 43, 3);
+        assert_eq!(leaf.allocate(false), None);
+    }
+
+    #[test]
+    fn stochastic_allocation_gives_up() {
+        let mut allocator = StochasticAddressAllocator::new(StdRng::seed_from_u64(0));
+        let address = allocator.allocate(|a| a < 0x8000).unwrap();
+        assert!((0x8000..=MAX_NETWORK_ADDRESS).contains(&address));
+        assert_eq!(allocator.allocate(|_| true), None);
+    }
+
+    fn manager(network_address: u16) -> AddressManager<StdRng> {
+        let allocator = AddressAllocator::Stochastic(StochasticAddressAllocator::new(StdRng::seed_from_u64(1)));
+        AddressManager::new(0x0011_2233_4455_6677, network_address, allocator)
+    }
+
+    #[test]
+    fn children_keep_their_address() {
+        let mut manager = manager(0x0000);
+        let address = manager.allocate_child_address(1, false).unwrap();
+        assert_ne!(address, 0x0000);
+        assert_eq!(manager.allocate_child_address(1, false), Some(address));
+        assert_eq!(manager.rejoin_child_address(2, 0x1234, false), Some(0x1234));
+        // The address is taken by another device.
+        let rejoined = manager.rejoin_child_address(3, 0x1234, false).unwrap();
+        assert_ne!(rejoined, 0x1234);
+        assert_eq!(manager.address_map().ieee_address(0x1234), Some(2));
+        manager.remove_child(2);
+        assert_eq!(manager.address_map().ieee_address(0x1234), None);
+    }
+
+    #[test]
+    fn address_conflicts() {
+        let mut manager = manager(0x0001);
+        let child = manager.allocate_child_address(1, false).unwrap();
+        assert_eq!(manager.process_address_pair(0x0002, 2), None);
+        assert_eq!(manager.address_map().network_address(2), Some(0x0002));
+        let report = |address| Some(ConflictAction::Report(NetworkStatus {
+            status: NetworkStatusCode::AddressConflict,
+            destination_address: address,
+        }));
+        assert_eq!(manager.process_address_pair(0x0002, 3), report(0x0002));
+        assert_eq!(manager.process_address_pair(0x0001, 3), report(0x0001));
+
+        let conflict = |address| NetworkStatus { status: NetworkStatusCode::AddressConflict,
+                                                 destination_address: address };
+        let new_address = match manager.handle_address_conflict(&conflict(0x0001)) {
+            Some(ConflictAction::AddressChanged(address)) => address,
+            action => panic!("unexpected action {:?}", action),
+        };
+        assert_eq!(manager.network_address(), new_address);
+        match manager.handle_address_conflict(&conflict(child)) {
+            Some(ConflictAction::ChildAddressChanged { ieee_address: 1, network_address }) => {
+                assert_ne!(network_address, child);
+                assert_eq!(manager.address_map().network_address(1), Some(network_address));
+            }
+            action => panic!("unexpected action {:?}", action),
+        }
+        // Only the end device children are moved.
+        assert_eq!(manager.handle_address_conflict(&conflict(0x0002)), None);
+    }
+
+    #[test]
+    fn address_responses() {
+        let mut manager = manager(0x0001);
+        let child = manager.allocate_child_address(1, false).unwrap();
+        manager.allocate_child_address(2, true).unwrap();
+        let response = manager.nwk_addr_rsp(0x0011_2233_4455_6677, 1, 0).unwrap();
+        assert_eq!(response.nwk_addr_remote_dev, NwkAddress(0x0001));
+        assert_eq!(response.nwk_addr_assoc_dev_list.len(), 2);
+        let response = manager.ieee_addr_rsp(child, 0, 0).unwrap();
+        assert_eq!(response.ieee_addr_remote_dev, IeeeAddress(1));
+        assert!(response.nwk_addr_assoc_dev_list.is_empty());
+        assert_eq!(manager.nwk_addr_rsp(1, 2, 0).unwrap().status, Status::InvRequestType);
+        // Router children answer for themselves.
+        assert!(manager.nwk_addr_rsp(2, 0, 0).is_none());
+    }
+}
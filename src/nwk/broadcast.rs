@@ -0,0 +1,481 @@
+//! The broadcast and multicast communication, as described in sections
+//! 3.6.5 and 3.6.6 of the specification.
+//!
+//! Every broadcast frame received or sent is remembered in the broadcast
+//! transaction table, so that duplicates are discarded. Routers relay a
+//! new broadcast after a random jitter, and retry until all the
+//! neighboring routers have been heard relaying it too (passive
+//! acknowledgement) or the retries are exhausted.
+
+use std::time::Duration;
+use rand::Rng;
+use nwk::{BROADCAST_ALL, BROADCAST_LOW_POWER_ROUTERS, BROADCAST_ROUTERS, BROADCAST_RX_ON_WHEN_IDLE};
+use nwk::frame_format::{MulticastControl, MulticastMode, Npdu};
+use nwk::neighbor::NeighborTable;
+use apl::framework::LogicalType;
+
+/// The value of the non-member radius meaning that it is not decremented.
+pub const INFINITE_NONMEMBER_RADIUS: u8 = 0x07;
+
+/// Whether `address` is one of the broadcast addresses, 0xFFF8 to 0xFFFF.
+/// The ones that are not listed in table 3.54 of the specification are
+/// reserved: they are never assigned to a device, and no device receives
+/// the frames sent to them.
+pub fn is_broadcast_address(address: u16) -> bool {
+    address >= 0xFFF8
+}
+
+pub struct BroadcastConfig {
+    /// The number of transactions that can be remembered at the same time.
+    pub capacity: usize,
+    /// How long a transaction is remembered (nwkNetworkBroadcastDeliveryTime).
+    pub delivery_time: Duration,
+    /// The time to wait for the neighbors to relay a broadcast before
+    /// retrying (nwkPassiveAckTimeout).
+    pub passive_ack_timeout: Duration,
+    /// The number of retransmissions after the first one
+    /// (nwkMaxBroadcastRetries).
+    pub max_retries: u8,
+    /// The maximum random delay before the first transmission
+    /// (nwkcMaxBroadcastJitter).
+    pub max_jitter: Duration,
+    /// Whether this device is a router, that relays the broadcasts.
+    pub router: bool,
+    pub rx_on_when_idle: bool,
+}
+
+impl Default for BroadcastConfig {
+    fn default() -> Self {
+        BroadcastConfig {
+            capacity: 9,
+            delivery_time: Duration::from_secs(9),
+            passive_ack_timeout: Duration::from_millis(500),
+            max_retries: 2,
+            max_jitter: Duration::from_millis(64),
+            router: true,
+            rx_on_when_idle: true,
+        }
+    }
+}
+
+struct Transaction {
+    source_address: u16,
+    sequence_number: u8,
+    expiration: Duration,
+    /// The encoded frame to relay, if this device relays it.
+    frame: Option<Vec<u8>>,
+    next_transmission: Duration,
+    transmissions: u8,
+    /// The neighboring routers that have not been heard relaying the frame
+    /// yet.
+    pending_acks: Vec<u16>,
+}
+
+/// What to do with a broadcast or multicast frame that was received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reception {
+    /// The frame was already received, and is discarded.
+    Duplicate,
+    /// The broadcast transaction table is full, and the frame is discarded.
+    TableFull,
+    /// A new frame. It is relayed later by `poll` if `relayed` is set.
+    Accepted { deliver: bool, relayed: bool },
+}
+
+/// The broadcast transaction table, generic over the random number
+/// generator used for the jitter.
+pub struct BroadcastTransactionTable<R> {
+    config: BroadcastConfig,
+    rng: R,
+    transactions: Vec<Transaction>,
+}
+
+impl<R: Rng> BroadcastTransactionTable<R> {
+    pub fn new(config: BroadcastConfig, rng: R) -> Self {
+        BroadcastTransactionTable {
+            transactions: Vec::with_capacity(config.capacity),
+            config,
+            rng,
+        }
+    }
+
+    pub fn config(&self) -> &BroadcastConfig {
+        &self.config
+    }
+
+    pub fn len(&self) -> usize {
+        self.transactions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.transactions.is_empty()
+    }
+
+    pub fn contains(&self, source_address: u16, sequence_number: u8) -> bool {
+        self.find(source_address, sequence_number).is_some()
+    }
+
+    fn find(&self, source_address: u16, sequence_number: u8) -> Option<usize> {
+        self.transactions.iter().position(|t| t.source_address == source_address
+                                          && t.sequence_number == sequence_number)
+    }
+
+    /// Whether this device is among the recipients of a broadcast to
+    /// `destination`.
+    pub fn is_recipient(&self, destination: u16) -> bool {
+        match destination {
+            BROADCAST_ALL => true,
+            BROADCAST_RX_ON_WHEN_IDLE => self.config.rx_on_when_idle,
+            BROADCAST_ROUTERS | BROADCAST_LOW_POWER_ROUTERS => self.config.router,
+            _ => false,
+        }
+    }
+
+    fn jitter(&mut self) -> Duration {
+        let max = self.config.max_jitter.as_micros() as u64;
+        Duration::from_micros(self.rng.gen_range(0..=max))
+    }
+
+    fn insert(&mut self, npdu: Option<&Npdu>, source_address: u16, sequence_number: u8,
+              sender: Option<u16>, neighbors: &NeighborTable, now: Duration) {
+        let pending_acks = neighbors.iter()
+            .filter(|n| n.device_type != LogicalType::EndDevice && n.rx_on_when_idle)
+            .map(|n| n.network_address)
+            .filter(|&address| Some(address) != sender && address != source_address)
+            .collect();
+        let frame = npdu.map(|npdu| {
+            let mut frame = Vec::new();
+            npdu.encode(&mut frame);
+            frame
+        });
+        let next_transmission = now + self.jitter();
+        self.transactions.push(Transaction {
+            source_address,
+            sequence_number,
+            expiration: now + self.config.delivery_time,
+            frame,
+            next_transmission,
+            transmissions: 0,
+            pending_acks,
+        });
+    }
+
+    /// Record a broadcast originated by this device. The frame is sent by
+    /// `poll` after the jitter. Return `false` if the table is full.
+    pub fn originate(&mut self, npdu: &Npdu, neighbors: &NeighborTable, now: Duration) -> bool {
+        self.expire(now);
+        if self.transactions.len() >= self.config.capacity {
+            return false;
+        }
+        let (source, sequence_number) = (npdu.header.source_address, npdu.header.sequence_number);
+        self.insert(Some(npdu), source, sequence_number, None, neighbors, now);
+        true
+    }
+
+    /// Handle a broadcast frame received from the neighbor `sender`.
+    pub fn handle_broadcast(&mut self, npdu: &Npdu, sender: u16, neighbors: &NeighborTable,
+                            now: Duration) -> Reception {
+        let deliver = self.is_recipient(npdu.header.destination_address);
+        self.receive(npdu, sender, neighbors, now, deliver)
+    }
+
+    /// Handle a multicast frame received from the neighbor `sender`.
+    /// Frames in non-member mode are only handled here when received by a
+    /// group member: the others are routed like unicast frames.
+    pub fn handle_multicast(&mut self, npdu: &Npdu, sender: u16, group_member: bool,
+                            neighbors: &NeighborTable, now: Duration) -> Reception {
+        let control = match npdu.header.multicast_control {
+            Some(control) => control,
+            None => return self.handle_broadcast(npdu, sender, neighbors, now),
+        };
+        let relayed_control = match (control.mode, group_member) {
+            (_, true) => Some(MulticastControl {
+                mode: MulticastMode::Member,
+                nonmember_radius: control.max_nonmember_radius,
+                ..control
+            }),
+            (MulticastMode::Member, false) => match control.nonmember_radius {
+                0 => None,
+                INFINITE_NONMEMBER_RADIUS => Some(control),
+                radius => Some(MulticastControl { nonmember_radius: radius - 1, ..control }),
+            },
+            (MulticastMode::NonMember, false) => Some(control),
+        };
+        match relayed_control {
+            Some(relayed_control) => {
+                let mut header = npdu.header.clone();
+                header.multicast_control = Some(relayed_control);
+                let relayed = Npdu { header, payload: npdu.payload.clone() };
+                self.receive(&relayed, sender, neighbors, now, group_member)
+            }
+            None => {
+                // Not relayed, but still remembered to discard the copies.
+                let reception = self.receive(npdu, sender, neighbors, now, false);
+                if let Some(i) = self.find(npdu.header.source_address, npdu.header.sequence_number) {
+                    self.transactions[i].frame = None;
+                }
+                match reception {
+                    Reception::Accepted { deliver, .. } => Reception::Accepted { deliver, relayed: false },
+                    other => other,
+                }
+            }
+        }
+    }
+
+    fn receive(&mut self, npdu: &Npdu, sender: u16, neighbors: &NeighborTable, now: Duration,
+               deliver: bool) -> Reception {
+        self.expire(now);
+        let (source, sequence_number) = (npdu.header.source_address, npdu.header.sequence_number);
+        if let Some(i) = self.find(source, sequence_number) {
+            self.transactions[i].pending_acks.retain(|&address| address != sender);
+            return Reception::Duplicate;
+        }
+        if self.transactions.len() >= self.config.capacity {
+            return Reception::TableFull;
+        }
+        let relayed = self.config.router && npdu.header.radius > 1;
+        if relayed {
+            let mut header = npdu.header.clone();
+            header.radius -= 1;
+            let npdu = Npdu { header, payload: npdu.payload.clone() };
+            self.insert(Some(&npdu), source, sequence_number, Some(sender), neighbors, now);
+        } else {
+            self.insert(None, source, sequence_number, Some(sender), neighbors, now);
+        }
+        Reception::Accepted { deliver, relayed }
+    }
+
+    /// Return the frames that must be broadcast by the MAC at time `now`.
+    /// A frame is retransmitted until all the neighboring routers have
+    /// relayed it, or the retries are exhausted.
+    pub fn poll(&mut self, now: Duration) -> Vec<Vec<u8>> {
+        self.expire(now);
+        let passive_ack_timeout = self.config.passive_ack_timeout;
+        let max_transmissions = self.config.max_retries + 1;
+        let mut frames = Vec::new();
+        for transaction in &mut self.transactions {
+            if transaction.next_transmission > now {
+                continue;
+            }
+            let acknowledged = transaction.transmissions > 0 && transaction.pending_acks.is_empty();
+            if acknowledged || transaction.transmissions >= max_transmissions {
+                transaction.frame = None;
+            }
+            if let Some(ref frame) = transaction.frame {
+                frames.push(frame.clone());
+                transaction.transmissions += 1;
+                transaction.next_transmission = now + passive_ack_timeout;
+            }
+        }
+        frames
+    }
+
+    /// The earliest time `poll` has something to do.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.transactions.iter()
+            .map(|t| if t.frame.is_some() { t.next_transmission } else { t.expiration })
+            .min()
+    }
+
+    fn expire(&mut self, now: Duration) {
+        self.transactions.retain(|t| t.expiration > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::borrow::Cow;
+    use rand::rngs::mock::StepRng;
+    use apl::device_profile::Relationship;
+    use nwk::frame_format::{FrameType, NwkHeader};
+    use nwk::neighbor::NeighborTableEntry;
+
+    const SOURCE: u16 = 0x1234;
+
+    fn table(capacity: usize) -> BroadcastTransactionTable<StepRng> {
+        let config = BroadcastConfig { capacity, max_jitter: Duration::from_millis(0), ..BroadcastConfig::default() };
+        BroadcastTransactionTable::new(config, StepRng::new(0, 0))
+    }
+
+    /// Two neighboring routers, 0x0001 and 0x0002, and an end device
+    /// child that does not relay the broadcasts.
+    fn neighbors() -> NeighborTable {
+        let mut neighbors = NeighborTable::new(0, 4);
+        neighbors.insert(NeighborTableEntry::new(1, 0x0001, LogicalType::Router, Relationship::Sibling)).unwrap();
+        neighbors.insert(NeighborTableEntry::new(2, 0x0002, LogicalType::Router, Relationship::Sibling)).unwrap();
+        neighbors.insert(NeighborTableEntry::new(3, 0x0003, LogicalType::EndDevice, Relationship::Child)).unwrap();
+        neighbors
+    }
+
+    fn npdu(sequence_number: u8) -> Npdu<'static> {
+        Npdu {
+            header: NwkHeader::new(FrameType::Data, BROADCAST_ALL, SOURCE, 5, sequence_number),
+            payload: Cow::Borrowed(b"payload"),
+        }
+    }
+
+    fn multicast(mode: MulticastMode, nonmember_radius: u8) -> Npdu<'static> {
+        let mut npdu = npdu(1);
+        npdu.header.destination_address = 0x0001;
+        npdu.header.multicast_control = Some(MulticastControl { mode, nonmember_radius, max_nonmember_radius: 3 });
+        npdu
+    }
+
+    fn ms(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    #[test]
+    fn broadcast_addresses() {
+        assert!(!is_broadcast_address(0x0000));
+        assert!(!is_broadcast_address(0xFFF7));
+        for address in 0xFFF8..=0xFFFF {
+            assert!(is_broadcast_address(address));
+        }
+    }
+
+    #[test]
+    fn recipients() {
+        let end_device = BroadcastConfig { router: false, rx_on_when_idle: false, ..BroadcastConfig::default() };
+        let router = BroadcastTransactionTable::new(BroadcastConfig::default(), StepRng::new(0, 1));
+        let end_device = BroadcastTransactionTable::new(end_device, StepRng::new(0, 1));
+        assert!(router.is_recipient(BROADCAST_ALL) && end_device.is_recipient(BROADCAST_ALL));
+        assert!(router.is_recipient(BROADCAST_RX_ON_WHEN_IDLE) && !end_device.is_recipient(BROADCAST_RX_ON_WHEN_IDLE));
+        assert!(router.is_recipient(BROADCAST_ROUTERS) && !end_device.is_recipient(BROADCAST_ROUTERS));
+        // Nobody receives the frames sent to the reserved addresses.
+        for &address in &[0xFFF8, 0xFFFA, 0xFFFE] {
+            assert!(!router.is_recipient(address) && !end_device.is_recipient(address));
+        }
+    }
+
+    #[test]
+    fn duplicates_are_discarded() {
+        let mut table = table(4);
+        let neighbors = neighbors();
+        assert_eq!(table.handle_broadcast(&npdu(1), 0x0001, &neighbors, ms(0)),
+                   Reception::Accepted { deliver: true, relayed: true });
+        assert_eq!(table.handle_broadcast(&npdu(1), 0x0002, &neighbors, ms(10)), Reception::Duplicate);
+        assert_eq!(table.handle_broadcast(&npdu(1), 0x0001, &neighbors, ms(20)), Reception::Duplicate);
+        assert_eq!(table.len(), 1);
+        assert!(table.contains(SOURCE, 1));
+        assert_eq!(table.handle_broadcast(&npdu(2), 0x0001, &neighbors, ms(30)),
+                   Reception::Accepted { deliver: true, relayed: true });
+        // A frame whose radius is exhausted is delivered but not relayed.
+        let mut last_hop = npdu(3);
+        last_hop.header.radius = 1;
+        assert_eq!(table.handle_broadcast(&last_hop, 0x0001, &neighbors, ms(40)),
+                   Reception::Accepted { deliver: true, relayed: false });
+    }
+
+    #[test]
+    fn relayed_until_passively_acknowledged() {
+        let mut table = table(4);
+        let neighbors = neighbors();
+        table.handle_broadcast(&npdu(1), 0x0001, &neighbors, ms(0));
+        let frames = table.poll(ms(0));
+        assert_eq!(frames.len(), 1);
+        let relayed = Npdu::decode(&frames[0]).unwrap();
+        assert_eq!((relayed.header.radius, &relayed.payload[..]), (4, &b"payload"[..]));
+        assert!(table.poll(ms(499)).is_empty());
+        assert_eq!(table.next_deadline(), Some(ms(500)));
+
+        // The other router relays it too: it is not retransmitted.
+        assert_eq!(table.handle_broadcast(&npdu(1), 0x0002, &neighbors, ms(100)), Reception::Duplicate);
+        assert!(table.poll(ms(500)).is_empty());
+        assert_eq!(table.next_deadline(), Some(ms(9000)));
+        assert!(table.contains(SOURCE, 1));
+    }
+
+    #[test]
+    fn retries_are_limited() {
+        let mut table = table(4);
+        let neighbors = neighbors();
+        assert!(table.originate(&npdu(1), &neighbors, ms(0)));
+        // The retransmissions after the first one, without any neighbor
+        // relaying the frame.
+        for &now in &[0, 500, 1000] {
+            let frames = table.poll(ms(now));
+            assert_eq!(frames.len(), 1);
+            // The frame is sent as originated, with the full radius.
+            assert_eq!(Npdu::decode(&frames[0]).unwrap().header.radius, 5);
+        }
+        assert!(table.poll(ms(1500)).is_empty());
+        assert!(table.poll(ms(2000)).is_empty());
+        assert_eq!(table.handle_broadcast(&npdu(1), 0x0001, &neighbors, ms(2000)), Reception::Duplicate);
+    }
+
+    #[test]
+    fn transactions_expire() {
+        let mut table = table(4);
+        let neighbors = neighbors();
+        table.handle_broadcast(&npdu(1), 0x0001, &neighbors, ms(0));
+        assert_eq!(table.poll(ms(8999)).len(), 1);
+        assert!(table.poll(ms(9000)).is_empty());
+        assert!(table.is_empty());
+        assert_eq!(table.next_deadline(), None);
+        // The same frame is new again once forgotten.
+        assert_eq!(table.handle_broadcast(&npdu(1), 0x0001, &neighbors, ms(9000)),
+                   Reception::Accepted { deliver: true, relayed: true });
+    }
+
+    #[test]
+    fn table_full() {
+        let mut table = table(2);
+        let neighbors = neighbors();
+        table.handle_broadcast(&npdu(1), 0x0001, &neighbors, ms(0));
+        assert!(table.originate(&npdu(2), &neighbors, ms(0)));
+        assert_eq!(table.handle_broadcast(&npdu(3), 0x0001, &neighbors, ms(0)), Reception::TableFull);
+        assert!(!table.originate(&npdu(4), &neighbors, ms(0)));
+        assert!(!table.contains(SOURCE, 3) && !table.contains(SOURCE, 4));
+        // The duplicates are still recognized.
+        assert_eq!(table.handle_broadcast(&npdu(1), 0x0002, &neighbors, ms(0)), Reception::Duplicate);
+        // There is room again once the transactions expire.
+        assert_eq!(table.handle_broadcast(&npdu(3), 0x0001, &neighbors, ms(9000)),
+                   Reception::Accepted { deliver: true, relayed: true });
+    }
+
+    fn relayed_control(table: &mut BroadcastTransactionTable<StepRng>, now: Duration) -> MulticastControl {
+        let frames = table.poll(now);
+        assert_eq!(frames.len(), 1);
+        Npdu::decode(&frames[0]).unwrap().header.multicast_control.unwrap()
+    }
+
+    #[test]
+    fn multicast_nonmember_radius() {
+        let neighbors = neighbors();
+        // A non-member relays a member mode frame with the radius
+        // decremented, without delivering it.
+        let mut table = table(4);
+        assert_eq!(table.handle_multicast(&multicast(MulticastMode::Member, 2), 0x0001, false, &neighbors, ms(0)),
+                   Reception::Accepted { deliver: false, relayed: true });
+        let control = relayed_control(&mut table, ms(0));
+        assert_eq!((control.mode, control.nonmember_radius), (MulticastMode::Member, 1));
+
+        // The infinite radius is not decremented.
+        let mut table = self::table(4);
+        let frame = multicast(MulticastMode::Member, INFINITE_NONMEMBER_RADIUS);
+        table.handle_multicast(&frame, 0x0001, false, &neighbors, ms(0));
+        assert_eq!(relayed_control(&mut table, ms(0)).nonmember_radius, INFINITE_NONMEMBER_RADIUS);
+
+        // A member delivers the frame and restores the radius.
+        let mut table = self::table(4);
+        assert_eq!(table.handle_multicast(&multicast(MulticastMode::NonMember, 0), 0x0001, true, &neighbors, ms(0)),
+                   Reception::Accepted { deliver: true, relayed: true });
+        let control = relayed_control(&mut table, ms(0));
+        assert_eq!((control.mode, control.nonmember_radius), (MulticastMode::Member, 3));
+    }
+
+    #[test]
+    fn multicast_dropped_when_the_radius_is_exhausted() {
+        let mut table = table(4);
+        let neighbors = neighbors();
+        let frame = multicast(MulticastMode::Member, 0);
+        assert_eq!(table.handle_multicast(&frame, 0x0001, false, &neighbors, ms(0)),
+                   Reception::Accepted { deliver: false, relayed: false });
+        assert!(table.poll(ms(0)).is_empty());
+        // Still remembered, so that the copies are discarded.
+        assert_eq!(table.handle_multicast(&frame, 0x0002, false, &neighbors, ms(10)), Reception::Duplicate);
+        assert_eq!(table.handle_multicast(&frame, 0x0002, true, &neighbors, ms(10)), Reception::Duplicate);
+    }
+}
@@ -0,0 +1,897 @@
+//! The NWK command frames, described in section 3.4 of the specification.
+//!
+//! Each command knows how to encode and decode its payload, that is
+//! what follows the command identifier in the NWK payload.
+
+use std::borrow::Cow;
+use std::time::Duration;
+use apl::framework::MacCapability;
+use codec::{DecodeError, Reader, write_u16, write_u64};
+use mac::AssociationStatus;
+use nwk::frame_format::{FrameType, NwkHeader, Npdu};
+
+/// The many-to-one subfield of the route request command options.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManyToOne {
+    /// The route request is not a many-to-one route request.
+    No = 0,
+    /// The route request is a many-to-one route request and the sender
+    /// keeps a route record table, so route records are sent only when
+    /// needed.
+    WithRouteRecordTable = 1,
+    /// The route request is a many-to-one route request and the sender
+    /// does not keep a route record table, so a route record must precede
+    /// every data frame directed to it.
+    WithoutRouteRecordTable = 2,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteRequest {
+    pub many_to_one: ManyToOne,
+    pub multicast: bool,
+    pub route_request_id: u8,
+    pub destination_address: u16,
+    pub path_cost: u8,
+    pub destination_ieee_address: Option<u64>,
+}
+
+impl RouteRequest {
+    pub const ID: u8 = 0x01;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        let mut options = (self.many_to_one as u8) << 3;
+        if self.destination_ieee_address.is_some() { options |= 1 << 5; }
+        if self.multicast { options |= 1 << 6; }
+        out.push(options);
+        out.push(self.route_request_id);
+        write_u16(out, self.destination_address);
+        out.push(self.path_cost);
+        if let Some(address) = self.destination_ieee_address {
+            write_u64(out, address);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let options = reader.read_u8()?;
+        let many_to_one = match (options >> 3) & 0x03 {
+            0 => ManyToOne::No,
+            1 => ManyToOne::WithRouteRecordTable,
+            2 => ManyToOne::WithoutRouteRecordTable,
+            _ => return Err(DecodeError::InvalidValue("many-to-one")),
+        };
+        let route_request_id = reader.read_u8()?;
+        let destination_address = reader.read_u16()?;
+        let path_cost = reader.read_u8()?;
+        let destination_ieee_address = if options & (1 << 5) != 0 {
+            Some(reader.read_u64()?)
+        } else {
+            None
+        };
+        Ok(RouteRequest {
+            many_to_one,
+            multicast: options & (1 << 6) != 0,
+            route_request_id,
+            destination_address,
+            path_cost,
+            destination_ieee_address,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RouteReply {
+    pub multicast: bool,
+    pub route_request_id: u8,
+    pub originator_address: u16,
+    pub responder_address: u16,
+    pub path_cost: u8,
+    pub originator_ieee_address: Option<u64>,
+    pub responder_ieee_address: Option<u64>,
+}
+
+impl RouteReply {
+    pub const ID: u8 = 0x02;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        let mut options = 0;
+        if self.originator_ieee_address.is_some() { options |= 1 << 4; }
+        if self.responder_ieee_address.is_some() { options |= 1 << 5; }
+        if self.multicast { options |= 1 << 6; }
+        out.push(options);
+        out.push(self.route_request_id);
+        write_u16(out, self.originator_address);
+        write_u16(out, self.responder_address);
+        out.push(self.path_cost);
+        if let Some(address) = self.originator_ieee_address {
+            write_u64(out, address);
+        }
+        if let Some(address) = self.responder_ieee_address {
+            write_u64(out, address);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let options = reader.read_u8()?;
+        let route_request_id = reader.read_u8()?;
+        let originator_address = reader.read_u16()?;
+        let responder_address = reader.read_u16()?;
+        let path_cost = reader.read_u8()?;
+        let originator_ieee_address = if options & (1 << 4) != 0 {
+            Some(reader.read_u64()?)
+        } else {
+            None
+        };
+        let responder_ieee_address = if options & (1 << 5) != 0 {
+            Some(reader.read_u64()?)
+        } else {
+            None
+        };
+        Ok(RouteReply {
+            multicast: options & (1 << 6) != 0,
+            route_request_id,
+            originator_address,
+            responder_address,
+            path_cost,
+            originator_ieee_address,
+            responder_ieee_address,
+        })
+    }
+}
+
+/// The route record command. Each relay appends its own address to the
+/// relay list, so the first relay is the one closest to the originator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteRecord {
+    pub relay_list: Vec<u16>,
+}
+
+impl RouteRecord {
+    pub const ID: u8 = 0x05;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.relay_list.len() as u8);
+        for relay in &self.relay_list {
+            write_u16(out, *relay);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let relay_count = reader.read_u8()?;
+        let mut relay_list = Vec::with_capacity(relay_count as usize);
+        for _ in 0..relay_count {
+            relay_list.push(reader.read_u16()?);
+        }
+        Ok(RouteRecord { relay_list })
+    }
+
+    /// Called by a relay that forwards the route record towards the
+    /// concentrator.
+    pub fn add_relay(&mut self, own_address: u16) {
+        self.relay_list.push(own_address);
+    }
+}
+
+/// The status codes carried by the network status command.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkStatusCode {
+    NoRouteAvailable = 0x00,
+    TreeLinkFailure = 0x01,
+    NonTreeLinkFailure = 0x02,
+    LowBatteryLevel = 0x03,
+    NoRoutingCapacity = 0x04,
+    NoIndirectCapacity = 0x05,
+    IndirectTransactionExpiry = 0x06,
+    TargetDeviceUnavailable = 0x07,
+    TargetAddressUnallocated = 0x08,
+    ParentLinkFailure = 0x09,
+    ValidateRoute = 0x0A,
+    SourceRouteFailure = 0x0B,
+    ManyToOneRouteFailure = 0x0C,
+    AddressConflict = 0x0D,
+    VerifyAddresses = 0x0E,
+    PanIdentifierUpdate = 0x0F,
+    NetworkAddressUpdate = 0x10,
+    BadFrameCounter = 0x11,
+    BadKeySequenceNumber = 0x12,
+    UnknownCommand = 0x13,
+}
+
+impl NetworkStatusCode {
+    pub fn from_u8(value: u8) -> Option<Self> {
+        use self::NetworkStatusCode::*;
+        Some(match value {
+            0x00 => NoRouteAvailable,
+            0x01 => TreeLinkFailure,
+            0x02 => NonTreeLinkFailure,
+            0x03 => LowBatteryLevel,
+            0x04 => NoRoutingCapacity,
+            0x05 => NoIndirectCapacity,
+            0x06 => IndirectTransactionExpiry,
+            0x07 => TargetDeviceUnavailable,
+            0x08 => TargetAddressUnallocated,
+            0x09 => ParentLinkFailure,
+            0x0A => ValidateRoute,
+            0x0B => SourceRouteFailure,
+            0x0C => ManyToOneRouteFailure,
+            0x0D => AddressConflict,
+            0x0E => VerifyAddresses,
+            0x0F => PanIdentifierUpdate,
+            0x10 => NetworkAddressUpdate,
+            0x11 => BadFrameCounter,
+            0x12 => BadKeySequenceNumber,
+            0x13 => UnknownCommand,
+            _ => return None,
+        })
+    }
+}
+
+/// The network status command. The destination address is the address
+/// the status refers to, e.g. the destination of the failed route or the
+/// address in conflict.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkStatus {
+    pub status: NetworkStatusCode,
+    pub destination_address: u16,
+}
+
+impl NetworkStatus {
+    pub const ID: u8 = 0x03;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.status as u8);
+        write_u16(out, self.destination_address);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let status = NetworkStatusCode::from_u8(reader.read_u8()?)
+            .ok_or(DecodeError::InvalidValue("network status code"))?;
+        let destination_address = reader.read_u16()?;
+        Ok(NetworkStatus { status, destination_address })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejoinRequest {
+    pub capability_information: MacCapability,
+}
+
+impl RejoinRequest {
+    pub const ID: u8 = 0x06;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.capability_information.to_u8());
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(RejoinRequest { capability_information: MacCapability::from_u8(reader.read_u8()?) })
+    }
+}
+
+/// The rejoin status carried by the rejoin response, with the same values
+/// of the MAC association status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RejoinResponse {
+    pub network_address: u16,
+    pub rejoin_status: AssociationStatus,
+}
+
+impl RejoinResponse {
+    pub const ID: u8 = 0x07;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.network_address);
+        out.push(self.rejoin_status as u8);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let network_address = reader.read_u16()?;
+        let rejoin_status = match reader.read_u8()? {
+            0x00 => AssociationStatus::Success,
+            0x01 => AssociationStatus::PanAtCapacity,
+            0x02 => AssociationStatus::PanAccessDenied,
+            _ => return Err(DecodeError::InvalidValue("rejoin status")),
+        };
+        Ok(RejoinResponse { network_address, rejoin_status })
+    }
+}
+
+/// The leave command. When `request` is set, the receiver is asked to
+/// leave; otherwise the sender informs its neighbors that it is leaving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Leave {
+    pub request: bool,
+    pub remove_children: bool,
+    pub rejoin: bool,
+}
+
+impl Leave {
+    pub const ID: u8 = 0x04;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push((self.rejoin as u8) << 5
+                 | (self.request as u8) << 6
+                 | (self.remove_children as u8) << 7);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let options = reader.read_u8()?;
+        Ok(Leave {
+            request: options & (1 << 6) != 0,
+            remove_children: options & (1 << 7) != 0,
+            rejoin: options & (1 << 5) != 0,
+        })
+    }
+}
+
+/// An entry of the link status command: the cost of the link with a
+/// neighbor, as measured in each direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LinkStatusEntry {
+    pub address: u16,
+    pub incoming_cost: u8,
+    pub outgoing_cost: u8,
+}
+
+/// The link status command, broadcast periodically by routers to their
+/// neighbors. A long list is split across several frames, the first and
+/// last of which are flagged.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkStatus {
+    pub first_frame: bool,
+    pub last_frame: bool,
+    pub entries: Vec<LinkStatusEntry>,
+}
+
+impl LinkStatus {
+    pub const ID: u8 = 0x08;
+    /// The maximum number of entries a single frame can carry.
+    pub const MAX_ENTRIES: usize = 0x1F;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.entries.len() as u8 & 0x1F
+                 | (self.first_frame as u8) << 5
+                 | (self.last_frame as u8) << 6);
+        for entry in &self.entries {
+            write_u16(out, entry.address);
+            out.push(entry.incoming_cost & 0x07 | (entry.outgoing_cost & 0x07) << 4);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let options = reader.read_u8()?;
+        let count = options & 0x1F;
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let address = reader.read_u16()?;
+            let link = reader.read_u8()?;
+            entries.push(LinkStatusEntry {
+                address,
+                incoming_cost: link & 0x07,
+                outgoing_cost: (link >> 4) & 0x07,
+            });
+        }
+        Ok(LinkStatus {
+            first_frame: options & (1 << 5) != 0,
+            last_frame: options & (1 << 6) != 0,
+            entries,
+        })
+    }
+}
+
+/// The network report command, sent to the network manager. The only
+/// report defined is the PAN identifier conflict, that lists the PAN
+/// identifiers heard in the neighborhood.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkReport {
+    PanIdConflict { extended_pan_id: u64, pan_ids: Vec<u16> },
+}
+
+impl NetworkReport {
+    pub const ID: u8 = 0x09;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        match *self {
+            NetworkReport::PanIdConflict { extended_pan_id, ref pan_ids } => {
+                out.push(pan_ids.len() as u8 & 0x1F);
+                write_u64(out, extended_pan_id);
+                for pan_id in pan_ids {
+                    write_u16(out, *pan_id);
+                }
+            }
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let options = reader.read_u8()?;
+        if options >> 5 != 0 {
+            return Err(DecodeError::InvalidValue("report command identifier"));
+        }
+        let extended_pan_id = reader.read_u64()?;
+        let mut pan_ids = Vec::with_capacity((options & 0x1F) as usize);
+        for _ in 0..options & 0x1F {
+            pan_ids.push(reader.read_u16()?);
+        }
+        Ok(NetworkReport::PanIdConflict { extended_pan_id, pan_ids })
+    }
+}
+
+/// The network update command, broadcast by the network manager. The only
+/// update defined is the change of the PAN identifier.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkUpdate {
+    PanIdUpdate { extended_pan_id: u64, update_id: u8, new_pan_id: u16 },
+}
+
+impl NetworkUpdate {
+    pub const ID: u8 = 0x0A;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        match *self {
+            NetworkUpdate::PanIdUpdate { extended_pan_id, update_id, new_pan_id } => {
+                out.push(1);
+                write_u64(out, extended_pan_id);
+                out.push(update_id);
+                write_u16(out, new_pan_id);
+            }
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let options = reader.read_u8()?;
+        if options >> 5 != 0 {
+            return Err(DecodeError::InvalidValue("update command identifier"));
+        }
+        if options & 0x1F != 1 {
+            return Err(DecodeError::InvalidValue("update information count"));
+        }
+        let extended_pan_id = reader.read_u64()?;
+        let update_id = reader.read_u8()?;
+        let new_pan_id = reader.read_u16()?;
+        Ok(NetworkUpdate::PanIdUpdate { extended_pan_id, update_id, new_pan_id })
+    }
+}
+
+/// The largest value of the requested timeout enumeration.
+pub const MAX_END_DEVICE_TIMEOUT: u8 = 14;
+
+/// The duration represented by a value of the requested timeout
+/// enumeration: 10 seconds for 0, 2^n minutes otherwise.
+pub fn end_device_timeout(value: u8) -> Option<Duration> {
+    match value {
+        0 => Some(Duration::from_secs(10)),
+        1..=MAX_END_DEVICE_TIMEOUT => Some(Duration::from_secs(60 << value)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndDeviceTimeoutRequest {
+    /// The requested timeout, in the format of `end_device_timeout`.
+    pub requested_timeout: u8,
+    pub end_device_configuration: u8,
+}
+
+impl EndDeviceTimeoutRequest {
+    pub const ID: u8 = 0x0B;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.requested_timeout);
+        out.push(self.end_device_configuration);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(EndDeviceTimeoutRequest {
+            requested_timeout: reader.read_u8()?,
+            end_device_configuration: reader.read_u8()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EndDeviceTimeoutStatus {
+    Success = 0x00,
+    IncorrectValue = 0x01,
+}
+
+/// The keep-alive methods supported by the parent, and whether it supports
+/// power negotiation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParentInformation {
+    pub mac_data_poll_keepalive: bool,
+    pub end_device_timeout_request_keepalive: bool,
+    pub power_negotiation: bool,
+}
+
+impl ParentInformation {
+    pub fn to_u8(&self) -> u8 {
+        self.mac_data_poll_keepalive as u8
+            | (self.end_device_timeout_request_keepalive as u8) << 1
+            | (self.power_negotiation as u8) << 2
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        ParentInformation {
+            mac_data_poll_keepalive: value & 0x01 != 0,
+            end_device_timeout_request_keepalive: value & 0x02 != 0,
+            power_negotiation: value & 0x04 != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EndDeviceTimeoutResponse {
+    pub status: EndDeviceTimeoutStatus,
+    pub parent_information: ParentInformation,
+}
+
+impl EndDeviceTimeoutResponse {
+    pub const ID: u8 = 0x0C;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.status as u8);
+        out.push(self.parent_information.to_u8());
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let status = match reader.read_u8()? {
+            0x00 => EndDeviceTimeoutStatus::Success,
+            0x01 => EndDeviceTimeoutStatus::IncorrectValue,
+            _ => return Err(DecodeError::InvalidValue("end device timeout status")),
+        };
+        let parent_information = ParentInformation::from_u8(reader.read_u8()?);
+        Ok(EndDeviceTimeoutResponse { status, parent_information })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkPowerDeltaType {
+    Notification = 0,
+    Request = 1,
+    Response = 2,
+}
+
+/// The transmit power adjustment, in dBm, suggested to a neighbor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PowerDelta {
+    pub address: u16,
+    pub delta: i8,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkPowerDelta {
+    pub command_type: LinkPowerDeltaType,
+    pub entries: Vec<PowerDelta>,
+}
+
+impl LinkPowerDelta {
+    pub const ID: u8 = 0x0D;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.command_type as u8);
+        out.push(self.entries.len() as u8);
+        for entry in &self.entries {
+            write_u16(out, entry.address);
+            out.push(entry.delta as u8);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let command_type = match reader.read_u8()? & 0x03 {
+            0 => LinkPowerDeltaType::Notification,
+            1 => LinkPowerDeltaType::Request,
+            2 => LinkPowerDeltaType::Response,
+            _ => return Err(DecodeError::InvalidValue("link power delta type")),
+        };
+        let count = reader.read_u8()?;
+        let mut entries = Vec::with_capacity(count as usize);
+        for _ in 0..count {
+            let address = reader.read_u16()?;
+            let delta = reader.read_u8()? as i8;
+            entries.push(PowerDelta { address, delta });
+        }
+        Ok(LinkPowerDelta { command_type, entries })
+    }
+}
+
+/// Any NWK command, as carried in the payload of a command frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    RouteRequest(RouteRequest),
+    RouteReply(RouteReply),
+    NetworkStatus(NetworkStatus),
+    Leave(Leave),
+    RouteRecord(RouteRecord),
+    RejoinRequest(RejoinRequest),
+    RejoinResponse(RejoinResponse),
+    LinkStatus(LinkStatus),
+    NetworkReport(NetworkReport),
+    NetworkUpdate(NetworkUpdate),
+    EndDeviceTimeoutRequest(EndDeviceTimeoutRequest),
+    EndDeviceTimeoutResponse(EndDeviceTimeoutResponse),
+    LinkPowerDelta(LinkPowerDelta),
+}
+
+impl Command {
+    pub fn id(&self) -> u8 {
+        match *self {
+            Command::RouteRequest(_) => RouteRequest::ID,
+            Command::RouteReply(_) => RouteReply::ID,
+            Command::NetworkStatus(_) => NetworkStatus::ID,
+            Command::Leave(_) => Leave::ID,
+            Command::RouteRecord(_) => RouteRecord::ID,
+            Command::RejoinRequest(_) => RejoinRequest::ID,
+            Command::RejoinResponse(_) => RejoinResponse::ID,
+            Command::LinkStatus(_) => LinkStatus::ID,
+            Command::NetworkReport(_) => NetworkReport::ID,
+            Command::NetworkUpdate(_) => NetworkUpdate::ID,
+            Command::EndDeviceTimeoutRequest(_) => EndDeviceTimeoutRequest::ID,
+            Command::EndDeviceTimeoutResponse(_) => EndDeviceTimeoutResponse::ID,
+            Command::LinkPowerDelta(_) => LinkPowerDelta::ID,
+        }
+    }
+
+    /// Encode the command identifier followed by the payload.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.id());
+        match *self {
+            Command::RouteRequest(ref command) => command.encode_payload(out),
+            Command::RouteReply(ref command) => command.encode_payload(out),
+            Command::NetworkStatus(ref command) => command.encode_payload(out),
+            Command::Leave(ref command) => command.encode_payload(out),
+            Command::RouteRecord(ref command) => command.encode_payload(out),
+            Command::RejoinRequest(ref command) => command.encode_payload(out),
+            Command::RejoinResponse(ref command) => command.encode_payload(out),
+            Command::LinkStatus(ref command) => command.encode_payload(out),
+            Command::NetworkReport(ref command) => command.encode_payload(out),
+            Command::NetworkUpdate(ref command) => command.encode_payload(out),
+            Command::EndDeviceTimeoutRequest(ref command) => command.encode_payload(out),
+            Command::EndDeviceTimeoutResponse(ref command) => command.encode_payload(out),
+            Command::LinkPowerDelta(ref command) => command.encode_payload(out),
+        }
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match reader.read_u8()? {
+            RouteRequest::ID => Command::RouteRequest(RouteRequest::decode_payload(reader)?),
+            RouteReply::ID => Command::RouteReply(RouteReply::decode_payload(reader)?),
+            NetworkStatus::ID => Command::NetworkStatus(NetworkStatus::decode_payload(reader)?),
+            Leave::ID => Command::Leave(Leave::decode_payload(reader)?),
+            RouteRecord::ID => Command::RouteRecord(RouteRecord::decode_payload(reader)?),
+            RejoinRequest::ID => Command::RejoinRequest(RejoinRequest::decode_payload(reader)?),
+            RejoinResponse::ID => Command::RejoinResponse(RejoinResponse::decode_payload(reader)?),
+            LinkStatus::ID => Command::LinkStatus(LinkStatus::decode_payload(reader)?),
+            NetworkReport::ID => Command::NetworkReport(NetworkReport::decode_payload(reader)?),
+            NetworkUpdate::ID => Command::NetworkUpdate(NetworkUpdate::decode_payload(reader)?),
+            EndDeviceTimeoutRequest::ID => {
+                Command::EndDeviceTimeoutRequest(EndDeviceTimeoutRequest::decode_payload(reader)?)
+            }
+            EndDeviceTimeoutResponse::ID => {
+                Command::EndDeviceTimeoutResponse(EndDeviceTimeoutResponse::decode_payload(reader)?)
+            }
+            LinkPowerDelta::ID => Command::LinkPowerDelta(LinkPowerDelta::decode_payload(reader)?),
+            _ => return Err(DecodeError::InvalidValue("command identifier")),
+        })
+    }
+
+    /// Build a command frame with the given header, whose frame type is
+    /// set to `FrameType::Command`.
+    pub fn to_npdu(&self, mut header: NwkHeader) -> Npdu<'static> {
+        header.frame_type = FrameType::Command;
+        let mut payload = Vec::new();
+        self.encode(&mut payload);
+        Npdu { header, payload: Cow::Owned(payload) }
+    }
+
+    /// Decode the command carried by a command frame.
+    pub fn from_npdu(npdu: &Npdu) -> Result<Self, DecodeError> {
+        if npdu.header.frame_type != FrameType::Command {
+            return Err(DecodeError::InvalidValue("frame type"));
+        }
+        Command::decode(&mut Reader::new(&npdu.payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IEEE_ADDRESS: u64 = 0x0011_2233_4455_6677;
+    const IEEE_ADDRESS_BYTES: [u8; 8] = [0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00];
+
+    /// Check that the command is encoded to `bytes`, identifier included,
+    /// and decoded back from them, alone and in a whole command frame.
+    fn round_trip(command: Command, bytes: &[u8]) {
+        let mut out = Vec::new();
+        command.encode(&mut out);
+        assert_eq!(out, bytes);
+        let mut reader = Reader::new(bytes);
+        assert_eq!(Command::decode(&mut reader), Ok(command.clone()));
+        assert!(reader.is_empty());
+
+        let mut header = NwkHeader::new(FrameType::Data, 0x0000, 0x1234, 1, 7);
+        header.source_ieee_address = Some(IEEE_ADDRESS);
+        let mut frame = Vec::new();
+        command.to_npdu(header).encode(&mut frame);
+        assert_eq!(frame[0] & 0x03, FrameType::Command as u8);
+        assert_eq!(&frame[frame.len() - bytes.len()..], bytes);
+        let npdu = Npdu::decode(&frame).unwrap();
+        assert_eq!(npdu.header.frame_type, FrameType::Command);
+        assert_eq!((npdu.header.source_address, npdu.header.sequence_number), (0x1234, 7));
+        assert_eq!(npdu.header.source_ieee_address, Some(IEEE_ADDRESS));
+        assert_eq!(Command::from_npdu(&npdu), Ok(command));
+    }
+
+    fn with_ieee_address(bytes: &[u8]) -> Vec<u8> {
+        let mut bytes = bytes.to_vec();
+        bytes.extend_from_slice(&IEEE_ADDRESS_BYTES);
+        bytes
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Command, DecodeError> {
+        Command::decode(&mut Reader::new(bytes))
+    }
+
+    #[test]
+    fn route_request() {
+        round_trip(Command::RouteRequest(RouteRequest {
+            many_to_one: ManyToOne::WithRouteRecordTable,
+            multicast: false,
+            route_request_id: 7,
+            destination_address: 0xFFFC,
+            path_cost: 0,
+            destination_ieee_address: None,
+        }), &[0x01, 0x08, 0x07, 0xFC, 0xFF, 0x00]);
+        round_trip(Command::RouteRequest(RouteRequest {
+            many_to_one: ManyToOne::No,
+            multicast: true,
+            route_request_id: 1,
+            destination_address: 0x1234,
+            path_cost: 5,
+            destination_ieee_address: Some(IEEE_ADDRESS),
+        }), &with_ieee_address(&[0x01, 0x60, 0x01, 0x34, 0x12, 0x05]));
+        assert_eq!(decode(&[0x01, 0x18, 0x07, 0xFC, 0xFF, 0x00]), Err(DecodeError::InvalidValue("many-to-one")));
+        assert_eq!(decode(&[0x01, 0x20, 0x07, 0xFC, 0xFF, 0x00]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn route_reply() {
+        round_trip(Command::RouteReply(RouteReply {
+            multicast: false,
+            route_request_id: 9,
+            originator_address: 0x0001,
+            responder_address: 0x1234,
+            path_cost: 3,
+            originator_ieee_address: Some(IEEE_ADDRESS),
+            responder_ieee_address: None,
+        }), &with_ieee_address(&[0x02, 0x10, 0x09, 0x01, 0x00, 0x34, 0x12, 0x03]));
+    }
+
+    #[test]
+    fn route_record() {
+        let mut record = RouteRecord { relay_list: vec![0x0003] };
+        record.add_relay(0x0002);
+        round_trip(Command::RouteRecord(record), &[0x05, 0x02, 0x03, 0x00, 0x02, 0x00]);
+        assert_eq!(decode(&[0x05, 0x02, 0x03, 0x00]), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn network_status() {
+        round_trip(Command::NetworkStatus(NetworkStatus {
+            status: NetworkStatusCode::AddressConflict,
+            destination_address: 0x1234,
+        }), &[0x03, 0x0D, 0x34, 0x12]);
+        for code in 0..=0x13 {
+            assert_eq!(NetworkStatusCode::from_u8(code).map(|c| c as u8), Some(code));
+        }
+        assert_eq!(decode(&[0x03, 0x14, 0x34, 0x12]), Err(DecodeError::InvalidValue("network status code")));
+    }
+
+    #[test]
+    fn leave() {
+        round_trip(Command::Leave(Leave { request: true, remove_children: false, rejoin: true }), &[0x04, 0x60]);
+        round_trip(Command::Leave(Leave { request: false, remove_children: true, rejoin: false }), &[0x04, 0x80]);
+    }
+
+    #[test]
+    fn rejoin() {
+        round_trip(Command::RejoinRequest(RejoinRequest { capability_information: MacCapability::from_u8(0x8E) }),
+                   &[0x06, 0x8E]);
+        round_trip(Command::RejoinResponse(RejoinResponse {
+            network_address: 0x1234,
+            rejoin_status: AssociationStatus::Success,
+        }), &[0x07, 0x34, 0x12, 0x00]);
+        round_trip(Command::RejoinResponse(RejoinResponse {
+            network_address: 0xFFFF,
+            rejoin_status: AssociationStatus::PanAccessDenied,
+        }), &[0x07, 0xFF, 0xFF, 0x02]);
+        assert_eq!(decode(&[0x07, 0x34, 0x12, 0x03]), Err(DecodeError::InvalidValue("rejoin status")));
+    }
+
+    #[test]
+    fn link_status() {
+        round_trip(Command::LinkStatus(LinkStatus {
+            first_frame: true,
+            last_frame: true,
+            entries: vec![
+                LinkStatusEntry { address: 0x0001, incoming_cost: 1, outgoing_cost: 3 },
+                LinkStatusEntry { address: 0x1234, incoming_cost: 7, outgoing_cost: 0 },
+            ],
+        }), &[0x08, 0x62, 0x01, 0x00, 0x31, 0x34, 0x12, 0x07]);
+        round_trip(Command::LinkStatus(LinkStatus { first_frame: false, last_frame: false, entries: vec![] }),
+                   &[0x08, 0x00]);
+    }
+
+    #[test]
+    fn network_report_and_update() {
+        round_trip(Command::NetworkReport(NetworkReport::PanIdConflict {
+            extended_pan_id: IEEE_ADDRESS,
+            pan_ids: vec![0x1234, 0xABCD],
+        }), &[&[0x09, 0x02][..], &IEEE_ADDRESS_BYTES, &[0x34, 0x12, 0xCD, 0xAB]].concat());
+        assert_eq!(decode(&with_ieee_address(&[0x09, 0x20])),
+                   Err(DecodeError::InvalidValue("report command identifier")));
+
+        round_trip(Command::NetworkUpdate(NetworkUpdate::PanIdUpdate {
+            extended_pan_id: IEEE_ADDRESS,
+            update_id: 5,
+            new_pan_id: 0x4321,
+        }), &[&[0x0A, 0x01][..], &IEEE_ADDRESS_BYTES, &[0x05, 0x21, 0x43]].concat());
+        assert_eq!(decode(&[&[0x0A, 0x02][..], &IEEE_ADDRESS_BYTES, &[0x05, 0x21, 0x43]].concat()),
+                   Err(DecodeError::InvalidValue("update information count")));
+    }
+
+    #[test]
+    fn end_device_timeout_commands() {
+        assert_eq!(end_device_timeout(0), Some(Duration::from_secs(10)));
+        assert_eq!(end_device_timeout(1), Some(Duration::from_secs(2 * 60)));
+        assert_eq!(end_device_timeout(MAX_END_DEVICE_TIMEOUT), Some(Duration::from_secs(16_384 * 60)));
+        assert_eq!(end_device_timeout(MAX_END_DEVICE_TIMEOUT + 1), None);
+
+        round_trip(Command::EndDeviceTimeoutRequest(EndDeviceTimeoutRequest {
+            requested_timeout: 3,
+            end_device_configuration: 0,
+        }), &[0x0B, 0x03, 0x00]);
+        round_trip(Command::EndDeviceTimeoutResponse(EndDeviceTimeoutResponse {
+            status: EndDeviceTimeoutStatus::Success,
+            parent_information: ParentInformation {
+                mac_data_poll_keepalive: true,
+                end_device_timeout_request_keepalive: true,
+                power_negotiation: false,
+            },
+        }), &[0x0C, 0x00, 0x03]);
+        assert_eq!(decode(&[0x0C, 0x02, 0x03]), Err(DecodeError::InvalidValue("end device timeout status")));
+    }
+
+    #[test]
+    fn link_power_delta() {
+        round_trip(Command::LinkPowerDelta(LinkPowerDelta {
+            command_type: LinkPowerDeltaType::Request,
+            entries: vec![PowerDelta { address: 0x0001, delta: -3 }, PowerDelta { address: 0x0002, delta: 4 }],
+        }), &[0x0D, 0x01, 0x02, 0x01, 0x00, 0xFD, 0x02, 0x00, 0x04]);
+        assert_eq!(decode(&[0x0D, 0x03, 0x00]), Err(DecodeError::InvalidValue("link power delta type")));
+    }
+
+    #[test]
+    fn command_frames() {
+        assert_eq!(decode(&[0x0E]), Err(DecodeError::InvalidValue("command identifier")));
+        assert_eq!(decode(&[]), Err(DecodeError::UnexpectedEnd));
+
+        let command = Command::Leave(Leave { request: false, remove_children: false, rejoin: false });
+        let npdu = command.to_npdu(NwkHeader::new(FrameType::Data, 0xFFFD, 0x1234, 1, 3));
+        assert_eq!(npdu.header.frame_type, FrameType::Command);
+        assert_eq!(&npdu.payload[..], &[0x04, 0x00]);
+        assert_eq!(Command::from_npdu(&npdu), Ok(command.clone()));
+
+        let data = Npdu {
+            header: NwkHeader::new(FrameType::Data, 0x0000, 0x1234, 30, 4),
+            payload: Cow::Borrowed(&[0x04, 0x00]),
+        };
+        assert_eq!(Command::from_npdu(&data), Err(DecodeError::InvalidValue("frame type")));
+
+        // A command frame cut in its payload.
+        let mut frame = Vec::new();
+        command.to_npdu(NwkHeader::new(FrameType::Command, 0x0000, 0x1234, 1, 5)).encode(&mut frame);
+        frame.pop();
+        let npdu = Npdu::decode(&frame).unwrap();
+        assert_eq!(Command::from_npdu(&npdu), Err(DecodeError::UnexpectedEnd));
+    }
+}
@@ -0,0 +1,490 @@
+//! The end device timeout negotiation and the keep-alive between an end
+//! device and its parent, as described in section 3.6.10 of the
+//! specification.
+//!
+//! A parent removes the end device children it did not hear from for
+//! longer than their timeout, so that their resources can be reused. The
+//! children negotiate the timeout with the End Device Timeout Request
+//! command, and then keep themselves alive with MAC data polls or further
+//! timeout requests, whichever the parent supports. A child that loses its
+//! parent rejoins the network.
+
+use std::time::Duration;
+use apl::framework::LogicalType;
+use mac::{MacAddress, MacStatus, MlmeSap};
+use nwk::commands::{end_device_timeout, EndDeviceTimeoutRequest, EndDeviceTimeoutResponse,
+                    EndDeviceTimeoutStatus, ParentInformation};
+use nwk::neighbor::{NeighborTable, NeighborTableEntry};
+
+/// The parent side configuration.
+pub struct ChildTimeoutConfig {
+    /// The timeout of the children that never sent a timeout request
+    /// (nwkEndDeviceTimeoutDefault), in the format of `end_device_timeout`.
+    pub default_timeout: u8,
+    /// The keep-alive methods supported by this device.
+    pub parent_information: ParentInformation,
+}
+
+impl Default for ChildTimeoutConfig {
+    fn default() -> Self {
+        ChildTimeoutConfig {
+            default_timeout: 8,
+            parent_information: ParentInformation {
+                mac_data_poll_keepalive: true,
+                end_device_timeout_request_keepalive: true,
+                power_negotiation: false,
+            },
+        }
+    }
+}
+
+/// The parent side: tracks the timeout of the end device children.
+pub struct ChildTimeouts {
+    config: ChildTimeoutConfig,
+    /// The negotiated timeouts, by network address of the child.
+    timeouts: Vec<(u16, Duration)>,
+}
+
+fn is_end_device_child(entry: &NeighborTableEntry) -> bool {
+    entry.is_child() && entry.device_type == LogicalType::EndDevice
+}
+
+impl ChildTimeouts {
+    pub fn new(config: ChildTimeoutConfig) -> Self {
+        ChildTimeouts { config, timeouts: Vec::new() }
+    }
+
+    pub fn config(&self) -> &ChildTimeoutConfig {
+        &self.config
+    }
+
+    /// The timeout of the child with the given network address.
+    pub fn timeout(&self, network_address: u16) -> Duration {
+        self.timeouts.iter()
+            .find(|&&(address, _)| address == network_address)
+            .map(|&(_, timeout)| timeout)
+            .or_else(|| end_device_timeout(self.config.default_timeout))
+            .unwrap_or_else(|| Duration::from_secs(0))
+    }
+
+    /// Handle an End Device Timeout Request received from `source`, and
+    /// return the response to send back. `None` is returned if the source
+    /// is not an end device child.
+    pub fn handle_timeout_request(&mut self, source: u16, request: &EndDeviceTimeoutRequest,
+                                  neighbors: &mut NeighborTable, now: Duration)
+                                  -> Option<EndDeviceTimeoutResponse> {
+        let entry = neighbors.get_mut(source).filter(|e| is_end_device_child(e))?;
+        let timeout = match end_device_timeout(request.requested_timeout) {
+            // No end device configuration is defined yet.
+            Some(timeout) if request.end_device_configuration == 0 => timeout,
+            _ => return Some(EndDeviceTimeoutResponse {
+                status: EndDeviceTimeoutStatus::IncorrectValue,
+                parent_information: self.config.parent_information,
+            }),
+        };
+        self.timeouts.retain(|&(address, _)| address != source);
+        self.timeouts.push((source, timeout));
+        entry.keepalive_deadline = Some(now + timeout);
+        Some(EndDeviceTimeoutResponse {
+            status: EndDeviceTimeoutStatus::Success,
+            parent_information: self.config.parent_information,
+        })
+    }
+
+    /// Handle a keep-alive from `source`: a MAC data request or any other
+    /// frame. Return `false` if the source is not an end device child, in
+    /// which case it should be told to rejoin with a leave command.
+    pub fn keep_alive(&self, source: u16, neighbors: &mut NeighborTable, now: Duration) -> bool {
+        let timeout = self.timeout(source);
+        match neighbors.get_mut(source).filter(|e| is_end_device_child(e)) {
+            Some(entry) => {
+                entry.keepalive_deadline = Some(now + timeout);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Remove the end device children whose timeout expired at time `now`.
+    /// The new children get the default timeout.
+    pub fn poll(&mut self, neighbors: &mut NeighborTable, now: Duration) -> Vec<NeighborTableEntry> {
+        let mut expired = Vec::new();
+        for entry in neighbors.iter_mut().filter(|e| is_end_device_child(e)) {
+            match entry.keepalive_deadline {
+                Some(deadline) if deadline <= now => expired.push(entry.extended_address),
+                Some(_) => {}
+                None => entry.keepalive_deadline = Some(now + self.timeout(entry.network_address)),
+            }
+        }
+        let removed: Vec<NeighborTableEntry> = expired.into_iter()
+            .filter_map(|address| neighbors.remove(address))
+            .collect();
+        self.timeouts.retain(|&(address, _)| !removed.iter().any(|e| e.network_address == address));
+        removed
+    }
+}
+
+/// The child side configuration.
+pub struct KeepAliveConfig {
+    /// The timeout requested to the parent, in the format of
+    /// `end_device_timeout`.
+    pub requested_timeout: u8,
+    /// The number of keep-alives sent in a timeout period.
+    pub keepalives_per_timeout: u32,
+    /// How long to wait for the End Device Timeout Response.
+    pub response_wait_time: Duration,
+    /// The number of consecutive failed keep-alives after which the parent
+    /// is considered lost.
+    pub max_failures: u8,
+}
+
+impl Default for KeepAliveConfig {
+    fn default() -> Self {
+        KeepAliveConfig {
+            requested_timeout: 8,
+            keepalives_per_timeout: 3,
+            response_wait_time: Duration::from_secs(5),
+            max_failures: 3,
+        }
+    }
+}
+
+/// The outcome of the events handled by a `KeepAlive`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeepAliveEvent {
+    /// An End Device Timeout Request must be sent to the parent.
+    SendTimeoutRequest { destination: u16, request: EndDeviceTimeoutRequest },
+    /// The parent no longer answers, or dropped this device: the device
+    /// must rejoin the network with `Joiner::rejoin`.
+    ParentLost,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    WaitingResponse { deadline: Duration },
+    WaitingPollConfirm,
+    Alive { next_keepalive: Duration },
+}
+
+/// The child side: negotiates the timeout with the parent and keeps this
+/// device alive, generic over the MAC used for the data polls.
+pub struct KeepAlive<M> {
+    mac: M,
+    config: KeepAliveConfig,
+    state: State,
+    parent_address: u16,
+    pan_id: u16,
+    parent_information: ParentInformation,
+    failures: u8,
+}
+
+impl<M: MlmeSap> KeepAlive<M> {
+    pub fn new(mac: M, config: KeepAliveConfig) -> Self {
+        KeepAlive {
+            mac,
+            config,
+            state: State::Idle,
+            parent_address: 0xFFFF,
+            pan_id: 0xFFFF,
+            parent_information: ParentInformation::default(),
+            failures: 0,
+        }
+    }
+
+    pub fn mac(&self) -> &M {
+        &self.mac
+    }
+
+    /// The keep-alive methods supported by the parent, as learned from the
+    /// End Device Timeout Response.
+    pub fn parent_information(&self) -> ParentInformation {
+        self.parent_information
+    }
+
+    fn request(&self) -> KeepAliveEvent {
+        KeepAliveEvent::SendTimeoutRequest {
+            destination: self.parent_address,
+            request: EndDeviceTimeoutRequest {
+                requested_timeout: self.config.requested_timeout,
+                end_device_configuration: 0,
+            },
+        }
+    }
+
+    fn interval(&self) -> Duration {
+        let timeout = end_device_timeout(self.config.requested_timeout)
+            .unwrap_or_else(|| Duration::from_secs(10));
+        timeout / self.config.keepalives_per_timeout.max(1)
+    }
+
+    /// Start the negotiation with a new parent, after joining or
+    /// rejoining.
+    pub fn start(&mut self, parent_address: u16, pan_id: u16, now: Duration) -> KeepAliveEvent {
+        self.parent_address = parent_address;
+        self.pan_id = pan_id;
+        self.parent_information = ParentInformation::default();
+        self.failures = 0;
+        self.state = State::WaitingResponse { deadline: now + self.config.response_wait_time };
+        self.request()
+    }
+
+    /// Stop sending keep-alives, e.g. after leaving the network.
+    pub fn stop(&mut self) {
+        self.state = State::Idle;
+    }
+
+    fn failure(&mut self, now: Duration) -> Option<KeepAliveEvent> {
+        self.failures += 1;
+        if self.failures >= self.config.max_failures {
+            self.state = State::Idle;
+            return Some(KeepAliveEvent::ParentLost);
+        }
+        self.state = State::Alive { next_keepalive: now };
+        None
+    }
+
+    /// Handle the End Device Timeout Response received from the parent.
+    pub fn handle_timeout_response(&mut self, source: u16, response: &EndDeviceTimeoutResponse,
+                                   now: Duration) {
+        if source != self.parent_address || self.state == State::Idle {
+            return;
+        }
+        // An incorrect value leaves the parent with its default timeout:
+        // keep-alives are still needed.
+        if response.status == EndDeviceTimeoutStatus::Success {
+            self.parent_information = response.parent_information;
+        }
+        self.failures = 0;
+        self.state = State::Alive { next_keepalive: now + self.interval() };
+    }
+
+    /// Handle the MLME-POLL.confirm of the data polls used as keep-alive.
+    pub fn handle_poll_confirm(&mut self, status: MacStatus, now: Duration) -> Option<KeepAliveEvent> {
+        if self.state != State::WaitingPollConfirm {
+            return None;
+        }
+        match status {
+            MacStatus::Success | MacStatus::NoData => {
+                self.failures = 0;
+                self.state = State::Alive { next_keepalive: now + self.interval() };
+                None
+            }
+            _ => self.failure(now),
+        }
+    }
+
+    /// Handle a leave command received from the parent that asks this
+    /// device to rejoin, as sent by parents that no longer know it.
+    pub fn handle_parent_leave(&mut self, source: u16, rejoin: bool) -> Option<KeepAliveEvent> {
+        if source != self.parent_address || !rejoin || self.state == State::Idle {
+            return None;
+        }
+        self.state = State::Idle;
+        Some(KeepAliveEvent::ParentLost)
+    }
+
+    /// Send the keep-alives that are due at time `now`.
+    pub fn tick(&mut self, now: Duration) -> Option<KeepAliveEvent> {
+        match self.state {
+            State::WaitingResponse { deadline } if now >= deadline => {
+                let event = self.failure(now);
+                if event.is_some() {
+                    return event;
+                }
+                self.state = State::WaitingResponse { deadline: now + self.config.response_wait_time };
+                Some(self.request())
+            }
+            State::Alive { next_keepalive } if now >= next_keepalive => {
+                if self.parent_information.end_device_timeout_request_keepalive
+                    && !self.parent_information.mac_data_poll_keepalive {
+                    self.state = State::WaitingResponse { deadline: now + self.config.response_wait_time };
+                    Some(self.request())
+                } else {
+                    self.state = State::WaitingPollConfirm;
+                    self.mac.poll_request(MacAddress::Short(self.parent_address), self.pan_id);
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apl::device_profile::Relationship;
+    use mac::mock::{MockMac, Request};
+    use nwk::commands::MAX_END_DEVICE_TIMEOUT;
+
+    const PARENT: u16 = 0x0000;
+    const PAN_ID: u16 = 0x1234;
+    const END_DEVICE: u16 = 0x0002;
+    const ROUTER: u16 = 0x0003;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn neighbors() -> NeighborTable {
+        let mut neighbors = NeighborTable::new(0, 4);
+        neighbors.insert(NeighborTableEntry::new(2, END_DEVICE, LogicalType::EndDevice, Relationship::Child))
+            .unwrap();
+        neighbors.insert(NeighborTableEntry::new(3, ROUTER, LogicalType::Router, Relationship::Child)).unwrap();
+        neighbors
+    }
+
+    fn timeout_request(requested_timeout: u8, end_device_configuration: u8) -> EndDeviceTimeoutRequest {
+        EndDeviceTimeoutRequest { requested_timeout, end_device_configuration }
+    }
+
+    #[test]
+    fn timeout_negotiation() {
+        let mut timeouts = ChildTimeouts::new(ChildTimeoutConfig::default());
+        let mut neighbors = neighbors();
+        assert_eq!(timeouts.timeout(END_DEVICE), secs(256 * 60));
+
+        let response = timeouts.handle_timeout_request(END_DEVICE, &timeout_request(3, 0), &mut neighbors, secs(10));
+        assert_eq!(response, Some(EndDeviceTimeoutResponse {
+            status: EndDeviceTimeoutStatus::Success,
+            parent_information: ChildTimeoutConfig::default().parent_information,
+        }));
+        assert_eq!(timeouts.timeout(END_DEVICE), secs(8 * 60));
+        assert_eq!(neighbors.get(END_DEVICE).unwrap().keepalive_deadline, Some(secs(10 + 8 * 60)));
+
+        for request in &[timeout_request(MAX_END_DEVICE_TIMEOUT + 1, 0), timeout_request(3, 1)] {
+            let response = timeouts.handle_timeout_request(END_DEVICE, request, &mut neighbors, secs(20));
+            assert_eq!(response.unwrap().status, EndDeviceTimeoutStatus::IncorrectValue);
+        }
+        assert_eq!(timeouts.timeout(END_DEVICE), secs(8 * 60));
+
+        // Only the end device children negotiate a timeout.
+        for &source in &[ROUTER, 0x0004] {
+            assert_eq!(timeouts.handle_timeout_request(source, &timeout_request(3, 0), &mut neighbors, secs(0)),
+                       None);
+        }
+    }
+
+    #[test]
+    fn silent_children_are_removed() {
+        let mut timeouts = ChildTimeouts::new(ChildTimeoutConfig { default_timeout: 0, ..Default::default() });
+        let mut neighbors = neighbors();
+        // The new children get the default timeout.
+        assert!(timeouts.poll(&mut neighbors, secs(0)).is_empty());
+        assert_eq!(neighbors.get(END_DEVICE).unwrap().keepalive_deadline, Some(secs(10)));
+
+        assert!(timeouts.keep_alive(END_DEVICE, &mut neighbors, secs(5)));
+        assert!(!timeouts.keep_alive(ROUTER, &mut neighbors, secs(5)));
+        assert!(timeouts.poll(&mut neighbors, secs(14)).is_empty());
+        let removed = timeouts.poll(&mut neighbors, secs(15));
+        assert_eq!(removed.iter().map(|e| e.network_address).collect::<Vec<_>>(), vec![END_DEVICE]);
+        assert_eq!(neighbors.get(END_DEVICE), None);
+        assert!(neighbors.get(ROUTER).is_some());
+        // The parent should tell the child to rejoin.
+        assert!(!timeouts.keep_alive(END_DEVICE, &mut neighbors, secs(20)));
+    }
+
+    fn config() -> KeepAliveConfig {
+        // A timeout of 2 minutes, with a keep-alive every 40 seconds.
+        KeepAliveConfig { requested_timeout: 1, ..KeepAliveConfig::default() }
+    }
+
+    fn expected_request() -> KeepAliveEvent {
+        KeepAliveEvent::SendTimeoutRequest { destination: PARENT, request: timeout_request(1, 0) }
+    }
+
+    fn response(mac_data_poll_keepalive: bool, end_device_timeout_request_keepalive: bool)
+                -> EndDeviceTimeoutResponse {
+        EndDeviceTimeoutResponse {
+            status: EndDeviceTimeoutStatus::Success,
+            parent_information: ParentInformation {
+                mac_data_poll_keepalive,
+                end_device_timeout_request_keepalive,
+                power_negotiation: false,
+            },
+        }
+    }
+
+    fn poll() -> Request {
+        Request::Poll { coord_address: MacAddress::Short(PARENT), coord_pan_id: PAN_ID }
+    }
+
+    #[test]
+    fn keep_alive_with_data_polls() {
+        let mac = MockMac::new(2);
+        let mut keep_alive = KeepAlive::new(&mac, config());
+        assert_eq!(keep_alive.start(PARENT, PAN_ID, secs(0)), expected_request());
+        keep_alive.handle_timeout_response(PARENT, &response(true, true), secs(1));
+        assert!(keep_alive.parent_information().mac_data_poll_keepalive);
+
+        assert_eq!(keep_alive.tick(secs(40)), None);
+        assert!(mac.take_requests().is_empty());
+        assert_eq!(keep_alive.tick(secs(41)), None);
+        assert_eq!(mac.take_requests(), vec![poll()]);
+        assert_eq!(keep_alive.handle_poll_confirm(MacStatus::NoData, secs(41)), None);
+        assert_eq!(keep_alive.tick(secs(80)), None);
+        assert_eq!(keep_alive.tick(secs(81)), None);
+        assert_eq!(mac.take_requests(), vec![poll()]);
+    }
+
+    #[test]
+    fn keep_alive_with_timeout_requests() {
+        let mac = MockMac::new(2);
+        let mut keep_alive = KeepAlive::new(&mac, config());
+        keep_alive.start(PARENT, PAN_ID, secs(0));
+        keep_alive.handle_timeout_response(PARENT, &response(false, true), secs(0));
+        assert_eq!(keep_alive.tick(secs(40)), Some(expected_request()));
+        keep_alive.handle_timeout_response(PARENT, &response(false, true), secs(41));
+        assert_eq!(keep_alive.tick(secs(80)), None);
+        assert_eq!(keep_alive.tick(secs(81)), Some(expected_request()));
+        assert!(mac.take_requests().is_empty());
+    }
+
+    #[test]
+    fn unanswered_timeout_requests() {
+        let mac = MockMac::new(2);
+        let mut keep_alive = KeepAlive::new(&mac, config());
+        keep_alive.start(PARENT, PAN_ID, secs(0));
+        // Responses from other devices are ignored.
+        keep_alive.handle_timeout_response(0x0005, &response(true, true), secs(1));
+        assert_eq!(keep_alive.tick(secs(4)), None);
+        assert_eq!(keep_alive.tick(secs(5)), Some(expected_request()));
+        assert_eq!(keep_alive.tick(secs(10)), Some(expected_request()));
+        assert_eq!(keep_alive.tick(secs(15)), Some(KeepAliveEvent::ParentLost));
+        assert_eq!(keep_alive.tick(secs(100)), None);
+    }
+
+    #[test]
+    fn failed_data_polls() {
+        let mac = MockMac::new(2);
+        let mut keep_alive = KeepAlive::new(&mac, config());
+        keep_alive.start(PARENT, PAN_ID, secs(0));
+        keep_alive.handle_timeout_response(PARENT, &response(true, false), secs(0));
+        keep_alive.tick(secs(40));
+        assert_eq!(keep_alive.handle_poll_confirm(MacStatus::NoAck, secs(40)), None);
+        // The keep-alive is retried at once.
+        keep_alive.tick(secs(40));
+        assert_eq!(keep_alive.handle_poll_confirm(MacStatus::ChannelAccessFailure, secs(40)), None);
+        keep_alive.tick(secs(40));
+        assert_eq!(keep_alive.handle_poll_confirm(MacStatus::NoAck, secs(40)), Some(KeepAliveEvent::ParentLost));
+        assert_eq!(mac.take_requests(), vec![poll(), poll(), poll()]);
+        assert_eq!(keep_alive.handle_poll_confirm(MacStatus::Success, secs(41)), None);
+    }
+
+    #[test]
+    fn parent_asks_to_rejoin() {
+        let mac = MockMac::new(2);
+        let mut keep_alive = KeepAlive::new(&mac, config());
+        assert_eq!(keep_alive.handle_parent_leave(PARENT, true), None);
+        keep_alive.start(PARENT, PAN_ID, secs(0));
+        assert_eq!(keep_alive.handle_parent_leave(0x0005, true), None);
+        assert_eq!(keep_alive.handle_parent_leave(PARENT, false), None);
+        assert_eq!(keep_alive.handle_parent_leave(PARENT, true), Some(KeepAliveEvent::ParentLost));
+        assert_eq!(keep_alive.tick(secs(100)), None);
+
+        keep_alive.start(PARENT, PAN_ID, secs(100));
+        keep_alive.stop();
+        assert_eq!(keep_alive.tick(secs(200)), None);
+    }
+}
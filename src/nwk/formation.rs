@@ -0,0 +1,369 @@
+//! The network formation procedure (NLME-NETWORK-FORMATION), performed by
+//! a device that becomes the coordinator of a new network. See section
+//! 3.6.1.1 of the specification.
+//!
+//! The procedure goes through an energy detection scan, to discard the
+//! noisy channels, and an active scan, to find the channel with the fewest
+//! networks and a PAN identifier that is not already in use.
+
+use std::collections::BTreeSet;
+use rand::Rng;
+use mac::{MacStatus, MlmeSap, ScanConfirm, ScanType};
+use nwk::NwkStatus;
+
+/// The highest PAN identifier that can be chosen for a Zigbee network.
+pub const MAX_PAN_ID: u16 = 0x3FFF;
+
+/// Choose a random PAN identifier that is not in use: the first free one
+/// from a random start, wrapping around, so that the choice always ends.
+/// Return `None` if they are all in use.
+pub fn choose_pan_id<R: Rng, F: Fn(u16) -> bool>(rng: &mut R, in_use: F) -> Option<u16> {
+    let start = rng.gen_range(0x0001..=MAX_PAN_ID);
+    (start..=MAX_PAN_ID).chain(0x0001..start).find(|&pan_id| !in_use(pan_id))
+}
+
+/// The arguments of the NLME-NETWORK-FORMATION.request primitive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormationRequest {
+    pub scan_channels: u32,
+    pub scan_duration: u8,
+    /// The PAN identifier to use. If `None`, a random one is chosen.
+    pub pan_id: Option<u16>,
+    /// The extended PAN identifier to use. If zero, the IEEE address of
+    /// this device is used.
+    pub extended_pan_id: u64,
+    /// The channels where the energy detected is above this threshold are
+    /// not considered.
+    pub energy_threshold: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormationConfirm {
+    pub status: NwkStatus,
+    pub pan_id: u16,
+    pub extended_pan_id: u64,
+    pub logical_channel: u8,
+}
+
+impl FormationConfirm {
+    fn failure(status: NwkStatus) -> Self {
+        FormationConfirm { status, pan_id: 0, extended_pan_id: 0, logical_channel: 0 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    EnergyScan,
+    ActiveScan,
+    Starting { pan_id: u16, logical_channel: u8 },
+    Formed,
+}
+
+/// The state machine of the network formation procedure, generic over the
+/// MAC it drives and the random number generator used to choose the PAN
+/// identifier.
+pub struct NetworkFormation<M, R> {
+    mac: M,
+    rng: R,
+    state: State,
+    request: Option<FormationRequest>,
+    /// The channels that passed the energy detection scan, with the energy
+    /// measured.
+    channels: Vec<(u8, u8)>,
+}
+
+/// The bit of `channel` in a channel mask, 0 for the channels that do not
+/// fit in one.
+fn channel_bit(channel: u8) -> u32 {
+    1u32.checked_shl(channel.into()).unwrap_or(0)
+}
+
+fn channel_mask(channels: &[(u8, u8)]) -> u32 {
+    channels.iter().fold(0, |mask, &(channel, _)| mask | channel_bit(channel))
+}
+
+impl<M: MlmeSap, R: Rng> NetworkFormation<M, R> {
+    pub fn new(mac: M, rng: R) -> Self {
+        NetworkFormation { mac, rng, state: State::Idle, request: None, channels: Vec::new() }
+    }
+
+    pub fn mac(&self) -> &M {
+        &self.mac
+    }
+
+    pub fn is_formed(&self) -> bool {
+        self.state == State::Formed
+    }
+
+    /// Start the formation of a new network. The MAC is asked to perform
+    /// the energy detection scan.
+    pub fn request(&mut self, request: FormationRequest) -> Result<(), NwkStatus> {
+        match self.state {
+            State::Idle | State::Formed => {}
+            _ => return Err(NwkStatus::InvalidRequest),
+        }
+        if request.scan_channels == 0 {
+            return Err(NwkStatus::InvalidParameter);
+        }
+        self.request = Some(request);
+        self.channels.clear();
+        self.state = State::EnergyScan;
+        self.mac.scan_request(ScanType::EnergyDetect, request.scan_channels, request.scan_duration);
+        Ok(())
+    }
+
+    /// Handle the MLME-SCAN.confirm of the scans requested by this state
+    /// machine. The confirm of the procedure is returned when it fails.
+    pub fn handle_scan_confirm(&mut self, confirm: &ScanConfirm) -> Option<FormationConfirm> {
+        let request = self.request?;
+        match (self.state, confirm.scan_type) {
+            (State::EnergyScan, ScanType::EnergyDetect) => {
+                self.channels = confirm.energy_detect_list.iter()
+                    .filter(|&&(channel, energy)| energy <= request.energy_threshold
+                            && request.scan_channels & channel_bit(channel) != 0)
+                    .cloned()
+                    .collect();
+                if confirm.status != MacStatus::Success || self.channels.is_empty() {
+                    return Some(self.fail(NwkStatus::StartupFailure));
+                }
+                self.state = State::ActiveScan;
+                self.mac.scan_request(ScanType::Active, channel_mask(&self.channels),
+                                      request.scan_duration);
+                None
+            }
+            (State::ActiveScan, ScanType::Active) => {
+                // A failed active scan with no beacons just means that
+                // there are no networks around.
+                if confirm.status != MacStatus::Success && confirm.status != MacStatus::NoBeacon {
+                    return Some(self.fail(NwkStatus::StartupFailure));
+                }
+                let logical_channel = self.channels.iter()
+                    .min_by_key(|&&(channel, energy)| {
+                        let networks = confirm.pan_descriptors.iter()
+                            .filter(|d| d.logical_channel == channel)
+                            .count();
+                        (networks, energy)
+                    })
+                    .map(|&(channel, _)| channel)?;
+                let pan_ids_in_use: BTreeSet<u16> = confirm.pan_descriptors.iter()
+                    .map(|d| d.coord_pan_id)
+                    .collect();
+                let in_use = |pan_id: u16| pan_ids_in_use.contains(&pan_id);
+                let pan_id = match request.pan_id {
+                    Some(pan_id) if in_use(pan_id) => return Some(self.fail(NwkStatus::StartupFailure)),
+                    Some(pan_id) => pan_id,
+                    None => match choose_pan_id(&mut self.rng, in_use) {
+                        Some(pan_id) => pan_id,
+                        None => return Some(self.fail(NwkStatus::StartupFailure)),
+                    },
+                };
+                self.state = State::Starting { pan_id, logical_channel };
+                self.mac.set_short_address(0x0000);
+                self.mac.set_pan_id(pan_id);
+                self.mac.set_logical_channel(logical_channel);
+                self.mac.start_request(pan_id, logical_channel, true);
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// Handle the MLME-START.confirm and complete the procedure.
+    pub fn handle_start_confirm(&mut self, status: MacStatus) -> Option<FormationConfirm> {
+        let (pan_id, logical_channel) = match self.state {
+            State::Starting { pan_id, logical_channel } => (pan_id, logical_channel),
+            _ => return None,
+        };
+        if status != MacStatus::Success {
+            return Some(self.fail(NwkStatus::StartupFailure));
+        }
+        let request = self.request?;
+        self.state = State::Formed;
+        Some(FormationConfirm {
+            status: NwkStatus::Success,
+            pan_id,
+            extended_pan_id: if request.extended_pan_id == 0 {
+                self.mac.extended_address()
+            } else {
+                request.extended_pan_id
+            },
+            logical_channel,
+        })
+    }
+
+    fn fail(&mut self, status: NwkStatus) -> FormationConfirm {
+        self.state = State::Idle;
+        self.request = None;
+        FormationConfirm::failure(status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use mac::{MacAddress, PanDescriptor};
+    use mac::mock::{MockMac, Request};
+
+    const EXTENDED_ADDRESS: u64 = 0x0011_2233_4455_6677;
+
+    fn formation(mac: &MockMac) -> NetworkFormation<&MockMac, StdRng> {
+        NetworkFormation::new(mac, StdRng::seed_from_u64(0))
+    }
+
+    fn request(scan_channels: u32, pan_id: Option<u16>) -> FormationRequest {
+        FormationRequest { scan_channels, scan_duration: 3, pan_id, extended_pan_id: 0, energy_threshold: 100 }
+    }
+
+    fn energy_scan(energy_detect_list: Vec<(u8, u8)>) -> ScanConfirm {
+        ScanConfirm {
+            status: MacStatus::Success,
+            scan_type: ScanType::EnergyDetect,
+            unscanned_channels: 0,
+            energy_detect_list,
+            pan_descriptors: Vec::new(),
+        }
+    }
+
+    fn active_scan(pans: &[(u16, u8)]) -> ScanConfirm {
+        ScanConfirm {
+            status: if pans.is_empty() { MacStatus::NoBeacon } else { MacStatus::Success },
+            scan_type: ScanType::Active,
+            unscanned_channels: 0,
+            energy_detect_list: Vec::new(),
+            pan_descriptors: pans.iter().map(|&(coord_pan_id, logical_channel)| PanDescriptor {
+                coord_address: MacAddress::Short(0x0000),
+                coord_pan_id,
+                logical_channel,
+                pan_coordinator: true,
+                association_permit: false,
+                link_quality: 0xFF,
+                beacon_payload: Vec::new(),
+            }).collect(),
+        }
+    }
+
+    #[test]
+    fn forms_on_the_channel_with_fewest_networks() {
+        let mac = MockMac::new(EXTENDED_ADDRESS);
+        let mut formation = formation(&mac);
+        formation.request(request(1 << 11 | 1 << 15 | 1 << 20, None)).unwrap();
+        assert_eq!(mac.take_requests(), vec![Request::Scan {
+            scan_type: ScanType::EnergyDetect,
+            scan_channels: 1 << 11 | 1 << 15 | 1 << 20,
+            scan_duration: 3,
+        }]);
+
+        // Channel 15 is too noisy and channel 25 was not requested.
+        let confirm = energy_scan(vec![(11, 10), (15, 200), (20, 5), (25, 0)]);
+        assert_eq!(formation.handle_scan_confirm(&confirm), None);
+        assert_eq!(mac.take_requests(), vec![Request::Scan {
+            scan_type: ScanType::Active,
+            scan_channels: 1 << 11 | 1 << 20,
+            scan_duration: 3,
+        }]);
+
+        // Channel 20 is quieter, but another network uses it.
+        assert_eq!(formation.handle_scan_confirm(&active_scan(&[(0x1234, 20)])), None);
+        let requests = mac.take_requests();
+        let pan_id = match requests[1] {
+            Request::SetPanId(pan_id) => pan_id,
+            ref request => panic!("unexpected request {:?}", request),
+        };
+        assert!(pan_id != 0x1234 && (0x0001..=MAX_PAN_ID).contains(&pan_id));
+        assert_eq!(requests, vec![
+            Request::SetShortAddress(0x0000),
+            Request::SetPanId(pan_id),
+            Request::SetLogicalChannel(11),
+            Request::Start { pan_id, logical_channel: 11, pan_coordinator: true },
+        ]);
+        assert!(!formation.is_formed());
+
+        assert_eq!(formation.handle_start_confirm(MacStatus::Success), Some(FormationConfirm {
+            status: NwkStatus::Success,
+            pan_id,
+            extended_pan_id: EXTENDED_ADDRESS,
+            logical_channel: 11,
+        }));
+        assert!(formation.is_formed());
+    }
+
+    #[test]
+    fn requested_pan_id() {
+        let mac = MockMac::new(EXTENDED_ADDRESS);
+        let mut formation = formation(&mac);
+        let request = FormationRequest { extended_pan_id: 0xDEAD, ..request(1 << 11, Some(0x0042)) };
+        formation.request(request).unwrap();
+        formation.handle_scan_confirm(&energy_scan(vec![(11, 0)]));
+        formation.handle_scan_confirm(&active_scan(&[]));
+        assert_eq!(mac.take_requests().last(),
+                   Some(&Request::Start { pan_id: 0x0042, logical_channel: 11, pan_coordinator: true }));
+        let confirm = formation.handle_start_confirm(MacStatus::Success).unwrap();
+        assert_eq!((confirm.pan_id, confirm.extended_pan_id), (0x0042, 0xDEAD));
+
+        // The same PAN identifier is now in use.
+        formation.request(request).unwrap();
+        formation.handle_scan_confirm(&energy_scan(vec![(11, 0)]));
+        assert_eq!(formation.handle_scan_confirm(&active_scan(&[(0x0042, 11)])),
+                   Some(FormationConfirm::failure(NwkStatus::StartupFailure)));
+        assert!(!formation.is_formed());
+    }
+
+    #[test]
+    fn pan_id_choice_ends() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..100 {
+            let pan_id = choose_pan_id(&mut rng, |pan_id| pan_id != 0x0001 && pan_id != MAX_PAN_ID).unwrap();
+            assert!(pan_id == 0x0001 || pan_id == MAX_PAN_ID);
+        }
+        assert_eq!(choose_pan_id(&mut rng, |_| true), None);
+    }
+
+    #[test]
+    fn every_pan_id_in_use() {
+        let mac = MockMac::new(EXTENDED_ADDRESS);
+        let mut formation = formation(&mac);
+        formation.request(request(1 << 11, None)).unwrap();
+        formation.handle_scan_confirm(&energy_scan(vec![(11, 0)]));
+        let pans: Vec<(u16, u8)> = (0x0001..=MAX_PAN_ID).map(|pan_id| (pan_id, 11)).collect();
+        assert_eq!(formation.handle_scan_confirm(&active_scan(&pans)),
+                   Some(FormationConfirm::failure(NwkStatus::StartupFailure)));
+        assert!(!formation.is_formed());
+    }
+
+    #[test]
+    fn failures() {
+        let mac = MockMac::new(EXTENDED_ADDRESS);
+        let mut formation = formation(&mac);
+        assert_eq!(formation.request(request(0, None)), Err(NwkStatus::InvalidParameter));
+        formation.request(request(1 << 11, None)).unwrap();
+        assert_eq!(formation.request(request(1 << 11, None)), Err(NwkStatus::InvalidRequest));
+        assert_eq!(formation.handle_scan_confirm(&energy_scan(vec![(11, 150)])),
+                   Some(FormationConfirm::failure(NwkStatus::StartupFailure)));
+
+        formation.request(request(1 << 11, None)).unwrap();
+        formation.handle_scan_confirm(&energy_scan(vec![(11, 0)]));
+        formation.handle_scan_confirm(&active_scan(&[]));
+        assert_eq!(formation.handle_start_confirm(MacStatus::ChannelAccessFailure),
+                   Some(FormationConfirm::failure(NwkStatus::StartupFailure)));
+        assert_eq!(formation.handle_start_confirm(MacStatus::Success), None);
+        assert!(!formation.is_formed());
+    }
+
+    #[test]
+    fn channels_out_of_the_mask_range_are_ignored() {
+        let mac = MockMac::new(EXTENDED_ADDRESS);
+        let mut formation = formation(&mac);
+        formation.request(request(u32::MAX, None)).unwrap();
+        mac.take_requests();
+        assert_eq!(formation.handle_scan_confirm(&energy_scan(vec![(40, 0), (32, 0), (26, 0)])), None);
+        assert_eq!(mac.take_requests(), vec![Request::Scan {
+            scan_type: ScanType::Active,
+            scan_channels: 1 << 26,
+            scan_duration: 3,
+        }]);
+        assert_eq!(channel_mask(&[(31, 0), (255, 0)]), 1 << 31);
+    }
+}
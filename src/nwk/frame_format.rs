@@ -0,0 +1,423 @@
+//! The general NWK frame format, as described in section 3.3.1 of the
+//! specification.
+
+use std::borrow::Cow;
+use codec::{DecodeError, Reader, write_u16, write_u64};
+
+pub const PROTOCOL_VERSION: u8 = 0x02;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    Data = 0,
+    Command = 1,
+    InterPan = 3,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MulticastMode {
+    NonMember = 0,
+    Member = 1,
+}
+
+/// The multicast control field, present only when the multicast flag of
+/// the frame control field is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MulticastControl {
+    pub mode: MulticastMode,
+    pub nonmember_radius: u8,
+    pub max_nonmember_radius: u8,
+}
+
+impl MulticastControl {
+    fn to_u8(self) -> u8 {
+        self.mode as u8
+            | (self.nonmember_radius & 0x07) << 2
+            | (self.max_nonmember_radius & 0x07) << 5
+    }
+
+    fn from_u8(value: u8) -> Result<Self, DecodeError> {
+        let mode = match value & 0x03 {
+            0 => MulticastMode::NonMember,
+            1 => MulticastMode::Member,
+            _ => return Err(DecodeError::InvalidValue("multicast mode")),
+        };
+        Ok(MulticastControl {
+            mode,
+            nonmember_radius: (value >> 2) & 0x07,
+            max_nonmember_radius: value >> 5,
+        })
+    }
+}
+
+/// The source route subframe, present only when the source route flag of
+/// the frame control field is set.
+///
+/// The relay closest to the destination is the first of the list, and the
+/// relay index points to the next relay that will receive the frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRouteSubframe {
+    pub relay_index: u8,
+    pub relay_list: Vec<u16>,
+}
+
+impl SourceRouteSubframe {
+    /// Build the subframe for a frame that is about to leave its
+    /// originator. The first relay to receive it is the last of the list.
+    pub fn new(relay_list: Vec<u16>) -> Self {
+        SourceRouteSubframe {
+            relay_index: relay_list.len().saturating_sub(1) as u8,
+            relay_list,
+        }
+    }
+
+    /// The relay that is expected to receive the frame next.
+    pub fn current_relay(&self) -> Option<u16> {
+        self.relay_list.get(self.relay_index as usize).cloned()
+    }
+
+    /// Called by a relay that received the frame. Update the relay index
+    /// and return the next hop, that is either the following relay or the
+    /// final destination. Return `None` if this device is not the expected
+    /// relay.
+    pub fn advance(&mut self, own_address: u16, destination: u16) -> Option<u16> {
+        if self.current_relay() != Some(own_address) {
+            return None;
+        }
+        if self.relay_index == 0 {
+            Some(destination)
+        } else {
+            self.relay_index -= 1;
+            self.current_relay()
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NwkHeader {
+    pub frame_type: FrameType,
+    pub protocol_version: u8,
+    pub discover_route: bool,
+    pub security: bool,
+    pub end_device_initiator: bool,
+    pub destination_address: u16,
+    pub source_address: u16,
+    pub radius: u8,
+    pub sequence_number: u8,
+    pub destination_ieee_address: Option<u64>,
+    pub source_ieee_address: Option<u64>,
+    pub multicast_control: Option<MulticastControl>,
+    pub source_route: Option<SourceRouteSubframe>,
+}
+
+impl NwkHeader {
+    pub fn new(frame_type: FrameType, destination_address: u16, source_address: u16,
+               radius: u8, sequence_number: u8) -> Self {
+        NwkHeader {
+            frame_type,
+            protocol_version: PROTOCOL_VERSION,
+            discover_route: false,
+            security: false,
+            end_device_initiator: false,
+            destination_address,
+            source_address,
+            radius,
+            sequence_number,
+            destination_ieee_address: None,
+            source_ieee_address: None,
+            multicast_control: None,
+            source_route: None,
+        }
+    }
+
+    fn frame_control(&self) -> u16 {
+        let mut fc = self.frame_type as u16 | u16::from(self.protocol_version & 0x0F) << 2;
+        if self.discover_route { fc |= 1 << 6; }
+        if self.multicast_control.is_some() { fc |= 1 << 8; }
+        if self.security { fc |= 1 << 9; }
+        if self.source_route.is_some() { fc |= 1 << 10; }
+        if self.destination_ieee_address.is_some() { fc |= 1 << 11; }
+        if self.source_ieee_address.is_some() { fc |= 1 << 12; }
+        if self.end_device_initiator { fc |= 1 << 13; }
+        fc
+    }
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.frame_control());
+        write_u16(out, self.destination_address);
+        write_u16(out, self.source_address);
+        out.push(self.radius);
+        out.push(self.sequence_number);
+        if let Some(address) = self.destination_ieee_address {
+            write_u64(out, address);
+        }
+        if let Some(address) = self.source_ieee_address {
+            write_u64(out, address);
+        }
+        if let Some(ref control) = self.multicast_control {
+            out.push(control.to_u8());
+        }
+        if let Some(ref route) = self.source_route {
+            out.push(route.relay_list.len() as u8);
+            out.push(route.relay_index);
+            for relay in &route.relay_list {
+                write_u16(out, *relay);
+            }
+        }
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let fc = reader.read_u16()?;
+        let frame_type = match fc & 0x03 {
+            0 => FrameType::Data,
+            1 => FrameType::Command,
+            3 => FrameType::InterPan,
+            _ => return Err(DecodeError::InvalidValue("frame type")),
+        };
+        let flag = |bit: u16| fc & (1 << bit) != 0;
+        let destination_address = reader.read_u16()?;
+        let source_address = reader.read_u16()?;
+        let radius = reader.read_u8()?;
+        let sequence_number = reader.read_u8()?;
+        let destination_ieee_address = if flag(11) { Some(reader.read_u64()?) } else { None };
+        let source_ieee_address = if flag(12) { Some(reader.read_u64()?) } else { None };
+        let multicast_control = if flag(8) {
+            Some(MulticastControl::from_u8(reader.read_u8()?)?)
+        } else {
+            None
+        };
+        let source_route = if flag(10) {
+            let relay_count = reader.read_u8()?;
+            let relay_index = reader.read_u8()?;
+            let mut relay_list = Vec::with_capacity(relay_count as usize);
+            for _ in 0..relay_count {
+                relay_list.push(reader.read_u16()?);
+            }
+            Some(SourceRouteSubframe { relay_index, relay_list })
+        } else {
+            None
+        };
+        Ok(NwkHeader {
+            frame_type,
+            protocol_version: ((fc >> 2) & 0x0F) as u8,
+            discover_route: fc & (0x03 << 6) != 0,
+            security: flag(9),
+            end_device_initiator: flag(13),
+            destination_address,
+            source_address,
+            radius,
+            sequence_number,
+            destination_ieee_address,
+            source_ieee_address,
+            multicast_control,
+            source_route,
+        })
+    }
+}
+
+/// A NWK protocol data unit: the header followed by the payload, that for
+/// command frames starts with the command identifier.
+pub struct Npdu<'a> {
+    pub header: NwkHeader,
+    pub payload: Cow<'a, [u8]>,
+}
+
+impl<'a> Npdu<'a> {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        self.header.encode(out);
+        out.extend_from_slice(&self.payload);
+    }
+
+    pub fn decode(data: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader::new(data);
+        let header = NwkHeader::decode(&mut reader)?;
+        Ok(Npdu { header, payload: Cow::Borrowed(reader.read_to_end()) })
+    }
+}
+
+/// The NWK beacon payload, sent by coordinators and routers in the beacons
+/// and used by joining devices to choose a network and a parent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeaconPayload {
+    pub stack_profile: u8,
+    pub protocol_version: u8,
+    pub router_capacity: bool,
+    pub device_depth: u8,
+    pub end_device_capacity: bool,
+    pub extended_pan_id: u64,
+    pub tx_offset: u32,
+    pub nwk_update_id: u8,
+}
+
+impl BeaconPayload {
+    /// The protocol identifier of Zigbee beacons.
+    pub const PROTOCOL_ID: u8 = 0x00;
+
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(Self::PROTOCOL_ID);
+        out.push((self.stack_profile & 0x0F) | (self.protocol_version & 0x0F) << 4);
+        out.push((self.router_capacity as u8) << 2
+                 | (self.device_depth & 0x0F) << 3
+                 | (self.end_device_capacity as u8) << 7);
+        write_u64(out, self.extended_pan_id);
+        out.extend_from_slice(&[self.tx_offset as u8, (self.tx_offset >> 8) as u8,
+                                (self.tx_offset >> 16) as u8]);
+        out.push(self.nwk_update_id);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        if reader.read_u8()? != Self::PROTOCOL_ID {
+            return Err(DecodeError::InvalidValue("protocol id"));
+        }
+        let profile = reader.read_u8()?;
+        let capacity = reader.read_u8()?;
+        let extended_pan_id = reader.read_u64()?;
+        let offset = reader.read_bytes(3)?;
+        let nwk_update_id = reader.read_u8()?;
+        Ok(BeaconPayload {
+            stack_profile: profile & 0x0F,
+            protocol_version: profile >> 4,
+            router_capacity: capacity & 0x04 != 0,
+            device_depth: (capacity >> 3) & 0x0F,
+            end_device_capacity: capacity & 0x80 != 0,
+            extended_pan_id,
+            tx_offset: u32::from(offset[0]) | u32::from(offset[1]) << 8 | u32::from(offset[2]) << 16,
+            nwk_update_id,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DESTINATION_IEEE: u64 = 0x0011_2233_4455_6677;
+    const SOURCE_IEEE: u64 = 0x8899_AABB_CCDD_EEFF;
+
+    /// A header with every optional field.
+    fn full_header() -> NwkHeader {
+        NwkHeader {
+            discover_route: true,
+            security: true,
+            end_device_initiator: true,
+            destination_ieee_address: Some(DESTINATION_IEEE),
+            source_ieee_address: Some(SOURCE_IEEE),
+            multicast_control: Some(MulticastControl {
+                mode: MulticastMode::Member,
+                nonmember_radius: 2,
+                max_nonmember_radius: 3,
+            }),
+            source_route: Some(SourceRouteSubframe { relay_index: 1, relay_list: vec![0x0002, 0x0003] }),
+            ..NwkHeader::new(FrameType::Command, 0x0001, 0x0000, 5, 9)
+        }
+    }
+
+    /// The frame with the header of `full_header`, 31 bytes long.
+    const FULL_FRAME: [u8; 35] = [
+        0x49, 0x3F, 0x01, 0x00, 0x00, 0x00, 0x05, 0x09,
+        0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00,
+        0xFF, 0xEE, 0xDD, 0xCC, 0xBB, 0xAA, 0x99, 0x88,
+        0x69,
+        0x02, 0x01, 0x02, 0x00, 0x03, 0x00,
+        // The payload.
+        0x04, 0x00, 0x00, 0x00,
+    ];
+
+    fn round_trip(header: &NwkHeader, bytes: &[u8]) {
+        let mut out = Vec::new();
+        header.encode(&mut out);
+        assert_eq!(out, bytes);
+        let mut reader = Reader::new(bytes);
+        assert_eq!(&NwkHeader::decode(&mut reader).unwrap(), header);
+        assert!(reader.is_empty());
+    }
+
+    #[test]
+    fn minimal_header() {
+        let header = NwkHeader::new(FrameType::Data, 0xFFFD, 0x1234, 30, 4);
+        round_trip(&header, &[0x08, 0x00, 0xFD, 0xFF, 0x34, 0x12, 0x1E, 0x04]);
+        let header = NwkHeader::new(FrameType::InterPan, 0x0000, 0x0001, 1, 0xFF);
+        round_trip(&header, &[0x0B, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01, 0xFF]);
+    }
+
+    #[test]
+    fn optional_fields() {
+        round_trip(&full_header(), &FULL_FRAME[..31]);
+
+        let mut header = NwkHeader::new(FrameType::Data, 0x0001, 0x0002, 5, 9);
+        header.destination_ieee_address = Some(DESTINATION_IEEE);
+        round_trip(&header, &[&[0x08, 0x08, 0x01, 0x00, 0x02, 0x00, 0x05, 0x09][..], &FULL_FRAME[8..16]].concat());
+        let mut header = NwkHeader::new(FrameType::Data, 0x0001, 0x0002, 5, 9);
+        header.source_ieee_address = Some(SOURCE_IEEE);
+        round_trip(&header, &[&[0x08, 0x10, 0x01, 0x00, 0x02, 0x00, 0x05, 0x09][..], &FULL_FRAME[16..24]].concat());
+        let mut header = NwkHeader::new(FrameType::Data, 0x0001, 0x0002, 5, 9);
+        header.multicast_control = full_header().multicast_control;
+        round_trip(&header, &[0x08, 0x01, 0x01, 0x00, 0x02, 0x00, 0x05, 0x09, 0x69]);
+        let mut header = NwkHeader::new(FrameType::Data, 0x0001, 0x0002, 5, 9);
+        header.source_route = Some(SourceRouteSubframe::new(vec![]));
+        round_trip(&header, &[0x08, 0x04, 0x01, 0x00, 0x02, 0x00, 0x05, 0x09, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn npdu_round_trip() {
+        let npdu = Npdu::decode(&FULL_FRAME).unwrap();
+        assert_eq!(npdu.header, full_header());
+        assert_eq!(&npdu.payload[..], &[0x04, 0x00, 0x00, 0x00]);
+        let mut out = Vec::new();
+        npdu.encode(&mut out);
+        assert_eq!(&out[..], &FULL_FRAME[..]);
+    }
+
+    #[test]
+    fn truncated_headers() {
+        // Every field of the header is needed, the optional ones included.
+        for len in 0..31 {
+            assert_eq!(NwkHeader::decode(&mut Reader::new(&FULL_FRAME[..len])), Err(DecodeError::UnexpectedEnd),
+                       "{} bytes", len);
+        }
+        assert!(Npdu::decode(&FULL_FRAME[..30]).is_err());
+    }
+
+    #[test]
+    fn invalid_headers() {
+        let mut bytes = FULL_FRAME;
+        bytes[0] = 0x4A;
+        assert_eq!(NwkHeader::decode(&mut Reader::new(&bytes)), Err(DecodeError::InvalidValue("frame type")));
+        let mut bytes = FULL_FRAME;
+        bytes[24] = 0x6A;
+        assert_eq!(NwkHeader::decode(&mut Reader::new(&bytes)), Err(DecodeError::InvalidValue("multicast mode")));
+    }
+
+    #[test]
+    fn multicast_control() {
+        for value in 0..=u8::MAX {
+            match MulticastControl::from_u8(value) {
+                Ok(control) => {
+                    assert!(value & 0x02 == 0);
+                    assert_eq!(control.to_u8(), value);
+                }
+                Err(error) => {
+                    assert!(value & 0x02 != 0);
+                    assert_eq!(error, DecodeError::InvalidValue("multicast mode"));
+                }
+            }
+        }
+        let control = MulticastControl { mode: MulticastMode::NonMember, nonmember_radius: 7, max_nonmember_radius: 7 };
+        assert_eq!(control.to_u8(), 0xFC);
+        assert_eq!(MulticastControl::from_u8(0xFC), Ok(control));
+    }
+
+    #[test]
+    fn source_route_subframe() {
+        let mut route = SourceRouteSubframe::new(vec![0x0003, 0x0002, 0x0001]);
+        assert_eq!((route.relay_index, route.current_relay()), (2, Some(0x0001)));
+        assert_eq!(route.advance(0x0002, 0x1234), None);
+        assert_eq!(route.advance(0x0001, 0x1234), Some(0x0002));
+        assert_eq!(route.advance(0x0002, 0x1234), Some(0x0003));
+        assert_eq!(route.advance(0x0003, 0x1234), Some(0x1234));
+        assert_eq!(route.relay_index, 0);
+
+        let mut empty = SourceRouteSubframe::new(vec![]);
+        assert_eq!((empty.relay_index, empty.current_relay()), (0, None));
+        assert_eq!(empty.advance(0x0001, 0x1234), None);
+    }
+}
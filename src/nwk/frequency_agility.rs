@@ -0,0 +1,700 @@
+//! Frequency agility: the network manager moves the whole network to a
+//! quieter channel when interference prevents communication. See annex E
+//! of the specification.
+//!
+//! Every device counts its transmission attempts and failures. When too
+//! many transmissions fail, it scans the channels and reports the energy
+//! measured to the network manager with a Mgmt_NWK_Update_notify. The
+//! network manager scans too, chooses the best channel and broadcasts a
+//! Mgmt_NWK_Update_req with a new update identifier; all the devices switch
+//! channel after the broadcast delivery time. The requests whose update
+//! identifier is not newer than the one of the device are stale, and
+//! ignored.
+//!
+//! The network manager keeps the channel attributes of the APS information
+//! base: the energy and the failure rate of the channel it left, and the
+//! time left before the next change is permitted.
+
+use std::borrow::Cow;
+use std::time::Duration;
+use apl::aps::{ApsStatus, ApsmeSap};
+use apl::device_profile::{MgmtNwkUpdateNotify, MgmtNwkUpdateReq, Status};
+use mac::{MacStatus, MlmeSap, ScanConfirm, ScanType};
+use nwk::pan_id_conflict::is_newer_update_id;
+
+/// The scan duration of a Mgmt_NWK_Update_req asking to change channel.
+pub const CHANGE_CHANNEL: u8 = 0xFE;
+/// The scan duration of a Mgmt_NWK_Update_req updating the channel mask
+/// and the network manager address.
+pub const UPDATE_MANAGER: u8 = 0xFF;
+/// The highest scan duration of a Mgmt_NWK_Update_req asking for an energy
+/// scan.
+pub const MAX_SCAN_DURATION: u8 = 0x05;
+/// The channels of the 2.4 GHz band.
+pub const ALL_CHANNELS: u32 = 0x07FF_F800;
+
+/// The channels set in a channel mask, in ascending order.
+pub fn channels(mask: u32) -> impl Iterator<Item = u8> {
+    (11..27).filter(move |channel| mask & 1 << channel != 0)
+}
+
+pub struct FrequencyAgilityConfig {
+    /// The number of transmissions needed before the failure rate is
+    /// considered meaningful.
+    pub min_transmissions: u16,
+    /// The failure rate above which the device reports interference.
+    pub failure_threshold: f32,
+    /// The energy above which a channel is considered too noisy.
+    pub energy_threshold: u8,
+    /// The minimum time between two reports of a device.
+    pub report_interval: Duration,
+    /// The minimum time between two channel changes
+    /// (apsChannelTimer).
+    pub channel_change_interval: Duration,
+    /// The time to wait before switching channel after the update request
+    /// (nwkNetworkBroadcastDeliveryTime).
+    pub switch_delay: Duration,
+    /// The duration of the energy detection scans.
+    pub scan_duration: u8,
+    /// The channels the network can use (apsChannelMask).
+    pub channel_mask: u32,
+}
+
+impl Default for FrequencyAgilityConfig {
+    fn default() -> Self {
+        FrequencyAgilityConfig {
+            min_transmissions: 20,
+            failure_threshold: 0.25,
+            energy_threshold: 0x80,
+            report_interval: Duration::from_secs(15 * 60),
+            channel_change_interval: Duration::from_secs(60 * 60),
+            switch_delay: Duration::from_secs(9),
+            scan_duration: 3,
+            channel_mask: ALL_CHANNELS,
+        }
+    }
+}
+
+/// The transmission counters of a device (nwkTxTotal and the failures).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TxCounters {
+    pub total: u16,
+    pub failures: u16,
+}
+
+impl TxCounters {
+    /// Count the outcome of a transmission, as reported by the
+    /// MCPS-DATA.confirm.
+    pub fn record(&mut self, status: MacStatus) {
+        self.total = self.total.saturating_add(1);
+        if status != MacStatus::Success {
+            self.failures = self.failures.saturating_add(1);
+        }
+    }
+
+    pub fn failure_rate(&self) -> f32 {
+        if self.total == 0 {
+            0.0
+        } else {
+            f32::from(self.failures) / f32::from(self.total)
+        }
+    }
+
+    pub fn reset(&mut self) {
+        *self = TxCounters::default();
+    }
+}
+
+/// The outcome of the events handled by a `ChannelMonitor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChannelEvent {
+    /// A Mgmt_NWK_Update_notify must be sent to the network manager.
+    Notify { destination: u16, notify: MgmtNwkUpdateNotify<'static> },
+    /// The device moved to a new channel.
+    ChannelChanged { logical_channel: u8, nwk_update_id: u8 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ScanReason {
+    Interference,
+    Request { remaining: u8 },
+}
+
+/// The device side of frequency agility, run by every device: reports the
+/// interference and applies the channel changes. Generic over the MAC it
+/// drives.
+pub struct ChannelMonitor<M> {
+    mac: M,
+    config: FrequencyAgilityConfig,
+    counters: TxCounters,
+    nwk_update_id: u8,
+    nwk_manager_address: u16,
+    last_report: Option<Duration>,
+    scan: Option<(ScanReason, u32, u8)>,
+    energies: Vec<(u8, u8)>,
+    pending_switch: Option<(u8, u8, Duration)>,
+}
+
+impl<M: MlmeSap> ChannelMonitor<M> {
+    pub fn new(mac: M, config: FrequencyAgilityConfig, nwk_update_id: u8) -> Self {
+        ChannelMonitor {
+            mac,
+            config,
+            counters: TxCounters::default(),
+            nwk_update_id,
+            nwk_manager_address: 0x0000,
+            last_report: None,
+            scan: None,
+            energies: Vec::new(),
+            pending_switch: None,
+        }
+    }
+
+    pub fn mac(&self) -> &M {
+        &self.mac
+    }
+
+    pub fn counters(&self) -> TxCounters {
+        self.counters
+    }
+
+    pub fn nwk_update_id(&self) -> u8 {
+        self.nwk_update_id
+    }
+
+    pub fn nwk_manager_address(&self) -> u16 {
+        self.nwk_manager_address
+    }
+
+    /// Count the outcome of a transmission, and start reporting
+    /// interference if too many failed.
+    pub fn record_transmission(&mut self, status: MacStatus, now: Duration) {
+        self.counters.record(status);
+        if self.scan.is_some() || self.counters.total < self.config.min_transmissions
+            || self.counters.failure_rate() <= self.config.failure_threshold {
+            return;
+        }
+        if let Some(last) = self.last_report {
+            if now < last + self.config.report_interval {
+                return;
+            }
+        }
+        self.last_report = Some(now);
+        self.start_scan(ScanReason::Interference, self.config.channel_mask, self.config.scan_duration);
+    }
+
+    fn start_scan(&mut self, reason: ScanReason, scan_channels: u32, scan_duration: u8) {
+        self.scan = Some((reason, scan_channels, scan_duration));
+        self.energies.clear();
+        self.mac.scan_request(ScanType::EnergyDetect, scan_channels, scan_duration);
+    }
+
+    fn notify(&mut self, scanned_channels: u32) -> ChannelEvent {
+        let energy_values: Vec<u8> = self.energies.iter().map(|&(_, energy)| energy).collect();
+        let notify = MgmtNwkUpdateNotify {
+            status: Status::Success,
+            scanned_channels,
+            total_transmissions: self.counters.total,
+            transmission_failures: self.counters.failures,
+            scanned_channels_list_count: energy_values.len() as u8,
+            energy_values: Cow::Owned(energy_values),
+        };
+        self.counters.reset();
+        ChannelEvent::Notify { destination: self.nwk_manager_address, notify }
+    }
+
+    /// Handle the MLME-SCAN.confirm of the energy scans requested by this
+    /// component.
+    pub fn handle_scan_confirm(&mut self, confirm: &ScanConfirm) -> Option<ChannelEvent> {
+        if confirm.scan_type != ScanType::EnergyDetect {
+            return None;
+        }
+        let (reason, scan_channels, scan_duration) = self.scan.take()?;
+        // With repeated scans, the worst measure of each channel is kept.
+        for &(channel, energy) in &confirm.energy_detect_list {
+            match self.energies.iter_mut().find(|&&mut (c, _)| c == channel) {
+                Some(entry) => entry.1 = entry.1.max(energy),
+                None => self.energies.push((channel, energy)),
+            }
+        }
+        match reason {
+            ScanReason::Request { remaining } if remaining > 1 && confirm.status == MacStatus::Success => {
+                self.scan = Some((ScanReason::Request { remaining: remaining - 1 }, scan_channels, scan_duration));
+                self.mac.scan_request(ScanType::EnergyDetect, scan_channels, scan_duration);
+                None
+            }
+            _ => {
+                self.energies.sort_by_key(|&(channel, _)| channel);
+                let scanned = scan_channels & !confirm.unscanned_channels;
+                Some(self.notify(scanned))
+            }
+        }
+    }
+
+    /// Handle a Mgmt_NWK_Update_req received from `source`, either
+    /// directly or broadcast by the network manager. The channel changes
+    /// and the updates of the network manager are ignored if their update
+    /// identifier is not newer than the current one.
+    pub fn handle_update_request(&mut self, source: u16, request: &MgmtNwkUpdateReq, now: Duration)
+                                 -> Result<(), Status> {
+        // A switch that is pending already counts as received.
+        let current = self.pending_switch.map_or(self.nwk_update_id, |(_, nwk_update_id, _)| nwk_update_id);
+        let stale = request.nwk_update_id.is_some_and(|nwk_update_id| !is_newer_update_id(nwk_update_id, current));
+        match request.scan_duration {
+            CHANGE_CHANNEL | UPDATE_MANAGER if stale => Ok(()),
+            CHANGE_CHANNEL => {
+                let mut requested = channels(request.scan_channels);
+                let channel = match (requested.next(), requested.next()) {
+                    (Some(channel), None) => channel,
+                    _ => return Err(Status::InvRequestType),
+                };
+                let nwk_update_id = request.nwk_update_id.unwrap_or_else(|| current.wrapping_add(1));
+                self.nwk_manager_address = source;
+                self.pending_switch = Some((channel, nwk_update_id, now + self.config.switch_delay));
+                Ok(())
+            }
+            UPDATE_MANAGER => {
+                self.config.channel_mask = request.scan_channels;
+                if let Some(nwk_update_id) = request.nwk_update_id {
+                    self.nwk_update_id = nwk_update_id;
+                }
+                if let Some(address) = request.nwk_manager_addr {
+                    self.nwk_manager_address = address.0;
+                }
+                Ok(())
+            }
+            duration if duration <= MAX_SCAN_DURATION => {
+                if self.scan.is_some() {
+                    return Err(Status::NotPermitted);
+                }
+                let remaining = request.scan_count.unwrap_or(1).max(1);
+                self.nwk_manager_address = source;
+                self.start_scan(ScanReason::Request { remaining }, request.scan_channels, duration);
+                Ok(())
+            }
+            _ => Err(Status::InvRequestType),
+        }
+    }
+
+    /// Switch channel if an update request asked for it and the delay
+    /// elapsed.
+    pub fn tick(&mut self, now: Duration) -> Option<ChannelEvent> {
+        match self.pending_switch {
+            Some((logical_channel, nwk_update_id, at)) if now >= at => {
+                self.pending_switch = None;
+                self.nwk_update_id = nwk_update_id;
+                self.counters.reset();
+                self.mac.set_logical_channel(logical_channel);
+                Some(ChannelEvent::ChannelChanged { logical_channel, nwk_update_id })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The network manager side of frequency agility: decides when the network
+/// must change channel and to which one.
+///
+/// The update requests are broadcast to all the devices with rx on when
+/// idle (0xFFFD), the network manager included: its own `ChannelMonitor`
+/// must handle them too.
+pub struct NetworkManager<M> {
+    mac: M,
+    config: FrequencyAgilityConfig,
+    logical_channel: u8,
+    nwk_update_id: u8,
+    /// When the next channel change is permitted.
+    next_change: Option<Duration>,
+    /// The energies and the failure rate reported by the device that
+    /// triggered the scan of the network manager.
+    reported: Option<(Vec<(u8, u8)>, f32)>,
+    /// The energy and the failure rate of the channel left by the last
+    /// change.
+    last_channel: Option<(u8, f32)>,
+}
+
+impl<M: MlmeSap> NetworkManager<M> {
+    pub fn new(mac: M, config: FrequencyAgilityConfig, logical_channel: u8, nwk_update_id: u8) -> Self {
+        NetworkManager {
+            mac,
+            config,
+            logical_channel,
+            nwk_update_id,
+            next_change: None,
+            reported: None,
+            last_channel: None,
+        }
+    }
+
+    pub fn mac(&self) -> &M {
+        &self.mac
+    }
+
+    pub fn logical_channel(&self) -> u8 {
+        self.logical_channel
+    }
+
+    pub fn nwk_update_id(&self) -> u8 {
+        self.nwk_update_id
+    }
+
+    fn can_change(&self, now: Duration) -> bool {
+        self.next_change.is_none_or(|next_change| now >= next_change)
+    }
+
+    /// Restore the channel timer of the APS information base
+    /// (apsChannelTimer), the hours left before the next channel change
+    /// is permitted, for instance after a restart.
+    pub fn load_channel_timer<A: ApsmeSap + ?Sized>(&mut self, aps: &A, now: Duration) {
+        if let Some(hours) = aps.channel_timer() {
+            self.next_change = Some(now + Duration::from_secs_f32(hours.max(0.0) * 3600.0));
+        }
+    }
+
+    /// Store the channel attributes of the APS information base after a
+    /// channel change: the energy measured on the channel left
+    /// (apsLastChannelEnergy), the failure rate of the transmissions on
+    /// it, in percent (apsLastChannelFailureRate), and the channel timer.
+    pub fn store_channel_attributes<A: ApsmeSap + ?Sized>(&self, aps: &A, now: Duration)
+                                                         -> Result<(), ApsStatus> {
+        let ((energy, failure_rate), next_change) = match (self.last_channel, self.next_change) {
+            (Some(last_channel), Some(next_change)) => (last_channel, next_change),
+            _ => return Ok(()),
+        };
+        aps.set_last_channel_energy(energy)?;
+        aps.set_last_channel_failure_rate(failure_rate * 100.0)?;
+        let left = next_change.checked_sub(now).unwrap_or_default();
+        aps.set_channel_timer(left.as_secs_f32() / 3600.0)
+    }
+
+    /// Handle a Mgmt_NWK_Update_notify reporting interference. If a channel
+    /// change is allowed, the network manager scans the channels itself
+    /// before deciding.
+    pub fn handle_update_notify(&mut self, notify: &MgmtNwkUpdateNotify, now: Duration) {
+        if notify.status != Status::Success || self.reported.is_some() || !self.can_change(now) {
+            return;
+        }
+        let reported = channels(notify.scanned_channels).zip(notify.energy_values.iter().cloned()).collect();
+        let counters = TxCounters { total: notify.total_transmissions, failures: notify.transmission_failures };
+        self.reported = Some((reported, counters.failure_rate()));
+        self.mac.scan_request(ScanType::EnergyDetect, self.config.channel_mask, self.config.scan_duration);
+    }
+
+    /// Handle the MLME-SCAN.confirm of the scan requested by
+    /// `handle_update_notify`. If a better channel is found, the update
+    /// request to broadcast is returned.
+    pub fn handle_scan_confirm(&mut self, confirm: &ScanConfirm, now: Duration) -> Option<MgmtNwkUpdateReq> {
+        if confirm.scan_type != ScanType::EnergyDetect {
+            return None;
+        }
+        let (reported, failure_rate) = self.reported.take()?;
+        let energy = |channel: u8| {
+            let own = confirm.energy_detect_list.iter().find(|&&(c, _)| c == channel).map(|&(_, e)| e);
+            let remote = reported.iter().find(|&&(c, _)| c == channel).map(|&(_, e)| e);
+            match (own, remote) {
+                (Some(own), Some(remote)) => Some(own.max(remote)),
+                (Some(energy), None) | (None, Some(energy)) => Some(energy),
+                (None, None) => None,
+            }
+        };
+        let current = energy(self.logical_channel).unwrap_or(0);
+        let (channel, best) = channels(self.config.channel_mask)
+            .filter(|&channel| channel != self.logical_channel)
+            .filter_map(|channel| energy(channel).map(|e| (channel, e)))
+            .min_by_key(|&(_, e)| e)?;
+        // Moving the network is only worth it to a quiet channel that is
+        // better than the current one.
+        if best > self.config.energy_threshold || best >= current {
+            return None;
+        }
+        self.next_change = Some(now + self.config.channel_change_interval);
+        self.last_channel = Some((current, failure_rate));
+        self.logical_channel = channel;
+        self.nwk_update_id = self.nwk_update_id.wrapping_add(1);
+        Some(MgmtNwkUpdateReq {
+            scan_channels: 1 << channel,
+            scan_duration: CHANGE_CHANNEL,
+            scan_count: None,
+            nwk_update_id: Some(self.nwk_update_id),
+            nwk_manager_addr: None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+    use apl::aps::{AddGroupConfirm, BindConfirm, BindRequest, BindingTable, ChannelMask, GroupTable,
+                   PermissionsConfiguration, RemoveAllGroupsConfirm, RemoveGroupConfirm, UnbindConfirm};
+    use mac::mock::{MockMac, Request};
+    use types::{Endpoint, ExtendedPanId, GroupId, NwkAddress};
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    fn energy_scan(energy_detect_list: Vec<(u8, u8)>) -> ScanConfirm {
+        ScanConfirm {
+            status: MacStatus::Success,
+            scan_type: ScanType::EnergyDetect,
+            unscanned_channels: 0,
+            energy_detect_list,
+            pan_descriptors: Vec::new(),
+        }
+    }
+
+    fn scan(scan_channels: u32, scan_duration: u8) -> Request {
+        Request::Scan { scan_type: ScanType::EnergyDetect, scan_channels, scan_duration }
+    }
+
+    fn update_request(scan_channels: u32, scan_duration: u8) -> MgmtNwkUpdateReq {
+        MgmtNwkUpdateReq { scan_channels, scan_duration, scan_count: None, nwk_update_id: None, nwk_manager_addr: None }
+    }
+
+    #[test]
+    fn channel_masks() {
+        assert_eq!(channels(ALL_CHANNELS).collect::<Vec<_>>(), (11..=26).collect::<Vec<_>>());
+        assert_eq!(channels(1 << 0 | 1 << 15 | 1 << 31).collect::<Vec<_>>(), vec![15]);
+        assert_eq!(channels(0).count(), 0);
+    }
+
+    #[test]
+    fn interference_is_reported() {
+        let mac = MockMac::new(1);
+        let mut monitor = ChannelMonitor::new(&mac, FrequencyAgilityConfig::default(), 0);
+        for i in 0..20 {
+            let status = if i % 4 == 0 { MacStatus::NoAck } else { MacStatus::Success };
+            monitor.record_transmission(status, secs(0));
+        }
+        // A failure rate of 25% is tolerated.
+        assert_eq!(monitor.counters(), TxCounters { total: 20, failures: 5 });
+        assert!(mac.take_requests().is_empty());
+        monitor.record_transmission(MacStatus::ChannelAccessFailure, secs(0));
+        monitor.record_transmission(MacStatus::NoAck, secs(0));
+        assert_eq!(mac.take_requests(), vec![scan(ALL_CHANNELS, 3)]);
+
+        let event = monitor.handle_scan_confirm(&energy_scan(vec![(15, 200), (11, 10)])).unwrap();
+        let notify = MgmtNwkUpdateNotify {
+            status: Status::Success,
+            scanned_channels: ALL_CHANNELS,
+            total_transmissions: 22,
+            transmission_failures: 7,
+            scanned_channels_list_count: 2,
+            energy_values: Cow::Owned(vec![10, 200]),
+        };
+        assert_eq!(event, ChannelEvent::Notify { destination: 0x0000, notify });
+        assert_eq!(monitor.counters(), TxCounters::default());
+
+        // The reports are rate limited.
+        for _ in 0..30 {
+            monitor.record_transmission(MacStatus::NoAck, secs(60));
+        }
+        assert!(mac.take_requests().is_empty());
+        monitor.record_transmission(MacStatus::NoAck, secs(15 * 60));
+        assert_eq!(mac.take_requests(), vec![scan(ALL_CHANNELS, 3)]);
+    }
+
+    #[test]
+    fn requested_scans() {
+        let mac = MockMac::new(1);
+        let mut monitor = ChannelMonitor::new(&mac, FrequencyAgilityConfig::default(), 0);
+        let request = MgmtNwkUpdateReq { scan_count: Some(2), ..update_request(1 << 11 | 1 << 12, 2) };
+        assert_eq!(monitor.handle_update_request(0x1234, &request, secs(0)), Ok(()));
+        assert_eq!(monitor.handle_update_request(0x1234, &request, secs(0)), Err(Status::NotPermitted));
+        assert_eq!(monitor.handle_update_request(0x1234, &update_request(1 << 11, 6), secs(0)),
+                   Err(Status::InvRequestType));
+
+        assert_eq!(monitor.handle_scan_confirm(&energy_scan(vec![(11, 5), (12, 50)])), None);
+        // The worst measure of each channel is reported.
+        let confirm = ScanConfirm { unscanned_channels: 1 << 12, ..energy_scan(vec![(11, 30), (12, 20)]) };
+        match monitor.handle_scan_confirm(&confirm) {
+            Some(ChannelEvent::Notify { destination, notify }) => {
+                assert_eq!(destination, 0x1234);
+                assert_eq!(notify.scanned_channels, 1 << 11);
+                assert_eq!(&notify.energy_values[..], &[30, 50]);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(mac.take_requests(), vec![scan(1 << 11 | 1 << 12, 2), scan(1 << 11 | 1 << 12, 2)]);
+        assert_eq!(monitor.handle_scan_confirm(&energy_scan(vec![])), None);
+    }
+
+    #[test]
+    fn channel_change() {
+        let mac = MockMac::new(1);
+        let mut monitor = ChannelMonitor::new(&mac, FrequencyAgilityConfig::default(), 0);
+        assert_eq!(monitor.handle_update_request(0x0000, &update_request(1 << 20 | 1 << 21, CHANGE_CHANNEL), secs(0)),
+                   Err(Status::InvRequestType));
+        let request = MgmtNwkUpdateReq { nwk_update_id: Some(3), ..update_request(1 << 20, CHANGE_CHANNEL) };
+        assert_eq!(monitor.handle_update_request(0x0000, &request, secs(0)), Ok(()));
+        assert_eq!(monitor.tick(secs(8)), None);
+        assert_eq!(monitor.tick(secs(9)), Some(ChannelEvent::ChannelChanged { logical_channel: 20, nwk_update_id: 3 }));
+        assert_eq!(monitor.nwk_update_id(), 3);
+        assert_eq!(mac.take_requests(), vec![Request::SetLogicalChannel(20)]);
+        assert_eq!(monitor.tick(secs(20)), None);
+
+        let request = MgmtNwkUpdateReq {
+            nwk_update_id: Some(7),
+            nwk_manager_addr: Some(NwkAddress(0x1234)),
+            ..update_request(1 << 25, UPDATE_MANAGER)
+        };
+        assert_eq!(monitor.handle_update_request(0x0000, &request, secs(30)), Ok(()));
+        assert_eq!((monitor.nwk_update_id(), monitor.nwk_manager_address()), (7, 0x1234));
+    }
+
+    #[test]
+    fn stale_updates_are_ignored() {
+        let mac = MockMac::new(1);
+        let mut monitor = ChannelMonitor::new(&mac, FrequencyAgilityConfig::default(), 0xFE);
+        let change = |channel: u8, nwk_update_id: u8| MgmtNwkUpdateReq {
+            nwk_update_id: Some(nwk_update_id),
+            ..update_request(1 << channel, CHANGE_CHANNEL)
+        };
+        for &nwk_update_id in &[0xFE, 0xFD, 0x7E] {
+            assert_eq!(monitor.handle_update_request(0x0000, &change(20, nwk_update_id), secs(0)), Ok(()));
+            assert_eq!(monitor.tick(secs(100)), None);
+        }
+        // The identifier wraps around.
+        assert_eq!(monitor.handle_update_request(0x0000, &change(20, 0x00), secs(100)), Ok(()));
+        // The pending switch is not replaced by a copy of its request, nor
+        // by an older one.
+        assert_eq!(monitor.handle_update_request(0x0000, &change(25, 0x00), secs(101)), Ok(()));
+        assert_eq!(monitor.handle_update_request(0x0000, &change(25, 0xFF), secs(101)), Ok(()));
+        assert_eq!(monitor.tick(secs(109)),
+                   Some(ChannelEvent::ChannelChanged { logical_channel: 20, nwk_update_id: 0 }));
+        assert_eq!(mac.take_requests(), vec![Request::SetLogicalChannel(20)]);
+
+        let update = |nwk_update_id: u8, address: u16| MgmtNwkUpdateReq {
+            nwk_update_id: Some(nwk_update_id),
+            nwk_manager_addr: Some(NwkAddress(address)),
+            ..update_request(1 << 15, UPDATE_MANAGER)
+        };
+        assert_eq!(monitor.handle_update_request(0x0000, &update(0x00, 0x1234), secs(110)), Ok(()));
+        assert_eq!((monitor.nwk_update_id(), monitor.nwk_manager_address()), (0, 0x0000));
+        assert_eq!(monitor.handle_update_request(0x0000, &update(0x01, 0x1234), secs(110)), Ok(()));
+        assert_eq!((monitor.nwk_update_id(), monitor.nwk_manager_address()), (1, 0x1234));
+    }
+
+    fn report(energy_values: Vec<u8>) -> MgmtNwkUpdateNotify<'static> {
+        MgmtNwkUpdateNotify {
+            status: Status::Success,
+            scanned_channels: 1 << 11 | 1 << 12 | 1 << 13,
+            total_transmissions: 30,
+            transmission_failures: 20,
+            scanned_channels_list_count: energy_values.len() as u8,
+            energy_values: Cow::Owned(energy_values),
+        }
+    }
+
+    #[test]
+    fn network_manager_moves_to_the_quietest_channel() {
+        let mac = MockMac::new(1);
+        let mut manager = NetworkManager::new(&mac, FrequencyAgilityConfig::default(), 11, 0);
+        manager.handle_update_notify(&report(vec![200, 10, 100]), secs(0));
+        assert_eq!(mac.take_requests(), vec![scan(ALL_CHANNELS, 3)]);
+        // The worst of the two measures counts.
+        let confirm = energy_scan(vec![(11, 220), (12, 90), (13, 20), (14, 255)]);
+        assert_eq!(manager.handle_scan_confirm(&confirm, secs(10)), Some(MgmtNwkUpdateReq {
+            nwk_update_id: Some(1),
+            ..update_request(1 << 12, CHANGE_CHANNEL)
+        }));
+        assert_eq!((manager.logical_channel(), manager.nwk_update_id()), (12, 1));
+
+        // The network does not move again before the channel timer.
+        manager.handle_update_notify(&report(vec![10, 200, 100]), secs(60));
+        assert!(mac.take_requests().is_empty());
+        manager.handle_update_notify(&report(vec![10, 200, 100]), secs(10 + 60 * 60));
+        assert_eq!(mac.take_requests(), vec![scan(ALL_CHANNELS, 3)]);
+    }
+
+    #[test]
+    fn network_manager_stays_without_a_better_channel() {
+        let mac = MockMac::new(1);
+        let mut manager = NetworkManager::new(&mac, FrequencyAgilityConfig::default(), 11, 0);
+        assert_eq!(manager.handle_scan_confirm(&energy_scan(vec![(12, 0)]), secs(0)), None);
+
+        manager.handle_update_notify(&report(vec![150, 160, 170]), secs(0));
+        assert_eq!(manager.handle_scan_confirm(&energy_scan(vec![]), secs(0)), None);
+        manager.handle_update_notify(&report(vec![50, 100, 10]), secs(0));
+        assert_eq!(manager.handle_scan_confirm(&energy_scan(vec![(13, 60)]), secs(0)), None);
+        assert_eq!(manager.logical_channel(), 11);
+
+        let failure = MgmtNwkUpdateNotify { status: Status::NotSupported, ..report(vec![200, 10, 10]) };
+        mac.take_requests();
+        manager.handle_update_notify(&failure, secs(0));
+        assert!(mac.take_requests().is_empty());
+    }
+
+    /// The channel attributes of an APS information base.
+    #[derive(Default)]
+    struct Aib {
+        last_channel_energy: Cell<Option<u8>>,
+        last_channel_failure_rate: Cell<Option<f32>>,
+        channel_timer: Cell<Option<f32>>,
+    }
+
+    impl ApsmeSap for Aib {
+        fn bind_request(&self, _: BindRequest) -> BindConfirm { unimplemented!() }
+        fn unbind_request(&self, _: BindRequest) -> UnbindConfirm { unimplemented!() }
+        fn channel_mask(&self) -> Option<ChannelMask> { None }
+        fn interframe_delay(&self) -> u8 { 0 }
+        fn last_channel_energy(&self) -> Option<u8> { self.last_channel_energy.get() }
+        fn last_channel_failure_rate(&self) -> Option<f32> { self.last_channel_failure_rate.get() }
+        fn channel_timer(&self) -> Option<f32> { self.channel_timer.get() }
+        fn max_window_size(&self) -> Option<u8> { None }
+        fn set_binding_table(&self, _: BindingTable) -> Result<(), ApsStatus> { unimplemented!() }
+        fn set_designated_coordinator(&self, _: bool) -> Result<(), ApsStatus> { unimplemented!() }
+        fn set_channel_mask(&self, _: ChannelMask) -> Result<(), ApsStatus> { unimplemented!() }
+        fn set_extended_pan_id(&self, _: ExtendedPanId) -> Result<(), ApsStatus> { unimplemented!() }
+        fn set_group_table(&self, _: GroupTable) -> Result<(), ApsStatus> { unimplemented!() }
+        fn set_nonmember_radius(&self, _: u8) -> Result<(), ApsStatus> { unimplemented!() }
+        fn store_permissions_configuration(&self, _: PermissionsConfiguration) -> Result<(), ApsStatus> {
+            unimplemented!()
+        }
+        fn set_insecure_join(&self, _: bool) -> Result<(), ApsStatus> { unimplemented!() }
+        fn set_interframe_delay(&self, _: u8) -> Result<(), ApsStatus> { unimplemented!() }
+        fn set_last_channel_energy(&self, energy: u8) -> Result<(), ApsStatus> {
+            self.last_channel_energy.set(Some(energy));
+            Ok(())
+        }
+        fn set_last_channel_failure_rate(&self, failure_rate: f32) -> Result<(), ApsStatus> {
+            self.last_channel_failure_rate.set(Some(failure_rate));
+            Ok(())
+        }
+        fn set_channel_timer(&self, hours: f32) -> Result<(), ApsStatus> {
+            self.channel_timer.set(Some(hours));
+            Ok(())
+        }
+        fn set_max_window_size(&self, _: u8) -> Result<(), ApsStatus> { unimplemented!() }
+        fn add_group_request(&self, _: GroupId, _: Endpoint) -> AddGroupConfirm { unimplemented!() }
+        fn remove_group_request(&self, _: GroupId, _: Endpoint) -> RemoveGroupConfirm { unimplemented!() }
+        fn remove_all_groups_request(&self, _: Endpoint) -> RemoveAllGroupsConfirm { unimplemented!() }
+    }
+
+    #[test]
+    fn channel_attributes() {
+        let mac = MockMac::new(1);
+        let aib = Aib::default();
+        let mut manager = NetworkManager::new(&mac, FrequencyAgilityConfig::default(), 11, 0);
+        // Nothing is stored before the first change.
+        assert_eq!(manager.store_channel_attributes(&aib, secs(0)), Ok(()));
+        assert_eq!(aib.channel_timer(), None);
+
+        manager.handle_update_notify(&report(vec![200, 10, 100]), secs(0));
+        assert!(manager.handle_scan_confirm(&energy_scan(vec![(11, 220), (12, 5)]), secs(0)).is_some());
+        assert_eq!(manager.store_channel_attributes(&aib, secs(30 * 60)), Ok(()));
+        assert_eq!(aib.last_channel_energy(), Some(220));
+        assert!((aib.last_channel_failure_rate().unwrap() - 20.0 / 30.0 * 100.0).abs() < 1e-3);
+        assert!((aib.channel_timer().unwrap() - 0.5).abs() < 1e-3);
+
+        // After a restart, the network manager waits for the time left.
+        let mut manager = NetworkManager::new(&mac, FrequencyAgilityConfig::default(), 12, 1);
+        manager.load_channel_timer(&aib, secs(0));
+        mac.take_requests();
+        manager.handle_update_notify(&report(vec![200, 10, 100]), secs(29 * 60));
+        assert!(mac.take_requests().is_empty());
+        manager.handle_update_notify(&report(vec![200, 10, 100]), secs(30 * 60));
+        assert_eq!(mac.take_requests(), vec![scan(ALL_CHANNELS, 3)]);
+    }
+}
@@ -0,0 +1,336 @@
+//! The indirect transmission of frames to the children that keep their
+//! receiver off when idle.
+//!
+//! A parent cannot send a frame to a sleeping child: the frame is queued
+//! until the child polls with a MAC data request, for at most
+//! macTransactionPersistenceTime. The answer to a poll tells the child
+//! whether more frames are waiting, so that it polls again. A frame stays
+//! in the queue until the MAC confirms its delivery: when the child did
+//! not acknowledge it, it is sent again at the next poll.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::time::Duration;
+use apl::aps::DataConfirmStatus;
+use mac::{AddressMode, MacAddress, MacStatus, McpsDataConfirm, McpsDataRequest, McpsSap};
+use nwk::commands::NetworkStatusCode;
+use nwk::neighbor::NeighborTable;
+
+pub struct IndirectConfig {
+    /// The number of frames that can wait for a single child.
+    pub max_frames_per_child: usize,
+    /// The number of frames that can wait for all the children.
+    pub max_frames: usize,
+    /// How long a frame waits for the child to poll
+    /// (macTransactionPersistenceTime).
+    pub persistence_time: Duration,
+}
+
+impl Default for IndirectConfig {
+    fn default() -> Self {
+        IndirectConfig {
+            max_frames_per_child: 2,
+            max_frames: 16,
+            persistence_time: Duration::from_millis(7680),
+        }
+    }
+}
+
+struct PendingFrame {
+    msdu_handle: u8,
+    msdu: Vec<u8>,
+    expiration: Duration,
+    /// Whether the frame was handed to the MAC and waits for its confirm.
+    in_flight: bool,
+}
+
+/// The outcome of an indirect transmission: the frame was delivered to the
+/// child, or dropped with the MAC status explaining why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IndirectConfirm {
+    pub child: u16,
+    pub msdu_handle: u8,
+    pub status: MacStatus,
+}
+
+impl IndirectConfirm {
+    /// The network status to report to the originator of a dropped frame,
+    /// if any.
+    pub fn network_status_code(&self) -> Option<NetworkStatusCode> {
+        match self.status {
+            MacStatus::TransactionOverflow => Some(NetworkStatusCode::NoIndirectCapacity),
+            MacStatus::TransactionExpired => Some(NetworkStatusCode::IndirectTransactionExpiry),
+            _ => None,
+        }
+    }
+
+    /// The status of the APSDE-DATA.confirm of the frame.
+    pub fn data_confirm_status(&self) -> DataConfirmStatus {
+        self.status.into()
+    }
+}
+
+/// Whether the frames for `destination` must be queued until it polls.
+pub fn needs_indirect(neighbors: &NeighborTable, destination: u16) -> bool {
+    neighbors.get(destination).is_some_and(|e| e.is_child() && !e.rx_on_when_idle)
+}
+
+/// The queues of the frames waiting for the sleeping children.
+pub struct IndirectQueue {
+    config: IndirectConfig,
+    queues: Vec<(u16, VecDeque<PendingFrame>)>,
+}
+
+impl IndirectQueue {
+    pub fn new(config: IndirectConfig) -> Self {
+        IndirectQueue { config, queues: Vec::new() }
+    }
+
+    pub fn config(&self) -> &IndirectConfig {
+        &self.config
+    }
+
+    /// The number of frames waiting for all the children.
+    pub fn len(&self) -> usize {
+        self.queues.iter().map(|(_, queue)| queue.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn queue(&self, child: u16) -> Option<&VecDeque<PendingFrame>> {
+        self.queues.iter().find(|&&(address, _)| address == child).map(|(_, queue)| queue)
+    }
+
+    /// Whether frames are waiting for `child`, besides the one being sent:
+    /// the value of the frame pending flag of the acknowledgement to its
+    /// data requests.
+    pub fn data_pending(&self, child: u16) -> bool {
+        self.queue(child).is_some_and(|queue| queue.iter().any(|frame| !frame.in_flight))
+    }
+
+    /// Queue a frame for `child`. The frame is returned with a
+    /// `TransactionOverflow` status if there is no room for it.
+    pub fn enqueue(&mut self, child: u16, msdu_handle: u8, msdu: Vec<u8>, now: Duration)
+                   -> Result<(), IndirectConfirm> {
+        let overflow = IndirectConfirm { child, msdu_handle, status: MacStatus::TransactionOverflow };
+        if self.len() >= self.config.max_frames {
+            return Err(overflow);
+        }
+        let frame = PendingFrame {
+            msdu_handle,
+            msdu,
+            expiration: now + self.config.persistence_time,
+            in_flight: false,
+        };
+        match self.queues.iter().position(|&(address, _)| address == child) {
+            Some(i) => {
+                if self.queues[i].1.len() >= self.config.max_frames_per_child {
+                    return Err(overflow);
+                }
+                self.queues[i].1.push_back(frame);
+            }
+            None => {
+                let mut queue = VecDeque::new();
+                queue.push_back(frame);
+                self.queues.push((child, queue));
+            }
+        }
+        Ok(())
+    }
+
+    /// Handle a MAC data request (poll) from `child`: the oldest frame
+    /// waiting for it is sent through the MCPS. It stays queued until its
+    /// confirm. Return `false` if there was nothing to send.
+    pub fn handle_data_request<M: McpsSap>(&mut self, mcps: &M, pan_id: u16, child: u16) -> bool {
+        let frame = self.queues.iter_mut()
+            .find(|&&mut (address, _)| address == child)
+            .and_then(|(_, queue)| queue.iter_mut().find(|frame| !frame.in_flight));
+        let frame = match frame {
+            Some(frame) => frame,
+            None => return false,
+        };
+        frame.in_flight = true;
+        mcps.data_request(McpsDataRequest {
+            src_addr_mode: AddressMode::Short,
+            dst_pan_id: pan_id,
+            dst_address: MacAddress::Short(child),
+            msdu: Cow::Owned(frame.msdu.clone()),
+            msdu_handle: frame.msdu_handle,
+            ack_tx: true,
+            indirect_tx: false,
+        });
+        true
+    }
+
+    /// Handle the MCPS-DATA.confirm of a frame sent by
+    /// `handle_data_request`. A frame that the child did not receive is
+    /// kept for its next poll, until it expires; the others are removed
+    /// from the queue and their outcome returned.
+    pub fn handle_data_confirm(&mut self, confirm: &McpsDataConfirm) -> Option<IndirectConfirm> {
+        let (child, queue) = self.queues.iter_mut()
+            .find(|(_, queue)| queue.iter().any(|f| f.in_flight && f.msdu_handle == confirm.msdu_handle))?;
+        let i = queue.iter().position(|f| f.in_flight && f.msdu_handle == confirm.msdu_handle)?;
+        match confirm.status {
+            MacStatus::NoAck | MacStatus::ChannelAccessFailure => {
+                queue[i].in_flight = false;
+                None
+            }
+            status => {
+                let frame = queue.remove(i)?;
+                Some(IndirectConfirm { child: *child, msdu_handle: frame.msdu_handle, status })
+            }
+        }
+    }
+
+    /// Drop the frames whose persistence time expired at time `now`. The
+    /// frames being sent wait for their confirm.
+    pub fn poll(&mut self, now: Duration) -> Vec<IndirectConfirm> {
+        let mut expired = Vec::new();
+        for &mut (child, ref mut queue) in &mut self.queues {
+            queue.retain(|frame| if frame.expiration <= now && !frame.in_flight {
+                expired.push(IndirectConfirm {
+                    child,
+                    msdu_handle: frame.msdu_handle,
+                    status: MacStatus::TransactionExpired,
+                });
+                false
+            } else {
+                true
+            });
+        }
+        self.queues.retain(|(_, queue)| !queue.is_empty());
+        expired
+    }
+
+    /// Drop the frames waiting for a child that left or expired.
+    pub fn remove_child(&mut self, child: u16) -> Vec<IndirectConfirm> {
+        let i = match self.queues.iter().position(|&(address, _)| address == child) {
+            Some(i) => i,
+            None => return Vec::new(),
+        };
+        self.queues.swap_remove(i).1.into_iter().map(|frame| IndirectConfirm {
+            child,
+            msdu_handle: frame.msdu_handle,
+            status: MacStatus::TransactionExpired,
+        }).collect()
+    }
+
+    /// The earliest time a frame expires.
+    pub fn next_deadline(&self) -> Option<Duration> {
+        self.queues.iter()
+            .filter_map(|(_, queue)| queue.iter().map(|frame| frame.expiration).min())
+            .min()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apl::device_profile::Relationship;
+    use apl::framework::LogicalType;
+    use mac::mock::{MockMac, Request};
+    use nwk::neighbor::NeighborTableEntry;
+    use nwk::NwkError;
+
+    const CHILD: u16 = 0x1234;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    fn sent(mac: &MockMac) -> Vec<(u8, Vec<u8>)> {
+        mac.take_requests().into_iter().map(|request| match request {
+            Request::Data(request) => {
+                assert_eq!(request.dst_address, MacAddress::Short(CHILD));
+                (request.msdu_handle, request.msdu.into_owned())
+            }
+            request => panic!("unexpected request {:?}", request),
+        }).collect()
+    }
+
+    fn confirm(msdu_handle: u8, status: MacStatus) -> McpsDataConfirm {
+        McpsDataConfirm { msdu_handle, status }
+    }
+
+    #[test]
+    fn frames_wait_for_the_poll() {
+        let mac = MockMac::new(1);
+        let mut queue = IndirectQueue::new(IndirectConfig::default());
+        assert!(!queue.handle_data_request(&mac, 0x1A62, CHILD));
+        queue.enqueue(CHILD, 1, vec![1], ms(0)).unwrap();
+        queue.enqueue(CHILD, 2, vec![2], ms(0)).unwrap();
+        assert!(queue.data_pending(CHILD));
+        assert!(!queue.data_pending(CHILD + 1));
+
+        assert!(queue.handle_data_request(&mac, 0x1A62, CHILD));
+        assert_eq!(sent(&mac), vec![(1, vec![1])]);
+        assert!(queue.data_pending(CHILD));
+        assert_eq!(queue.handle_data_confirm(&confirm(1, MacStatus::Success)),
+                   Some(IndirectConfirm { child: CHILD, msdu_handle: 1, status: MacStatus::Success }));
+        assert_eq!(queue.len(), 1);
+
+        assert!(queue.handle_data_request(&mac, 0x1A62, CHILD));
+        assert_eq!(sent(&mac), vec![(2, vec![2])]);
+        // No other frame is pending while the last one is sent.
+        assert!(!queue.data_pending(CHILD));
+        assert!(!queue.handle_data_request(&mac, 0x1A62, CHILD));
+        queue.handle_data_confirm(&confirm(2, MacStatus::Success)).unwrap();
+        assert!(queue.is_empty());
+        assert_eq!(queue.handle_data_confirm(&confirm(2, MacStatus::Success)), None);
+    }
+
+    #[test]
+    fn unacknowledged_frames_are_sent_again() {
+        let mac = MockMac::new(1);
+        let mut queue = IndirectQueue::new(IndirectConfig::default());
+        queue.enqueue(CHILD, 7, vec![7], ms(0)).unwrap();
+        queue.handle_data_request(&mac, 0x1A62, CHILD);
+        assert_eq!(queue.handle_data_confirm(&confirm(7, MacStatus::NoAck)), None);
+        assert!(queue.data_pending(CHILD));
+        // The frame does not expire while it is being sent.
+        queue.handle_data_request(&mac, 0x1A62, CHILD);
+        assert_eq!(queue.poll(ms(10_000)), vec![]);
+        assert_eq!(sent(&mac), vec![(7, vec![7]), (7, vec![7])]);
+        let dropped = queue.handle_data_confirm(&confirm(7, MacStatus::FrameTooLong)).unwrap();
+        assert_eq!(dropped.data_confirm_status(), DataConfirmStatus::AsduTooLong);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn expired_and_overflowing_frames() {
+        let mut queue = IndirectQueue::new(IndirectConfig { max_frames: 3, ..Default::default() });
+        queue.enqueue(CHILD, 1, vec![1], ms(0)).unwrap();
+        queue.enqueue(CHILD, 2, vec![2], ms(1000)).unwrap();
+        let overflow = queue.enqueue(CHILD, 3, vec![3], ms(1000)).unwrap_err();
+        assert_eq!(overflow.status, MacStatus::TransactionOverflow);
+        assert_eq!(overflow.network_status_code(), Some(NetworkStatusCode::NoIndirectCapacity));
+        queue.enqueue(CHILD + 1, 4, vec![4], ms(1000)).unwrap();
+        assert_eq!(queue.enqueue(CHILD + 2, 5, vec![5], ms(1000)).unwrap_err().msdu_handle, 5);
+
+        assert_eq!(queue.next_deadline(), Some(ms(7680)));
+        let expired = queue.poll(ms(7680));
+        assert_eq!(expired, vec![IndirectConfirm { child: CHILD, msdu_handle: 1,
+                                                   status: MacStatus::TransactionExpired }]);
+        assert_eq!(expired[0].network_status_code(), Some(NetworkStatusCode::IndirectTransactionExpiry));
+        assert_eq!(expired[0].data_confirm_status(),
+                   DataConfirmStatus::NldeError(NwkError::Mac(MacStatus::TransactionExpired)));
+
+        let removed = queue.remove_child(CHILD);
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].msdu_handle, 2);
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.next_deadline(), Some(ms(8680)));
+    }
+
+    #[test]
+    fn sleeping_children_need_indirect_transmission() {
+        let mut neighbors = NeighborTable::new(1, 4);
+        neighbors.insert(NeighborTableEntry::new(1, CHILD, LogicalType::EndDevice, Relationship::Child)).unwrap();
+        neighbors.insert(NeighborTableEntry::new(2, 0x0001, LogicalType::Router, Relationship::Child)).unwrap();
+        assert!(needs_indirect(&neighbors, CHILD));
+        assert!(!needs_indirect(&neighbors, 0x0001));
+        assert!(!needs_indirect(&neighbors, 0x0002));
+    }
+}
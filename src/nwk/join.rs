@@ -0,0 +1,873 @@
+//! The procedures to join a network, through MAC association or NWK
+//! rejoin, seen from both the joining device and its parent. See section
+//! 3.6.1.4 of the specification.
+//!
+//! A device first discovers the networks around with an active scan, then
+//! chooses a parent among the routers of the selected network that still
+//! have capacity and asks it to join. A device that lost its parent, or
+//! that was dropped by it, rejoins sending a rejoin request command, that
+//! is secured with the network key when the device still has it (secure
+//! rejoin), or sent in the clear otherwise (trust center rejoin).
+
+use std::time::Duration;
+use rand::Rng;
+use apl::device_profile::{NetworkListRecord, Relationship};
+use apl::framework::{LogicalType, MacCapability};
+use codec::Reader;
+use mac::{AssociateConfirm, AssociateIndication, AssociationStatus, MacAddress, MlmeSap,
+          PanDescriptor, ScanConfirm, ScanType};
+use nwk::NwkStatus;
+use nwk::addressing::AddressManager;
+use nwk::commands::{RejoinRequest, RejoinResponse};
+use nwk::frame_format::{BeaconPayload, PROTOCOL_VERSION};
+use nwk::neighbor::{link_cost, NeighborTable, NeighborTableEntry};
+use types::ExtendedPanId;
+
+/// The highest link cost accepted towards a potential parent.
+pub const MAX_PARENT_LINK_COST: u8 = 3;
+
+/// A network found during the network discovery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkDescriptor {
+    pub extended_pan_id: u64,
+    pub pan_id: u16,
+    pub nwk_update_id: u8,
+    pub logical_channel: u8,
+    pub stack_profile: u8,
+    pub zigbee_version: u8,
+    pub beacon_order: u8,
+    pub superframe_order: u8,
+    pub permit_joining: bool,
+    pub router_capacity: bool,
+    pub end_device_capacity: bool,
+}
+
+impl<'a> From<&'a NetworkDescriptor> for NetworkListRecord {
+    fn from(network: &'a NetworkDescriptor) -> Self {
+        NetworkListRecord {
+            extended_pan_id: ExtendedPanId(network.extended_pan_id),
+            logical_channel: network.logical_channel,
+            stack_profile: network.stack_profile,
+            zigbee_version: network.zigbee_version,
+            beacon_order: network.beacon_order,
+            superframe_order: network.superframe_order,
+            permit_joining: network.permit_joining,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NetworkDiscoveryConfirm {
+    pub status: NwkStatus,
+    pub networks: Vec<NetworkDescriptor>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinConfirm {
+    pub status: NwkStatus,
+    pub network_address: u16,
+    pub extended_pan_id: u64,
+    pub logical_channel: u8,
+    pub parent_address: u16,
+}
+
+impl JoinConfirm {
+    fn failure(status: NwkStatus, extended_pan_id: u64) -> Self {
+        JoinConfirm {
+            status,
+            network_address: 0xFFFF,
+            extended_pan_id,
+            logical_channel: 0,
+            parent_address: 0xFFFF,
+        }
+    }
+}
+
+/// The outcome of the events handled by a `Joiner`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JoinEvent {
+    NetworkDiscoveryConfirm(NetworkDiscoveryConfirm),
+    JoinConfirm(JoinConfirm),
+    /// A rejoin request command must be sent to the chosen parent,
+    /// secured with the network key if `secure` is set.
+    SendRejoinRequest { destination: u16, request: RejoinRequest, secure: bool },
+}
+
+/// A router or coordinator heard during a scan.
+struct Candidate {
+    descriptor: PanDescriptor,
+    payload: BeaconPayload,
+    tried: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Idle,
+    Discovering,
+    Associating { candidate: usize },
+    RejoinScanning,
+    WaitingRejoinResponse { candidate: usize, deadline: Option<Duration> },
+    Joined,
+}
+
+/// The joining device side of the join and rejoin procedures, generic over
+/// the MAC it drives.
+pub struct Joiner<M> {
+    mac: M,
+    capability: MacCapability,
+    state: State,
+    candidates: Vec<Candidate>,
+    extended_pan_id: u64,
+    secure_rejoin: bool,
+    network_address: u16,
+    /// How long to wait for a rejoin response before trying another parent.
+    pub response_wait_time: Duration,
+}
+
+impl<M: MlmeSap> Joiner<M> {
+    pub fn new(mac: M, capability: MacCapability) -> Self {
+        Joiner {
+            mac,
+            capability,
+            state: State::Idle,
+            candidates: Vec::new(),
+            extended_pan_id: 0,
+            secure_rejoin: false,
+            network_address: 0xFFFF,
+            response_wait_time: Duration::from_millis(500),
+        }
+    }
+
+    pub fn mac(&self) -> &M {
+        &self.mac
+    }
+
+    pub fn is_joined(&self) -> bool {
+        self.state == State::Joined
+    }
+
+    /// The network address of this device, 0xFFFF when it never joined.
+    pub fn network_address(&self) -> u16 {
+        self.network_address
+    }
+
+    /// Forget the network, e.g. after leaving it.
+    pub fn reset(&mut self) {
+        self.state = State::Idle;
+        self.candidates.clear();
+        self.network_address = 0xFFFF;
+    }
+
+    fn is_router(&self) -> bool {
+        self.capability.device_type
+    }
+
+    /// Start the network discovery (NLME-NETWORK-DISCOVERY.request).
+    pub fn discover_networks(&mut self, scan_channels: u32, scan_duration: u8) -> Result<(), NwkStatus> {
+        if self.state != State::Idle && self.state != State::Joined {
+            return Err(NwkStatus::InvalidRequest);
+        }
+        self.state = State::Discovering;
+        self.mac.scan_request(ScanType::Active, scan_channels, scan_duration);
+        Ok(())
+    }
+
+    /// Start the rejoin procedure on the network identified by
+    /// `extended_pan_id`.
+    pub fn rejoin(&mut self, extended_pan_id: u64, secure: bool, scan_channels: u32,
+                  scan_duration: u8) -> Result<(), NwkStatus> {
+        match self.state {
+            State::Idle | State::Joined => {}
+            _ => return Err(NwkStatus::InvalidRequest),
+        }
+        self.extended_pan_id = extended_pan_id;
+        self.secure_rejoin = secure;
+        self.state = State::RejoinScanning;
+        self.mac.scan_request(ScanType::Active, scan_channels, scan_duration);
+        Ok(())
+    }
+
+    fn store_candidates(&mut self, confirm: &ScanConfirm) {
+        self.candidates = confirm.pan_descriptors.iter()
+            .filter_map(|descriptor| {
+                let payload = BeaconPayload::decode(&mut Reader::new(&descriptor.beacon_payload)).ok()?;
+                Some(Candidate { descriptor: descriptor.clone(), payload, tried: false })
+            })
+            .collect();
+    }
+
+    /// Handle the MLME-SCAN.confirm of the active scans requested by this
+    /// state machine.
+    pub fn handle_scan_confirm(&mut self, confirm: &ScanConfirm) -> Option<JoinEvent> {
+        if confirm.scan_type != ScanType::Active {
+            return None;
+        }
+        match self.state {
+            State::Discovering => {
+                self.store_candidates(confirm);
+                self.state = State::Idle;
+                let mut networks: Vec<NetworkDescriptor> = Vec::new();
+                for candidate in &self.candidates {
+                    let payload = &candidate.payload;
+                    if let Some(network) = networks.iter_mut()
+                        .find(|n| n.extended_pan_id == payload.extended_pan_id) {
+                        network.permit_joining |= candidate.descriptor.association_permit;
+                        network.router_capacity |= payload.router_capacity;
+                        network.end_device_capacity |= payload.end_device_capacity;
+                        continue;
+                    }
+                    networks.push(NetworkDescriptor {
+                        extended_pan_id: payload.extended_pan_id,
+                        pan_id: candidate.descriptor.coord_pan_id,
+                        nwk_update_id: payload.nwk_update_id,
+                        logical_channel: candidate.descriptor.logical_channel,
+                        stack_profile: payload.stack_profile,
+                        zigbee_version: payload.protocol_version,
+                        beacon_order: 15,
+                        superframe_order: 15,
+                        permit_joining: candidate.descriptor.association_permit,
+                        router_capacity: payload.router_capacity,
+                        end_device_capacity: payload.end_device_capacity,
+                    });
+                }
+                let status = if networks.is_empty() { NwkStatus::NoNetworks } else { NwkStatus::Success };
+                Some(JoinEvent::NetworkDiscoveryConfirm(NetworkDiscoveryConfirm { status, networks }))
+            }
+            State::RejoinScanning => {
+                self.store_candidates(confirm);
+                self.next_rejoin_attempt()
+            }
+            _ => None,
+        }
+    }
+
+    /// The best parent not tried yet: the one with the lowest link cost
+    /// and then the lowest depth. When rejoining, the association permit
+    /// flag is not required.
+    fn best_candidate(&self, rejoin: bool) -> Option<usize> {
+        let router = self.is_router();
+        self.candidates.iter()
+            .enumerate()
+            .filter(|&(_, c)| !c.tried
+                    && c.payload.extended_pan_id == self.extended_pan_id
+                    && c.payload.protocol_version == PROTOCOL_VERSION
+                    && (rejoin || c.descriptor.association_permit)
+                    && if router { c.payload.router_capacity } else { c.payload.end_device_capacity }
+                    && link_cost(c.descriptor.link_quality) <= MAX_PARENT_LINK_COST)
+            .min_by_key(|&(_, c)| (link_cost(c.descriptor.link_quality), c.payload.device_depth))
+            .map(|(i, _)| i)
+    }
+
+    /// Join the network identified by `extended_pan_id`, among the ones
+    /// found by the last network discovery, through MAC association
+    /// (NLME-JOIN.request with RejoinNetwork = 0x00).
+    pub fn join(&mut self, extended_pan_id: u64) -> Option<JoinEvent> {
+        match self.state {
+            State::Idle => {}
+            _ => return Some(JoinEvent::JoinConfirm(
+                JoinConfirm::failure(NwkStatus::InvalidRequest, extended_pan_id))),
+        }
+        self.extended_pan_id = extended_pan_id;
+        for candidate in &mut self.candidates {
+            candidate.tried = false;
+        }
+        self.next_association_attempt()
+    }
+
+    fn next_association_attempt(&mut self) -> Option<JoinEvent> {
+        let index = match self.best_candidate(false) {
+            Some(index) => index,
+            None => {
+                self.state = State::Idle;
+                return Some(JoinEvent::JoinConfirm(
+                    JoinConfirm::failure(NwkStatus::NotPermitted, self.extended_pan_id)));
+            }
+        };
+        self.candidates[index].tried = true;
+        self.state = State::Associating { candidate: index };
+        let descriptor = &self.candidates[index].descriptor;
+        self.mac.set_logical_channel(descriptor.logical_channel);
+        self.mac.set_pan_id(descriptor.coord_pan_id);
+        self.mac.associate_request(descriptor.logical_channel, descriptor.coord_pan_id,
+                                   descriptor.coord_address, self.capability);
+        None
+    }
+
+    /// Handle the MLME-ASSOCIATE.confirm. If the parent refused the
+    /// association, the next best one is tried.
+    pub fn handle_associate_confirm(&mut self, confirm: AssociateConfirm) -> Option<JoinEvent> {
+        let index = match self.state {
+            State::Associating { candidate } => candidate,
+            _ => return None,
+        };
+        match confirm {
+            AssociateConfirm::Success { short_address } => {
+                let parent_address = match self.candidates[index].descriptor.coord_address {
+                    MacAddress::Short(address) => address,
+                    MacAddress::Extended(_) => 0xFFFE,
+                };
+                Some(self.joined(index, short_address, parent_address))
+            }
+            AssociateConfirm::Refused(_) | AssociateConfirm::Failed(_) => self.next_association_attempt(),
+        }
+    }
+
+    fn joined(&mut self, index: usize, network_address: u16, parent_address: u16) -> JoinEvent {
+        let descriptor = &self.candidates[index].descriptor;
+        self.state = State::Joined;
+        self.network_address = network_address;
+        self.mac.set_short_address(network_address);
+        self.mac.set_rx_on_when_idle(self.capability.receiver_on_when_idle);
+        JoinEvent::JoinConfirm(JoinConfirm {
+            status: NwkStatus::Success,
+            network_address,
+            extended_pan_id: self.extended_pan_id,
+            logical_channel: descriptor.logical_channel,
+            parent_address,
+        })
+    }
+
+    fn next_rejoin_attempt(&mut self) -> Option<JoinEvent> {
+        let index = loop {
+            match self.best_candidate(true) {
+                Some(index) => {
+                    self.candidates[index].tried = true;
+                    if let MacAddress::Short(_) = self.candidates[index].descriptor.coord_address {
+                        break index;
+                    }
+                }
+                None => {
+                    self.state = State::Idle;
+                    return Some(JoinEvent::JoinConfirm(
+                        JoinConfirm::failure(NwkStatus::NoNetworks, self.extended_pan_id)));
+                }
+            }
+        };
+        let descriptor = &self.candidates[index].descriptor;
+        let destination = match descriptor.coord_address {
+            MacAddress::Short(address) => address,
+            MacAddress::Extended(_) => unreachable!(),
+        };
+        self.mac.set_logical_channel(descriptor.logical_channel);
+        self.mac.set_pan_id(descriptor.coord_pan_id);
+        self.state = State::WaitingRejoinResponse { candidate: index, deadline: None };
+        Some(JoinEvent::SendRejoinRequest {
+            destination,
+            request: RejoinRequest { capability_information: self.capability },
+            secure: self.secure_rejoin,
+        })
+    }
+
+    /// Drive the timers of the rejoin procedure. A device that keeps its
+    /// receiver off polls the parent for the rejoin response; when the
+    /// response does not arrive in time, the next parent is tried.
+    pub fn tick(&mut self, now: Duration) -> Option<JoinEvent> {
+        let (index, deadline) = match self.state {
+            State::WaitingRejoinResponse { candidate, deadline } => (candidate, deadline),
+            _ => return None,
+        };
+        match deadline {
+            None => {
+                self.state = State::WaitingRejoinResponse {
+                    candidate: index,
+                    deadline: Some(now + self.response_wait_time),
+                };
+                None
+            }
+            Some(deadline) if now >= deadline => self.next_rejoin_attempt(),
+            Some(_) => {
+                if !self.capability.receiver_on_when_idle {
+                    let descriptor = &self.candidates[index].descriptor;
+                    self.mac.poll_request(descriptor.coord_address, descriptor.coord_pan_id);
+                }
+                None
+            }
+        }
+    }
+
+    /// Handle a rejoin response command received from `source`.
+    pub fn handle_rejoin_response(&mut self, source: u16, response: &RejoinResponse) -> Option<JoinEvent> {
+        let index = match self.state {
+            State::WaitingRejoinResponse { candidate, .. } => candidate,
+            _ => return None,
+        };
+        if self.candidates[index].descriptor.coord_address != MacAddress::Short(source) {
+            return None;
+        }
+        match response.rejoin_status {
+            AssociationStatus::Success => Some(self.joined(index, response.network_address, source)),
+            _ => self.next_rejoin_attempt(),
+        }
+    }
+}
+
+/// How a device joined, as reported by the NLME-JOIN.indication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RejoinNetwork {
+    Association = 0x00,
+    DirectJoin = 0x01,
+    Rejoin = 0x02,
+}
+
+/// NLME-JOIN.indication: a new device joined the network through this one.
+/// When the join was not secured, the trust center must be informed so
+/// that it can authenticate the device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct JoinIndication {
+    pub network_address: u16,
+    pub extended_address: u64,
+    pub capability_information: MacCapability,
+    pub rejoin_network: RejoinNetwork,
+    pub secure_rejoin: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParentConfig {
+    /// The maximum number of children (nwkMaxChildren).
+    pub max_children: u8,
+    /// The maximum number of router children (nwkMaxRouters).
+    pub max_routers: u8,
+    /// The depth of this device in the network.
+    pub depth: u8,
+    /// Whether unsecured rejoin requests are accepted.
+    pub allow_trust_center_rejoin: bool,
+}
+
+/// The parent side of the join and rejoin procedures.
+///
+/// The neighbor table and the address manager are not owned, since other
+/// NWK components need them too, and are passed to the methods that need
+/// them.
+pub struct JoinHandler<M> {
+    mac: M,
+    config: ParentConfig,
+    /// `None` when joining is not permitted, `Some(None)` when it is
+    /// permitted with no time limit.
+    permit_joining_until: Option<Option<Duration>>,
+}
+
+impl<M: MlmeSap> JoinHandler<M> {
+    pub fn new(mac: M, config: ParentConfig) -> Self {
+        JoinHandler { mac, config, permit_joining_until: None }
+    }
+
+    pub fn mac(&self) -> &M {
+        &self.mac
+    }
+
+    pub fn config(&self) -> &ParentConfig {
+        &self.config
+    }
+
+    /// NLME-PERMIT-JOINING.request: permit joining for `duration` seconds.
+    /// Zero disables joining and 0xFF enables it with no time limit.
+    pub fn permit_joining(&mut self, duration: u8, now: Duration) {
+        self.permit_joining_until = match duration {
+            0x00 => None,
+            0xFF => Some(None),
+            seconds => Some(Some(now + Duration::from_secs(u64::from(seconds)))),
+        };
+        self.mac.set_association_permit(self.permit_joining_until.is_some());
+    }
+
+    pub fn is_permit_joining(&self) -> bool {
+        self.permit_joining_until.is_some()
+    }
+
+    /// Disable joining when the permit joining time expires.
+    pub fn tick(&mut self, now: Duration) {
+        if let Some(Some(until)) = self.permit_joining_until {
+            if now >= until {
+                self.permit_joining_until = None;
+                self.mac.set_association_permit(false);
+            }
+        }
+    }
+
+    /// Whether there is room for another child of the given kind.
+    pub fn has_capacity(&self, neighbors: &NeighborTable, router: bool) -> bool {
+        let children = neighbors.children().count();
+        let routers = neighbors.children()
+            .filter(|c| c.device_type != LogicalType::EndDevice)
+            .count();
+        children < self.config.max_children as usize
+            && (!router || routers < self.config.max_routers as usize)
+    }
+
+    fn add_child(&self, neighbors: &mut NeighborTable, extended_address: u64, network_address: u16,
+                 capability: MacCapability) -> Result<(), NwkStatus> {
+        let device_type = if capability.device_type { LogicalType::Router } else { LogicalType::EndDevice };
+        let mut entry = NeighborTableEntry::new(extended_address, network_address, device_type,
+                                                Relationship::Child);
+        entry.rx_on_when_idle = capability.receiver_on_when_idle;
+        entry.depth = self.config.depth + 1;
+        neighbors.insert(entry).map_err(|_| NwkStatus::NeighborTableFull)
+    }
+
+    /// Handle an MLME-ASSOCIATE.indication, answering the device with the
+    /// MLME-ASSOCIATE.response.
+    pub fn handle_associate_indication<R: Rng>(&mut self, indication: &AssociateIndication,
+                                               neighbors: &mut NeighborTable,
+                                               addresses: &mut AddressManager<R>)
+                                               -> Option<JoinIndication> {
+        let device = indication.device_address;
+        let capability = indication.capability_information;
+        let refuse = |mac: &M, status| {
+            mac.associate_response(device, 0xFFFF, status);
+            None
+        };
+        if !self.is_permit_joining() {
+            return refuse(&self.mac, AssociationStatus::PanAccessDenied);
+        }
+        let known = neighbors.get_by_extended(device).is_some_and(|e| e.is_child());
+        if !known && !self.has_capacity(neighbors, capability.device_type) {
+            return refuse(&self.mac, AssociationStatus::PanAtCapacity);
+        }
+        let address = match addresses.allocate_child_address(device, capability.device_type) {
+            Some(address) => address,
+            None => return refuse(&self.mac, AssociationStatus::PanAtCapacity),
+        };
+        if self.add_child(neighbors, device, address, capability).is_err() {
+            addresses.remove_child(device);
+            return refuse(&self.mac, AssociationStatus::PanAtCapacity);
+        }
+        self.mac.associate_response(device, address, AssociationStatus::Success);
+        Some(JoinIndication {
+            network_address: address,
+            extended_address: device,
+            capability_information: capability,
+            rejoin_network: RejoinNetwork::Association,
+            secure_rejoin: false,
+        })
+    }
+
+    /// Handle a rejoin request command received from `source`, whose IEEE
+    /// address is `source_ieee_address`. `secured` tells whether the
+    /// command was secured with the network key. The returned response
+    /// must be sent back to `source`.
+    pub fn handle_rejoin_request<R: Rng>(&mut self, source: u16, source_ieee_address: u64,
+                                         request: &RejoinRequest, secured: bool,
+                                         neighbors: &mut NeighborTable,
+                                         addresses: &mut AddressManager<R>)
+                                         -> (RejoinResponse, Option<JoinIndication>) {
+        let capability = request.capability_information;
+        let refuse = |status| (RejoinResponse { network_address: 0xFFFF, rejoin_status: status }, None);
+        if !secured && !self.config.allow_trust_center_rejoin {
+            return refuse(AssociationStatus::PanAccessDenied);
+        }
+        let known = neighbors.get_by_extended(source_ieee_address).is_some_and(|e| e.is_child());
+        if !known && !self.has_capacity(neighbors, capability.device_type) {
+            return refuse(AssociationStatus::PanAtCapacity);
+        }
+        let address = match addresses.rejoin_child_address(source_ieee_address, source,
+                                                           capability.device_type) {
+            Some(address) => address,
+            None => return refuse(AssociationStatus::PanAtCapacity),
+        };
+        if self.add_child(neighbors, source_ieee_address, address, capability).is_err() {
+            addresses.remove_child(source_ieee_address);
+            return refuse(AssociationStatus::PanAtCapacity);
+        }
+        (RejoinResponse { network_address: address, rejoin_status: AssociationStatus::Success },
+         Some(JoinIndication {
+             network_address: address,
+             extended_address: source_ieee_address,
+             capability_information: capability,
+             rejoin_network: RejoinNetwork::Rejoin,
+             secure_rejoin: secured,
+         }))
+    }
+
+    /// NLME-DIRECT-JOIN.request: add a device as a child without any
+    /// exchange with it, as requested by Mgmt_Direct_Join_req. The device
+    /// will later find its parent through an orphan scan or a rejoin.
+    pub fn direct_join<R: Rng>(&mut self, device_address: u64, capability: MacCapability,
+                               neighbors: &mut NeighborTable, addresses: &mut AddressManager<R>)
+                               -> Result<u16, NwkStatus> {
+        if neighbors.get_by_extended(device_address).is_some() {
+            return Err(NwkStatus::AlreadyPresent);
+        }
+        if !self.has_capacity(neighbors, capability.device_type) {
+            return Err(NwkStatus::NeighborTableFull);
+        }
+        let address = addresses.allocate_child_address(device_address, capability.device_type)
+            .ok_or(NwkStatus::NeighborTableFull)?;
+        if let Err(status) = self.add_child(neighbors, device_address, address, capability) {
+            addresses.remove_child(device_address);
+            return Err(status);
+        }
+        Ok(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+    use mac::MacStatus;
+    use mac::mock::{MockMac, Request};
+    use nwk::addressing::{AddressAllocator, TreeAddressAllocator, TreeParameters};
+
+    const NETWORK: u64 = 0x0011_2233_4455_6677;
+    const OTHER_NETWORK: u64 = 0x8899_AABB_CCDD_EEFF;
+    const DEVICE: u64 = 0x0102_0304_0506_0708;
+
+    fn capability(router: bool, receiver_on_when_idle: bool) -> MacCapability {
+        MacCapability {
+            alternate_pan_coordinator: false,
+            device_type: router,
+            power_source: router,
+            receiver_on_when_idle,
+            security: false,
+            allocate_address: true,
+        }
+    }
+
+    /// The beacon of a router at `address` and `depth`, heard with
+    /// `link_quality`.
+    fn beacon(extended_pan_id: u64, address: u16, depth: u8, link_quality: u8, permit: bool) -> PanDescriptor {
+        let mut beacon_payload = Vec::new();
+        BeaconPayload {
+            stack_profile: 2,
+            protocol_version: PROTOCOL_VERSION,
+            router_capacity: true,
+            device_depth: depth,
+            end_device_capacity: true,
+            extended_pan_id,
+            tx_offset: 0x00FF_FFFF,
+            nwk_update_id: 0,
+        }.encode(&mut beacon_payload);
+        PanDescriptor {
+            coord_address: MacAddress::Short(address),
+            coord_pan_id: 0x1234,
+            logical_channel: 15,
+            pan_coordinator: address == 0x0000,
+            association_permit: permit,
+            link_quality,
+            beacon_payload,
+        }
+    }
+
+    fn active_scan(pan_descriptors: Vec<PanDescriptor>) -> ScanConfirm {
+        ScanConfirm {
+            status: MacStatus::Success,
+            scan_type: ScanType::Active,
+            unscanned_channels: 0,
+            energy_detect_list: Vec::new(),
+            pan_descriptors,
+        }
+    }
+
+    fn joined(network_address: u16, parent_address: u16) -> Option<JoinEvent> {
+        Some(JoinEvent::JoinConfirm(JoinConfirm {
+            status: NwkStatus::Success,
+            network_address,
+            extended_pan_id: NETWORK,
+            logical_channel: 15,
+            parent_address,
+        }))
+    }
+
+    #[test]
+    fn discovery_and_association() {
+        let mac = MockMac::new(DEVICE);
+        let mut joiner = Joiner::new(&mac, capability(false, true));
+        joiner.discover_networks(1 << 15, 3).unwrap();
+        assert_eq!(mac.take_requests(), vec![Request::Scan {
+            scan_type: ScanType::Active,
+            scan_channels: 1 << 15,
+            scan_duration: 3,
+        }]);
+        let scan = active_scan(vec![
+            beacon(NETWORK, 0x0000, 0, 0xFF, false),
+            beacon(NETWORK, 0x0001, 1, 0xFF, true),
+            beacon(NETWORK, 0x0002, 2, 0xFF, true),
+            beacon(NETWORK, 0x0003, 1, 0x10, true),
+            beacon(OTHER_NETWORK, 0x0000, 0, 0xFF, true),
+        ]);
+        let networks = match joiner.handle_scan_confirm(&scan) {
+            Some(JoinEvent::NetworkDiscoveryConfirm(NetworkDiscoveryConfirm {
+                status: NwkStatus::Success,
+                networks,
+            })) => networks,
+            event => panic!("unexpected event {:?}", event),
+        };
+        assert_eq!(networks.len(), 2);
+        assert_eq!(networks[0].extended_pan_id, NETWORK);
+        assert!(networks[0].permit_joining);
+        assert_eq!(networks[1].extended_pan_id, OTHER_NETWORK);
+
+        // The coordinator does not permit joining and router 3 is too far:
+        // router 1 is tried first, being the closest to the coordinator.
+        assert_eq!(joiner.join(NETWORK), None);
+        let associate = |address| Request::Associate {
+            logical_channel: 15,
+            coord_pan_id: 0x1234,
+            coord_address: MacAddress::Short(address),
+            capability_information: capability(false, true),
+        };
+        assert_eq!(mac.take_requests(), vec![Request::SetLogicalChannel(15), Request::SetPanId(0x1234),
+                                             associate(0x0001)]);
+        assert_eq!(joiner.handle_associate_confirm(AssociateConfirm::Refused(AssociationStatus::PanAtCapacity)),
+                   None);
+        assert_eq!(mac.take_requests()[2], associate(0x0002));
+        assert_eq!(joiner.handle_associate_confirm(AssociateConfirm::Success { short_address: 0x0201 }),
+                   joined(0x0201, 0x0002));
+        assert_eq!(mac.take_requests(), vec![Request::SetShortAddress(0x0201), Request::SetRxOnWhenIdle(true)]);
+        assert!(joiner.is_joined());
+        assert_eq!(joiner.network_address(), 0x0201);
+    }
+
+    #[test]
+    fn association_fails_when_no_parent_is_left() {
+        let mac = MockMac::new(DEVICE);
+        let mut joiner = Joiner::new(&mac, capability(true, true));
+        joiner.discover_networks(1 << 15, 3).unwrap();
+        joiner.handle_scan_confirm(&active_scan(vec![beacon(NETWORK, 0x0001, 1, 0xFF, true)]));
+        assert_eq!(joiner.join(NETWORK), None);
+        assert_eq!(joiner.handle_associate_confirm(AssociateConfirm::Failed(MacStatus::NoAck)),
+                   Some(JoinEvent::JoinConfirm(JoinConfirm::failure(NwkStatus::NotPermitted, NETWORK))));
+        assert_eq!(joiner.join(OTHER_NETWORK),
+                   Some(JoinEvent::JoinConfirm(JoinConfirm::failure(NwkStatus::NotPermitted, OTHER_NETWORK))));
+        assert!(!joiner.is_joined());
+    }
+
+    #[test]
+    fn rejoin_tries_the_next_parent_on_timeout() {
+        let mac = MockMac::new(DEVICE);
+        let mut joiner = Joiner::new(&mac, capability(false, false));
+        joiner.rejoin(NETWORK, true, 1 << 15, 3).unwrap();
+        assert_eq!(joiner.rejoin(NETWORK, true, 1 << 15, 3), Err(NwkStatus::InvalidRequest));
+        mac.take_requests();
+
+        // The association permit flag is not needed to rejoin.
+        let scan = active_scan(vec![beacon(NETWORK, 0x0001, 1, 0xFF, false),
+                                    beacon(NETWORK, 0x0002, 1, 0xC0, false)]);
+        let request = RejoinRequest { capability_information: capability(false, false) };
+        assert_eq!(joiner.handle_scan_confirm(&scan),
+                   Some(JoinEvent::SendRejoinRequest { destination: 0x0001, request, secure: true }));
+        assert_eq!(mac.take_requests(), vec![Request::SetLogicalChannel(15), Request::SetPanId(0x1234)]);
+
+        let ms = Duration::from_millis;
+        assert_eq!(joiner.tick(ms(0)), None);
+        // The device keeps its receiver off and polls for the response.
+        assert_eq!(joiner.tick(ms(100)), None);
+        assert_eq!(mac.take_requests(), vec![Request::Poll { coord_address: MacAddress::Short(0x0001),
+                                                             coord_pan_id: 0x1234 }]);
+        assert_eq!(joiner.tick(ms(500)),
+                   Some(JoinEvent::SendRejoinRequest { destination: 0x0002, request, secure: true }));
+
+        let response = RejoinResponse { network_address: 0x0203, rejoin_status: AssociationStatus::Success };
+        assert_eq!(joiner.handle_rejoin_response(0x0001, &response), None);
+        assert_eq!(joiner.handle_rejoin_response(0x0002, &response), joined(0x0203, 0x0002));
+        assert!(joiner.is_joined());
+    }
+
+    #[test]
+    fn rejoin_refused_by_every_parent() {
+        let mac = MockMac::new(DEVICE);
+        let mut joiner = Joiner::new(&mac, capability(false, true));
+        joiner.rejoin(NETWORK, false, 1 << 15, 3).unwrap();
+        joiner.handle_scan_confirm(&active_scan(vec![beacon(NETWORK, 0x0001, 1, 0xFF, false),
+                                                     beacon(OTHER_NETWORK, 0x0002, 1, 0xFF, true)]));
+        let refused = RejoinResponse { network_address: 0xFFFF, rejoin_status: AssociationStatus::PanAccessDenied };
+        assert_eq!(joiner.handle_rejoin_response(0x0001, &refused),
+                   Some(JoinEvent::JoinConfirm(JoinConfirm::failure(NwkStatus::NoNetworks, NETWORK))));
+        assert!(!joiner.is_joined());
+    }
+
+    fn parent(mac: &MockMac, allow_trust_center_rejoin: bool) -> JoinHandler<&MockMac> {
+        JoinHandler::new(mac, ParentConfig { max_children: 2, max_routers: 1, depth: 0, allow_trust_center_rejoin })
+    }
+
+    fn addresses() -> AddressManager<StepRng> {
+        let params = TreeParameters { max_children: 2, max_routers: 1, max_depth: 2 };
+        AddressManager::new(NETWORK, 0x0000, AddressAllocator::Tree(TreeAddressAllocator::new(params, 0x0000, 0)))
+    }
+
+    #[test]
+    fn parent_accepts_associations_while_permitted() {
+        let mac = MockMac::new(NETWORK);
+        let mut handler = parent(&mac, false);
+        let mut neighbors = NeighborTable::new(NETWORK, 4);
+        let mut addresses = addresses();
+        let indication = |device_address, router| AssociateIndication {
+            device_address,
+            capability_information: capability(router, router),
+        };
+
+        assert_eq!(handler.handle_associate_indication(&indication(DEVICE, false), &mut neighbors, &mut addresses),
+                   None);
+        assert_eq!(mac.take_requests(), vec![Request::AssociateResponse {
+            device_address: DEVICE,
+            short_address: 0xFFFF,
+            status: AssociationStatus::PanAccessDenied,
+        }]);
+
+        handler.permit_joining(60, Duration::from_secs(0));
+        let join = handler.handle_associate_indication(&indication(DEVICE, false), &mut neighbors, &mut addresses)
+            .unwrap();
+        assert_eq!(join.rejoin_network, RejoinNetwork::Association);
+        assert_eq!(mac.take_requests(), vec![
+            Request::SetAssociationPermit(true),
+            Request::AssociateResponse { device_address: DEVICE, short_address: join.network_address,
+                                         status: AssociationStatus::Success },
+        ]);
+        assert!(neighbors.get_by_extended(DEVICE).unwrap().is_child());
+        assert_eq!(addresses.address_map().network_address(DEVICE), Some(join.network_address));
+
+        // No room for a second end device.
+        assert_eq!(handler.handle_associate_indication(&indication(DEVICE + 1, false), &mut neighbors,
+                                                       &mut addresses), None);
+        assert_eq!(mac.take_requests()[0], Request::AssociateResponse {
+            device_address: DEVICE + 1,
+            short_address: 0xFFFF,
+            status: AssociationStatus::PanAtCapacity,
+        });
+        assert!(handler.handle_associate_indication(&indication(DEVICE + 2, true), &mut neighbors,
+                                                    &mut addresses).is_some());
+
+        handler.tick(Duration::from_secs(59));
+        assert!(handler.is_permit_joining());
+        handler.tick(Duration::from_secs(60));
+        assert!(!handler.is_permit_joining());
+    }
+
+    #[test]
+    fn parent_handles_rejoin_requests() {
+        let mac = MockMac::new(NETWORK);
+        let mut handler = parent(&mac, false);
+        let mut neighbors = NeighborTable::new(NETWORK, 4);
+        let mut addresses = addresses();
+        let request = RejoinRequest { capability_information: capability(false, false) };
+
+        let (response, indication) = handler.handle_rejoin_request(0x1234, DEVICE, &request, false,
+                                                                   &mut neighbors, &mut addresses);
+        assert_eq!(response.rejoin_status, AssociationStatus::PanAccessDenied);
+        assert_eq!(indication, None);
+
+        let (response, indication) = handler.handle_rejoin_request(0x1234, DEVICE, &request, true,
+                                                                   &mut neighbors, &mut addresses);
+        assert_eq!(response.rejoin_status, AssociationStatus::Success);
+        let indication = indication.unwrap();
+        assert_eq!((indication.rejoin_network, indication.secure_rejoin), (RejoinNetwork::Rejoin, true));
+        assert_eq!(indication.network_address, response.network_address);
+        assert!(!neighbors.get_by_extended(DEVICE).unwrap().rx_on_when_idle);
+    }
+
+    #[test]
+    fn direct_join() {
+        let mac = MockMac::new(NETWORK);
+        let mut handler = parent(&mac, false);
+        let mut neighbors = NeighborTable::new(NETWORK, 4);
+        let mut addresses = addresses();
+        let address = handler.direct_join(DEVICE, capability(false, true), &mut neighbors, &mut addresses)
+            .unwrap();
+        assert_eq!(neighbors.get_by_extended(DEVICE).unwrap().network_address, address);
+        assert_eq!(handler.direct_join(DEVICE, capability(false, true), &mut neighbors, &mut addresses),
+                   Err(NwkStatus::AlreadyPresent));
+        assert_eq!(handler.direct_join(DEVICE + 1, capability(false, true), &mut neighbors, &mut addresses),
+                   Err(NwkStatus::NeighborTableFull));
+        assert!(mac.take_requests().is_empty());
+    }
+}
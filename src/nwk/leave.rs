@@ -0,0 +1,373 @@
+//! The procedures to leave a network (NLME-LEAVE), as described in
+//! section 3.6.1.10 of the specification.
+//!
+//! A device leaves on its own initiative, when asked by its parent or by a
+//! remote device, or a parent removes one of its children. A leaving
+//! router can ask its children to leave first, and each of them does the
+//! same with its own children, so that a whole branch is removed. Unless
+//! the device is going to rejoin, all the state tied to the network is
+//! dropped.
+
+use rand::Rng;
+use apl::aps::{BindingTable, GroupTable};
+use nwk::{NwkStatus, BROADCAST_RX_ON_WHEN_IDLE};
+use nwk::addressing::AddressManager;
+use nwk::commands::Leave;
+use nwk::neighbor::NeighborTable;
+use nwk::routing::RoutingTable;
+use types::IeeeAddress;
+
+/// The arguments of the NLME-LEAVE.request primitive. `device_address` is
+/// `None` when this device is leaving, or the IEEE address of the child to
+/// remove.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaveRequest {
+    pub device_address: Option<u64>,
+    pub remove_children: bool,
+    pub rejoin: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaveConfirm {
+    pub device_address: Option<u64>,
+    pub status: NwkStatus,
+}
+
+/// NLME-LEAVE.indication: a device left the network. `device_address` is
+/// `None` when this device was asked to leave.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaveIndication {
+    pub device_address: Option<u64>,
+    pub rejoin: bool,
+}
+
+/// What must be done to carry on a leave procedure, in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveAction {
+    /// Send a leave command to `destination`. Broadcasts are sent with a
+    /// radius of 1, so that only the neighbors receive them.
+    Send { destination: u16, command: Leave },
+    Confirm(LeaveConfirm),
+    Indication(LeaveIndication),
+}
+
+/// The tables cleaned up when devices leave.
+pub struct LeaveTables<'a, R: 'a> {
+    pub neighbors: &'a mut NeighborTable,
+    pub routing: &'a mut RoutingTable,
+    pub addresses: &'a mut AddressManager<R>,
+    pub bindings: &'a mut BindingTable,
+    pub groups: &'a mut GroupTable,
+}
+
+impl<'a, R: Rng> LeaveTables<'a, R> {
+    /// Drop everything this device knows about another device. Its address
+    /// and bindings are kept if it is going to rejoin.
+    fn remove_device(&mut self, ieee_address: u64, network_address: u16, rejoin: bool) {
+        self.neighbors.remove(ieee_address);
+        self.routing.remove_device(network_address);
+        if !rejoin {
+            self.addresses.remove_child(ieee_address);
+            self.bindings.remove_device(IeeeAddress(ieee_address));
+        }
+    }
+
+    /// Drop the state tied to the network this device is leaving. The
+    /// application tables are kept if the device is going to rejoin.
+    fn clear(&mut self, rejoin: bool) {
+        self.neighbors.clear();
+        self.routing.clear();
+        self.addresses.clear();
+        if !rejoin {
+            self.bindings.clear();
+            self.groups.clear();
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LeaveHandler {
+    /// Whether leave requests from devices other than the parent and the
+    /// trust center are honoured (nwkLeaveRequestAllowed).
+    pub leave_request_allowed: bool,
+    /// The network address of the trust center.
+    pub trust_center_address: u16,
+}
+
+impl Default for LeaveHandler {
+    fn default() -> Self {
+        LeaveHandler { leave_request_allowed: true, trust_center_address: 0x0000 }
+    }
+}
+
+impl LeaveHandler {
+    /// Handle an NLME-LEAVE.request.
+    pub fn leave_request<R: Rng>(&self, request: &LeaveRequest, tables: &mut LeaveTables<R>)
+                                 -> Vec<LeaveAction> {
+        match request.device_address {
+            None => self.leave(request.remove_children, request.rejoin, tables),
+            Some(device) => {
+                let child = match tables.neighbors.get_by_extended(device) {
+                    Some(entry) if entry.is_child() => entry.network_address,
+                    _ => return vec![LeaveAction::Confirm(LeaveConfirm {
+                        device_address: Some(device),
+                        status: NwkStatus::UnknownDevice,
+                    })],
+                };
+                tables.remove_device(device, child, request.rejoin);
+                vec![
+                    LeaveAction::Send {
+                        destination: child,
+                        command: Leave {
+                            request: true,
+                            remove_children: request.remove_children,
+                            rejoin: request.rejoin,
+                        },
+                    },
+                    LeaveAction::Confirm(LeaveConfirm {
+                        device_address: Some(device),
+                        status: NwkStatus::Success,
+                    }),
+                ]
+            }
+        }
+    }
+
+    /// Leave the network: ask the children to leave if required, tell the
+    /// neighbors and drop the network state.
+    fn leave<R: Rng>(&self, remove_children: bool, rejoin: bool, tables: &mut LeaveTables<R>)
+                     -> Vec<LeaveAction> {
+        let mut actions: Vec<LeaveAction> = Vec::new();
+        if remove_children {
+            actions.extend(tables.neighbors.children().map(|child| LeaveAction::Send {
+                destination: child.network_address,
+                command: Leave { request: true, remove_children: true, rejoin },
+            }));
+        }
+        actions.push(LeaveAction::Send {
+            destination: BROADCAST_RX_ON_WHEN_IDLE,
+            command: Leave { request: false, remove_children, rejoin },
+        });
+        tables.clear(rejoin);
+        actions.push(LeaveAction::Confirm(LeaveConfirm {
+            device_address: None,
+            status: NwkStatus::Success,
+        }));
+        actions
+    }
+
+    /// Handle a leave command received from the device with network
+    /// address `source` and IEEE address `source_ieee_address`.
+    pub fn handle_leave_command<R: Rng>(&self, source: u16, source_ieee_address: u64,
+                                        command: &Leave, tables: &mut LeaveTables<R>)
+                                        -> Vec<LeaveAction> {
+        if command.request {
+            let from_parent = tables.neighbors.parent()
+                .is_some_and(|parent| parent.network_address == source);
+            if !from_parent && source != self.trust_center_address && !self.leave_request_allowed {
+                return Vec::new();
+            }
+            let mut actions = self.leave(command.remove_children, command.rejoin, tables);
+            actions.push(LeaveAction::Indication(LeaveIndication {
+                device_address: None,
+                rejoin: command.rejoin,
+            }));
+            actions
+        } else {
+            tables.remove_device(source_ieee_address, source, command.rejoin);
+            vec![LeaveAction::Indication(LeaveIndication {
+                device_address: Some(source_ieee_address),
+                rejoin: command.rejoin,
+            })]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+    use apl::AddrAndEp;
+    use apl::aps::BindRequest;
+    use apl::device_profile::Relationship;
+    use apl::framework::LogicalType;
+    use nwk::addressing::{AddressAllocator, TreeAddressAllocator, TreeParameters};
+    use nwk::neighbor::NeighborTableEntry;
+    use types::{ClusterId, Endpoint, GroupId};
+
+    const NETWORK: u64 = 0x00AB_CDEF_0000_0001;
+    const PARENT: u64 = 0x0000_0000_0000_0001;
+    const END_DEVICE: u64 = 0x0000_0000_0000_0002;
+    const ROUTER: u64 = 0x0000_0000_0000_0003;
+    const OTHER: u64 = 0x0000_0000_0000_0004;
+
+    /// A router at depth 1, with an end device and a router as children.
+    struct Device {
+        neighbors: NeighborTable,
+        routing: RoutingTable,
+        addresses: AddressManager<StepRng>,
+        bindings: BindingTable,
+        groups: GroupTable,
+        end_device: u16,
+        router: u16,
+    }
+
+    impl Device {
+        fn new() -> Self {
+            let params = TreeParameters { max_children: 4, max_routers: 2, max_depth: 3 };
+            let allocator = TreeAddressAllocator::new(params, 0x0001, 1);
+            let mut addresses = AddressManager::new(NETWORK, 0x0001, AddressAllocator::Tree(allocator));
+            let end_device = addresses.allocate_child_address(END_DEVICE, false).unwrap();
+            let router = addresses.allocate_child_address(ROUTER, true).unwrap();
+
+            let mut neighbors = NeighborTable::new(NETWORK, 4);
+            for &(ieee, address, device_type, relationship) in &[
+                (PARENT, 0x0000, LogicalType::Coordinator, Relationship::Parent),
+                (END_DEVICE, end_device, LogicalType::EndDevice, Relationship::Child),
+                (ROUTER, router, LogicalType::Router, Relationship::Child),
+            ] {
+                neighbors.insert(NeighborTableEntry::new(ieee, address, device_type, relationship)).unwrap();
+            }
+
+            let mut bindings = BindingTable::new(4);
+            bindings.bind(&BindRequest {
+                src_addr: IeeeAddress(NETWORK),
+                src_endpoint: Endpoint(1),
+                cluster_id: ClusterId(0x0006),
+                dst_addr: AddrAndEp::LongAddress(IeeeAddress(END_DEVICE), Endpoint(1)),
+            });
+            let mut groups = GroupTable::new(4);
+            groups.add(GroupId(0x0001), Endpoint(1));
+            Device {
+                neighbors,
+                routing: RoutingTable::new(4),
+                addresses,
+                bindings,
+                groups,
+                end_device,
+                router,
+            }
+        }
+
+        fn tables(&mut self) -> LeaveTables<'_, StepRng> {
+            LeaveTables {
+                neighbors: &mut self.neighbors,
+                routing: &mut self.routing,
+                addresses: &mut self.addresses,
+                bindings: &mut self.bindings,
+                groups: &mut self.groups,
+            }
+        }
+    }
+
+    fn confirm(device_address: Option<u64>, status: NwkStatus) -> LeaveAction {
+        LeaveAction::Confirm(LeaveConfirm { device_address, status })
+    }
+
+    #[test]
+    fn leave_with_the_children() {
+        let mut device = Device::new();
+        let request = LeaveRequest { device_address: None, remove_children: true, rejoin: false };
+        let actions = LeaveHandler::default().leave_request(&request, &mut device.tables());
+        let remove = Leave { request: true, remove_children: true, rejoin: false };
+        assert_eq!(actions, vec![
+            LeaveAction::Send { destination: device.end_device, command: remove },
+            LeaveAction::Send { destination: device.router, command: remove },
+            LeaveAction::Send {
+                destination: BROADCAST_RX_ON_WHEN_IDLE,
+                command: Leave { request: false, remove_children: true, rejoin: false },
+            },
+            confirm(None, NwkStatus::Success),
+        ]);
+        assert!(device.neighbors.is_empty());
+        assert!(device.bindings.is_empty());
+        assert_eq!(device.groups.iter().count(), 0);
+        assert_eq!(device.addresses.address_map().network_address(END_DEVICE), None);
+    }
+
+    #[test]
+    fn leave_to_rejoin_keeps_the_application_tables() {
+        let mut device = Device::new();
+        let request = LeaveRequest { device_address: None, remove_children: false, rejoin: true };
+        let actions = LeaveHandler::default().leave_request(&request, &mut device.tables());
+        assert_eq!(actions, vec![
+            LeaveAction::Send {
+                destination: BROADCAST_RX_ON_WHEN_IDLE,
+                command: Leave { request: false, remove_children: false, rejoin: true },
+            },
+            confirm(None, NwkStatus::Success),
+        ]);
+        assert!(device.neighbors.is_empty());
+        assert_eq!(device.bindings.len(), 1);
+        assert_eq!(device.groups.iter().count(), 1);
+    }
+
+    #[test]
+    fn remove_a_child() {
+        let mut device = Device::new();
+        let request = LeaveRequest { device_address: Some(END_DEVICE), remove_children: false, rejoin: false };
+        let actions = LeaveHandler::default().leave_request(&request, &mut device.tables());
+        assert_eq!(actions, vec![
+            LeaveAction::Send {
+                destination: device.end_device,
+                command: Leave { request: true, remove_children: false, rejoin: false },
+            },
+            confirm(Some(END_DEVICE), NwkStatus::Success),
+        ]);
+        assert_eq!(device.neighbors.get_by_extended(END_DEVICE), None);
+        assert_eq!(device.addresses.address_map().network_address(END_DEVICE), None);
+        assert!(device.bindings.is_empty());
+        assert_eq!(device.neighbors.len(), 2);
+
+        // Only children can be removed.
+        for &address in &[END_DEVICE, PARENT, OTHER] {
+            let request = LeaveRequest { device_address: Some(address), remove_children: false, rejoin: false };
+            assert_eq!(LeaveHandler::default().leave_request(&request, &mut device.tables()),
+                       vec![confirm(Some(address), NwkStatus::UnknownDevice)]);
+        }
+        assert_eq!(device.neighbors.len(), 2);
+    }
+
+    #[test]
+    fn leave_requests_from_other_devices_may_be_refused() {
+        let handler = LeaveHandler { leave_request_allowed: false, trust_center_address: 0x1234 };
+        let command = Leave { request: true, remove_children: false, rejoin: true };
+        let mut device = Device::new();
+        assert_eq!(handler.handle_leave_command(0x5678, OTHER, &command, &mut device.tables()), vec![]);
+        assert_eq!(device.neighbors.len(), 3);
+
+        for &(source, ieee) in &[(0x0000, PARENT), (0x1234, OTHER)] {
+            let mut device = Device::new();
+            let actions = handler.handle_leave_command(source, ieee, &command, &mut device.tables());
+            assert_eq!(actions.last(),
+                       Some(&LeaveAction::Indication(LeaveIndication { device_address: None, rejoin: true })));
+            assert!(device.neighbors.is_empty());
+        }
+
+        let mut device = Device::new();
+        let actions = LeaveHandler::default().handle_leave_command(0x5678, OTHER, &command, &mut device.tables());
+        assert_eq!(actions.len(), 3);
+        assert!(device.neighbors.is_empty());
+    }
+
+    #[test]
+    fn neighbor_leaving() {
+        let mut device = Device::new();
+        let command = Leave { request: false, remove_children: false, rejoin: true };
+        let actions = LeaveHandler::default()
+            .handle_leave_command(device.end_device, END_DEVICE, &command, &mut device.tables());
+        assert_eq!(actions, vec![
+            LeaveAction::Indication(LeaveIndication { device_address: Some(END_DEVICE), rejoin: true }),
+        ]);
+        // The device is going to rejoin: it keeps its address and bindings.
+        assert_eq!(device.neighbors.get_by_extended(END_DEVICE), None);
+        assert_eq!(device.addresses.address_map().network_address(END_DEVICE), Some(device.end_device));
+        assert_eq!(device.bindings.len(), 1);
+
+        let command = Leave { request: false, remove_children: false, rejoin: false };
+        let router = device.router;
+        LeaveHandler::default().handle_leave_command(router, ROUTER, &command, &mut device.tables());
+        assert_eq!(device.neighbors.len(), 1);
+        assert_eq!(device.addresses.address_map().network_address(ROUTER), None);
+    }
+}
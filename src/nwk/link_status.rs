@@ -0,0 +1,265 @@
+//! The exchange of link status commands between routers, as described in
+//! section 3.6.3.4 of the specification.
+//!
+//! Every router periodically tells its neighboring routers the cost of the
+//! links it receives them with. Each neighbor learns this way the cost of
+//! its outgoing link, and routers that are not heard for a few periods are
+//! considered gone.
+
+use std::time::Duration;
+use rand::Rng;
+use apl::device_profile::Relationship;
+use apl::framework::LogicalType;
+use nwk::commands::{LinkStatus, LinkStatusEntry};
+use nwk::neighbor::{NeighborTable, NeighborTableEntry};
+
+pub struct LinkStatusConfig {
+    /// The time between two link status commands (nwkLinkStatusPeriod).
+    pub period: Duration,
+    /// The maximum random delay added to the period.
+    pub max_jitter: Duration,
+    /// The number of periods after which a silent router is considered
+    /// gone (nwkRouterAgeLimit).
+    pub router_age_limit: u8,
+}
+
+impl Default for LinkStatusConfig {
+    fn default() -> Self {
+        LinkStatusConfig {
+            period: Duration::from_secs(15),
+            max_jitter: Duration::from_secs(1),
+            router_age_limit: 3,
+        }
+    }
+}
+
+/// What happened at the end of a link status period.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkStatusUpdate {
+    /// The link status commands to broadcast to the neighboring routers
+    /// (0xFFFC) with a radius of 1.
+    pub frames: Vec<LinkStatus>,
+    /// The routers removed from the neighbor table because they were not
+    /// heard for too long.
+    pub removed: Vec<NeighborTableEntry>,
+}
+
+fn is_router(entry: &NeighborTableEntry) -> bool {
+    entry.device_type != LogicalType::EndDevice
+}
+
+/// The state of the link status exchange of a router, generic over the
+/// random number generator used for the jitter.
+pub struct LinkStatusManager<R> {
+    config: LinkStatusConfig,
+    rng: R,
+    next_period: Duration,
+}
+
+impl<R: Rng> LinkStatusManager<R> {
+    /// Create the manager. The first link status is sent at the first call
+    /// to `poll`.
+    pub fn new(config: LinkStatusConfig, rng: R) -> Self {
+        LinkStatusManager { config, rng, next_period: Duration::from_secs(0) }
+    }
+
+    pub fn config(&self) -> &LinkStatusConfig {
+        &self.config
+    }
+
+    /// The time of the next link status period.
+    pub fn next_deadline(&self) -> Duration {
+        self.next_period
+    }
+
+    /// Age the neighboring routers and build the link status commands, if
+    /// a period ended at time `now`.
+    pub fn poll(&mut self, neighbors: &mut NeighborTable, now: Duration) -> Option<LinkStatusUpdate> {
+        if now < self.next_period {
+            return None;
+        }
+        let jitter = self.config.max_jitter.as_micros() as u64;
+        self.next_period = now + self.config.period
+            + Duration::from_micros(self.rng.gen_range(0..=jitter));
+        let removed = self.age(neighbors);
+        Some(LinkStatusUpdate { frames: link_status_frames(neighbors), removed })
+    }
+
+    fn age(&self, neighbors: &mut NeighborTable) -> Vec<NeighborTableEntry> {
+        let limit = self.config.router_age_limit;
+        let mut stale = Vec::new();
+        for entry in neighbors.iter_mut().filter(|e| is_router(e)) {
+            entry.age = entry.age.saturating_add(1);
+            if entry.age > limit {
+                entry.outgoing_cost = 0;
+                // The parent and the children are tracked by the join and
+                // leave procedures.
+                match entry.relationship {
+                    Relationship::Parent | Relationship::Child => {}
+                    _ => stale.push(entry.extended_address),
+                }
+            }
+        }
+        stale.into_iter().filter_map(|address| neighbors.remove(address)).collect()
+    }
+
+    /// Handle a link status command received from the neighbor `sender`
+    /// with link quality `lqi`. Return `false` if the sender is not in the
+    /// neighbor table.
+    pub fn handle_link_status(&self, sender: u16, lqi: u8, own_address: u16,
+                              command: &LinkStatus, neighbors: &mut NeighborTable) -> bool {
+        let entry = match neighbors.get_mut(sender) {
+            Some(entry) => entry,
+            None => return false,
+        };
+        entry.lqi = lqi;
+        entry.age = 0;
+        // Routers are not listed when they are not heard by the sender: the
+        // link is then unusable in that direction.
+        entry.outgoing_cost = command.entries.iter()
+            .find(|e| e.address == own_address)
+            .map_or(0, |e| e.incoming_cost);
+        true
+    }
+}
+
+/// Build the link status commands that describe the links with the
+/// neighboring routers, sorted by address and split in as many frames as
+/// needed.
+pub fn link_status_frames(neighbors: &NeighborTable) -> Vec<LinkStatus> {
+    let mut entries: Vec<LinkStatusEntry> = neighbors.iter()
+        .filter(|e| is_router(e))
+        .map(|e| LinkStatusEntry {
+            address: e.network_address,
+            incoming_cost: e.incoming_cost(),
+            outgoing_cost: e.outgoing_cost,
+        })
+        .collect();
+    entries.sort_by_key(|e| e.address);
+    if entries.is_empty() {
+        return vec![LinkStatus { first_frame: true, last_frame: true, entries }];
+    }
+    let count = entries.len().div_ceil(LinkStatus::MAX_ENTRIES);
+    entries.chunks(LinkStatus::MAX_ENTRIES).enumerate().map(|(i, chunk)| LinkStatus {
+        first_frame: i == 0,
+        last_frame: i == count - 1,
+        entries: chunk.to_vec(),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::mock::StepRng;
+
+    const OWN_ADDRESS: u16 = 0x0001;
+
+    fn neighbor(address: u16, device_type: LogicalType, relationship: Relationship, lqi: u8) -> NeighborTableEntry {
+        let mut entry = NeighborTableEntry::new(u64::from(address) << 8, address, device_type, relationship);
+        entry.lqi = lqi;
+        entry
+    }
+
+    fn manager() -> LinkStatusManager<StepRng> {
+        LinkStatusManager::new(LinkStatusConfig { max_jitter: Duration::from_secs(0), ..LinkStatusConfig::default() },
+                               StepRng::new(0, 0))
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn frames_list_the_routers_by_address() {
+        let mut neighbors = NeighborTable::new(0, 8);
+        assert_eq!(link_status_frames(&neighbors),
+                   vec![LinkStatus { first_frame: true, last_frame: true, entries: vec![] }]);
+
+        neighbors.insert(neighbor(0x0300, LogicalType::Router, Relationship::Sibling, 255)).unwrap();
+        neighbors.insert(neighbor(0x0200, LogicalType::EndDevice, Relationship::Child, 255)).unwrap();
+        neighbors.insert(neighbor(0x0100, LogicalType::Coordinator, Relationship::Parent, 100)).unwrap();
+        neighbors.get_mut(0x0100).unwrap().outgoing_cost = 3;
+        assert_eq!(link_status_frames(&neighbors), vec![LinkStatus {
+            first_frame: true,
+            last_frame: true,
+            entries: vec![
+                LinkStatusEntry { address: 0x0100, incoming_cost: 5, outgoing_cost: 3 },
+                LinkStatusEntry { address: 0x0300, incoming_cost: 1, outgoing_cost: 0 },
+            ],
+        }]);
+    }
+
+    #[test]
+    fn long_lists_are_split() {
+        for &(routers, sizes) in &[(31, &[31][..]), (32, &[31, 1][..]), (70, &[31, 31, 8][..])] {
+            let mut neighbors = NeighborTable::new(0, routers);
+            for address in 0..routers as u16 {
+                neighbors.insert(neighbor(0x1000 - address, LogicalType::Router, Relationship::Sibling, 255))
+                    .unwrap();
+            }
+            let frames = link_status_frames(&neighbors);
+            assert_eq!(frames.iter().map(|f| f.entries.len()).collect::<Vec<_>>(), sizes);
+            assert!(frames[0].first_frame && frames.last().unwrap().last_frame);
+            assert_eq!(frames.iter().filter(|f| f.first_frame || f.last_frame).count(), frames.len().min(2));
+            let addresses: Vec<u16> = frames.iter().flat_map(|f| f.entries.iter().map(|e| e.address)).collect();
+            assert!(addresses.windows(2).all(|w| w[0] < w[1]));
+        }
+    }
+
+    #[test]
+    fn periods() {
+        let mut manager = manager();
+        let mut neighbors = NeighborTable::new(0, 4);
+        assert!(manager.poll(&mut neighbors, secs(0)).is_some());
+        assert_eq!(manager.next_deadline(), secs(15));
+        assert_eq!(manager.poll(&mut neighbors, secs(14)), None);
+        assert!(manager.poll(&mut neighbors, secs(15)).is_some());
+    }
+
+    #[test]
+    fn silent_routers_are_removed() {
+        let mut manager = manager();
+        let mut neighbors = NeighborTable::new(0, 4);
+        neighbors.insert(neighbor(0x0100, LogicalType::Coordinator, Relationship::Parent, 255)).unwrap();
+        neighbors.insert(neighbor(0x0200, LogicalType::Router, Relationship::Sibling, 255)).unwrap();
+        neighbors.insert(neighbor(0x0300, LogicalType::EndDevice, Relationship::Child, 255)).unwrap();
+        neighbors.get_mut(0x0100).unwrap().outgoing_cost = 1;
+
+        for period in 0..3 {
+            let update = manager.poll(&mut neighbors, secs(15 * period)).unwrap();
+            assert!(update.removed.is_empty());
+        }
+        let update = manager.poll(&mut neighbors, secs(45)).unwrap();
+        assert_eq!(update.removed.iter().map(|e| e.network_address).collect::<Vec<_>>(), vec![0x0200]);
+        // The parent is kept, but its link is considered broken.
+        assert_eq!(neighbors.get(0x0100).unwrap().outgoing_cost, 0);
+        assert_eq!(neighbors.get(0x0300).unwrap().age, 0);
+        assert_eq!(update.frames[0].entries.len(), 1);
+    }
+
+    #[test]
+    fn received_link_status() {
+        let manager = manager();
+        let mut neighbors = NeighborTable::new(0, 4);
+        neighbors.insert(neighbor(0x0200, LogicalType::Router, Relationship::Sibling, 0)).unwrap();
+        neighbors.get_mut(0x0200).unwrap().age = 2;
+
+        let command = LinkStatus {
+            first_frame: true,
+            last_frame: true,
+            entries: vec![
+                LinkStatusEntry { address: OWN_ADDRESS, incoming_cost: 2, outgoing_cost: 1 },
+                LinkStatusEntry { address: 0x0300, incoming_cost: 7, outgoing_cost: 7 },
+            ],
+        };
+        assert!(manager.handle_link_status(0x0200, 180, OWN_ADDRESS, &command, &mut neighbors));
+        let entry = neighbors.get(0x0200).unwrap();
+        assert_eq!((entry.lqi, entry.age, entry.outgoing_cost), (180, 0, 2));
+
+        // A sender that does not list this device does not hear it.
+        let command = LinkStatus { entries: command.entries[1..].to_vec(), ..command };
+        assert!(manager.handle_link_status(0x0200, 180, OWN_ADDRESS, &command, &mut neighbors));
+        assert_eq!(neighbors.get(0x0200).unwrap().outgoing_cost, 0);
+        assert!(!manager.handle_link_status(0x0400, 180, OWN_ADDRESS, &command, &mut neighbors));
+    }
+}
@@ -0,0 +1,107 @@
+//! this module contains traits and data structures for the network layer.
+pub mod frame_format;
+pub mod commands;
+pub mod routing;
+pub mod addressing;
+pub mod neighbor;
+pub mod formation;
+pub mod join;
+pub mod leave;
+pub mod broadcast;
+pub mod link_status;
+pub mod end_device;
+pub mod indirect;
+pub mod frequency_agility;
+pub mod pan_id_conflict;
+pub mod security;
+
+use std::error;
+use std::fmt;
+use mac::MacStatus;
+use security::SecurityError;
+
+/// Broadcast to all the devices in the PAN.
+pub const BROADCAST_ALL: u16 = 0xFFFF;
+/// Broadcast to all the devices with macRxOnWhenIdle = TRUE.
+pub const BROADCAST_RX_ON_WHEN_IDLE: u16 = 0xFFFD;
+/// Broadcast to all the routers and the coordinator.
+pub const BROADCAST_ROUTERS: u16 = 0xFFFC;
+/// Broadcast to the low power routers only.
+pub const BROADCAST_LOW_POWER_ROUTERS: u16 = 0xFFFB;
+
+status_codes! {
+    /// The status values of the NLME confirm primitives.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum NwkStatus {
+        Success = 0x00,
+        InvalidParameter = 0xC1,
+        InvalidRequest = 0xC2,
+        NotPermitted = 0xC3,
+        StartupFailure = 0xC4,
+        AlreadyPresent = 0xC5,
+        SyncFailure = 0xC6,
+        NeighborTableFull = 0xC7,
+        UnknownDevice = 0xC8,
+        UnsupportedAttribute = 0xC9,
+        NoNetworks = 0xCA,
+        MaxFrmCounter = 0xCC,
+        NoKey = 0xCD,
+        BadCcmOutput = 0xCE,
+        RouteDiscoveryFailed = 0xD0,
+        RouteError = 0xD1,
+        BtTableFull = 0xD2,
+        FrameNotBuffered = 0xD3,
+    }
+}
+
+/// The failure of a NWK request: a NLME status, or the failure of the MAC
+/// or of the security processing that caused it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NwkError {
+    Status(NwkStatus),
+    Mac(MacStatus),
+    Security(SecurityError),
+}
+
+impl fmt::Display for NwkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            NwkError::Status(ref status) => write!(f, "{}", status),
+            NwkError::Mac(ref status) => write!(f, "MAC: {}", status),
+            NwkError::Security(ref error) => write!(f, "security: {}", error),
+        }
+    }
+}
+
+impl error::Error for NwkError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            NwkError::Status(_) => None,
+            NwkError::Mac(ref status) => Some(status),
+            NwkError::Security(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<NwkStatus> for NwkError {
+    fn from(status: NwkStatus) -> Self {
+        NwkError::Status(status)
+    }
+}
+
+impl From<MacStatus> for NwkError {
+    fn from(status: MacStatus) -> Self {
+        NwkError::Mac(status)
+    }
+}
+
+impl From<SecurityError> for NwkError {
+    fn from(error: SecurityError) -> Self {
+        NwkError::Security(error)
+    }
+}
+
+pub trait NlmeSap{
+    fn nlme_get(&self);
+    fn nlme_set(&self);
+}
@@ -0,0 +1,197 @@
+//! The neighbor table, as described in section 3.6.1.5 of the
+//! specification.
+
+use std::time::Duration;
+use apl::device_profile::{NeighborTableListRecord, Relationship};
+use apl::framework::LogicalType;
+use types::{ExtendedPanId, IeeeAddress, NwkAddress};
+use Unknownable;
+
+/// Estimate the cost of a link from the link quality indicator reported
+/// by the MAC, in the range 1 (best) to 7 (worst).
+pub fn link_cost(lqi: u8) -> u8 {
+    match lqi {
+        0..=39 => 7,
+        40..=79 => 6,
+        80..=119 => 5,
+        120..=159 => 4,
+        160..=199 => 3,
+        200..=239 => 2,
+        _ => 1,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NeighborTableEntry {
+    pub extended_address: u64,
+    pub network_address: u16,
+    pub device_type: LogicalType,
+    pub rx_on_when_idle: bool,
+    pub relationship: Relationship,
+    pub depth: u8,
+    pub permit_joining: bool,
+    /// Whether the neighbor can be chosen as parent when joining or
+    /// rejoining.
+    pub potential_parent: bool,
+    pub lqi: u8,
+    /// The cost of the link towards the neighbor, as reported by the
+    /// neighbor in its link status commands. Zero when unknown.
+    pub outgoing_cost: u8,
+    /// The number of link status periods since the last link status
+    /// received from the neighbor.
+    pub age: u8,
+    /// For end device children, the time after which the child is removed
+    /// if it did not send any keep-alive.
+    pub keepalive_deadline: Option<Duration>,
+}
+
+impl NeighborTableEntry {
+    pub fn new(extended_address: u64, network_address: u16, device_type: LogicalType,
+               relationship: Relationship) -> Self {
+        NeighborTableEntry {
+            extended_address,
+            network_address,
+            device_type,
+            rx_on_when_idle: device_type != LogicalType::EndDevice,
+            relationship,
+            depth: 0,
+            permit_joining: false,
+            potential_parent: false,
+            lqi: 0,
+            outgoing_cost: 0,
+            age: 0,
+            keepalive_deadline: None,
+        }
+    }
+
+    /// The cost of the link with the neighbor, estimated from the quality
+    /// of the frames received from it.
+    pub fn incoming_cost(&self) -> u8 {
+        link_cost(self.lqi)
+    }
+
+    /// The cost of the link used for routing: the worst of the two
+    /// directions, if the outgoing cost is known.
+    pub fn link_cost(&self) -> u8 {
+        self.incoming_cost().max(self.outgoing_cost)
+    }
+
+    pub fn is_child(&self) -> bool {
+        self.relationship == Relationship::Child
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NeighborTableFull;
+
+pub struct NeighborTable {
+    extended_pan_id: u64,
+    entries: Vec<NeighborTableEntry>,
+    capacity: usize,
+}
+
+impl NeighborTable {
+    pub fn new(extended_pan_id: u64, capacity: usize) -> Self {
+        NeighborTable { extended_pan_id, entries: Vec::with_capacity(capacity), capacity }
+    }
+
+    pub fn extended_pan_id(&self) -> u64 {
+        self.extended_pan_id
+    }
+
+    pub fn set_extended_pan_id(&mut self, extended_pan_id: u64) {
+        self.extended_pan_id = extended_pan_id;
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &NeighborTableEntry> {
+        self.entries.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut NeighborTableEntry> {
+        self.entries.iter_mut()
+    }
+
+    pub fn children(&self) -> impl Iterator<Item = &NeighborTableEntry> {
+        self.entries.iter().filter(|e| e.is_child())
+    }
+
+    pub fn get(&self, network_address: u16) -> Option<&NeighborTableEntry> {
+        self.entries.iter().find(|e| e.network_address == network_address)
+    }
+
+    pub fn get_mut(&mut self, network_address: u16) -> Option<&mut NeighborTableEntry> {
+        self.entries.iter_mut().find(|e| e.network_address == network_address)
+    }
+
+    pub fn get_by_extended(&self, extended_address: u64) -> Option<&NeighborTableEntry> {
+        self.entries.iter().find(|e| e.extended_address == extended_address)
+    }
+
+    pub fn get_by_extended_mut(&mut self, extended_address: u64) -> Option<&mut NeighborTableEntry> {
+        self.entries.iter_mut().find(|e| e.extended_address == extended_address)
+    }
+
+    /// Insert a new neighbor, or replace the entry with the same extended
+    /// address.
+    pub fn insert(&mut self, entry: NeighborTableEntry) -> Result<(), NeighborTableFull> {
+        if let Some(existing) = self.get_by_extended_mut(entry.extended_address) {
+            *existing = entry;
+            return Ok(());
+        }
+        if self.entries.len() >= self.capacity {
+            return Err(NeighborTableFull);
+        }
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, extended_address: u64) -> Option<NeighborTableEntry> {
+        self.entries.iter()
+            .position(|e| e.extended_address == extended_address)
+            .map(|i| self.entries.swap_remove(i))
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Update the link quality of the neighbor that sent a frame. Return
+    /// `false` if the neighbor is not in the table.
+    pub fn heard(&mut self, network_address: u16, lqi: u8) -> bool {
+        match self.get_mut(network_address) {
+            Some(entry) => {
+                entry.lqi = lqi;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn parent(&self) -> Option<&NeighborTableEntry> {
+        self.entries.iter().find(|e| e.relationship == Relationship::Parent)
+    }
+
+    /// The content of the neighbor table in the format used by the
+    /// Mgmt_Lqi_rsp command.
+    pub fn records(&self) -> Vec<NeighborTableListRecord> {
+        self.entries.iter().map(|e| NeighborTableListRecord {
+            extended_pan_id: ExtendedPanId(self.extended_pan_id),
+            extended_address: IeeeAddress(e.extended_address),
+            network_address: NwkAddress(e.network_address),
+            device_type: Unknownable::Known(e.device_type),
+            rx_on_when_idle: Unknownable::Known(e.rx_on_when_idle),
+            relationship: e.relationship,
+            permit_joining: Unknownable::Known(e.permit_joining),
+            depth: e.depth,
+            lqi: e.lqi,
+        }).collect()
+    }
+}
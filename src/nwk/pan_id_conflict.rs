@@ -0,0 +1,401 @@
+//! The detection and resolution of PAN identifier conflicts, as described
+//! in section 3.6.1.13 of the specification.
+//!
+//! Two networks with the same PAN identifier on the same channel disturb
+//! each other. A router that hears, on its PAN identifier, a beacon or a
+//! NWK frame carrying the extended PAN identifier of another network
+//! reports the conflict to the network manager, with the list of the PAN
+//! identifiers heard around it. The network manager
+//! chooses a new PAN identifier and broadcasts a network update command;
+//! all the devices move to it after the broadcast delivery time.
+
+use std::collections::BTreeSet;
+use std::time::Duration;
+use rand::Rng;
+use codec::Reader;
+use mac::{MlmeSap, PanDescriptor};
+use nwk::commands::{NetworkReport, NetworkUpdate};
+use nwk::formation::choose_pan_id;
+use nwk::frame_format::BeaconPayload;
+
+/// Whether `update_id` is more recent than `current`, taking the wrap
+/// around into account.
+pub fn is_newer_update_id(update_id: u8, current: u8) -> bool {
+    (update_id.wrapping_sub(current) as i8) > 0
+}
+
+pub struct PanIdConflictConfig {
+    /// Whether this device reports the conflicts. End devices only apply
+    /// the updates.
+    pub report: bool,
+    /// The minimum time between two reports.
+    pub report_interval: Duration,
+    /// The time to wait before moving to the new PAN identifier
+    /// (nwkNetworkBroadcastDeliveryTime).
+    pub switch_delay: Duration,
+}
+
+impl Default for PanIdConflictConfig {
+    fn default() -> Self {
+        PanIdConflictConfig {
+            report: true,
+            report_interval: Duration::from_secs(60),
+            switch_delay: Duration::from_secs(9),
+        }
+    }
+}
+
+/// The outcome of the events handled by a `PanIdConflictMonitor`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PanIdEvent {
+    /// A network report command must be sent to the network manager.
+    Report { destination: u16, report: NetworkReport },
+    /// The device moved to a new PAN identifier.
+    PanIdChanged { pan_id: u16, nwk_update_id: u8 },
+}
+
+/// The device side: detects the conflicts and applies the updates.
+/// Generic over the MAC it drives.
+pub struct PanIdConflictMonitor<M> {
+    mac: M,
+    config: PanIdConflictConfig,
+    pan_id: u16,
+    extended_pan_id: u64,
+    nwk_update_id: u8,
+    nwk_manager_address: u16,
+    /// The PAN identifiers heard in the beacons of the neighborhood.
+    neighborhood: Vec<u16>,
+    last_report: Option<Duration>,
+    pending_update: Option<(u16, u8, Duration)>,
+}
+
+impl<M: MlmeSap> PanIdConflictMonitor<M> {
+    pub fn new(mac: M, config: PanIdConflictConfig, pan_id: u16, extended_pan_id: u64,
+               nwk_update_id: u8) -> Self {
+        PanIdConflictMonitor {
+            mac,
+            config,
+            pan_id,
+            extended_pan_id,
+            nwk_update_id,
+            nwk_manager_address: 0x0000,
+            neighborhood: Vec::new(),
+            last_report: None,
+            pending_update: None,
+        }
+    }
+
+    pub fn mac(&self) -> &M {
+        &self.mac
+    }
+
+    pub fn pan_id(&self) -> u16 {
+        self.pan_id
+    }
+
+    pub fn nwk_update_id(&self) -> u8 {
+        self.nwk_update_id
+    }
+
+    pub fn set_nwk_manager_address(&mut self, address: u16) {
+        self.nwk_manager_address = address;
+    }
+
+    /// Handle a beacon received during a scan or heard passively. A beacon
+    /// of another Zigbee network using the PAN identifier of this one is a
+    /// conflict, reported to the network manager.
+    pub fn handle_beacon(&mut self, descriptor: &PanDescriptor, now: Duration) -> Option<PanIdEvent> {
+        if !self.neighborhood.contains(&descriptor.coord_pan_id) {
+            self.neighborhood.push(descriptor.coord_pan_id);
+        }
+        if descriptor.coord_pan_id != self.pan_id {
+            return None;
+        }
+        let payload = BeaconPayload::decode(&mut Reader::new(&descriptor.beacon_payload)).ok()?;
+        self.handle_frame(descriptor.coord_pan_id, payload.extended_pan_id, now)
+    }
+
+    /// Handle a NWK frame received on the PAN `pan_id`, as found in its MAC
+    /// header, that carries the extended PAN identifier of its network,
+    /// such as the network report and update commands. A frame of another
+    /// network using the PAN identifier of this one is a conflict too.
+    pub fn handle_frame(&mut self, pan_id: u16, extended_pan_id: u64, now: Duration) -> Option<PanIdEvent> {
+        if pan_id != self.pan_id || extended_pan_id == self.extended_pan_id || !self.config.report {
+            return None;
+        }
+        // The network manager may already be resolving it.
+        if self.pending_update.is_some() {
+            return None;
+        }
+        if let Some(last) = self.last_report {
+            if now < last + self.config.report_interval {
+                return None;
+            }
+        }
+        self.last_report = Some(now);
+        Some(PanIdEvent::Report {
+            destination: self.nwk_manager_address,
+            report: NetworkReport::PanIdConflict {
+                extended_pan_id: self.extended_pan_id,
+                pan_ids: self.neighborhood.clone(),
+            },
+        })
+    }
+
+    /// Handle a network update command broadcast by the network manager.
+    pub fn handle_network_update(&mut self, update: &NetworkUpdate, now: Duration) {
+        match *update {
+            NetworkUpdate::PanIdUpdate { extended_pan_id, update_id, new_pan_id } => {
+                if extended_pan_id != self.extended_pan_id
+                    || !is_newer_update_id(update_id, self.nwk_update_id) {
+                    return;
+                }
+                self.pending_update = Some((new_pan_id, update_id, now + self.config.switch_delay));
+            }
+        }
+    }
+
+    /// Move to the new PAN identifier if an update was received and the
+    /// delay elapsed.
+    pub fn tick(&mut self, now: Duration) -> Option<PanIdEvent> {
+        match self.pending_update {
+            Some((pan_id, nwk_update_id, at)) if now >= at => {
+                self.pending_update = None;
+                self.pan_id = pan_id;
+                self.nwk_update_id = nwk_update_id;
+                self.neighborhood.clear();
+                self.mac.set_pan_id(pan_id);
+                Some(PanIdEvent::PanIdChanged { pan_id, nwk_update_id })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// The network manager side: chooses the new PAN identifier. Generic over
+/// the random number generator used to choose it.
+///
+/// The update is broadcast to all the devices (0xFFFF), and must be
+/// handled by the `PanIdConflictMonitor` of the network manager too.
+pub struct PanIdConflictResolver<R> {
+    rng: R,
+    extended_pan_id: u64,
+    pan_id: u16,
+    nwk_update_id: u8,
+}
+
+impl<R: Rng> PanIdConflictResolver<R> {
+    pub fn new(rng: R, extended_pan_id: u64, pan_id: u16, nwk_update_id: u8) -> Self {
+        PanIdConflictResolver { rng, extended_pan_id, pan_id, nwk_update_id }
+    }
+
+    pub fn pan_id(&self) -> u16 {
+        self.pan_id
+    }
+
+    pub fn nwk_update_id(&self) -> u8 {
+        self.nwk_update_id
+    }
+
+    /// Handle a network report, sent by a device of the network or by the
+    /// network manager itself, and return the update to broadcast. The new
+    /// PAN identifier is not in use by any of the reported networks; there
+    /// is no update if they use all of them.
+    pub fn handle_network_report(&mut self, report: &NetworkReport) -> Option<NetworkUpdate> {
+        match *report {
+            NetworkReport::PanIdConflict { extended_pan_id, ref pan_ids } => {
+                if extended_pan_id != self.extended_pan_id {
+                    return None;
+                }
+                let in_use: BTreeSet<u16> = pan_ids.iter().cloned().collect();
+                let current = self.pan_id;
+                let pan_id = choose_pan_id(&mut self.rng, |pan_id| pan_id == current || in_use.contains(&pan_id))?;
+                self.pan_id = pan_id;
+                self.nwk_update_id = self.nwk_update_id.wrapping_add(1);
+                Some(NetworkUpdate::PanIdUpdate {
+                    extended_pan_id,
+                    update_id: self.nwk_update_id,
+                    new_pan_id: pan_id,
+                })
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nwk::formation::MAX_PAN_ID;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use mac::MacAddress;
+    use mac::mock::{MockMac, Request};
+
+    const PAN_ID: u16 = 0x1234;
+    /// Our network, and a neighboring one that chose the same PAN
+    /// identifier.
+    const OURS: u64 = 0x0011_2233_4455_6677;
+    const THEIRS: u64 = 0x8899_AABB_CCDD_EEFF;
+
+    fn beacon(pan_id: u16, extended_pan_id: u64) -> PanDescriptor {
+        let mut beacon_payload = Vec::new();
+        BeaconPayload {
+            stack_profile: 2,
+            protocol_version: 2,
+            router_capacity: true,
+            device_depth: 0,
+            end_device_capacity: true,
+            extended_pan_id,
+            tx_offset: 0x00FF_FFFF,
+            nwk_update_id: 0,
+        }.encode(&mut beacon_payload);
+        PanDescriptor {
+            coord_address: MacAddress::Short(0x0000),
+            coord_pan_id: pan_id,
+            logical_channel: 15,
+            pan_coordinator: true,
+            association_permit: true,
+            link_quality: 0xFF,
+            beacon_payload,
+        }
+    }
+
+    fn monitor(mac: &MockMac) -> PanIdConflictMonitor<&MockMac> {
+        PanIdConflictMonitor::new(mac, PanIdConflictConfig::default(), PAN_ID, OURS, 0)
+    }
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn conflict_between_two_networks() {
+        let coordinator_mac = MockMac::new(1);
+        let router_mac = MockMac::new(2);
+        let mut coordinator = monitor(&coordinator_mac);
+        let mut router = monitor(&router_mac);
+        let mut resolver = PanIdConflictResolver::new(StdRng::seed_from_u64(0), OURS, PAN_ID, 0);
+
+        // The router hears a beacon of its own network, then one of a
+        // network on another PAN, then one of the other network.
+        assert_eq!(router.handle_beacon(&beacon(PAN_ID, OURS), secs(0)), None);
+        assert_eq!(router.handle_beacon(&beacon(0x0042, THEIRS), secs(1)), None);
+        let report = match router.handle_beacon(&beacon(PAN_ID, THEIRS), secs(2)) {
+            Some(PanIdEvent::Report { destination: 0x0000, report }) => report,
+            event => panic!("unexpected event {:?}", event),
+        };
+        assert_eq!(report, NetworkReport::PanIdConflict { extended_pan_id: OURS, pan_ids: vec![PAN_ID, 0x0042] });
+
+        // The report crosses the network.
+        let mut payload = Vec::new();
+        report.encode_payload(&mut payload);
+        let report = NetworkReport::decode_payload(&mut Reader::new(&payload)).unwrap();
+
+        let update = resolver.handle_network_report(&report).unwrap();
+        let new_pan_id = match update {
+            NetworkUpdate::PanIdUpdate { extended_pan_id, update_id, new_pan_id } => {
+                assert_eq!(extended_pan_id, OURS);
+                assert_eq!(update_id, 1);
+                new_pan_id
+            }
+        };
+        assert!(new_pan_id != PAN_ID && new_pan_id != 0x0042);
+        assert!((0x0001..=MAX_PAN_ID).contains(&new_pan_id));
+        assert_eq!(resolver.pan_id(), new_pan_id);
+
+        // The update is broadcast, and every device moves after the
+        // broadcast delivery time.
+        coordinator.handle_network_update(&update, secs(3));
+        router.handle_network_update(&update, secs(3));
+        assert_eq!(router.handle_beacon(&beacon(PAN_ID, THEIRS), secs(4)), None);
+        assert_eq!(coordinator.tick(secs(11)), None);
+        assert_eq!(router.tick(secs(11)), None);
+        let changed = Some(PanIdEvent::PanIdChanged { pan_id: new_pan_id, nwk_update_id: 1 });
+        assert_eq!(coordinator.tick(secs(12)), changed);
+        assert_eq!(router.tick(secs(12)), changed);
+        assert_eq!(router.tick(secs(13)), None);
+        assert_eq!(coordinator_mac.take_requests(), vec![Request::SetPanId(new_pan_id)]);
+        assert_eq!(router_mac.take_requests(), vec![Request::SetPanId(new_pan_id)]);
+        assert_eq!(router.pan_id(), new_pan_id);
+        assert_eq!(router.nwk_update_id(), 1);
+
+        // The other network no longer conflicts.
+        assert_eq!(router.handle_beacon(&beacon(PAN_ID, THEIRS), secs(100)), None);
+
+        // The update is not applied twice.
+        router.handle_network_update(&update, secs(101));
+        assert_eq!(router.tick(secs(200)), None);
+    }
+
+    #[test]
+    fn reports_are_rate_limited() {
+        let mac = MockMac::new(2);
+        let mut router = monitor(&mac);
+        assert!(router.handle_beacon(&beacon(PAN_ID, THEIRS), secs(0)).is_some());
+        assert_eq!(router.handle_beacon(&beacon(PAN_ID, THEIRS), secs(59)), None);
+        assert!(router.handle_beacon(&beacon(PAN_ID, THEIRS), secs(60)).is_some());
+    }
+
+    #[test]
+    fn end_devices_do_not_report() {
+        let mac = MockMac::new(3);
+        let config = PanIdConflictConfig { report: false, ..Default::default() };
+        let mut end_device = PanIdConflictMonitor::new(&mac, config, PAN_ID, OURS, 0);
+        assert_eq!(end_device.handle_beacon(&beacon(PAN_ID, THEIRS), secs(0)), None);
+    }
+
+    #[test]
+    fn updates_of_other_networks_are_ignored() {
+        let mac = MockMac::new(2);
+        let mut router = monitor(&mac);
+        let update = NetworkUpdate::PanIdUpdate { extended_pan_id: THEIRS, update_id: 1, new_pan_id: 0x0100 };
+        router.handle_network_update(&update, secs(0));
+        assert_eq!(router.tick(secs(100)), None);
+        let mut resolver = PanIdConflictResolver::new(StdRng::seed_from_u64(0), OURS, PAN_ID, 0);
+        let report = NetworkReport::PanIdConflict { extended_pan_id: THEIRS, pan_ids: vec![PAN_ID] };
+        assert_eq!(resolver.handle_network_report(&report), None);
+    }
+
+    #[test]
+    fn update_ids_wrap_around() {
+        assert!(is_newer_update_id(1, 0));
+        assert!(is_newer_update_id(0, 0xFF));
+        assert!(!is_newer_update_id(0, 0));
+        assert!(!is_newer_update_id(0xFF, 0));
+        let mac = MockMac::new(2);
+        let mut router = PanIdConflictMonitor::new(&mac, PanIdConflictConfig::default(), PAN_ID, OURS, 0xFF);
+        let update = NetworkUpdate::PanIdUpdate { extended_pan_id: OURS, update_id: 0, new_pan_id: 0x0100 };
+        router.handle_network_update(&update, secs(0));
+        assert_eq!(router.tick(secs(9)), Some(PanIdEvent::PanIdChanged { pan_id: 0x0100, nwk_update_id: 0 }));
+    }
+
+    #[test]
+    fn conflicts_detected_from_frames() {
+        let mac = MockMac::new(2);
+        let mut router = monitor(&mac);
+        // Frames of this network, or of another network on another PAN.
+        assert_eq!(router.handle_frame(PAN_ID, OURS, secs(0)), None);
+        assert_eq!(router.handle_frame(0x0042, THEIRS, secs(0)), None);
+        assert_eq!(router.handle_frame(PAN_ID, THEIRS, secs(1)), Some(PanIdEvent::Report {
+            destination: 0x0000,
+            report: NetworkReport::PanIdConflict { extended_pan_id: OURS, pan_ids: vec![] },
+        }));
+        // The frames and the beacons share the rate limit.
+        assert_eq!(router.handle_beacon(&beacon(PAN_ID, THEIRS), secs(2)), None);
+        assert_eq!(router.handle_frame(PAN_ID, THEIRS, secs(60)), None);
+        assert!(router.handle_frame(PAN_ID, THEIRS, secs(61)).is_some());
+    }
+
+    #[test]
+    fn resolution_ends_when_every_pan_id_is_in_use() {
+        let mut resolver = PanIdConflictResolver::new(StdRng::seed_from_u64(0), OURS, PAN_ID, 0);
+        // A single PAN identifier is left.
+        let pan_ids = (0x0001..=MAX_PAN_ID).filter(|&pan_id| pan_id != 0x0100).collect();
+        let report = NetworkReport::PanIdConflict { extended_pan_id: OURS, pan_ids };
+        assert_eq!(resolver.handle_network_report(&report),
+                   Some(NetworkUpdate::PanIdUpdate { extended_pan_id: OURS, update_id: 1, new_pan_id: 0x0100 }));
+        // The only one left is now the one of this network.
+        assert_eq!(resolver.handle_network_report(&report), None);
+        assert_eq!((resolver.pan_id(), resolver.nwk_update_id()), (0x0100, 1));
+    }
+}
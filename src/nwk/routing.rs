@@ -0,0 +1,544 @@
+//! Routing tables, many-to-one routing and source routing.
+//!
+//! A concentrator periodically broadcasts a many-to-one route request, so
+//! that every router learns the next hop towards it without performing a
+//! route discovery. Devices then send route record commands to the
+//! concentrator, that stores the path in its route record table and uses
+//! it to add a source route subframe to the frames it sends back.
+
+use std::collections::HashMap;
+use std::time::Duration;
+use apl::device_profile::{RouteStatus, RoutingTableListRecord};
+use nwk::commands::{ManyToOne, RouteRecord, RouteRequest};
+use nwk::frame_format::{NwkHeader, SourceRouteSubframe};
+use types::NwkAddress;
+
+/// An entry of the routing table, as described in section 3.6.3.2 of the
+/// specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RoutingTableEntry {
+    pub destination_address: u16,
+    pub status: RouteStatus,
+    /// The destination does not store source routes.
+    pub no_route_cache: bool,
+    /// The destination is a concentrator that issued a many-to-one route
+    /// request.
+    pub many_to_one: bool,
+    /// A route record must be sent to the destination before the next
+    /// data frame.
+    pub route_record_required: bool,
+    /// The destination address is a group identifier.
+    pub group_id: bool,
+    pub next_hop_address: u16,
+}
+
+impl<'a> From<&'a RoutingTableEntry> for RoutingTableListRecord {
+    fn from(entry: &'a RoutingTableEntry) -> Self {
+        RoutingTableListRecord {
+            destination_address: NwkAddress(entry.destination_address),
+            status: entry.status,
+            memory_constrained: entry.no_route_cache,
+            many_to_one: entry.many_to_one,
+            route_record_required: entry.route_record_required,
+            next_hop_address: NwkAddress(entry.next_hop_address),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RoutingTableFull;
+
+pub struct RoutingTable {
+    entries: Vec<RoutingTableEntry>,
+    capacity: usize,
+    /// The last many-to-one route request seen from each concentrator,
+    /// with the lowest path cost received for it.
+    many_to_one_requests: HashMap<u16, (u8, u8)>,
+}
+
+impl RoutingTable {
+    pub fn new(capacity: usize) -> Self {
+        RoutingTable {
+            entries: Vec::with_capacity(capacity),
+            capacity,
+            many_to_one_requests: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, destination: u16) -> Option<&RoutingTableEntry> {
+        self.entries.iter().find(|e| e.destination_address == destination)
+    }
+
+    pub fn get_mut(&mut self, destination: u16) -> Option<&mut RoutingTableEntry> {
+        self.entries.iter_mut().find(|e| e.destination_address == destination)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RoutingTableEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Insert a new entry or replace the one with the same destination.
+    /// When the table is full, an inactive entry is replaced if any.
+    pub fn insert(&mut self, entry: RoutingTableEntry) -> Result<(), RoutingTableFull> {
+        if let Some(existing) = self.get_mut(entry.destination_address) {
+            *existing = entry;
+            return Ok(());
+        }
+        if self.entries.len() < self.capacity {
+            self.entries.push(entry);
+            return Ok(());
+        }
+        match self.entries.iter_mut().find(|e| e.status == RouteStatus::Inactive) {
+            Some(inactive) => {
+                *inactive = entry;
+                Ok(())
+            }
+            None => Err(RoutingTableFull),
+        }
+    }
+
+    pub fn remove(&mut self, destination: u16) -> Option<RoutingTableEntry> {
+        self.many_to_one_requests.remove(&destination);
+        self.entries.iter()
+            .position(|e| e.destination_address == destination)
+            .map(|i| self.entries.swap_remove(i))
+    }
+
+    /// The next hop towards `destination`, if an active route exists.
+    pub fn next_hop(&self, destination: u16) -> Option<u16> {
+        self.get(destination)
+            .filter(|e| e.status == RouteStatus::Active)
+            .map(|e| e.next_hop_address)
+    }
+
+    /// The content of the routing table in the format used by the
+    /// Mgmt_Rtg_rsp command.
+    pub fn records(&self) -> Vec<RoutingTableListRecord> {
+        self.entries.iter().map(RoutingTableListRecord::from).collect()
+    }
+
+    /// Process a many-to-one route request received from `previous_hop`
+    /// and originated by the concentrator `originator`, over a link with
+    /// cost `link_cost`.
+    ///
+    /// The route towards the concentrator is updated if the request is new
+    /// or has a lower path cost than the previous copies. In that case the
+    /// request that must be broadcast again is returned, with the path cost
+    /// updated.
+    pub fn process_many_to_one_request(&mut self, previous_hop: u16, originator: u16,
+                                       request: &RouteRequest, link_cost: u8)
+                                       -> Option<RouteRequest> {
+        if request.many_to_one == ManyToOne::No {
+            return None;
+        }
+        let path_cost = request.path_cost.saturating_add(link_cost);
+        if let Some(&(id, cost)) = self.many_to_one_requests.get(&originator) {
+            if id == request.route_request_id && cost <= path_cost {
+                return None;
+            }
+        }
+        let entry = RoutingTableEntry {
+            destination_address: originator,
+            status: RouteStatus::Active,
+            no_route_cache: request.many_to_one == ManyToOne::WithoutRouteRecordTable,
+            many_to_one: true,
+            route_record_required: request.many_to_one == ManyToOne::WithRouteRecordTable,
+            group_id: false,
+            next_hop_address: previous_hop,
+        };
+        self.insert(entry).ok()?;
+        self.many_to_one_requests.insert(originator, (request.route_request_id, path_cost));
+        Some(RouteRequest { path_cost, ..request.clone() })
+    }
+
+    /// Whether a route record command must be sent to `destination` before
+    /// a data frame. The route record required flag is consumed, so that
+    /// the next call returns `false` unless the concentrator does not keep
+    /// a route record table.
+    pub fn take_route_record_required(&mut self, destination: u16) -> bool {
+        match self.get_mut(destination) {
+            Some(ref mut entry) if entry.many_to_one => {
+                let required = entry.route_record_required || entry.no_route_cache;
+                entry.route_record_required = false;
+                required
+            }
+            _ => false,
+        }
+    }
+
+    /// Drop the routes towards a device that left the network and mark
+    /// inactive the ones that go through it.
+    pub fn remove_device(&mut self, network_address: u16) {
+        self.remove(network_address);
+        for entry in &mut self.entries {
+            if entry.next_hop_address == network_address {
+                entry.status = RouteStatus::Inactive;
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+        self.many_to_one_requests.clear();
+    }
+
+    /// Mark the route towards a concentrator as failed, e.g. because the
+    /// next hop does not acknowledge any more. The caller should then
+    /// report a many-to-one route failure to the concentrator.
+    pub fn many_to_one_route_failure(&mut self, destination: u16) {
+        if let Some(entry) = self.get_mut(destination) {
+            if entry.many_to_one {
+                entry.status = RouteStatus::Inactive;
+            }
+        }
+    }
+}
+
+/// The route record table of a concentrator, that maps each device to the
+/// relays that lead to it. Relays are stored in the same order as in the
+/// source route subframe: the one closest to the device comes first.
+pub struct RouteRecordTable {
+    routes: HashMap<u16, (Vec<u16>, u64)>,
+    capacity: usize,
+    counter: u64,
+}
+
+impl RouteRecordTable {
+    pub fn new(capacity: usize) -> Self {
+        RouteRecordTable { routes: HashMap::new(), capacity, counter: 0 }
+    }
+
+    /// Store the path to `source`. When the table is full, the least
+    /// recently recorded path is dropped.
+    pub fn insert(&mut self, source: u16, relay_list: Vec<u16>) {
+        if self.capacity == 0 {
+            return;
+        }
+        if !self.routes.contains_key(&source) && self.routes.len() >= self.capacity {
+            let oldest = self.routes.iter()
+                .min_by_key(|&(_, &(_, stamp))| stamp)
+                .map(|(&address, _)| address);
+            if let Some(address) = oldest {
+                self.routes.remove(&address);
+            }
+        }
+        self.counter += 1;
+        self.routes.insert(source, (relay_list, self.counter));
+    }
+
+    pub fn get(&self, destination: u16) -> Option<&[u16]> {
+        self.routes.get(&destination).map(|(relays, _)| relays.as_slice())
+    }
+
+    pub fn remove(&mut self, destination: u16) -> Option<Vec<u16>> {
+        self.routes.remove(&destination).map(|(relays, _)| relays)
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+}
+
+pub struct ConcentratorConfig {
+    /// The radius of the many-to-one route requests (nwkConcentratorRadius).
+    pub radius: u8,
+    /// The time between two many-to-one route requests
+    /// (nwkConcentratorDiscoveryTime). Zero disables the periodic requests,
+    /// that are then sent only when triggered.
+    pub discovery_time: Duration,
+    /// The number of paths kept in the route record table. A low RAM
+    /// concentrator should still keep a few, so that the route record
+    /// received just before a data frame can be used to reply.
+    pub route_record_table_size: usize,
+    /// Whether the concentrator keeps a route record table. When `false`,
+    /// devices send a route record before every data frame.
+    pub high_ram: bool,
+}
+
+/// The many-to-one routing behaviour of a concentrator.
+pub struct Concentrator {
+    config: ConcentratorConfig,
+    route_records: RouteRecordTable,
+    next_discovery: Option<Duration>,
+    route_request_id: u8,
+}
+
+impl Concentrator {
+    /// Create the concentrator. The first many-to-one route request is sent
+    /// at the first call to `poll`.
+    pub fn new(config: ConcentratorConfig) -> Self {
+        Concentrator {
+            route_records: RouteRecordTable::new(config.route_record_table_size),
+            config,
+            next_discovery: Some(Duration::from_secs(0)),
+            route_request_id: 0,
+        }
+    }
+
+    pub fn config(&self) -> &ConcentratorConfig {
+        &self.config
+    }
+
+    pub fn route_records(&self) -> &RouteRecordTable {
+        &self.route_records
+    }
+
+    /// Send a many-to-one route request at the next call to `poll`.
+    pub fn trigger_discovery(&mut self) {
+        self.next_discovery = Some(Duration::from_secs(0));
+    }
+
+    /// Return the many-to-one route request to broadcast to all the routers
+    /// (0xFFFC) with `config().radius`, if one is due at time `now`.
+    pub fn poll(&mut self, now: Duration) -> Option<RouteRequest> {
+        match self.next_discovery {
+            Some(due) if due <= now => {}
+            _ => return None,
+        }
+        self.next_discovery = if self.config.discovery_time > Duration::from_secs(0) {
+            Some(now + self.config.discovery_time)
+        } else {
+            None
+        };
+        self.route_request_id = self.route_request_id.wrapping_add(1);
+        Some(RouteRequest {
+            many_to_one: if self.config.high_ram {
+                ManyToOne::WithRouteRecordTable
+            } else {
+                ManyToOne::WithoutRouteRecordTable
+            },
+            multicast: false,
+            route_request_id: self.route_request_id,
+            destination_address: ::nwk::BROADCAST_ROUTERS,
+            path_cost: 0,
+            destination_ieee_address: None,
+        })
+    }
+
+    /// Store the path carried by a route record command originated by
+    /// `source`.
+    pub fn handle_route_record(&mut self, source: u16, record: &RouteRecord) {
+        self.route_records.insert(source, record.relay_list.clone());
+    }
+
+    /// The source route subframe for a frame directed to `destination`.
+    /// `None` is returned when there is no known path, or when the
+    /// destination is a neighbor and no relay is needed.
+    pub fn source_route(&self, destination: u16) -> Option<SourceRouteSubframe> {
+        match self.route_records.get(destination) {
+            Some(relays) if !relays.is_empty() => Some(SourceRouteSubframe::new(relays.to_vec())),
+            _ => None,
+        }
+    }
+
+    /// Add the source route subframe to an outgoing frame, if a path to its
+    /// destination is known, and return the next hop the frame must be
+    /// sent to.
+    pub fn apply_source_route(&self, header: &mut NwkHeader) -> Option<u16> {
+        let destination = header.destination_address;
+        match self.route_records.get(destination) {
+            Some([]) => Some(destination),
+            Some(_) => {
+                let subframe = self.source_route(destination)?;
+                let next_hop = subframe.current_relay();
+                header.source_route = Some(subframe);
+                next_hop
+            }
+            None => None,
+        }
+    }
+
+    /// Forget the path to `destination` after a source route failure was
+    /// reported, and schedule a new many-to-one route request so that the
+    /// routers refresh their routes.
+    pub fn source_route_failure(&mut self, destination: u16) {
+        self.route_records.remove(destination);
+        self.trigger_discovery();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nwk::frame_format::FrameType;
+
+    const CONCENTRATOR: u16 = 0x0000;
+
+    fn many_to_one_request(route_request_id: u8, path_cost: u8, many_to_one: ManyToOne) -> RouteRequest {
+        RouteRequest {
+            many_to_one,
+            multicast: false,
+            route_request_id,
+            destination_address: ::nwk::BROADCAST_ROUTERS,
+            path_cost,
+            destination_ieee_address: None,
+        }
+    }
+
+    fn entry(destination_address: u16, status: RouteStatus, next_hop_address: u16) -> RoutingTableEntry {
+        RoutingTableEntry {
+            destination_address,
+            status,
+            no_route_cache: false,
+            many_to_one: false,
+            route_record_required: false,
+            group_id: false,
+            next_hop_address,
+        }
+    }
+
+    #[test]
+    fn full_table_replaces_inactive_entries() {
+        let mut table = RoutingTable::new(2);
+        table.insert(entry(0x0001, RouteStatus::Active, 0x0010)).unwrap();
+        table.insert(entry(0x0002, RouteStatus::Inactive, 0x0010)).unwrap();
+        table.insert(entry(0x0001, RouteStatus::Active, 0x0020)).unwrap();
+        assert_eq!(table.next_hop(0x0001), Some(0x0020));
+        assert_eq!(table.next_hop(0x0002), None);
+        table.insert(entry(0x0003, RouteStatus::Active, 0x0030)).unwrap();
+        assert_eq!(table.get(0x0002), None);
+        assert_eq!(table.insert(entry(0x0004, RouteStatus::Active, 0x0040)), Err(RoutingTableFull));
+        assert_eq!(table.len(), 2);
+    }
+
+    #[test]
+    fn many_to_one_route_requests() {
+        let mut table = RoutingTable::new(4);
+        let request = many_to_one_request(1, 2, ManyToOne::WithRouteRecordTable);
+        let relayed = table.process_many_to_one_request(0x0010, CONCENTRATOR, &request, 3).unwrap();
+        assert_eq!(relayed.path_cost, 5);
+        assert_eq!(table.next_hop(CONCENTRATOR), Some(0x0010));
+
+        // Copies with a higher cost are dropped, cheaper ones update the
+        // route and are relayed.
+        assert_eq!(table.process_many_to_one_request(0x0020, CONCENTRATOR, &request, 5), None);
+        assert_eq!(table.next_hop(CONCENTRATOR), Some(0x0010));
+        let relayed = table.process_many_to_one_request(0x0020, CONCENTRATOR, &request, 1).unwrap();
+        assert_eq!(relayed.path_cost, 3);
+        assert_eq!(table.next_hop(CONCENTRATOR), Some(0x0020));
+
+        // A new request is always relayed.
+        let request = many_to_one_request(2, 2, ManyToOne::WithRouteRecordTable);
+        assert!(table.process_many_to_one_request(0x0010, CONCENTRATOR, &request, 7).is_some());
+        assert_eq!(table.next_hop(CONCENTRATOR), Some(0x0010));
+
+        // Ordinary route requests are not handled here.
+        let request = many_to_one_request(3, 0, ManyToOne::No);
+        assert_eq!(table.process_many_to_one_request(0x0030, 0x1234, &request, 1), None);
+        assert_eq!(table.get(0x1234), None);
+    }
+
+    #[test]
+    fn route_records_are_required_once_by_high_ram_concentrators() {
+        let mut table = RoutingTable::new(4);
+        let request = many_to_one_request(1, 0, ManyToOne::WithRouteRecordTable);
+        table.process_many_to_one_request(0x0010, CONCENTRATOR, &request, 1);
+        assert!(table.take_route_record_required(CONCENTRATOR));
+        assert!(!table.take_route_record_required(CONCENTRATOR));
+
+        let request = many_to_one_request(2, 0, ManyToOne::WithoutRouteRecordTable);
+        table.process_many_to_one_request(0x0010, CONCENTRATOR, &request, 1);
+        assert!(table.take_route_record_required(CONCENTRATOR));
+        assert!(table.take_route_record_required(CONCENTRATOR));
+        assert!(!table.take_route_record_required(0x1234));
+    }
+
+    #[test]
+    fn routes_through_a_device_that_left_become_inactive() {
+        let mut table = RoutingTable::new(4);
+        table.insert(entry(0x0001, RouteStatus::Active, 0x0010)).unwrap();
+        table.insert(entry(0x0002, RouteStatus::Active, 0x0001)).unwrap();
+        table.remove_device(0x0001);
+        assert_eq!(table.get(0x0001), None);
+        assert_eq!(table.get(0x0002).unwrap().status, RouteStatus::Inactive);
+
+        let request = many_to_one_request(1, 0, ManyToOne::WithRouteRecordTable);
+        table.process_many_to_one_request(0x0010, CONCENTRATOR, &request, 1);
+        table.many_to_one_route_failure(CONCENTRATOR);
+        assert_eq!(table.next_hop(CONCENTRATOR), None);
+    }
+
+    #[test]
+    fn route_record_table_drops_the_oldest_path() {
+        let mut table = RouteRecordTable::new(2);
+        table.insert(0x0001, vec![0x0010]);
+        table.insert(0x0002, vec![0x0020]);
+        table.insert(0x0001, vec![0x0011]);
+        table.insert(0x0003, vec![0x0030]);
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get(0x0001), Some(&[0x0011][..]));
+        assert_eq!(table.get(0x0002), None);
+        assert_eq!(table.remove(0x0003), Some(vec![0x0030]));
+
+        let mut table = RouteRecordTable::new(0);
+        table.insert(0x0001, vec![]);
+        assert!(table.is_empty());
+    }
+
+    fn config(discovery_time: u64, high_ram: bool) -> ConcentratorConfig {
+        ConcentratorConfig {
+            radius: 30,
+            discovery_time: Duration::from_secs(discovery_time),
+            route_record_table_size: 4,
+            high_ram,
+        }
+    }
+
+    #[test]
+    fn periodic_route_requests() {
+        let mut concentrator = Concentrator::new(config(60, true));
+        let request = concentrator.poll(Duration::from_secs(0)).unwrap();
+        assert_eq!(request.many_to_one, ManyToOne::WithRouteRecordTable);
+        assert_eq!(request.destination_address, ::nwk::BROADCAST_ROUTERS);
+        assert_eq!(concentrator.poll(Duration::from_secs(59)), None);
+        assert_eq!(concentrator.poll(Duration::from_secs(60)).unwrap().route_request_id,
+                   request.route_request_id.wrapping_add(1));
+
+        // Without a discovery time, the requests are only triggered.
+        let mut low_ram = Concentrator::new(config(0, false));
+        let request = low_ram.poll(Duration::from_secs(0)).unwrap();
+        assert_eq!(request.many_to_one, ManyToOne::WithoutRouteRecordTable);
+        assert_eq!(low_ram.poll(Duration::from_secs(1000)), None);
+        low_ram.trigger_discovery();
+        assert!(low_ram.poll(Duration::from_secs(1000)).is_some());
+    }
+
+    #[test]
+    fn source_routes() {
+        let mut concentrator = Concentrator::new(config(60, true));
+        // The relay closest to the device comes first.
+        concentrator.handle_route_record(0x1234, &RouteRecord { relay_list: vec![0x0003, 0x0002, 0x0001] });
+        concentrator.handle_route_record(0x5678, &RouteRecord { relay_list: vec![] });
+
+        let mut header = NwkHeader::new(FrameType::Data, 0x1234, CONCENTRATOR, 30, 1);
+        assert_eq!(concentrator.apply_source_route(&mut header), Some(0x0001));
+        let mut subframe = header.source_route.unwrap();
+        assert_eq!(subframe.advance(0x0001, 0x1234), Some(0x0002));
+        assert_eq!(subframe.advance(0x0002, 0x1234), Some(0x0003));
+        assert_eq!(subframe.advance(0x0003, 0x1234), Some(0x1234));
+
+        // Neighbors need no relay, unknown devices have no source route.
+        let mut header = NwkHeader::new(FrameType::Data, 0x5678, CONCENTRATOR, 30, 2);
+        assert_eq!(concentrator.apply_source_route(&mut header), Some(0x5678));
+        assert_eq!(header.source_route, None);
+        let mut header = NwkHeader::new(FrameType::Data, 0x9ABC, CONCENTRATOR, 30, 3);
+        assert_eq!(concentrator.apply_source_route(&mut header), None);
+
+        concentrator.poll(Duration::from_secs(0));
+        concentrator.source_route_failure(0x1234);
+        assert_eq!(concentrator.source_route(0x1234), None);
+        assert!(concentrator.poll(Duration::from_secs(1)).is_some());
+    }
+}
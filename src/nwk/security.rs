@@ -0,0 +1,362 @@
+//! The protection of NWK frames with the network key, as described in
+//! section 4.3.1 of the specification.
+//!
+//! The NWK header is authenticated and the payload is encrypted, with a 32
+//! bit message integrity code. Every device keeps an outgoing frame
+//! counter, and remembers the last frame counter received from each
+//! neighbor to reject replayed frames. When there is no room left for the
+//! counter of a new neighbor, its frames are dropped: forgetting the
+//! counter of another device would let its old frames be replayed. The
+//! counters of the devices that are no longer neighbors must be forgotten
+//! explicitly. A new network key is distributed
+//! in advance and used when the trust center asks to switch to it, or as
+//! soon as a frame secured with it is received.
+//!
+//! With `secure_persistent`, the outgoing frame counter is kept in a key
+//! store; after a reboot, it is restored with `set_outgoing_frame_counter`
+//! from the value found in the store.
+
+use codec::Reader;
+use nwk::frame_format::NwkHeader;
+use security::{ccm, AuxiliaryHeader, Key, KeyIdentifier, SecurityError, SecurityLevel,
+               ZIGBEE_SECURITY_LEVEL};
+use security::key_store::{FrameCounter, FrameCounterPersistence, KeyStore};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NetworkKey {
+    pub sequence_number: u8,
+    pub key: Key,
+}
+
+/// The last frame counter received from a device with a given key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IncomingFrameCounter {
+    source_address: u64,
+    key_sequence_number: u8,
+    frame_counter: u32,
+}
+
+/// A NWK frame whose security was successfully removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsecuredFrame {
+    pub header: NwkHeader,
+    pub source_address: u64,
+    pub key_sequence_number: u8,
+    pub payload: Vec<u8>,
+}
+
+/// The NWK security material of a device.
+pub struct NwkSecurity {
+    extended_address: u64,
+    security_level: SecurityLevel,
+    /// The active network key and, possibly, the next one.
+    keys: Vec<NetworkKey>,
+    active_key_sequence_number: Option<u8>,
+    outgoing_frame_counter: u32,
+    incoming_frame_counters: Vec<IncomingFrameCounter>,
+    /// The number of devices whose frame counter is remembered, usually
+    /// the size of the neighbor table.
+    capacity: usize,
+}
+
+impl NwkSecurity {
+    /// Return `None` if `capacity` is zero, since no secured frame could be
+    /// received.
+    pub fn new(extended_address: u64, capacity: usize) -> Option<Self> {
+        if capacity == 0 {
+            return None;
+        }
+        Some(NwkSecurity {
+            extended_address,
+            security_level: ZIGBEE_SECURITY_LEVEL,
+            keys: Vec::with_capacity(2),
+            active_key_sequence_number: None,
+            outgoing_frame_counter: 0,
+            incoming_frame_counters: Vec::with_capacity(capacity),
+            capacity,
+        })
+    }
+
+    pub fn security_level(&self) -> SecurityLevel {
+        self.security_level
+    }
+
+    pub fn active_key_sequence_number(&self) -> Option<u8> {
+        self.active_key_sequence_number
+    }
+
+    pub fn outgoing_frame_counter(&self) -> u32 {
+        self.outgoing_frame_counter
+    }
+
+    /// Restore the outgoing frame counter, e.g. from persistent storage
+    /// after a reboot.
+    pub fn set_outgoing_frame_counter(&mut self, frame_counter: u32) {
+        self.outgoing_frame_counter = frame_counter;
+    }
+
+    fn key(&self, sequence_number: u8) -> Option<&NetworkKey> {
+        self.keys.iter().find(|k| k.sequence_number == sequence_number)
+    }
+
+    /// Store a network key received from the trust center. The first key
+    /// becomes active; the following ones replace the alternate key.
+    pub fn set_key(&mut self, key: NetworkKey) {
+        let active = self.active_key_sequence_number;
+        self.keys.retain(|k| Some(k.sequence_number) == active && k.sequence_number != key.sequence_number);
+        self.keys.push(key);
+        if active.is_none() {
+            self.active_key_sequence_number = Some(key.sequence_number);
+        }
+    }
+
+    /// Make the key with the given sequence number the active one
+    /// (APSME-SWITCH-KEY). The outgoing frame counter restarts from zero.
+    pub fn switch_key(&mut self, sequence_number: u8) -> Result<(), SecurityError> {
+        if self.key(sequence_number).is_none() {
+            return Err(SecurityError::UnknownKey);
+        }
+        if self.active_key_sequence_number == Some(sequence_number) {
+            return Ok(());
+        }
+        self.keys.retain(|k| k.sequence_number == sequence_number);
+        self.active_key_sequence_number = Some(sequence_number);
+        self.outgoing_frame_counter = 0;
+        self.incoming_frame_counters.retain(|c| c.key_sequence_number == sequence_number);
+        Ok(())
+    }
+
+    /// Forget the frame counters received from a device, e.g. when it is
+    /// removed from the neighbor table, to make room for other devices.
+    pub fn forget(&mut self, source_address: u64) {
+        self.incoming_frame_counters.retain(|c| c.source_address != source_address);
+    }
+
+    /// Forget all the keys and frame counters, e.g. after leaving the
+    /// network.
+    pub fn clear(&mut self) {
+        self.keys.clear();
+        self.active_key_sequence_number = None;
+        self.outgoing_frame_counter = 0;
+        self.incoming_frame_counters.clear();
+    }
+
+    /// Secure a frame like `secure`, after storing a value of the outgoing
+    /// frame counter ahead of the one used, when `persistence` requires it.
+    pub fn secure_persistent<S: KeyStore>(&mut self, header: &NwkHeader, payload: &[u8], store: &mut S,
+                                          persistence: &FrameCounterPersistence)
+                                          -> Result<Vec<u8>, SecurityError> {
+        persistence.update(store, FrameCounter::Network, self.outgoing_frame_counter)
+            .map_err(|_| SecurityError::FrameCounterNotStored)?;
+        self.secure(header, payload)
+    }
+
+    /// Secure a frame with the active network key: return the encoded
+    /// header, with the security flag set, followed by the auxiliary header,
+    /// the encrypted payload and the message integrity code.
+    pub fn secure(&mut self, header: &NwkHeader, payload: &[u8]) -> Result<Vec<u8>, SecurityError> {
+        let sequence_number = self.active_key_sequence_number.ok_or(SecurityError::UnknownKey)?;
+        let key = self.key(sequence_number).ok_or(SecurityError::UnknownKey)?.key;
+        if self.outgoing_frame_counter == u32::MAX {
+            return Err(SecurityError::FrameCounterExhausted);
+        }
+        let aux = AuxiliaryHeader {
+            security_level: self.security_level,
+            key_identifier: KeyIdentifier::Network,
+            frame_counter: self.outgoing_frame_counter,
+            source_address: Some(self.extended_address),
+            key_sequence_number: Some(sequence_number),
+        };
+        self.outgoing_frame_counter += 1;
+
+        let mut header = header.clone();
+        header.security = true;
+        let mut frame = Vec::new();
+        header.encode(&mut frame);
+        let mut a = frame.clone();
+        aux.encode_with_level(&mut a);
+        aux.encode(&mut frame);
+
+        let mut m = payload.to_vec();
+        ccm::encrypt(&key, &aux.nonce(self.extended_address), self.security_level, &a, &mut m);
+        frame.extend_from_slice(&m);
+        Ok(frame)
+    }
+
+    /// Check and remove the security of a received frame.
+    pub fn unsecure(&mut self, frame: &[u8]) -> Result<UnsecuredFrame, SecurityError> {
+        let mut reader = Reader::new(frame);
+        let header = NwkHeader::decode(&mut reader)?;
+        let header_length = frame.len() - reader.remaining().len();
+        let aux = AuxiliaryHeader::decode(&mut reader, self.security_level)?;
+        let source_address = aux.source_address.ok_or(SecurityError::MissingSourceAddress)?;
+        let sequence_number = aux.key_sequence_number.ok_or(SecurityError::UnknownKey)?;
+        if aux.key_identifier != KeyIdentifier::Network {
+            return Err(SecurityError::UnknownKey);
+        }
+        let key = self.key(sequence_number).ok_or(SecurityError::UnknownKey)?.key;
+
+        let last = self.incoming_frame_counters.iter()
+            .position(|c| c.source_address == source_address && c.key_sequence_number == sequence_number);
+        match last {
+            Some(i) if aux.frame_counter <= self.incoming_frame_counters[i].frame_counter => {
+                return Err(SecurityError::BadFrameCounter);
+            }
+            None if self.incoming_frame_counters.len() >= self.capacity => {
+                return Err(SecurityError::FrameCounterTableFull);
+            }
+            _ => {}
+        }
+
+        let mut a = frame[..header_length].to_vec();
+        aux.encode_with_level(&mut a);
+        let mut payload = reader.read_to_end().to_vec();
+        ccm::decrypt(&key, &aux.nonce(source_address), self.security_level, &a, &mut payload)?;
+
+        match last {
+            Some(i) => self.incoming_frame_counters[i].frame_counter = aux.frame_counter,
+            None => self.incoming_frame_counters.push(IncomingFrameCounter {
+                source_address,
+                key_sequence_number: sequence_number,
+                frame_counter: aux.frame_counter,
+            }),
+        }
+        // A device that already switched to the next key proves that the
+        // switch happened.
+        if let Some(active) = self.active_key_sequence_number {
+            if (sequence_number.wrapping_sub(active) as i8) > 0 {
+                self.switch_key(sequence_number)?;
+            }
+        }
+        Ok(UnsecuredFrame { header, source_address, key_sequence_number: sequence_number, payload })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nwk::frame_format::FrameType;
+
+    const SENDER: u64 = 0x0011_2233_4455_6677;
+    const RECEIVER: u64 = 0x8899_AABB_CCDD_EEFF;
+    const KEY: NetworkKey = NetworkKey {
+        sequence_number: 0,
+        key: [0x01, 0x03, 0x05, 0x07, 0x09, 0x0B, 0x0D, 0x0F, 0x00, 0x02, 0x04, 0x06, 0x08, 0x0A, 0x0C, 0x0D],
+    };
+
+    fn security(address: u64, capacity: usize) -> NwkSecurity {
+        let mut security = NwkSecurity::new(address, capacity).unwrap();
+        security.set_key(KEY);
+        security
+    }
+
+    fn header() -> NwkHeader {
+        NwkHeader::new(FrameType::Data, 0x0000, 0x1234, 30, 7)
+    }
+
+    /// The frame was checked against an independent implementation of
+    /// CCM: the nonce is the source address, the frame counter and the
+    /// security control with level 5, and the header and the auxiliary
+    /// header, with the level, are authenticated.
+    const SECURED: [u8; 31] = [
+        0x08, 0x02, 0x00, 0x00, 0x34, 0x12, 0x1E, 0x07,
+        0x28, 0x04, 0x03, 0x02, 0x01, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00, 0x00,
+        0x3F, 0xF8, 0x56, 0xFF, 0x60,
+        0xD2, 0xEE, 0xE9, 0x74,
+    ];
+
+    #[test]
+    fn secure_frame() {
+        let mut sender = security(SENDER, 4);
+        sender.set_outgoing_frame_counter(0x0102_0304);
+        assert_eq!(sender.secure(&header(), b"hello").unwrap(), SECURED.to_vec());
+        assert_eq!(sender.outgoing_frame_counter(), 0x0102_0305);
+
+        let mut receiver = security(RECEIVER, 4);
+        let frame = receiver.unsecure(&SECURED).unwrap();
+        assert_eq!(frame.source_address, SENDER);
+        assert_eq!(frame.key_sequence_number, 0);
+        assert_eq!(frame.payload, b"hello");
+        assert!(frame.header.security);
+        assert_eq!(frame.header.source_address, 0x1234);
+    }
+
+    #[test]
+    fn altered_frame_is_rejected() {
+        let mut receiver = security(RECEIVER, 4);
+        let mut frame = SECURED;
+        frame[4] ^= 0x01;
+        assert_eq!(receiver.unsecure(&frame), Err(SecurityError::AuthenticationFailed));
+        let mut frame = SECURED;
+        frame[24] ^= 0x80;
+        assert_eq!(receiver.unsecure(&frame), Err(SecurityError::AuthenticationFailed));
+        // The failures do not update the frame counter.
+        assert!(receiver.unsecure(&SECURED).is_ok());
+    }
+
+    #[test]
+    fn replayed_frames_are_rejected() {
+        let mut sender = security(SENDER, 4);
+        let mut receiver = security(RECEIVER, 4);
+        let first = sender.secure(&header(), b"one").unwrap();
+        let second = sender.secure(&header(), b"two").unwrap();
+        assert!(receiver.unsecure(&second).is_ok());
+        assert_eq!(receiver.unsecure(&second), Err(SecurityError::BadFrameCounter));
+        assert_eq!(receiver.unsecure(&first), Err(SecurityError::BadFrameCounter));
+        let third = sender.secure(&header(), b"three").unwrap();
+        assert_eq!(receiver.unsecure(&third).unwrap().payload, b"three");
+    }
+
+    #[test]
+    fn full_table_drops_new_sources() {
+        assert!(NwkSecurity::new(RECEIVER, 0).is_none());
+        let mut receiver = security(RECEIVER, 1);
+        let mut known = security(SENDER, 1);
+        let mut other = security(SENDER + 1, 1);
+        let old = known.secure(&header(), b"old").unwrap();
+        assert!(receiver.unsecure(&known.secure(&header(), b"new").unwrap()).is_ok());
+        // The counter of the known device is not evicted, so its old frames
+        // are still replays.
+        assert_eq!(receiver.unsecure(&other.secure(&header(), b"hi").unwrap()),
+                   Err(SecurityError::FrameCounterTableFull));
+        assert_eq!(receiver.unsecure(&old), Err(SecurityError::BadFrameCounter));
+        receiver.forget(SENDER);
+        assert!(receiver.unsecure(&other.secure(&header(), b"hi").unwrap()).is_ok());
+    }
+
+    #[test]
+    fn frame_with_next_key_switches() {
+        let next = NetworkKey { sequence_number: 1, key: [0x55; 16] };
+        let mut sender = security(SENDER, 4);
+        let mut receiver = security(RECEIVER, 4);
+        sender.set_key(next);
+        receiver.set_key(next);
+        sender.switch_key(1).unwrap();
+        assert_eq!(sender.outgoing_frame_counter(), 0);
+        assert!(receiver.unsecure(&sender.secure(&header(), b"x").unwrap()).is_ok());
+        assert_eq!(receiver.active_key_sequence_number(), Some(1));
+        assert_eq!(receiver.switch_key(0), Err(SecurityError::UnknownKey));
+    }
+
+    #[test]
+    fn outgoing_frame_counter_is_stored_ahead() {
+        use security::key_store::MemoryKeyStore;
+
+        let persistence = FrameCounterPersistence { interval: 4 };
+        let mut store = MemoryKeyStore::new();
+        let mut sender = security(SENDER, 4);
+        for _ in 0..5 {
+            sender.secure_persistent(&header(), b"x", &mut store, &persistence).unwrap();
+            assert!(store.outgoing_frame_counter(FrameCounter::Network) >= sender.outgoing_frame_counter());
+        }
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), 8);
+
+        // After a reboot, the counter restarts from the stored value.
+        let mut rebooted = security(SENDER, 4);
+        rebooted.set_outgoing_frame_counter(store.outgoing_frame_counter(FrameCounter::Network));
+        let frame = rebooted.secure_persistent(&header(), b"x", &mut store, &persistence).unwrap();
+        let frame = security(RECEIVER, 4).unsecure(&frame).unwrap();
+        assert_eq!(frame.payload, b"x");
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), 12);
+    }
+}
@@ -0,0 +1,198 @@
+//! The CCM* mode of operation with AES-128, as described in annex A of the
+//! specification: CCM extended to allow encryption without authentication.
+//!
+//! Zigbee always uses a 13 byte nonce, so the length of the messages is
+//! encoded on 2 bytes.
+
+use aes::Aes128;
+use aes::cipher::{BlockEncrypt, KeyInit};
+use aes::cipher::generic_array::GenericArray;
+use security::{Key, SecurityError, SecurityLevel};
+
+pub const BLOCK_SIZE: usize = 16;
+pub const NONCE_SIZE: usize = 13;
+/// The number of bytes that encode the length of the message.
+const L: usize = 2;
+
+/// An AES-128 block cipher with its expanded key.
+pub struct BlockCipher(Aes128);
+
+impl BlockCipher {
+    pub fn new(key: &Key) -> Self {
+        BlockCipher(Aes128::new(GenericArray::from_slice(key)))
+    }
+
+    pub fn encrypt_block(&self, block: &mut [u8; BLOCK_SIZE]) {
+        self.0.encrypt_block(GenericArray::from_mut_slice(block));
+    }
+}
+
+fn xor_block(block: &mut [u8; BLOCK_SIZE], data: &[u8]) {
+    for (b, d) in block.iter_mut().zip(data) {
+        *b ^= *d;
+    }
+}
+
+/// The CBC-MAC of the authenticated data and of the message.
+fn authentication_tag(cipher: &BlockCipher, nonce: &[u8; NONCE_SIZE], mic_length: usize,
+                      a: &[u8], m: &[u8]) -> [u8; BLOCK_SIZE] {
+    let mut x = [0; BLOCK_SIZE];
+    x[0] = ((!a.is_empty()) as u8) << 6 | (((mic_length.max(2) - 2) / 2) as u8) << 3 | (L - 1) as u8;
+    x[1..1 + NONCE_SIZE].copy_from_slice(nonce);
+    x[14] = (m.len() >> 8) as u8;
+    x[15] = m.len() as u8;
+    cipher.encrypt_block(&mut x);
+
+    let mut blocks = Vec::new();
+    if !a.is_empty() {
+        // Zigbee headers are always shorter than 2^16 - 2^8 bytes.
+        blocks.push((a.len() >> 8) as u8);
+        blocks.push(a.len() as u8);
+        blocks.extend_from_slice(a);
+        let padding = (BLOCK_SIZE - blocks.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        blocks.extend(::std::iter::repeat_n(0, padding));
+    }
+    blocks.extend_from_slice(m);
+    let padding = (BLOCK_SIZE - blocks.len() % BLOCK_SIZE) % BLOCK_SIZE;
+    blocks.extend(::std::iter::repeat_n(0, padding));
+
+    for block in blocks.chunks(BLOCK_SIZE) {
+        xor_block(&mut x, block);
+        cipher.encrypt_block(&mut x);
+    }
+    x
+}
+
+/// The key stream block A_i encrypted.
+fn key_stream(cipher: &BlockCipher, nonce: &[u8; NONCE_SIZE], i: u16) -> [u8; BLOCK_SIZE] {
+    let mut a = [0; BLOCK_SIZE];
+    a[0] = (L - 1) as u8;
+    a[1..1 + NONCE_SIZE].copy_from_slice(nonce);
+    a[14] = (i >> 8) as u8;
+    a[15] = i as u8;
+    cipher.encrypt_block(&mut a);
+    a
+}
+
+/// Encrypt or decrypt `data` in place with the counter mode, starting
+/// from the counter 1.
+fn ctr(cipher: &BlockCipher, nonce: &[u8; NONCE_SIZE], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(BLOCK_SIZE).enumerate() {
+        let s = key_stream(cipher, nonce, i as u16 + 1);
+        for (d, s) in chunk.iter_mut().zip(s.iter()) {
+            *d ^= *s;
+        }
+    }
+}
+
+/// The CCM* forward transformation. `a` is authenticated and `m`, the
+/// message, is encrypted in place if the level requires it. The encrypted
+/// message integrity code is appended to `m`.
+pub fn encrypt(key: &Key, nonce: &[u8; NONCE_SIZE], level: SecurityLevel, a: &[u8], m: &mut Vec<u8>) {
+    let cipher = BlockCipher::new(key);
+    let mic_length = level.mic_length();
+    let tag = if mic_length > 0 {
+        authentication_tag(&cipher, nonce, mic_length, a, m)
+    } else {
+        [0; BLOCK_SIZE]
+    };
+    if level.encrypted() {
+        ctr(&cipher, nonce, m);
+    }
+    let s0 = key_stream(&cipher, nonce, 0);
+    m.extend(tag.iter().zip(s0.iter()).take(mic_length).map(|(t, s)| t ^ s));
+}
+
+/// The CCM* inverse transformation. `c` is the encrypted message followed
+/// by the message integrity code; on success, it is replaced by the
+/// plain message.
+pub fn decrypt(key: &Key, nonce: &[u8; NONCE_SIZE], level: SecurityLevel, a: &[u8], c: &mut Vec<u8>)
+               -> Result<(), SecurityError> {
+    let mic_length = level.mic_length();
+    if c.len() < mic_length {
+        return Err(SecurityError::AuthenticationFailed);
+    }
+    let cipher = BlockCipher::new(key);
+    let message_length = c.len() - mic_length;
+    let s0 = key_stream(&cipher, nonce, 0);
+    let received: Vec<u8> = c[message_length..].iter().zip(s0.iter()).map(|(u, s)| u ^ s).collect();
+    c.truncate(message_length);
+    if level.encrypted() {
+        ctr(&cipher, nonce, c);
+    }
+    if mic_length > 0 {
+        let tag = authentication_tag(&cipher, nonce, mic_length, a, c);
+        // Compare without an early exit, so that the time taken does not
+        // reveal how much of the tag is right.
+        let difference = tag.iter().zip(&received).fold(0, |acc, (t, r)| acc | (t ^ r));
+        if difference != 0 {
+            return Err(SecurityError::AuthenticationFailed);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: Key = [0xC0, 0xC1, 0xC2, 0xC3, 0xC4, 0xC5, 0xC6, 0xC7,
+                      0xC8, 0xC9, 0xCA, 0xCB, 0xCC, 0xCD, 0xCE, 0xCF];
+
+    fn range(start: u8, end: u8) -> Vec<u8> {
+        (start..end).collect()
+    }
+
+    /// The test vector of annex C.6.1 of the specification.
+    #[test]
+    fn zigbee_test_vector() {
+        let nonce = [0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7, 0x03, 0x02, 0x01, 0x00, 0x06];
+        let a = range(0x00, 0x08);
+        let mut m = range(0x08, 0x1F);
+        encrypt(&KEY, &nonce, SecurityLevel::EncMic64, &a, &mut m);
+        assert_eq!(m, vec![0x1A, 0x55, 0xA3, 0x6A, 0xBB, 0x6C, 0x61, 0x0D, 0x06, 0x6B, 0x33, 0x75, 0x64,
+                           0x9C, 0xEF, 0x10, 0xD4, 0x66, 0x4E, 0xCA, 0xD8, 0x54, 0xA8,
+                           0x0A, 0x89, 0x5C, 0xC1, 0xD8, 0xFF, 0x94, 0x69]);
+        decrypt(&KEY, &nonce, SecurityLevel::EncMic64, &a, &mut m).unwrap();
+        assert_eq!(m, range(0x08, 0x1F));
+    }
+
+    /// Packet vector #1 of RFC 3610: CCM* with a MIC and encryption is CCM.
+    #[test]
+    fn rfc3610_packet_vector_1() {
+        let nonce = [0x00, 0x00, 0x00, 0x03, 0x02, 0x01, 0x00, 0xA0, 0xA1, 0xA2, 0xA3, 0xA4, 0xA5];
+        let a = range(0x00, 0x08);
+        let mut m = range(0x08, 0x1F);
+        encrypt(&KEY, &nonce, SecurityLevel::EncMic64, &a, &mut m);
+        assert_eq!(m, vec![0x58, 0x8C, 0x97, 0x9A, 0x61, 0xC6, 0x63, 0xD2, 0xF0, 0x66, 0xD0, 0xC2, 0xC0,
+                           0xF9, 0x89, 0x80, 0x6D, 0x5F, 0x6B, 0x61, 0xDA, 0xC3, 0x84,
+                           0x17, 0xE8, 0xD1, 0x2C, 0xFD, 0xF9, 0x26, 0xE0]);
+    }
+
+    #[test]
+    fn tampering_is_detected() {
+        let nonce = [0; NONCE_SIZE];
+        let mut c = b"payload".to_vec();
+        encrypt(&KEY, &nonce, SecurityLevel::EncMic32, b"header", &mut c);
+        assert_eq!(c.len(), 7 + 4);
+        let mut altered = c.clone();
+        altered[0] ^= 1;
+        assert_eq!(decrypt(&KEY, &nonce, SecurityLevel::EncMic32, b"header", &mut altered),
+                   Err(SecurityError::AuthenticationFailed));
+        let mut altered = c.clone();
+        assert_eq!(decrypt(&KEY, &nonce, SecurityLevel::EncMic32, b"Header", &mut altered),
+                   Err(SecurityError::AuthenticationFailed));
+        assert_eq!(decrypt(&KEY, &nonce, SecurityLevel::EncMic32, b"header", &mut vec![0; 3]),
+                   Err(SecurityError::AuthenticationFailed));
+    }
+
+    #[test]
+    fn authentication_without_encryption() {
+        let nonce = [1; NONCE_SIZE];
+        let mut m = b"in clear".to_vec();
+        encrypt(&KEY, &nonce, SecurityLevel::Mic32, &[], &mut m);
+        assert_eq!(&m[..8], b"in clear");
+        decrypt(&KEY, &nonce, SecurityLevel::Mic32, &[], &mut m).unwrap();
+        assert_eq!(m, b"in clear");
+    }
+}
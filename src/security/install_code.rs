@@ -0,0 +1,109 @@
+//! The install codes, described in section 10.1 of the Base Device
+//! Behavior specification: a random code printed on the device, followed
+//! by its CRC, from which the device and the trust center derive the link
+//! key they share.
+
+use std::error;
+use std::fmt;
+use security::Key;
+use security::mmo::hash;
+
+/// The valid lengths of an install code, without the CRC.
+const CODE_LENGTHS: [usize; 4] = [6, 8, 12, 16];
+const CRC_LENGTH: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallCodeError {
+    InvalidLength,
+    InvalidCrc,
+}
+
+impl fmt::Display for InstallCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InstallCodeError::InvalidLength => write!(f, "invalid install code length"),
+            InstallCodeError::InvalidCrc => write!(f, "invalid install code CRC"),
+        }
+    }
+}
+
+impl error::Error for InstallCodeError {}
+
+/// The CRC-16 of the install codes (CRC-16/X-25).
+pub fn crc(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFF;
+    for byte in data {
+        crc ^= *byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0x8408 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+/// Check the length and the CRC of an install code, given with its CRC.
+pub fn check(install_code: &[u8]) -> Result<(), InstallCodeError> {
+    if install_code.len() < CRC_LENGTH
+        || !CODE_LENGTHS.contains(&(install_code.len() - CRC_LENGTH)) {
+        return Err(InstallCodeError::InvalidLength);
+    }
+    let (code, received) = install_code.split_at(install_code.len() - CRC_LENGTH);
+    let expected = crc(code);
+    if received != [expected as u8, (expected >> 8) as u8] {
+        return Err(InstallCodeError::InvalidCrc);
+    }
+    Ok(())
+}
+
+/// Check an install code and derive the link key from it: the
+/// Matyas-Meyer-Oseas hash of the code followed by its CRC.
+pub fn link_key(install_code: &[u8]) -> Result<Key, InstallCodeError> {
+    check(install_code)?;
+    Ok(hash(install_code))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The install code of the example in section 10.1 of the Base Device
+    /// Behavior specification, with its CRC.
+    const INSTALL_CODE: [u8; 18] = [0x83, 0xFE, 0xD3, 0x40, 0x7A, 0x93, 0x97, 0x23, 0xA5, 0xC6,
+                                    0x39, 0xB2, 0x69, 0x16, 0xD5, 0x05, 0xC3, 0xB5];
+
+    #[test]
+    fn crc_of_the_example() {
+        assert_eq!(crc(&INSTALL_CODE[..16]), 0xB5C3);
+        assert_eq!(crc(b"123456789"), 0x906E);
+    }
+
+    #[test]
+    fn link_key_of_the_example() {
+        assert_eq!(link_key(&INSTALL_CODE),
+                   Ok([0x66, 0xB6, 0x90, 0x09, 0x81, 0xE1, 0xEE, 0x3C,
+                       0xA4, 0x20, 0x6B, 0x6B, 0x86, 0x1C, 0x02, 0xBB]));
+    }
+
+    #[test]
+    fn invalid_install_codes() {
+        let mut code = INSTALL_CODE;
+        code[17] ^= 0x01;
+        assert_eq!(link_key(&code), Err(InstallCodeError::InvalidCrc));
+        code = INSTALL_CODE;
+        code[0] ^= 0x01;
+        assert_eq!(link_key(&code), Err(InstallCodeError::InvalidCrc));
+        assert_eq!(link_key(&INSTALL_CODE[..17]), Err(InstallCodeError::InvalidLength));
+        assert_eq!(link_key(&[]), Err(InstallCodeError::InvalidLength));
+        assert_eq!(link_key(&[0xFF]), Err(InstallCodeError::InvalidLength));
+    }
+
+    #[test]
+    fn every_valid_length() {
+        for &length in &CODE_LENGTHS {
+            let mut code = vec![0x5A; length];
+            let crc = crc(&code);
+            code.extend_from_slice(&[crc as u8, (crc >> 8) as u8]);
+            assert_eq!(link_key(&code), Ok(hash(&code)));
+        }
+    }
+}
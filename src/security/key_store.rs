@@ -0,0 +1,565 @@
+//! The persistent storage of the security material: the network key, the
+//! link keys, the install codes and the outgoing frame counters.
+//!
+//! A frame counter must never be reused with the same key, even after a
+//! reboot, but storing it after every frame would wear out the flash
+//! memory. `FrameCounterPersistence` stores a value ahead of the counter
+//! every N frames instead: after a reboot, the device restarts from the
+//! stored value, which was never used.
+
+use std::error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use rand::{CryptoRng, RngCore};
+use codec::{DecodeError, Reader, write_u16, write_u32, write_u64};
+use nwk::security::NetworkKey;
+use security::{ccm, Key, SecurityLevel, KEY_SIZE};
+use security::ccm::NONCE_SIZE;
+use security::install_code::{self, InstallCodeError};
+
+/// An outgoing frame counter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameCounter {
+    /// The counter of the frames secured with the network key.
+    Network,
+    /// The counter of the frames secured with the link key shared with a
+    /// device.
+    Link(u64),
+}
+
+/// A storage for the security material of a device.
+pub trait KeyStore {
+    type Error;
+
+    fn network_key(&self) -> Option<NetworkKey>;
+    fn set_network_key(&mut self, key: NetworkKey) -> Result<(), Self::Error>;
+    /// The address of the trust center and the link key shared with it.
+    fn trust_center_link_key(&self) -> Option<(u64, Key)>;
+    fn set_trust_center_link_key(&mut self, trust_center_address: u64, key: Key) -> Result<(), Self::Error>;
+    fn application_link_key(&self, partner_address: u64) -> Option<Key>;
+    fn set_application_link_key(&mut self, partner_address: u64, key: Key) -> Result<(), Self::Error>;
+    fn remove_application_link_key(&mut self, partner_address: u64) -> Result<(), Self::Error>;
+    fn install_code(&self, address: u64) -> Option<&[u8]>;
+    fn set_install_code(&mut self, address: u64, install_code: &[u8]) -> Result<(), Self::Error>;
+    /// The stored value of an outgoing frame counter, zero if it was never
+    /// stored.
+    fn outgoing_frame_counter(&self, counter: FrameCounter) -> u32;
+    fn set_outgoing_frame_counter(&mut self, counter: FrameCounter, value: u32) -> Result<(), Self::Error>;
+}
+
+/// Stores the outgoing frame counters every `interval` increments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameCounterPersistence {
+    pub interval: u32,
+}
+
+impl Default for FrameCounterPersistence {
+    fn default() -> Self {
+        FrameCounterPersistence { interval: 1024 }
+    }
+}
+
+impl FrameCounterPersistence {
+    /// Tell that the counter reached `next`, the next value that will be
+    /// used. When it reaches the stored value, a value `interval` frames
+    /// ahead is stored.
+    pub fn update<S: KeyStore>(&self, store: &mut S, counter: FrameCounter, next: u32) -> Result<(), S::Error> {
+        if next >= store.outgoing_frame_counter(counter) {
+            store.set_outgoing_frame_counter(counter, next.saturating_add(self.interval))?;
+        }
+        Ok(())
+    }
+}
+
+/// A key store that keeps the material in memory only. It fails only to
+/// store an install code whose length or CRC is invalid.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MemoryKeyStore {
+    network_key: Option<NetworkKey>,
+    trust_center_link_key: Option<(u64, Key)>,
+    application_link_keys: Vec<(u64, Key)>,
+    install_codes: Vec<(u64, Vec<u8>)>,
+    network_frame_counter: u32,
+    link_frame_counters: Vec<(u64, u32)>,
+}
+
+impl MemoryKeyStore {
+    pub fn new() -> Self {
+        MemoryKeyStore::default()
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self.network_key {
+            Some(key) => {
+                out.push(1);
+                out.push(key.sequence_number);
+                out.extend_from_slice(&key.key);
+            }
+            None => out.push(0),
+        }
+        match self.trust_center_link_key {
+            Some((address, key)) => {
+                out.push(1);
+                write_u64(out, address);
+                out.extend_from_slice(&key);
+            }
+            None => out.push(0),
+        }
+        write_u16(out, self.application_link_keys.len() as u16);
+        for &(address, key) in &self.application_link_keys {
+            write_u64(out, address);
+            out.extend_from_slice(&key);
+        }
+        write_u16(out, self.install_codes.len() as u16);
+        for &(address, ref code) in &self.install_codes {
+            write_u64(out, address);
+            out.push(code.len() as u8);
+            out.extend_from_slice(code);
+        }
+        write_u32(out, self.network_frame_counter);
+        write_u16(out, self.link_frame_counters.len() as u16);
+        for &(address, value) in &self.link_frame_counters {
+            write_u64(out, address);
+            write_u32(out, value);
+        }
+    }
+
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        fn read_key(reader: &mut Reader) -> Result<Key, DecodeError> {
+            let mut key = [0; KEY_SIZE];
+            key.copy_from_slice(reader.read_bytes(KEY_SIZE)?);
+            Ok(key)
+        }
+
+        let mut store = MemoryKeyStore::new();
+        if reader.read_u8()? != 0 {
+            let sequence_number = reader.read_u8()?;
+            store.network_key = Some(NetworkKey { sequence_number, key: read_key(reader)? });
+        }
+        if reader.read_u8()? != 0 {
+            let address = reader.read_u64()?;
+            store.trust_center_link_key = Some((address, read_key(reader)?));
+        }
+        for _ in 0..reader.read_u16()? {
+            let address = reader.read_u64()?;
+            store.application_link_keys.push((address, read_key(reader)?));
+        }
+        for _ in 0..reader.read_u16()? {
+            let address = reader.read_u64()?;
+            let len = reader.read_u8()? as usize;
+            store.install_codes.push((address, reader.read_bytes(len)?.to_vec()));
+        }
+        store.network_frame_counter = reader.read_u32()?;
+        for _ in 0..reader.read_u16()? {
+            let address = reader.read_u64()?;
+            store.link_frame_counters.push((address, reader.read_u32()?));
+        }
+        Ok(store)
+    }
+}
+
+impl KeyStore for MemoryKeyStore {
+    type Error = InstallCodeError;
+
+    fn network_key(&self) -> Option<NetworkKey> {
+        self.network_key
+    }
+
+    fn set_network_key(&mut self, key: NetworkKey) -> Result<(), InstallCodeError> {
+        // The frames secured with a new key restart from zero.
+        if self.network_key.is_none_or(|k| k != key) {
+            self.network_frame_counter = 0;
+        }
+        self.network_key = Some(key);
+        Ok(())
+    }
+
+    fn trust_center_link_key(&self) -> Option<(u64, Key)> {
+        self.trust_center_link_key
+    }
+
+    fn set_trust_center_link_key(&mut self, trust_center_address: u64, key: Key) -> Result<(), InstallCodeError> {
+        self.trust_center_link_key = Some((trust_center_address, key));
+        Ok(())
+    }
+
+    fn application_link_key(&self, partner_address: u64) -> Option<Key> {
+        self.application_link_keys.iter().find(|&&(a, _)| a == partner_address).map(|&(_, k)| k)
+    }
+
+    fn set_application_link_key(&mut self, partner_address: u64, key: Key) -> Result<(), InstallCodeError> {
+        self.application_link_keys.retain(|&(a, _)| a != partner_address);
+        self.application_link_keys.push((partner_address, key));
+        Ok(())
+    }
+
+    fn remove_application_link_key(&mut self, partner_address: u64) -> Result<(), InstallCodeError> {
+        self.application_link_keys.retain(|&(a, _)| a != partner_address);
+        self.link_frame_counters.retain(|&(a, _)| a != partner_address);
+        Ok(())
+    }
+
+    fn install_code(&self, address: u64) -> Option<&[u8]> {
+        self.install_codes.iter().find(|&&(a, _)| a == address).map(|(_, c)| &c[..])
+    }
+
+    fn set_install_code(&mut self, address: u64, install_code: &[u8]) -> Result<(), InstallCodeError> {
+        install_code::check(install_code)?;
+        self.install_codes.retain(|&(a, _)| a != address);
+        self.install_codes.push((address, install_code.to_vec()));
+        Ok(())
+    }
+
+    fn outgoing_frame_counter(&self, counter: FrameCounter) -> u32 {
+        match counter {
+            FrameCounter::Network => self.network_frame_counter,
+            FrameCounter::Link(address) => {
+                self.link_frame_counters.iter().find(|&&(a, _)| a == address).map_or(0, |&(_, v)| v)
+            }
+        }
+    }
+
+    fn set_outgoing_frame_counter(&mut self, counter: FrameCounter, value: u32) -> Result<(), InstallCodeError> {
+        match counter {
+            FrameCounter::Network => self.network_frame_counter = value,
+            FrameCounter::Link(address) => {
+                self.link_frame_counters.retain(|&(a, _)| a != address);
+                self.link_frame_counters.push((address, value));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Identifies the files written by `FileKeyStore`.
+const MAGIC: &[u8; 4] = b"ZBKS";
+/// The level that secures the file: a corrupted or tampered file is
+/// detected by the 128 bit message integrity code.
+const FILE_SECURITY_LEVEL: SecurityLevel = SecurityLevel::EncMic128;
+
+#[derive(Debug)]
+pub enum FileKeyStoreError {
+    Io(io::Error),
+    /// The file is not a key store, or was written with another key.
+    Corrupted,
+    InvalidInstallCode(InstallCodeError),
+}
+
+impl fmt::Display for FileKeyStoreError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            FileKeyStoreError::Io(ref error) => write!(f, "key store I/O error: {}", error),
+            FileKeyStoreError::Corrupted => write!(f, "corrupted key store"),
+            FileKeyStoreError::InvalidInstallCode(ref error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl error::Error for FileKeyStoreError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            FileKeyStoreError::Io(ref error) => Some(error),
+            FileKeyStoreError::Corrupted => None,
+            FileKeyStoreError::InvalidInstallCode(ref error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for FileKeyStoreError {
+    fn from(error: io::Error) -> Self {
+        FileKeyStoreError::Io(error)
+    }
+}
+
+impl From<InstallCodeError> for FileKeyStoreError {
+    fn from(error: InstallCodeError) -> Self {
+        FileKeyStoreError::InvalidInstallCode(error)
+    }
+}
+
+/// A key store that writes the whole material to a file, encrypted and
+/// authenticated with AES-128 CCM* under a storage key, after every
+/// change. Generic over the random number generator that generates the
+/// nonces: a nonce used twice with the storage key would reveal the
+/// material, so it must be a cryptographically secure one.
+///
+/// The file is replaced atomically: a new file is written next to it and
+/// renamed.
+pub struct FileKeyStore<R> {
+    path: PathBuf,
+    storage_key: Key,
+    rng: R,
+    store: MemoryKeyStore,
+}
+
+impl<R: RngCore + CryptoRng> FileKeyStore<R> {
+    /// Open the key store at `path`, empty if the file does not exist.
+    pub fn open<P: Into<PathBuf>>(path: P, storage_key: Key, rng: R) -> Result<Self, FileKeyStoreError> {
+        let path = path.into();
+        let store = match fs::read(&path) {
+            Ok(data) => FileKeyStore::<R>::decrypt(&data, &storage_key)?,
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => MemoryKeyStore::new(),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(FileKeyStore { path, storage_key, rng, store })
+    }
+
+    fn decrypt(data: &[u8], storage_key: &Key) -> Result<MemoryKeyStore, FileKeyStoreError> {
+        let mut reader = Reader::new(data);
+        let magic = reader.read_bytes(MAGIC.len()).map_err(|_| FileKeyStoreError::Corrupted)?;
+        if magic != MAGIC {
+            return Err(FileKeyStoreError::Corrupted);
+        }
+        let mut nonce = [0; NONCE_SIZE];
+        nonce.copy_from_slice(reader.read_bytes(NONCE_SIZE).map_err(|_| FileKeyStoreError::Corrupted)?);
+        let mut content = reader.read_to_end().to_vec();
+        ccm::decrypt(storage_key, &nonce, FILE_SECURITY_LEVEL, MAGIC, &mut content)
+            .map_err(|_| FileKeyStoreError::Corrupted)?;
+        MemoryKeyStore::decode(&mut Reader::new(&content)).map_err(|_| FileKeyStoreError::Corrupted)
+    }
+
+    fn save(&mut self, store: &MemoryKeyStore) -> Result<(), FileKeyStoreError> {
+        let mut nonce = [0; NONCE_SIZE];
+        self.rng.fill_bytes(&mut nonce);
+        let mut content = Vec::new();
+        store.encode(&mut content);
+        ccm::encrypt(&self.storage_key, &nonce, FILE_SECURITY_LEVEL, MAGIC, &mut content);
+
+        let mut data = MAGIC.to_vec();
+        data.extend_from_slice(&nonce);
+        data.extend_from_slice(&content);
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+        let mut file = File::create(&temporary)?;
+        file.write_all(&data)?;
+        // The content must reach the disk before the rename, or a crash
+        // could leave an empty file in place of the previous one.
+        file.sync_all()?;
+        fs::rename(&temporary, &self.path)?;
+        sync_directory(&self.path)?;
+        Ok(())
+    }
+
+    /// Apply a change to the material and write it to the file. The
+    /// material in memory is left unchanged when the file cannot be
+    /// written, so that it never holds what a reboot would lose.
+    fn update<F: FnOnce(&mut MemoryKeyStore)>(&mut self, change: F) -> Result<(), FileKeyStoreError> {
+        let mut store = self.store.clone();
+        change(&mut store);
+        self.save(&store)?;
+        self.store = store;
+        Ok(())
+    }
+}
+
+/// Make the rename of the file durable.
+#[cfg(unix)]
+fn sync_directory(path: &Path) -> io::Result<()> {
+    let directory = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    };
+    File::open(directory)?.sync_all()
+}
+
+/// The directories cannot be opened to be synchronized on other platforms.
+#[cfg(not(unix))]
+fn sync_directory(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+impl<R: RngCore + CryptoRng> KeyStore for FileKeyStore<R> {
+    type Error = FileKeyStoreError;
+
+    fn network_key(&self) -> Option<NetworkKey> {
+        self.store.network_key()
+    }
+
+    fn set_network_key(&mut self, key: NetworkKey) -> Result<(), FileKeyStoreError> {
+        self.update(|s| { let _ = s.set_network_key(key); })
+    }
+
+    fn trust_center_link_key(&self) -> Option<(u64, Key)> {
+        self.store.trust_center_link_key()
+    }
+
+    fn set_trust_center_link_key(&mut self, trust_center_address: u64, key: Key) -> Result<(), FileKeyStoreError> {
+        self.update(|s| { let _ = s.set_trust_center_link_key(trust_center_address, key); })
+    }
+
+    fn application_link_key(&self, partner_address: u64) -> Option<Key> {
+        self.store.application_link_key(partner_address)
+    }
+
+    fn set_application_link_key(&mut self, partner_address: u64, key: Key) -> Result<(), FileKeyStoreError> {
+        self.update(|s| { let _ = s.set_application_link_key(partner_address, key); })
+    }
+
+    fn remove_application_link_key(&mut self, partner_address: u64) -> Result<(), FileKeyStoreError> {
+        self.update(|s| { let _ = s.remove_application_link_key(partner_address); })
+    }
+
+    fn install_code(&self, address: u64) -> Option<&[u8]> {
+        self.store.install_code(address)
+    }
+
+    fn set_install_code(&mut self, address: u64, install_code: &[u8]) -> Result<(), FileKeyStoreError> {
+        install_code::check(install_code)?;
+        self.update(|s| { let _ = s.set_install_code(address, install_code); })
+    }
+
+    fn outgoing_frame_counter(&self, counter: FrameCounter) -> u32 {
+        self.store.outgoing_frame_counter(counter)
+    }
+
+    fn set_outgoing_frame_counter(&mut self, counter: FrameCounter, value: u32) -> Result<(), FileKeyStoreError> {
+        self.update(|s| { let _ = s.set_outgoing_frame_counter(counter, value); })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const STORAGE_KEY: Key = *b"0123456789abcdef";
+    const TRUST_CENTER: u64 = 0x0011_2233_4455_6677;
+    const PARTNER: u64 = 0x8899_AABB_CCDD_EEFF;
+    const NETWORK_KEY: NetworkKey = NetworkKey { sequence_number: 1, key: *b"network key 0001" };
+
+    /// A path in the temporary directory, unique to the test.
+    fn path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("zigbee-key-store-{}-{}", process::id(), name));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    /// The install code of the example in section 10.1 of the Base Device
+    /// Behavior specification, with its CRC.
+    const INSTALL_CODE: [u8; 18] = [0x83, 0xFE, 0xD3, 0x40, 0x7A, 0x93, 0x97, 0x23, 0xA5, 0xC6,
+                                    0x39, 0xB2, 0x69, 0x16, 0xD5, 0x05, 0xC3, 0xB5];
+
+    fn open(path: &Path) -> Result<FileKeyStore<StdRng>, FileKeyStoreError> {
+        FileKeyStore::open(path, STORAGE_KEY, StdRng::seed_from_u64(0))
+    }
+
+    #[test]
+    fn memory_store() {
+        let mut store = MemoryKeyStore::new();
+        store.set_network_key(NETWORK_KEY).unwrap();
+        store.set_outgoing_frame_counter(FrameCounter::Network, 100).unwrap();
+        store.set_application_link_key(PARTNER, [1; KEY_SIZE]).unwrap();
+        store.set_application_link_key(PARTNER, [2; KEY_SIZE]).unwrap();
+        store.set_outgoing_frame_counter(FrameCounter::Link(PARTNER), 7).unwrap();
+        assert_eq!(store.application_link_key(PARTNER), Some([2; KEY_SIZE]));
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Link(PARTNER)), 7);
+
+        // The same key keeps its counter, a new one restarts from zero.
+        store.set_network_key(NETWORK_KEY).unwrap();
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), 100);
+        store.set_network_key(NetworkKey { sequence_number: 2, ..NETWORK_KEY }).unwrap();
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), 0);
+
+        store.remove_application_link_key(PARTNER).unwrap();
+        assert_eq!(store.application_link_key(PARTNER), None);
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Link(PARTNER)), 0);
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let path = path("round-trip");
+        {
+            let mut store = open(&path).unwrap();
+            assert_eq!(store.network_key(), None);
+            store.set_network_key(NETWORK_KEY).unwrap();
+            store.set_trust_center_link_key(TRUST_CENTER, *b"ZigBeeAlliance09").unwrap();
+            store.set_application_link_key(PARTNER, [3; KEY_SIZE]).unwrap();
+            store.set_install_code(PARTNER, &INSTALL_CODE).unwrap();
+            store.set_outgoing_frame_counter(FrameCounter::Network, 2048).unwrap();
+            store.set_outgoing_frame_counter(FrameCounter::Link(PARTNER), 1024).unwrap();
+        }
+        let store = open(&path).unwrap();
+        assert_eq!(store.network_key(), Some(NETWORK_KEY));
+        assert_eq!(store.trust_center_link_key(), Some((TRUST_CENTER, *b"ZigBeeAlliance09")));
+        assert_eq!(store.application_link_key(PARTNER), Some([3; KEY_SIZE]));
+        assert_eq!(store.install_code(PARTNER), Some(&INSTALL_CODE[..]));
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), 2048);
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Link(PARTNER)), 1024);
+        // The material is encrypted.
+        let data = fs::read(&path).unwrap();
+        assert!(!data.windows(KEY_SIZE).any(|w| w == &NETWORK_KEY.key[..]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn corrupted_file() {
+        let path = path("corrupted");
+        open(&path).unwrap().set_network_key(NETWORK_KEY).unwrap();
+        let mut data = fs::read(&path).unwrap();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        fs::write(&path, &data).unwrap();
+        assert!(matches!(open(&path), Err(FileKeyStoreError::Corrupted)));
+        // Another storage key cannot read it either.
+        data[last] ^= 1;
+        fs::write(&path, &data).unwrap();
+        assert!(open(&path).is_ok());
+        let other = FileKeyStore::open(&path, [0; KEY_SIZE], StdRng::seed_from_u64(0));
+        assert!(matches!(other, Err(FileKeyStoreError::Corrupted)));
+        fs::write(&path, b"ZB").unwrap();
+        assert!(matches!(open(&path), Err(FileKeyStoreError::Corrupted)));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn failed_save_leaves_the_material_unchanged() {
+        let path = path("missing-directory").join("keys");
+        let mut store = open(&path).unwrap();
+        assert!(matches!(store.set_network_key(NETWORK_KEY), Err(FileKeyStoreError::Io(_))));
+        assert_eq!(store.network_key(), None);
+        assert!(store.set_outgoing_frame_counter(FrameCounter::Network, 1024).is_err());
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), 0);
+    }
+
+    #[test]
+    fn persistence_stays_ahead_of_the_counter() {
+        let persistence = FrameCounterPersistence { interval: 16 };
+        let mut store = MemoryKeyStore::new();
+        let mut stored = Vec::new();
+        for next in 0..40 {
+            persistence.update(&mut store, FrameCounter::Network, next).unwrap();
+            let value = store.outgoing_frame_counter(FrameCounter::Network);
+            // A reboot would restart from a counter that was never used.
+            assert!(value > next);
+            if stored.last() != Some(&value) {
+                stored.push(value);
+            }
+        }
+        assert_eq!(stored, vec![16, 32, 48]);
+        persistence.update(&mut store, FrameCounter::Network, u32::MAX).unwrap();
+        assert_eq!(store.outgoing_frame_counter(FrameCounter::Network), u32::MAX);
+    }
+
+    #[test]
+    fn invalid_install_codes() {
+        let mut store = MemoryKeyStore::new();
+        let mut code = INSTALL_CODE;
+        code[17] ^= 0x01;
+        assert_eq!(store.set_install_code(PARTNER, &code), Err(InstallCodeError::InvalidCrc));
+        assert_eq!(store.set_install_code(PARTNER, &INSTALL_CODE[..17]), Err(InstallCodeError::InvalidLength));
+        assert_eq!(store.set_install_code(PARTNER, &[0; 300]), Err(InstallCodeError::InvalidLength));
+        assert_eq!(store.install_code(PARTNER), None);
+
+        let path = path("invalid-install-code");
+        let mut store = open(&path).unwrap();
+        assert!(matches!(store.set_install_code(PARTNER, &code),
+                         Err(FileKeyStoreError::InvalidInstallCode(InstallCodeError::InvalidCrc))));
+        assert_eq!(store.install_code(PARTNER), None);
+        assert!(!path.exists());
+    }
+}
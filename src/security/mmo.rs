@@ -0,0 +1,124 @@
+//! The Matyas-Meyer-Oseas hash function built on AES-128, and the keyed
+//! hash message authentication code built on it, as described in annex B
+//! of the specification. They derive the keys used to transport keys, and
+//! the link keys from the install codes.
+
+use security::{Key, KEY_SIZE};
+use security::ccm::{BlockCipher, BLOCK_SIZE};
+
+const IPAD: u8 = 0x36;
+const OPAD: u8 = 0x5C;
+
+/// The input of the keyed hash that derives the key-transport key.
+const KEY_TRANSPORT: u8 = 0x00;
+/// The input of the keyed hash that derives the key-load key.
+const KEY_LOAD: u8 = 0x02;
+/// The input of the keyed hash that proves the knowledge of a link key in
+/// the Verify-Key command.
+const VERIFY_KEY: u8 = 0x03;
+
+/// The Matyas-Meyer-Oseas hash of `data`.
+pub fn hash(data: &[u8]) -> Key {
+    let bits = data.len() * 8;
+    let mut message = data.to_vec();
+    message.push(0x80);
+    if bits < 1 << 16 {
+        while message.len() % BLOCK_SIZE != BLOCK_SIZE - 2 {
+            message.push(0);
+        }
+        message.extend_from_slice(&[(bits >> 8) as u8, bits as u8]);
+    } else {
+        while message.len() % BLOCK_SIZE != BLOCK_SIZE - 6 {
+            message.push(0);
+        }
+        message.extend_from_slice(&[(bits >> 24) as u8, (bits >> 16) as u8, (bits >> 8) as u8,
+                                    bits as u8, 0, 0]);
+    }
+
+    let mut digest = [0; KEY_SIZE];
+    for block in message.chunks(BLOCK_SIZE) {
+        let cipher = BlockCipher::new(&digest);
+        let mut output = [0; BLOCK_SIZE];
+        output.copy_from_slice(block);
+        cipher.encrypt_block(&mut output);
+        for (d, (o, m)) in digest.iter_mut().zip(output.iter().zip(block)) {
+            *d = o ^ m;
+        }
+    }
+    digest
+}
+
+/// The keyed hash message authentication code of `message` with `key`.
+pub fn hmac(key: &Key, message: &[u8]) -> Key {
+    let mut inner: Vec<u8> = key.iter().map(|k| k ^ IPAD).collect();
+    inner.extend_from_slice(message);
+    let mut outer: Vec<u8> = key.iter().map(|k| k ^ OPAD).collect();
+    outer.extend_from_slice(&hash(&inner));
+    hash(&outer)
+}
+
+/// The key that secures the transport of the network key, derived from a
+/// link key.
+pub fn key_transport_key(link_key: &Key) -> Key {
+    hmac(link_key, &[KEY_TRANSPORT])
+}
+
+/// The key that secures the transport of link keys, derived from a link
+/// key.
+pub fn key_load_key(link_key: &Key) -> Key {
+    hmac(link_key, &[KEY_LOAD])
+}
+
+/// The hash sent in the Verify-Key command, derived from the trust center
+/// link key.
+pub fn verify_key_hash(link_key: &Key) -> Key {
+    hmac(link_key, &[VERIFY_KEY])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counting(length: usize) -> Vec<u8> {
+        (0..length).map(|i| i as u8).collect()
+    }
+
+    /// The test vectors of annex C.5 of the specification.
+    #[test]
+    fn hash_test_vectors() {
+        assert_eq!(hash(&[0xC0]), [0xAE, 0x3A, 0x10, 0x2A, 0x28, 0xD4, 0x3E, 0xE0,
+                                   0xD4, 0xA0, 0x9E, 0x22, 0x78, 0x8B, 0x20, 0x6C]);
+        let data: Vec<u8> = (0xC0..0xD0).collect();
+        assert_eq!(hash(&data), [0xA7, 0x97, 0x7E, 0x88, 0xBC, 0x0B, 0x61, 0xE8,
+                                 0x21, 0x08, 0x27, 0x10, 0x9A, 0x22, 0x8F, 0x2D]);
+    }
+
+    /// The length is padded on 2 bytes below 2^16 bits, and on 6 bytes
+    /// from there.
+    #[test]
+    fn hash_of_long_messages() {
+        assert_eq!(hash(&counting(8191)), [0x24, 0xEC, 0x2F, 0xE7, 0x5B, 0xBF, 0xFC, 0xB3,
+                                           0x47, 0x89, 0xBC, 0x06, 0x10, 0xE7, 0xF1, 0x65]);
+        assert_eq!(hash(&counting(8192)), [0xDC, 0x6B, 0x06, 0x87, 0xF0, 0x9F, 0x86, 0x07,
+                                           0x13, 0x1C, 0x17, 0x0B, 0x3B, 0xD3, 0x15, 0x91]);
+    }
+
+    /// The test vector of the keyed hash in annex C.6 of the specification.
+    #[test]
+    fn hmac_test_vector() {
+        let key = [0x40, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49, 0x4A, 0x4B, 0x4C, 0x4D, 0x4E, 0x4F];
+        assert_eq!(hmac(&key, &[0xC0]), [0x45, 0x12, 0x80, 0x7B, 0xF9, 0x4C, 0xB3, 0x40,
+                                         0x0F, 0x0E, 0x2C, 0x25, 0xFB, 0x76, 0xE9, 0x99]);
+    }
+
+    #[test]
+    fn derived_keys() {
+        let link_key = *b"ZigBeeAlliance09";
+        assert_eq!(key_transport_key(&link_key), [0x4B, 0xAB, 0x0F, 0x17, 0x3E, 0x14, 0x34, 0xA2,
+                                                  0xD5, 0x72, 0xE1, 0xC1, 0xEF, 0x47, 0x87, 0x82]);
+        assert_eq!(key_load_key(&link_key), [0xC5, 0xA4, 0x70, 0x35, 0xC3, 0x32, 0xCC, 0xBF,
+                                             0x25, 0x15, 0x71, 0xD8, 0xBA, 0xDE, 0xD1, 0x88]);
+        assert_eq!(verify_key_hash(&link_key), [0x1A, 0xB1, 0x28, 0xDF, 0x16, 0x39, 0xA1, 0x24,
+                                                0x6A, 0xAB, 0xA7, 0x2A, 0x6A, 0x55, 0x91, 0x24]);
+    }
+}
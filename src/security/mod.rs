@@ -0,0 +1,210 @@
+//! The security services shared by the NWK and APS layers, as described in
+//! chapter 4 of the specification: the auxiliary frame header, the CCM*
+//! mode of operation and the nonce construction.
+
+use std::error;
+use std::fmt;
+use codec::{DecodeError, Reader, write_u32, write_u64};
+
+pub mod ccm;
+pub mod mmo;
+pub mod install_code;
+pub mod key_store;
+
+pub const KEY_SIZE: usize = 16;
+
+pub type Key = [u8; KEY_SIZE];
+
+/// The security level used by every Zigbee secured frame: encryption with
+/// a 32 bit message integrity code.
+pub const ZIGBEE_SECURITY_LEVEL: SecurityLevel = SecurityLevel::EncMic32;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityLevel {
+    None = 0,
+    Mic32 = 1,
+    Mic64 = 2,
+    Mic128 = 3,
+    Enc = 4,
+    EncMic32 = 5,
+    EncMic64 = 6,
+    EncMic128 = 7,
+}
+
+impl SecurityLevel {
+    pub fn from_u8(value: u8) -> Self {
+        match value & 0x07 {
+            0 => SecurityLevel::None,
+            1 => SecurityLevel::Mic32,
+            2 => SecurityLevel::Mic64,
+            3 => SecurityLevel::Mic128,
+            4 => SecurityLevel::Enc,
+            5 => SecurityLevel::EncMic32,
+            6 => SecurityLevel::EncMic64,
+            _ => SecurityLevel::EncMic128,
+        }
+    }
+
+    /// The length of the message integrity code, in bytes.
+    pub fn mic_length(self) -> usize {
+        match (self as u8) & 0x03 {
+            0 => 0,
+            1 => 4,
+            2 => 8,
+            _ => 16,
+        }
+    }
+
+    pub fn encrypted(self) -> bool {
+        (self as u8) & 0x04 != 0
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyIdentifier {
+    /// A link key shared by two devices.
+    Data = 0,
+    Network = 1,
+    /// The key derived from a link key to secure the transport of keys.
+    KeyTransport = 2,
+    /// The key derived from a link key to secure the transport of link
+    /// keys.
+    KeyLoad = 3,
+}
+
+/// The auxiliary frame header that precedes the secured payload.
+///
+/// The security level is not sent over the air: it is written as zero and
+/// the receiver restores it from the level used by the whole network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuxiliaryHeader {
+    pub security_level: SecurityLevel,
+    pub key_identifier: KeyIdentifier,
+    pub frame_counter: u32,
+    /// The extended address of the source, present when the extended nonce
+    /// flag is set.
+    pub source_address: Option<u64>,
+    /// Present only for frames secured with the network key.
+    pub key_sequence_number: Option<u8>,
+}
+
+impl AuxiliaryHeader {
+    fn security_control(&self, over_the_air: bool) -> u8 {
+        let level = if over_the_air { 0 } else { self.security_level as u8 };
+        level | (self.key_identifier as u8) << 3 | (self.source_address.is_some() as u8) << 5
+    }
+
+    /// Encode the header as sent over the air, with the security level set
+    /// to zero.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.security_control(true));
+        self.encode_rest(out);
+    }
+
+    /// Encode the header as used in the authenticated data of CCM*, with
+    /// the real security level.
+    pub fn encode_with_level(&self, out: &mut Vec<u8>) {
+        out.push(self.security_control(false));
+        self.encode_rest(out);
+    }
+
+    fn encode_rest(&self, out: &mut Vec<u8>) {
+        write_u32(out, self.frame_counter);
+        if let Some(address) = self.source_address {
+            write_u64(out, address);
+        }
+        if let Some(sequence_number) = self.key_sequence_number {
+            out.push(sequence_number);
+        }
+    }
+
+    /// Decode the header; the security level is replaced by
+    /// `security_level`.
+    pub fn decode(reader: &mut Reader, security_level: SecurityLevel) -> Result<Self, DecodeError> {
+        let control = reader.read_u8()?;
+        let key_identifier = match (control >> 3) & 0x03 {
+            0 => KeyIdentifier::Data,
+            1 => KeyIdentifier::Network,
+            2 => KeyIdentifier::KeyTransport,
+            _ => KeyIdentifier::KeyLoad,
+        };
+        let frame_counter = reader.read_u32()?;
+        let source_address = if control & (1 << 5) != 0 { Some(reader.read_u64()?) } else { None };
+        let key_sequence_number = if key_identifier == KeyIdentifier::Network {
+            Some(reader.read_u8()?)
+        } else {
+            None
+        };
+        Ok(AuxiliaryHeader {
+            security_level,
+            key_identifier,
+            frame_counter,
+            source_address,
+            key_sequence_number,
+        })
+    }
+
+    /// The CCM* nonce: the source address, the frame counter and the
+    /// security control field, with the real security level.
+    pub fn nonce(&self, source_address: u64) -> [u8; ccm::NONCE_SIZE] {
+        let mut nonce = [0; ccm::NONCE_SIZE];
+        let mut bytes = Vec::with_capacity(ccm::NONCE_SIZE);
+        write_u64(&mut bytes, source_address);
+        write_u32(&mut bytes, self.frame_counter);
+        bytes.push(self.security_control(false));
+        nonce.copy_from_slice(&bytes);
+        nonce
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SecurityError {
+    Decode(DecodeError),
+    /// The frame does not carry the source address needed for the nonce.
+    MissingSourceAddress,
+    /// The key needed to secure or unsecure the frame is not available.
+    UnknownKey,
+    /// The frame counter is not higher than the last one received from the
+    /// same source: the frame is a replay.
+    BadFrameCounter,
+    /// The outgoing frame counter reached its maximum value.
+    FrameCounterExhausted,
+    /// The frame comes from a new source, whose frame counter cannot be
+    /// remembered because the table is full.
+    FrameCounterTableFull,
+    /// The outgoing frame counter could not be stored: the frame is not
+    /// secured, since the counter could be used again after a reboot.
+    FrameCounterNotStored,
+    /// The message integrity code does not match.
+    AuthenticationFailed,
+}
+
+impl fmt::Display for SecurityError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            SecurityError::Decode(ref error) => write!(f, "malformed secured frame: {}", error),
+            SecurityError::MissingSourceAddress => write!(f, "missing source address"),
+            SecurityError::UnknownKey => write!(f, "unknown key"),
+            SecurityError::BadFrameCounter => write!(f, "replayed frame counter"),
+            SecurityError::FrameCounterExhausted => write!(f, "outgoing frame counter exhausted"),
+            SecurityError::FrameCounterTableFull => write!(f, "incoming frame counter table full"),
+            SecurityError::FrameCounterNotStored => write!(f, "outgoing frame counter not stored"),
+            SecurityError::AuthenticationFailed => write!(f, "authentication failed"),
+        }
+    }
+}
+
+impl error::Error for SecurityError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            SecurityError::Decode(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<DecodeError> for SecurityError {
+    fn from(error: DecodeError) -> Self {
+        SecurityError::Decode(error)
+    }
+}
@@ -0,0 +1,267 @@
+//! The addresses and identifiers used across the stack, each wrapped in a
+//! type of its own so that a cluster identifier cannot be passed where a
+//! network address is expected.
+//!
+//! The EUI-64 addresses are displayed and parsed as eight colon separated
+//! hexadecimal octets, most significant first, e.g.
+//! `00:0d:6f:00:0a:90:69:e8`.
+
+use std::error;
+use std::fmt;
+use std::str::FromStr;
+use nwk::{BROADCAST_ALL, BROADCAST_LOW_POWER_ROUTERS, BROADCAST_ROUTERS, BROADCAST_RX_ON_WHEN_IDLE};
+use nwk::broadcast::is_broadcast_address;
+
+/// Declare a newtype over an integer, convertible from and to it.
+macro_rules! identifier {
+    ($(#[$meta:meta])* $name:ident($inner:ty)) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Default,
+                 Serialize, Deserialize)]
+        pub struct $name(pub $inner);
+
+        impl From<$inner> for $name {
+            fn from(value: $inner) -> Self {
+                $name(value)
+            }
+        }
+
+        impl From<$name> for $inner {
+            fn from(value: $name) -> Self {
+                value.0
+            }
+        }
+    };
+}
+
+identifier! {
+    /// A 16 bit network address.
+    NwkAddress(u16)
+}
+
+identifier! {
+    /// A 64 bit IEEE (EUI-64) address.
+    IeeeAddress(u64)
+}
+
+identifier! {
+    /// A 16 bit group address.
+    GroupId(u16)
+}
+
+identifier! {
+    ClusterId(u16)
+}
+
+identifier! {
+    ProfileId(u16)
+}
+
+identifier! {
+    /// The identifier of an attribute within its cluster.
+    AttributeId(u16)
+}
+
+identifier! {
+    Endpoint(u8)
+}
+
+identifier! {
+    PanId(u16)
+}
+
+identifier! {
+    ExtendedPanId(u64)
+}
+
+impl NwkAddress {
+    pub const COORDINATOR: NwkAddress = NwkAddress(0x0000);
+    pub const BROADCAST_ALL: NwkAddress = NwkAddress(BROADCAST_ALL);
+    pub const BROADCAST_RX_ON_WHEN_IDLE: NwkAddress = NwkAddress(BROADCAST_RX_ON_WHEN_IDLE);
+    pub const BROADCAST_ROUTERS: NwkAddress = NwkAddress(BROADCAST_ROUTERS);
+    pub const BROADCAST_LOW_POWER_ROUTERS: NwkAddress = NwkAddress(BROADCAST_LOW_POWER_ROUTERS);
+
+    /// Whether the address is one of the broadcast addresses, reserved ones
+    /// included.
+    pub fn is_broadcast(self) -> bool {
+        is_broadcast_address(self.0)
+    }
+}
+
+impl Endpoint {
+    /// The endpoint of the Zigbee device object.
+    pub const ZDO: Endpoint = Endpoint(0x00);
+    pub const MIN_APPLICATION: Endpoint = Endpoint(0x01);
+    pub const MAX_APPLICATION: Endpoint = Endpoint(0xF0);
+    pub const GREEN_POWER: Endpoint = Endpoint(0xF2);
+    /// Deliver to all the active endpoints.
+    pub const BROADCAST: Endpoint = Endpoint(0xFF);
+
+    /// Whether the endpoint may host an application object.
+    pub fn is_application(self) -> bool {
+        self >= Endpoint::MIN_APPLICATION && self <= Endpoint::MAX_APPLICATION
+    }
+}
+
+impl ProfileId {
+    /// The Zigbee device profile.
+    pub const ZDP: ProfileId = ProfileId(0x0000);
+    pub const HOME_AUTOMATION: ProfileId = ProfileId(0x0104);
+    /// Matches any profile.
+    pub const WILDCARD: ProfileId = ProfileId(0xFFFF);
+}
+
+impl PanId {
+    pub const BROADCAST: PanId = PanId(0xFFFF);
+}
+
+impl ExtendedPanId {
+    /// The value of apsUseExtendedPANID that lets the device join any
+    /// network.
+    pub const ANY: ExtendedPanId = ExtendedPanId(0);
+}
+
+impl fmt::Display for NwkAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04X}", self.0)
+    }
+}
+
+impl fmt::Display for GroupId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04X}", self.0)
+    }
+}
+
+impl fmt::Display for ClusterId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04X}", self.0)
+    }
+}
+
+impl fmt::Display for AttributeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04X}", self.0)
+    }
+}
+
+impl fmt::Display for ProfileId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04X}", self.0)
+    }
+}
+
+impl fmt::Display for PanId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:04X}", self.0)
+    }
+}
+
+impl fmt::Display for Endpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+fn fmt_eui64(value: u64, f: &mut fmt::Formatter) -> fmt::Result {
+    for i in (0..8).rev() {
+        write!(f, "{:02x}", (value >> (i * 8)) as u8)?;
+        if i > 0 {
+            write!(f, ":")?;
+        }
+    }
+    Ok(())
+}
+
+/// A string that is not an EUI-64 written as eight colon separated
+/// hexadecimal octets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParseEui64Error;
+
+impl fmt::Display for ParseEui64Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid EUI-64")
+    }
+}
+
+impl error::Error for ParseEui64Error {}
+
+fn parse_eui64(s: &str) -> Result<u64, ParseEui64Error> {
+    let mut value = 0;
+    let mut octets = 0;
+    for octet in s.split(':') {
+        // from_str_radix accepts a sign, e.g. "+f".
+        if octet.len() != 2 || !octet.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(ParseEui64Error);
+        }
+        value = value << 8 | u64::from(u8::from_str_radix(octet, 16).map_err(|_| ParseEui64Error)?);
+        octets += 1;
+    }
+    if octets != 8 {
+        return Err(ParseEui64Error);
+    }
+    Ok(value)
+}
+
+impl fmt::Display for IeeeAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_eui64(self.0, f)
+    }
+}
+
+impl FromStr for IeeeAddress {
+    type Err = ParseEui64Error;
+
+    fn from_str(s: &str) -> Result<Self, ParseEui64Error> {
+        parse_eui64(s).map(IeeeAddress)
+    }
+}
+
+impl fmt::Display for ExtendedPanId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_eui64(self.0, f)
+    }
+}
+
+impl FromStr for ExtendedPanId {
+    type Err = ParseEui64Error;
+
+    fn from_str(s: &str) -> Result<Self, ParseEui64Error> {
+        parse_eui64(s).map(ExtendedPanId)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broadcast_addresses() {
+        assert!(NwkAddress::BROADCAST_ALL.is_broadcast());
+        assert!(NwkAddress::BROADCAST_LOW_POWER_ROUTERS.is_broadcast());
+        assert!(NwkAddress(0xFFF8).is_broadcast());
+        assert!(!NwkAddress(0xFFF7).is_broadcast());
+        assert!(!NwkAddress::COORDINATOR.is_broadcast());
+    }
+
+    #[test]
+    fn eui64_round_trip() {
+        let address = IeeeAddress(0x000D_6F00_0A90_69E8);
+        assert_eq!(address.to_string(), "00:0d:6f:00:0a:90:69:e8");
+        assert_eq!("00:0d:6f:00:0a:90:69:e8".parse(), Ok(address));
+        assert_eq!("00:0D:6F:00:0A:90:69:E8".parse(), Ok(address));
+        for &value in &[0, u64::MAX, 0x0123_4567_89AB_CDEF] {
+            assert_eq!(IeeeAddress(value).to_string().parse(), Ok(IeeeAddress(value)));
+            assert_eq!(ExtendedPanId(value).to_string().parse(), Ok(ExtendedPanId(value)));
+        }
+    }
+
+    #[test]
+    fn invalid_eui64() {
+        for s in &["", "00:0d:6f:00:0a:90:69", "00:0d:6f:00:0a:90:69:e8:00", "00:0d:6f:00:0a:90:69:e",
+                   "000:d:6f:00:0a:90:69:e8", "00:0d:6f:00:0a:90:69:g8", "+0:0d:6f:00:0a:90:69:e8",
+                   "00:0d:6f:00:0a:90:69:+f", "-0:0d:6f:00:0a:90:69:e8", "00-0d-6f-00-0a-90-69-e8"] {
+            assert_eq!(s.parse::<IeeeAddress>(), Err(ParseEui64Error), "{}", s);
+        }
+    }
+}
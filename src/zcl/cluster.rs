@@ -0,0 +1,1154 @@
+//! The clusters implemented by the application objects.
+//!
+//! A cluster holds a table of attributes, with their type, access control
+//! and default value, and handles the commands specific to it. The general
+//! commands that read, write and discover the attributes and the commands
+//! are answered from the table by the `ClusterSet` of the endpoint, that
+//! also sends the Default Response when no other response is due.
+//!
+//! The table also holds the reporting configuration of each attribute,
+//! set with the Configure Reporting command. The cluster set builds the
+//! Report Attributes frames when they are due, and encodes the
+//! configurations so that they can be stored and restored after a reboot.
+
+use std::borrow::Cow;
+use std::time::Duration;
+use codec::{DecodeError, Reader, write_u16};
+use apl::framework::SimpleDescriptor;
+use types::{AttributeId, ClusterId, Endpoint, ProfileId};
+use zcl::{Direction, FrameType, ZclHeader, ZclStatus};
+use zcl::data_type::{AttributeValue, DataType};
+use zcl::general::{AccessControl, ConfigureReporting, ConfigureReportingResponse, DefaultResponse,
+                   DiscoverAttributes, DiscoverAttributesExtendedResponse, DiscoverAttributesResponse,
+                   DiscoverCommands, DiscoverCommandsResponse, ExtendedAttributeInformation, GeneralCommand,
+                   ReadAttributeStatusRecord, ReadAttributesResponse, ReadReportingConfigurationResponse,
+                   ReportAttributes, ReportingConfiguration, ReportingDirection, ReportingStatusRecord,
+                   WriteAttributeRecord, WriteAttributeStatusRecord, WriteAttributes, WriteAttributesResponse};
+use zcl::reporting::AttributeReporting;
+
+/// Whether a cluster is the server, that holds the attributes, or the
+/// client, that manipulates them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ClusterSide {
+    Server,
+    Client,
+}
+
+impl ClusterSide {
+    /// The side that receives the commands sent in the direction.
+    pub fn receiving(direction: Direction) -> Self {
+        match direction {
+            Direction::ClientToServer => ClusterSide::Server,
+            Direction::ServerToClient => ClusterSide::Client,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeDefinition {
+    pub id: AttributeId,
+    /// The code of the manufacturer that defined the attribute, `None` for
+    /// the attributes defined by the specification.
+    pub manufacturer_code: Option<u16>,
+    pub data_type: DataType,
+    pub access: AccessControl,
+    pub default: AttributeValue,
+}
+
+struct Attribute {
+    definition: AttributeDefinition,
+    value: AttributeValue,
+    reporting: Option<AttributeReporting>,
+    /// The maximum expected interval between the reports received for the
+    /// attribute, in seconds.
+    timeout_period: Option<u16>,
+}
+
+/// The attributes of a cluster, ordered by manufacturer code and
+/// identifier.
+#[derive(Default)]
+pub struct AttributeTable {
+    attributes: Vec<Attribute>,
+    reporting_modified: bool,
+}
+
+impl AttributeTable {
+    pub fn new() -> Self {
+        AttributeTable { attributes: Vec::new(), reporting_modified: false }
+    }
+
+    fn position(&self, id: AttributeId, manufacturer_code: Option<u16>) -> Result<usize, usize> {
+        self.attributes.binary_search_by_key(&(manufacturer_code, id), |attribute| {
+            (attribute.definition.manufacturer_code, attribute.definition.id)
+        })
+    }
+
+    fn attribute(&self, id: AttributeId, manufacturer_code: Option<u16>) -> Option<&Attribute> {
+        self.position(id, manufacturer_code).ok().map(|i| &self.attributes[i])
+    }
+
+    /// Add an attribute, set to its default value.
+    pub fn add(&mut self, definition: AttributeDefinition) -> Result<(), ZclStatus> {
+        if definition.default.data_type() != definition.data_type {
+            return Err(ZclStatus::InvalidDataType);
+        }
+        match self.position(definition.id, definition.manufacturer_code) {
+            Ok(_) => Err(ZclStatus::DuplicateExists),
+            Err(i) => {
+                let value = definition.default.clone();
+                self.attributes.insert(i, Attribute { definition, value, reporting: None, timeout_period: None });
+                Ok(())
+            }
+        }
+    }
+
+    pub fn definitions<'a>(&'a self) -> impl Iterator<Item = &'a AttributeDefinition> + 'a {
+        self.attributes.iter().map(|attribute| &attribute.definition)
+    }
+
+    pub fn definition(&self, id: AttributeId, manufacturer_code: Option<u16>) -> Option<&AttributeDefinition> {
+        self.attribute(id, manufacturer_code).map(|attribute| &attribute.definition)
+    }
+
+    pub fn get(&self, id: AttributeId, manufacturer_code: Option<u16>) -> Option<&AttributeValue> {
+        self.attribute(id, manufacturer_code).map(|attribute| &attribute.value)
+    }
+
+    /// Set the value of an attribute on behalf of the application,
+    /// regardless of its access control.
+    pub fn set(&mut self, id: AttributeId, manufacturer_code: Option<u16>, value: AttributeValue)
+               -> Result<(), ZclStatus> {
+        let i = self.position(id, manufacturer_code).map_err(|_| ZclStatus::UnsupportedAttribute)?;
+        let attribute = &mut self.attributes[i];
+        if value.data_type() != attribute.definition.data_type {
+            return Err(ZclStatus::InvalidDataType);
+        }
+        attribute.value = value;
+        Ok(())
+    }
+
+    /// Read an attribute on behalf of a remote device.
+    pub fn read(&self, id: AttributeId, manufacturer_code: Option<u16>) -> Result<AttributeValue, ZclStatus> {
+        let attribute = self.attribute(id, manufacturer_code).ok_or(ZclStatus::UnsupportedAttribute)?;
+        if !attribute.definition.access.readable {
+            return Err(ZclStatus::WriteOnly);
+        }
+        Ok(attribute.value.clone())
+    }
+
+    /// Check whether a remote device may write the value to an attribute.
+    pub fn check_write(&self, id: AttributeId, manufacturer_code: Option<u16>, value: &AttributeValue)
+                       -> Result<(), ZclStatus> {
+        let attribute = self.attribute(id, manufacturer_code).ok_or(ZclStatus::UnsupportedAttribute)?;
+        if value.data_type() != attribute.definition.data_type {
+            return Err(ZclStatus::InvalidDataType);
+        }
+        if !attribute.definition.access.writable {
+            return Err(ZclStatus::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Restore the default values of all the attributes.
+    pub fn reset(&mut self) {
+        for attribute in &mut self.attributes {
+            attribute.value = attribute.definition.default.clone();
+        }
+    }
+
+    /// Apply the reporting configuration of an attribute.
+    pub fn configure_reporting(&mut self, manufacturer_code: Option<u16>, configuration: &ReportingConfiguration)
+                               -> Result<(), ZclStatus> {
+        let i = self.position(configuration.attribute_id(), manufacturer_code)
+            .map_err(|_| ZclStatus::UnsupportedAttribute)?;
+        let attribute = &mut self.attributes[i];
+        match *configuration {
+            ReportingConfiguration::Reported { data_type, min_interval, max_interval, ref reportable_change, .. } => {
+                if !attribute.definition.access.reportable {
+                    return Err(ZclStatus::UnreportableAttribute);
+                }
+                if data_type != attribute.definition.data_type
+                    || reportable_change.as_ref().is_some_and(|change| change.data_type() != data_type) {
+                    return Err(ZclStatus::InvalidDataType);
+                }
+                // The reportable change is encoded only for the analog types.
+                if reportable_change.is_some() != data_type.is_analog() {
+                    return Err(ZclStatus::InvalidValue);
+                }
+                attribute.reporting = if AttributeReporting::is_disabled(min_interval, max_interval) {
+                    None
+                } else if max_interval != 0 && min_interval > max_interval {
+                    return Err(ZclStatus::InvalidValue);
+                } else {
+                    Some(AttributeReporting::new(min_interval, max_interval, reportable_change.clone()))
+                };
+            }
+            ReportingConfiguration::Received { timeout_period, .. } => {
+                attribute.timeout_period = Some(timeout_period);
+            }
+        }
+        self.reporting_modified = true;
+        Ok(())
+    }
+
+    /// The reporting configuration of an attribute in the direction.
+    pub fn reporting_configuration(&self, manufacturer_code: Option<u16>, direction: ReportingDirection,
+                                   id: AttributeId) -> Result<ReportingConfiguration, ZclStatus> {
+        let attribute = self.attribute(id, manufacturer_code).ok_or(ZclStatus::UnsupportedAttribute)?;
+        match direction {
+            ReportingDirection::Reported => match attribute.reporting {
+                Some(ref reporting) => Ok(ReportingConfiguration::Reported {
+                    attribute_id: id,
+                    data_type: attribute.definition.data_type,
+                    min_interval: reporting.min_interval,
+                    max_interval: reporting.max_interval,
+                    reportable_change: reporting.reportable_change.clone(),
+                }),
+                None if !attribute.definition.access.reportable => Err(ZclStatus::UnreportableAttribute),
+                None => Err(ZclStatus::NotFound),
+            },
+            ReportingDirection::Received => attribute.timeout_period
+                .map(|timeout_period| ReportingConfiguration::Received { attribute_id: id, timeout_period })
+                .ok_or(ZclStatus::NotFound),
+        }
+    }
+
+    /// All the reporting configurations, with the manufacturer code of
+    /// their attribute.
+    pub fn reporting_configurations(&self) -> Vec<(Option<u16>, ReportingConfiguration)> {
+        let mut configurations = Vec::new();
+        for attribute in &self.attributes {
+            let manufacturer_code = attribute.definition.manufacturer_code;
+            for &direction in &[ReportingDirection::Reported, ReportingDirection::Received] {
+                if let Ok(configuration) = self.reporting_configuration(manufacturer_code, direction,
+                                                                        attribute.definition.id) {
+                    configurations.push((manufacturer_code, configuration));
+                }
+            }
+        }
+        configurations
+    }
+
+    /// Whether the reporting configurations changed since the last call.
+    pub fn take_reporting_modified(&mut self) -> bool {
+        ::std::mem::take(&mut self.reporting_modified)
+    }
+
+    /// The attributes to report at time `now`, with their manufacturer
+    /// code. They are recorded as reported.
+    pub fn poll_reports(&mut self, now: Duration) -> Vec<(Option<u16>, WriteAttributeRecord)> {
+        let mut reports = Vec::new();
+        for attribute in &mut self.attributes {
+            let value = &attribute.value;
+            if let Some(ref mut reporting) = attribute.reporting {
+                if reporting.is_due(now, value) {
+                    reporting.reported(now, value.clone());
+                    reports.push((attribute.definition.manufacturer_code, WriteAttributeRecord {
+                        attribute_id: attribute.definition.id,
+                        value: value.clone(),
+                    }));
+                }
+            }
+        }
+        reports
+    }
+
+    /// The time of the next report of an attribute.
+    pub fn next_report(&self) -> Option<Duration> {
+        self.attributes.iter()
+            .filter_map(|attribute| attribute.reporting.as_ref()
+                        .and_then(|reporting| reporting.next_report(&attribute.value)))
+            .min()
+    }
+}
+
+/// A response to a cluster specific command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClusterResponse {
+    pub command_id: u8,
+    pub payload: Vec<u8>,
+}
+
+/// A cluster implemented on an endpoint.
+pub trait Cluster {
+    fn id(&self) -> ClusterId;
+    fn side(&self) -> ClusterSide;
+    fn attributes(&self) -> &AttributeTable;
+    fn attributes_mut(&mut self) -> &mut AttributeTable;
+
+    /// Check the value that a remote device writes to an attribute, beyond
+    /// its type and access control.
+    fn validate_write(&self, _id: AttributeId, _manufacturer_code: Option<u16>, _value: &AttributeValue)
+                      -> Result<(), ZclStatus> {
+        Ok(())
+    }
+
+    /// The cluster specific commands that the cluster handles, reported by
+    /// the command discovery.
+    fn commands_received(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// The cluster specific commands that the cluster may send.
+    fn commands_generated(&self) -> Vec<u8> {
+        Vec::new()
+    }
+
+    /// Handle a cluster specific command, returning the response, if the
+    /// command has one.
+    fn handle_command(&mut self, _header: &ZclHeader, _payload: &[u8])
+                      -> Result<Option<ClusterResponse>, ZclStatus> {
+        Err(ZclStatus::UnsupClusterCommand)
+    }
+
+    /// Handle a general command that is not answered from the attribute
+    /// table, such as the responses and the reports received by a client.
+    fn handle_general_command(&mut self, _header: &ZclHeader, _command: &GeneralCommand)
+                              -> Result<Option<GeneralCommand>, ZclStatus> {
+        Err(ZclStatus::UnsupGeneralCommand)
+    }
+}
+
+/// A cluster that only holds attributes.
+pub struct GenericCluster {
+    pub id: ClusterId,
+    pub side: ClusterSide,
+    pub attributes: AttributeTable,
+}
+
+impl Cluster for GenericCluster {
+    fn id(&self) -> ClusterId {
+        self.id
+    }
+
+    fn side(&self) -> ClusterSide {
+        self.side
+    }
+
+    fn attributes(&self) -> &AttributeTable {
+        &self.attributes
+    }
+
+    fn attributes_mut(&mut self) -> &mut AttributeTable {
+        &mut self.attributes
+    }
+}
+
+enum Response {
+    General(GeneralCommand),
+    Cluster(ClusterResponse),
+}
+
+/// The clusters implemented on an endpoint.
+#[derive(Default)]
+pub struct ClusterSet {
+    clusters: Vec<Box<dyn Cluster + Send>>,
+    /// The sequence number of the next report.
+    sequence_number: u8,
+}
+
+impl ClusterSet {
+    pub fn new() -> Self {
+        ClusterSet { clusters: Vec::new(), sequence_number: 0 }
+    }
+
+    pub fn add(&mut self, cluster: Box<dyn Cluster + Send>) -> Result<(), ZclStatus> {
+        if self.get(cluster.id(), cluster.side()).is_some() {
+            return Err(ZclStatus::DuplicateExists);
+        }
+        self.clusters.push(cluster);
+        Ok(())
+    }
+
+    pub fn get(&self, id: ClusterId, side: ClusterSide) -> Option<&(dyn Cluster + Send)> {
+        self.clusters.iter()
+            .find(|cluster| cluster.id() == id && cluster.side() == side)
+            .map(|cluster| &**cluster)
+    }
+
+    pub fn get_mut(&mut self, id: ClusterId, side: ClusterSide) -> Option<&mut (dyn Cluster + Send)> {
+        match self.clusters.iter_mut().find(|cluster| cluster.id() == id && cluster.side() == side) {
+            Some(cluster) => Some(&mut **cluster),
+            None => None,
+        }
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a (dyn Cluster + Send)> + 'a {
+        self.clusters.iter().map(|cluster| &**cluster)
+    }
+
+    fn ids(&self, side: ClusterSide) -> Vec<ClusterId> {
+        self.clusters.iter().filter(|cluster| cluster.side() == side).map(|cluster| cluster.id()).collect()
+    }
+
+    /// The simple descriptor of an endpoint that implements the clusters:
+    /// the servers are the input clusters and the clients the output ones.
+    pub fn simple_descriptor(&self, endpoint: Endpoint, profile_id: ProfileId, device_id: u16,
+                             device_version: u8) -> SimpleDescriptor<'static, 'static> {
+        SimpleDescriptor {
+            endpoint,
+            appl_prof_id: profile_id,
+            appl_dev_id: device_id,
+            appl_dev_vers: device_version,
+            appl_input_clusters: Cow::Owned(self.ids(ClusterSide::Server)),
+            appl_output_clusters: Cow::Owned(self.ids(ClusterSide::Client)),
+        }
+    }
+
+    /// Handle a ZCL frame received for the cluster. Return the frame to
+    /// send back to its source, if any.
+    pub fn handle_frame(&mut self, cluster_id: ClusterId, frame: &[u8]) -> Option<Vec<u8>> {
+        let mut reader = Reader::new(frame);
+        let header = ZclHeader::decode(&mut reader).ok()?;
+        let is_default_response = header.frame_type == FrameType::Global
+            && header.command_id == DefaultResponse::ID;
+        let result = match self.get_mut(cluster_id, ClusterSide::receiving(header.direction)) {
+            None => Err(ZclStatus::UnsupportedCluster),
+            Some(cluster) => match header.frame_type {
+                FrameType::Global => match GeneralCommand::decode_payload(header.command_id, &mut reader) {
+                    Ok(command) => handle_general(cluster, &header, command).map(|r| r.map(Response::General)),
+                    Err(DecodeError::InvalidValue("general command")) => Err(ZclStatus::UnsupGeneralCommand),
+                    Err(_) => Err(ZclStatus::MalformedCommand),
+                },
+                FrameType::ClusterSpecific => cluster.handle_command(&header, reader.remaining())
+                    .map(|r| r.map(Response::Cluster)),
+            },
+        };
+        let response = match result {
+            Ok(Some(response)) => response,
+            Ok(None) if header.disable_default_response || is_default_response => return None,
+            Ok(None) => Response::General(GeneralCommand::DefaultResponse(DefaultResponse {
+                command_id: header.command_id,
+                status: ZclStatus::Success,
+            })),
+            Err(_) if is_default_response => return None,
+            Err(status) => Response::General(GeneralCommand::DefaultResponse(DefaultResponse {
+                command_id: header.command_id,
+                status,
+            })),
+        };
+        let mut out = Vec::new();
+        let mut response_header = ZclHeader {
+            frame_type: FrameType::Global,
+            manufacturer_code: header.manufacturer_code,
+            direction: header.direction.reverse(),
+            disable_default_response: true,
+            sequence_number: header.sequence_number,
+            command_id: 0,
+        };
+        match response {
+            Response::General(command) => {
+                response_header.command_id = command.id();
+                response_header.encode(&mut out);
+                command.encode_payload(&mut out);
+            }
+            Response::Cluster(response) => {
+                response_header.frame_type = FrameType::ClusterSpecific;
+                response_header.command_id = response.command_id;
+                response_header.encode(&mut out);
+                out.extend_from_slice(&response.payload);
+            }
+        }
+        Some(out)
+    }
+
+    /// The Report Attributes frames due at time `now`, with the cluster
+    /// they are sent from.
+    pub fn poll_reports(&mut self, now: Duration) -> Vec<(ClusterId, Vec<u8>)> {
+        let mut frames = Vec::new();
+        for cluster in &mut self.clusters {
+            let direction = match cluster.side() {
+                ClusterSide::Server => Direction::ServerToClient,
+                ClusterSide::Client => Direction::ClientToServer,
+            };
+            let mut records = cluster.attributes_mut().poll_reports(now).into_iter().peekable();
+            // The attributes are ordered by manufacturer code: a frame
+            // reports those of one manufacturer.
+            while let Some((manufacturer_code, record)) = records.next() {
+                let mut reports = vec![record];
+                while records.peek().is_some_and(|&(code, _)| code == manufacturer_code) {
+                    reports.extend(records.next().map(|(_, record)| record));
+                }
+                let mut frame = Vec::new();
+                GeneralCommand::ReportAttributes(ReportAttributes { reports })
+                    .encode(direction, manufacturer_code, true, self.sequence_number, &mut frame);
+                self.sequence_number = self.sequence_number.wrapping_add(1);
+                frames.push((cluster.id(), frame));
+            }
+        }
+        frames
+    }
+
+    /// The time of the next report of an attribute of the clusters.
+    pub fn next_report(&self) -> Option<Duration> {
+        self.clusters.iter().filter_map(|cluster| cluster.attributes().next_report()).min()
+    }
+
+    /// Whether the reporting configurations changed since the last call,
+    /// and must be stored again.
+    pub fn take_reporting_modified(&mut self) -> bool {
+        let mut modified = false;
+        for cluster in &mut self.clusters {
+            modified |= cluster.attributes_mut().take_reporting_modified();
+        }
+        modified
+    }
+
+    /// Encode the reporting configurations of the clusters, to be stored.
+    pub fn encode_reporting(&self, out: &mut Vec<u8>) {
+        for cluster in &self.clusters {
+            for (manufacturer_code, configuration) in cluster.attributes().reporting_configurations() {
+                write_u16(out, cluster.id().0);
+                out.push(cluster.side() as u8);
+                match manufacturer_code {
+                    Some(code) => {
+                        out.push(1);
+                        write_u16(out, code);
+                    }
+                    None => out.push(0),
+                }
+                let mut record = Vec::new();
+                ConfigureReporting { records: vec![configuration] }.encode_payload(&mut record);
+                out.push(record.len() as u8);
+                out.extend_from_slice(&record);
+            }
+        }
+    }
+
+    /// Restore the reporting configurations encoded by `encode_reporting`.
+    /// The configurations of the clusters and attributes that no longer
+    /// exist are ignored.
+    pub fn restore_reporting(&mut self, reader: &mut Reader) -> Result<(), DecodeError> {
+        while !reader.is_empty() {
+            let cluster_id = ClusterId(reader.read_u16()?);
+            let side = match reader.read_u8()? {
+                0 => ClusterSide::Server,
+                1 => ClusterSide::Client,
+                _ => return Err(DecodeError::InvalidValue("cluster side")),
+            };
+            let manufacturer_code = match reader.read_u8()? {
+                0 => None,
+                _ => Some(reader.read_u16()?),
+            };
+            let len = reader.read_u8()? as usize;
+            let request = ConfigureReporting::decode_payload(&mut Reader::new(reader.read_bytes(len)?))?;
+            if let Some(cluster) = self.get_mut(cluster_id, side) {
+                for configuration in &request.records {
+                    let _ = cluster.attributes_mut().configure_reporting(manufacturer_code, configuration);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_attribute(cluster: &(dyn Cluster + Send), manufacturer_code: Option<u16>, record: &WriteAttributeRecord)
+                   -> Result<(), ZclStatus> {
+    cluster.attributes().check_write(record.attribute_id, manufacturer_code, &record.value)?;
+    cluster.validate_write(record.attribute_id, manufacturer_code, &record.value)
+}
+
+/// The attributes of the manufacturer from the start of a discovery.
+fn discover<'a>(cluster: &'a (dyn Cluster + Send), manufacturer_code: Option<u16>, request: &DiscoverAttributes)
+                -> (bool, Vec<&'a AttributeDefinition>) {
+    let mut definitions: Vec<_> = cluster.attributes().definitions()
+        .filter(|definition| definition.manufacturer_code == manufacturer_code
+                && definition.id >= request.start_attribute_id)
+        .collect();
+    let complete = definitions.len() <= request.max_attribute_ids as usize;
+    definitions.truncate(request.max_attribute_ids as usize);
+    (complete, definitions)
+}
+
+fn discover_commands(commands: Vec<u8>, request: &DiscoverCommands) -> DiscoverCommandsResponse {
+    let mut commands: Vec<u8> = commands.into_iter().filter(|&id| id >= request.start_command_id).collect();
+    commands.sort();
+    let discovery_complete = commands.len() <= request.max_command_ids as usize;
+    commands.truncate(request.max_command_ids as usize);
+    DiscoverCommandsResponse { discovery_complete, commands }
+}
+
+/// Answer the general commands that act on the attribute table, and pass
+/// the others to the cluster.
+fn handle_general(cluster: &mut (dyn Cluster + Send), header: &ZclHeader, command: GeneralCommand)
+                  -> Result<Option<GeneralCommand>, ZclStatus> {
+    let manufacturer_code = header.manufacturer_code;
+    match command {
+        GeneralCommand::ReadAttributes(ref request) => {
+            let records = request.attributes.iter()
+                .map(|&attribute_id| ReadAttributeStatusRecord {
+                    attribute_id,
+                    result: cluster.attributes().read(attribute_id, manufacturer_code),
+                })
+                .collect();
+            Ok(Some(GeneralCommand::ReadAttributesResponse(ReadAttributesResponse { records })))
+        }
+        GeneralCommand::WriteAttributes(WriteAttributes { records: ref requests })
+        | GeneralCommand::WriteAttributesNoResponse(WriteAttributes { records: ref requests }) => {
+            let mut records = Vec::new();
+            for record in requests {
+                let result = write_attribute(cluster, manufacturer_code, record).and_then(|()| {
+                    cluster.attributes_mut().set(record.attribute_id, manufacturer_code, record.value.clone())
+                });
+                if let Err(status) = result {
+                    records.push(WriteAttributeStatusRecord { status, attribute_id: record.attribute_id });
+                }
+            }
+            match command {
+                GeneralCommand::WriteAttributesNoResponse(_) => Ok(None),
+                _ => Ok(Some(GeneralCommand::WriteAttributesResponse(WriteAttributesResponse { records }))),
+            }
+        }
+        GeneralCommand::WriteAttributesUndivided(ref request) => {
+            // Either all the attributes are written or none of them.
+            let records: Vec<_> = request.records.iter()
+                .filter_map(|record| write_attribute(cluster, manufacturer_code, record).err()
+                            .map(|status| WriteAttributeStatusRecord { status, attribute_id: record.attribute_id }))
+                .collect();
+            if records.is_empty() {
+                for record in &request.records {
+                    cluster.attributes_mut().set(record.attribute_id, manufacturer_code, record.value.clone())?;
+                }
+            }
+            Ok(Some(GeneralCommand::WriteAttributesResponse(WriteAttributesResponse { records })))
+        }
+        GeneralCommand::ConfigureReporting(ref request) => {
+            let records = request.records.iter()
+                .filter_map(|configuration| {
+                    cluster.attributes_mut().configure_reporting(manufacturer_code, configuration).err()
+                        .map(|status| ReportingStatusRecord {
+                            status,
+                            direction: configuration.direction(),
+                            attribute_id: configuration.attribute_id(),
+                        })
+                })
+                .collect();
+            Ok(Some(GeneralCommand::ConfigureReportingResponse(ConfigureReportingResponse { records })))
+        }
+        GeneralCommand::ReadReportingConfiguration(ref request) => {
+            let records = request.records.iter()
+                .map(|&(direction, attribute_id)| {
+                    cluster.attributes().reporting_configuration(manufacturer_code, direction, attribute_id)
+                        .map_err(|status| ReportingStatusRecord { status, direction, attribute_id })
+                })
+                .collect();
+            Ok(Some(GeneralCommand::ReadReportingConfigurationResponse(ReadReportingConfigurationResponse {
+                records
+            })))
+        }
+        GeneralCommand::DiscoverAttributes(ref request) => {
+            let (discovery_complete, definitions) = discover(cluster, manufacturer_code, request);
+            Ok(Some(GeneralCommand::DiscoverAttributesResponse(DiscoverAttributesResponse {
+                discovery_complete,
+                attributes: definitions.iter().map(|definition| (definition.id, definition.data_type)).collect(),
+            })))
+        }
+        GeneralCommand::DiscoverAttributesExtended(ref request) => {
+            let (discovery_complete, definitions) = discover(cluster, manufacturer_code, request);
+            Ok(Some(GeneralCommand::DiscoverAttributesExtendedResponse(DiscoverAttributesExtendedResponse {
+                discovery_complete,
+                attributes: definitions.iter()
+                    .map(|definition| ExtendedAttributeInformation {
+                        attribute_id: definition.id,
+                        data_type: definition.data_type,
+                        access_control: definition.access,
+                    })
+                    .collect(),
+            })))
+        }
+        GeneralCommand::DiscoverCommandsReceived(ref request) => Ok(Some(
+            GeneralCommand::DiscoverCommandsReceivedResponse(discover_commands(cluster.commands_received(), request)))),
+        GeneralCommand::DiscoverCommandsGenerated(ref request) => Ok(Some(
+            GeneralCommand::DiscoverCommandsGeneratedResponse(discover_commands(cluster.commands_generated(), request)))),
+        ref command => cluster.handle_general_command(header, command),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcl::data_type::AttributeValue as V;
+    use zcl::general::{ReadAttributes, ReadReportingConfiguration};
+
+    const TEMPERATURE: ClusterId = ClusterId(0x0402);
+    const MEASURED_VALUE: AttributeId = AttributeId(0x0000);
+    const TOLERANCE: AttributeId = AttributeId(0x0003);
+    const LOCATION: AttributeId = AttributeId(0x0010);
+    const ALARM: AttributeId = AttributeId(0x0011);
+
+    fn definition(id: AttributeId, data_type: DataType, default: AttributeValue, writable: bool,
+                  reportable: bool) -> AttributeDefinition {
+        AttributeDefinition {
+            id,
+            manufacturer_code: None,
+            data_type,
+            access: AccessControl { readable: true, writable, reportable },
+            default,
+        }
+    }
+
+    /// A temperature measurement server with a reportable measured value
+    /// and tolerance, and a writable location.
+    fn clusters() -> ClusterSet {
+        let mut attributes = AttributeTable::new();
+        attributes.add(definition(MEASURED_VALUE, DataType::Int16, V::Int16(0), false, true)).unwrap();
+        attributes.add(definition(TOLERANCE, DataType::Uint16, V::Uint16(5), false, true)).unwrap();
+        attributes.add(definition(LOCATION, DataType::Uint8, V::Uint8(0), true, false)).unwrap();
+        let mut clusters = ClusterSet::new();
+        clusters.add(Box::new(GenericCluster { id: TEMPERATURE, side: ClusterSide::Server, attributes })).unwrap();
+        clusters
+    }
+
+    fn request(command: GeneralCommand) -> Vec<u8> {
+        let mut frame = Vec::new();
+        command.encode(Direction::ClientToServer, None, false, 7, &mut frame);
+        frame
+    }
+
+    fn decode(frame: &[u8]) -> (ZclHeader, GeneralCommand) {
+        let mut reader = Reader::new(frame);
+        let header = ZclHeader::decode(&mut reader).unwrap();
+        let command = GeneralCommand::decode_payload(header.command_id, &mut reader).unwrap();
+        (header, command)
+    }
+
+    fn configure(clusters: &mut ClusterSet, records: Vec<ReportingConfiguration>) -> Vec<ReportingStatusRecord> {
+        let frame = clusters.handle_frame(TEMPERATURE, &request(GeneralCommand::ConfigureReporting(
+            ConfigureReporting { records }))).unwrap();
+        match decode(&frame).1 {
+            GeneralCommand::ConfigureReportingResponse(response) => response.records,
+            command => panic!("unexpected command {:?}", command),
+        }
+    }
+
+    fn reported(attribute_id: AttributeId, min_interval: u16, max_interval: u16,
+                reportable_change: Option<AttributeValue>) -> ReportingConfiguration {
+        ReportingConfiguration::Reported {
+            attribute_id,
+            data_type: reportable_change.as_ref().map_or(DataType::Int16, |change| change.data_type()),
+            min_interval,
+            max_interval,
+            reportable_change,
+        }
+    }
+
+    /// The attributes reported by the frames due at time `now`.
+    fn reports(clusters: &mut ClusterSet, now: u64) -> Vec<WriteAttributeRecord> {
+        let mut records = Vec::new();
+        for (cluster_id, frame) in clusters.poll_reports(Duration::from_secs(now)) {
+            assert_eq!(cluster_id, TEMPERATURE);
+            match decode(&frame) {
+                (ZclHeader { direction: Direction::ServerToClient, disable_default_response: true, .. },
+                 GeneralCommand::ReportAttributes(report)) => records.extend(report.reports),
+                frame => panic!("unexpected frame {:?}", frame),
+            }
+        }
+        records
+    }
+
+    fn record(attribute_id: AttributeId, value: AttributeValue) -> WriteAttributeRecord {
+        WriteAttributeRecord { attribute_id, value }
+    }
+
+    /// The response of the clusters to the command.
+    fn respond(clusters: &mut ClusterSet, command: GeneralCommand) -> GeneralCommand {
+        let frame = clusters.handle_frame(TEMPERATURE, &request(command)).unwrap();
+        decode(&frame).1
+    }
+
+    fn value(clusters: &ClusterSet, attribute_id: AttributeId) -> AttributeValue {
+        clusters.get(TEMPERATURE, ClusterSide::Server).unwrap().attributes().get(attribute_id, None).unwrap().clone()
+    }
+
+    fn default_response(command_id: u8, status: ZclStatus) -> GeneralCommand {
+        GeneralCommand::DefaultResponse(DefaultResponse { command_id, status })
+    }
+
+    /// A cluster that handles the cluster specific commands 0x00 and 0x01,
+    /// and answers the first with the command 0x00.
+    struct Switch {
+        attributes: AttributeTable,
+    }
+
+    impl Cluster for Switch {
+        fn id(&self) -> ClusterId {
+            ClusterId(0x0006)
+        }
+
+        fn side(&self) -> ClusterSide {
+            ClusterSide::Server
+        }
+
+        fn attributes(&self) -> &AttributeTable {
+            &self.attributes
+        }
+
+        fn attributes_mut(&mut self) -> &mut AttributeTable {
+            &mut self.attributes
+        }
+
+        fn commands_received(&self) -> Vec<u8> {
+            vec![0x01, 0x00]
+        }
+
+        fn handle_command(&mut self, _header: &ZclHeader, payload: &[u8])
+                          -> Result<Option<ClusterResponse>, ZclStatus> {
+            match *payload {
+                [0x00] => Ok(Some(ClusterResponse { command_id: 0x00, payload: vec![0xAA] })),
+                [0x01] => Ok(None),
+                _ => Err(ZclStatus::InvalidField),
+            }
+        }
+    }
+
+    fn set(clusters: &mut ClusterSet, value: i16) {
+        clusters.get_mut(TEMPERATURE, ClusterSide::Server).unwrap().attributes_mut()
+            .set(MEASURED_VALUE, None, V::Int16(value)).unwrap();
+    }
+
+    #[test]
+    fn reports_follow_the_intervals_and_the_reportable_change() {
+        let mut clusters = clusters();
+        assert_eq!(clusters.next_report(), None);
+        assert_eq!(configure(&mut clusters, vec![reported(MEASURED_VALUE, 5, 60, Some(V::Int16(50)))]),
+                   vec![]);
+        assert!(clusters.take_reporting_modified());
+        assert!(!clusters.take_reporting_modified());
+
+        // The first report is sent at once, then only after the maximum
+        // interval or a large enough change.
+        assert_eq!(reports(&mut clusters, 100), vec![record(MEASURED_VALUE, V::Int16(0))]);
+        assert_eq!(reports(&mut clusters, 101), vec![]);
+        set(&mut clusters, 49);
+        assert_eq!(clusters.next_report(), Some(Duration::from_secs(160)));
+        assert_eq!(reports(&mut clusters, 159), vec![]);
+        assert_eq!(reports(&mut clusters, 160), vec![record(MEASURED_VALUE, V::Int16(49))]);
+
+        // A large change waits for the minimum interval.
+        set(&mut clusters, -1);
+        assert_eq!(clusters.next_report(), Some(Duration::from_secs(165)));
+        assert_eq!(reports(&mut clusters, 161), vec![]);
+        assert_eq!(reports(&mut clusters, 165), vec![record(MEASURED_VALUE, V::Int16(-1))]);
+    }
+
+    #[test]
+    fn attributes_due_together_share_a_frame() {
+        let mut clusters = clusters();
+        configure(&mut clusters, vec![reported(MEASURED_VALUE, 0, 10, Some(V::Int16(1))),
+                                      reported(TOLERANCE, 0, 10, Some(V::Uint16(1)))]);
+        let frames = clusters.poll_reports(Duration::from_secs(0));
+        assert_eq!(frames.len(), 1);
+        assert_eq!(reports(&mut clusters, 10), vec![record(MEASURED_VALUE, V::Int16(0)),
+                                                    record(TOLERANCE, V::Uint16(5))]);
+    }
+
+    #[test]
+    fn invalid_configurations() {
+        let mut clusters = clusters();
+        let status = |status, attribute_id| ReportingStatusRecord {
+            status,
+            direction: ReportingDirection::Reported,
+            attribute_id,
+        };
+        assert_eq!(configure(&mut clusters, vec![
+            reported(LOCATION, 0, 10, Some(V::Uint8(1))),
+            reported(MEASURED_VALUE, 20, 10, Some(V::Int16(1))),
+            reported(MEASURED_VALUE, 0, 10, Some(V::Uint16(1))),
+            reported(AttributeId(0x1234), 0, 10, Some(V::Int16(1))),
+        ]), vec![
+            status(ZclStatus::UnreportableAttribute, LOCATION),
+            status(ZclStatus::InvalidValue, MEASURED_VALUE),
+            status(ZclStatus::InvalidDataType, MEASURED_VALUE),
+            status(ZclStatus::UnsupportedAttribute, AttributeId(0x1234)),
+        ]);
+        assert_eq!(clusters.next_report(), None);
+    }
+
+    #[test]
+    fn read_and_disable_the_configuration() {
+        let mut clusters = clusters();
+        let configuration = reported(MEASURED_VALUE, 1, 0, Some(V::Int16(10)));
+        configure(&mut clusters, vec![configuration.clone()]);
+        let read = ReadReportingConfiguration {
+            records: vec![(ReportingDirection::Reported, MEASURED_VALUE),
+                          (ReportingDirection::Reported, TOLERANCE),
+                          (ReportingDirection::Reported, LOCATION)],
+        };
+        let frame = clusters.handle_frame(TEMPERATURE, &request(GeneralCommand::ReadReportingConfiguration(read)))
+            .unwrap();
+        let records = match decode(&frame).1 {
+            GeneralCommand::ReadReportingConfigurationResponse(response) => response.records,
+            command => panic!("unexpected command {:?}", command),
+        };
+        assert_eq!(records, vec![
+            Ok(configuration),
+            Err(ReportingStatusRecord {
+                status: ZclStatus::NotFound,
+                direction: ReportingDirection::Reported,
+                attribute_id: TOLERANCE,
+            }),
+            Err(ReportingStatusRecord {
+                status: ZclStatus::UnreportableAttribute,
+                direction: ReportingDirection::Reported,
+                attribute_id: LOCATION,
+            }),
+        ]);
+
+        // A maximum interval of 0 only reports the changes.
+        assert_eq!(reports(&mut clusters, 0).len(), 1);
+        assert_eq!(reports(&mut clusters, 1000), vec![]);
+        set(&mut clusters, 10);
+        assert_eq!(reports(&mut clusters, 1001), vec![record(MEASURED_VALUE, V::Int16(10))]);
+
+        configure(&mut clusters, vec![reported(MEASURED_VALUE, 1, 0xFFFF, Some(V::Int16(10)))]);
+        set(&mut clusters, 100);
+        assert_eq!(clusters.next_report(), None);
+        assert_eq!(reports(&mut clusters, 2000), vec![]);
+    }
+
+    #[test]
+    fn store_and_restore_the_configurations() {
+        let mut clusters = clusters();
+        configure(&mut clusters, vec![
+            reported(MEASURED_VALUE, 5, 60, Some(V::Int16(50))),
+            ReportingConfiguration::Received { attribute_id: TOLERANCE, timeout_period: 120 },
+        ]);
+        let mut stored = Vec::new();
+        clusters.encode_reporting(&mut stored);
+
+        let mut restored = self::clusters();
+        restored.restore_reporting(&mut Reader::new(&stored)).unwrap();
+        let configurations = |clusters: &ClusterSet| clusters.get(TEMPERATURE, ClusterSide::Server).unwrap()
+            .attributes().reporting_configurations();
+        assert_eq!(configurations(&restored), configurations(&clusters));
+        assert_eq!(configurations(&restored).len(), 2);
+        assert!(restored.restore_reporting(&mut Reader::new(&stored[..stored.len() - 1])).is_err());
+    }
+
+    #[test]
+    fn read_attributes() {
+        let mut clusters = clusters();
+        let frame = clusters.handle_frame(TEMPERATURE, &request(GeneralCommand::ReadAttributes(ReadAttributes {
+            attributes: vec![TOLERANCE, AttributeId(0x1234)],
+        }))).unwrap();
+        let (header, response) = decode(&frame);
+        // The response goes back to the client, with the sequence number of
+        // the request.
+        assert_eq!(header.direction, Direction::ServerToClient);
+        assert_eq!(header.sequence_number, 7);
+        assert!(header.disable_default_response);
+        assert_eq!(response, GeneralCommand::ReadAttributesResponse(ReadAttributesResponse {
+            records: vec![
+                ReadAttributeStatusRecord { attribute_id: TOLERANCE, result: Ok(V::Uint16(5)) },
+                ReadAttributeStatusRecord {
+                    attribute_id: AttributeId(0x1234),
+                    result: Err(ZclStatus::UnsupportedAttribute),
+                },
+            ],
+        }));
+    }
+
+    #[test]
+    fn write_attributes() {
+        let mut clusters = clusters();
+        assert_eq!(respond(&mut clusters, GeneralCommand::WriteAttributes(WriteAttributes {
+            records: vec![record(LOCATION, V::Uint8(3)), record(MEASURED_VALUE, V::Int16(1)),
+                          record(TOLERANCE, V::Uint8(1)), record(AttributeId(0x1234), V::Uint8(1))],
+        })), GeneralCommand::WriteAttributesResponse(WriteAttributesResponse {
+            records: vec![
+                WriteAttributeStatusRecord { status: ZclStatus::ReadOnly, attribute_id: MEASURED_VALUE },
+                WriteAttributeStatusRecord { status: ZclStatus::InvalidDataType, attribute_id: TOLERANCE },
+                WriteAttributeStatusRecord {
+                    status: ZclStatus::UnsupportedAttribute,
+                    attribute_id: AttributeId(0x1234),
+                },
+            ],
+        }));
+        // The writable attribute was written anyway.
+        assert_eq!(value(&clusters, LOCATION), V::Uint8(3));
+        assert_eq!(value(&clusters, MEASURED_VALUE), V::Int16(0));
+
+        // Without an answer, the default response confirms the write.
+        assert_eq!(respond(&mut clusters, GeneralCommand::WriteAttributesNoResponse(WriteAttributes {
+            records: vec![record(LOCATION, V::Uint8(4))],
+        })), default_response(WriteAttributes::NO_RESPONSE_ID, ZclStatus::Success));
+        assert_eq!(value(&clusters, LOCATION), V::Uint8(4));
+    }
+
+    #[test]
+    fn write_attributes_undivided() {
+        let mut clusters = clusters();
+        // Nothing is written when an attribute can not be.
+        assert_eq!(respond(&mut clusters, GeneralCommand::WriteAttributesUndivided(WriteAttributes {
+            records: vec![record(LOCATION, V::Uint8(3)), record(TOLERANCE, V::Uint16(1))],
+        })), GeneralCommand::WriteAttributesResponse(WriteAttributesResponse {
+            records: vec![WriteAttributeStatusRecord { status: ZclStatus::ReadOnly, attribute_id: TOLERANCE }],
+        }));
+        assert_eq!(value(&clusters, LOCATION), V::Uint8(0));
+
+        assert_eq!(respond(&mut clusters, GeneralCommand::WriteAttributesUndivided(WriteAttributes {
+            records: vec![record(LOCATION, V::Uint8(3))],
+        })), GeneralCommand::WriteAttributesResponse(WriteAttributesResponse { records: vec![] }));
+        assert_eq!(value(&clusters, LOCATION), V::Uint8(3));
+    }
+
+    #[test]
+    fn discover_attributes() {
+        let mut clusters = clusters();
+        assert_eq!(respond(&mut clusters, GeneralCommand::DiscoverAttributes(DiscoverAttributes {
+            start_attribute_id: AttributeId(0x0001),
+            max_attribute_ids: 1,
+        })), GeneralCommand::DiscoverAttributesResponse(DiscoverAttributesResponse {
+            discovery_complete: false,
+            attributes: vec![(TOLERANCE, DataType::Uint16)],
+        }));
+        assert_eq!(respond(&mut clusters, GeneralCommand::DiscoverAttributesExtended(DiscoverAttributes {
+            start_attribute_id: AttributeId(0x0004),
+            max_attribute_ids: 10,
+        })), GeneralCommand::DiscoverAttributesExtendedResponse(DiscoverAttributesExtendedResponse {
+            discovery_complete: true,
+            attributes: vec![ExtendedAttributeInformation {
+                attribute_id: LOCATION,
+                data_type: DataType::Uint8,
+                access_control: AccessControl { readable: true, writable: true, reportable: false },
+            }],
+        }));
+    }
+
+    #[test]
+    fn cluster_specific_commands() {
+        let switch = ClusterId(0x0006);
+        let mut clusters = ClusterSet::new();
+        clusters.add(Box::new(Switch { attributes: AttributeTable::new() })).unwrap();
+        let command = |command_id, disable_default_response| {
+            let mut frame = Vec::new();
+            ZclHeader {
+                frame_type: FrameType::ClusterSpecific,
+                manufacturer_code: None,
+                direction: Direction::ClientToServer,
+                disable_default_response,
+                sequence_number: 3,
+                command_id,
+            }.encode(&mut frame);
+            frame.push(command_id);
+            frame
+        };
+
+        assert_eq!(clusters.handle_frame(switch, &command(0x00, false)), Some(vec![0x19, 0x03, 0x00, 0xAA]));
+        assert_eq!(clusters.handle_frame(switch, &command(0x01, true)), None);
+        let frame = clusters.handle_frame(switch, &command(0x01, false)).unwrap();
+        assert_eq!(decode(&frame).1, default_response(0x01, ZclStatus::Success));
+        // The failures are reported even when the default response is
+        // disabled.
+        let frame = clusters.handle_frame(switch, &command(0x02, true)).unwrap();
+        assert_eq!(decode(&frame).1, default_response(0x02, ZclStatus::InvalidField));
+
+        let frame = clusters.handle_frame(switch, &request(GeneralCommand::DiscoverCommandsReceived(
+            DiscoverCommands { start_command_id: 0x00, max_command_ids: 0x10 }))).unwrap();
+        assert_eq!(decode(&frame).1, GeneralCommand::DiscoverCommandsReceivedResponse(DiscoverCommandsResponse {
+            discovery_complete: true,
+            commands: vec![0x00, 0x01],
+        }));
+        let frame = clusters.handle_frame(switch, &request(GeneralCommand::DiscoverCommandsGenerated(
+            DiscoverCommands { start_command_id: 0x00, max_command_ids: 0x10 }))).unwrap();
+        assert_eq!(decode(&frame).1, GeneralCommand::DiscoverCommandsGeneratedResponse(DiscoverCommandsResponse {
+            discovery_complete: true,
+            commands: vec![],
+        }));
+    }
+
+    #[test]
+    fn default_responses() {
+        let mut clusters = clusters();
+        // A cluster that is not implemented.
+        let frame = clusters.handle_frame(ClusterId(0x0006), &request(GeneralCommand::ReadAttributes(
+            ReadAttributes { attributes: vec![MEASURED_VALUE] }))).unwrap();
+        assert_eq!(decode(&frame).1, default_response(ReadAttributes::ID, ZclStatus::UnsupportedCluster));
+        // A cluster specific command that the cluster does not handle.
+        let frame = clusters.handle_frame(TEMPERATURE, &[0x01, 0x07, 0x00]).unwrap();
+        assert_eq!(decode(&frame).1, default_response(0x00, ZclStatus::UnsupClusterCommand));
+        // An unknown general command.
+        let frame = clusters.handle_frame(TEMPERATURE, &[0x00, 0x07, 0x20]).unwrap();
+        assert_eq!(decode(&frame).1, default_response(0x20, ZclStatus::UnsupGeneralCommand));
+        // A truncated general command.
+        let frame = clusters.handle_frame(TEMPERATURE, &[0x00, 0x07, ReadAttributes::ID, 0x00]).unwrap();
+        assert_eq!(decode(&frame).1, default_response(ReadAttributes::ID, ZclStatus::MalformedCommand));
+        // A general command that the server does not expect.
+        let frame = clusters.handle_frame(TEMPERATURE, &request(GeneralCommand::ReportAttributes(
+            ReportAttributes { reports: vec![] }))).unwrap();
+        assert_eq!(decode(&frame).1, default_response(ReportAttributes::ID, ZclStatus::UnsupGeneralCommand));
+
+        // A default response is never answered, nor a truncated header.
+        assert_eq!(clusters.handle_frame(TEMPERATURE, &request(default_response(0x00, ZclStatus::Success))), None);
+        assert_eq!(clusters.handle_frame(TEMPERATURE, &[0x00, 0x07]), None);
+    }
+
+    #[test]
+    fn manufacturer_specific_attributes() {
+        let mut clusters = clusters();
+        clusters.get_mut(TEMPERATURE, ClusterSide::Server).unwrap().attributes_mut().add(AttributeDefinition {
+            manufacturer_code: Some(0x1037),
+            ..definition(MEASURED_VALUE, DataType::Uint8, V::Uint8(9), false, false)
+        }).unwrap();
+        let mut frame = Vec::new();
+        GeneralCommand::ReadAttributes(ReadAttributes { attributes: vec![MEASURED_VALUE, TOLERANCE] })
+            .encode(Direction::ClientToServer, Some(0x1037), false, 7, &mut frame);
+        let (header, response) = decode(&clusters.handle_frame(TEMPERATURE, &frame).unwrap());
+        assert_eq!(header.manufacturer_code, Some(0x1037));
+        assert_eq!(response, GeneralCommand::ReadAttributesResponse(ReadAttributesResponse {
+            records: vec![
+                ReadAttributeStatusRecord { attribute_id: MEASURED_VALUE, result: Ok(V::Uint8(9)) },
+                ReadAttributeStatusRecord { attribute_id: TOLERANCE, result: Err(ZclStatus::UnsupportedAttribute) },
+            ],
+        }));
+    }
+
+    #[test]
+    fn reportable_change_only_for_the_analog_attributes() {
+        let mut clusters = clusters();
+        let attributes = clusters.get_mut(TEMPERATURE, ClusterSide::Server).unwrap().attributes_mut();
+        attributes.add(definition(ALARM, DataType::Boolean, V::Boolean(Some(false)), false, true)).unwrap();
+        assert_eq!(attributes.configure_reporting(None, &reported(MEASURED_VALUE, 0, 10, None)),
+                   Err(ZclStatus::InvalidValue));
+        assert_eq!(attributes.configure_reporting(None, &ReportingConfiguration::Reported {
+            attribute_id: ALARM,
+            data_type: DataType::Boolean,
+            min_interval: 0,
+            max_interval: 10,
+            reportable_change: Some(V::Boolean(Some(true))),
+        }), Err(ZclStatus::InvalidValue));
+        assert!(attributes.reporting_configurations().is_empty());
+    }
+
+    #[test]
+    fn store_and_restore_analog_and_discrete_configurations() {
+        let with_alarm = || {
+            let mut clusters = clusters();
+            clusters.get_mut(TEMPERATURE, ClusterSide::Server).unwrap().attributes_mut()
+                .add(definition(ALARM, DataType::Boolean, V::Boolean(Some(false)), false, true)).unwrap();
+            clusters
+        };
+        let mut clusters = with_alarm();
+        let alarm = ReportingConfiguration::Reported {
+            attribute_id: ALARM,
+            data_type: DataType::Boolean,
+            min_interval: 0,
+            max_interval: 30,
+            reportable_change: None,
+        };
+        let attributes = clusters.get_mut(TEMPERATURE, ClusterSide::Server).unwrap().attributes_mut();
+        attributes.configure_reporting(None, &reported(MEASURED_VALUE, 5, 60, Some(V::Int16(50)))).unwrap();
+        attributes.configure_reporting(None, &alarm).unwrap();
+        let mut stored = Vec::new();
+        clusters.encode_reporting(&mut stored);
+
+        let mut restored = with_alarm();
+        restored.restore_reporting(&mut Reader::new(&stored)).unwrap();
+        let configurations = |clusters: &ClusterSet| clusters.get(TEMPERATURE, ClusterSide::Server).unwrap()
+            .attributes().reporting_configurations();
+        assert_eq!(configurations(&restored), vec![
+            (None, reported(MEASURED_VALUE, 5, 60, Some(V::Int16(50)))),
+            (None, alarm),
+        ]);
+    }
+}
@@ -0,0 +1,825 @@
+//! The data types of the attributes, described in section 2.6.2 of the
+//! ZCL specification, and the values of the attributes.
+//!
+//! Most types reserve a value, usually all the bits set, that means that
+//! the attribute is invalid or has no value; it is encoded like any other
+//! value of the type and detected with `AttributeValue::is_invalid`.
+
+use std::cmp::min;
+use codec::{DecodeError, Reader, write_u16};
+use security::{Key, KEY_SIZE};
+use types::{AttributeId, ClusterId, IeeeAddress};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    NoData = 0x00,
+    Data8 = 0x08,
+    Data16 = 0x09,
+    Data24 = 0x0A,
+    Data32 = 0x0B,
+    Data40 = 0x0C,
+    Data48 = 0x0D,
+    Data56 = 0x0E,
+    Data64 = 0x0F,
+    Boolean = 0x10,
+    Bitmap8 = 0x18,
+    Bitmap16 = 0x19,
+    Bitmap24 = 0x1A,
+    Bitmap32 = 0x1B,
+    Bitmap40 = 0x1C,
+    Bitmap48 = 0x1D,
+    Bitmap56 = 0x1E,
+    Bitmap64 = 0x1F,
+    Uint8 = 0x20,
+    Uint16 = 0x21,
+    Uint24 = 0x22,
+    Uint32 = 0x23,
+    Uint40 = 0x24,
+    Uint48 = 0x25,
+    Uint56 = 0x26,
+    Uint64 = 0x27,
+    Int8 = 0x28,
+    Int16 = 0x29,
+    Int24 = 0x2A,
+    Int32 = 0x2B,
+    Int40 = 0x2C,
+    Int48 = 0x2D,
+    Int56 = 0x2E,
+    Int64 = 0x2F,
+    Enum8 = 0x30,
+    Enum16 = 0x31,
+    /// Half precision floating point.
+    Semi = 0x38,
+    Single = 0x39,
+    Double = 0x3A,
+    OctetString = 0x41,
+    CharacterString = 0x42,
+    LongOctetString = 0x43,
+    LongCharacterString = 0x44,
+    Array = 0x48,
+    Structure = 0x4C,
+    Set = 0x50,
+    Bag = 0x51,
+    TimeOfDay = 0xE0,
+    Date = 0xE1,
+    UtcTime = 0xE2,
+    ClusterId = 0xE8,
+    AttributeId = 0xE9,
+    BacnetOid = 0xEA,
+    IeeeAddress = 0xF0,
+    SecurityKey = 0xF1,
+    Unknown = 0xFF,
+}
+
+const DATA_TYPES: [DataType; 56] = [
+    DataType::NoData, DataType::Data8, DataType::Data16, DataType::Data24, DataType::Data32,
+    DataType::Data40, DataType::Data48, DataType::Data56, DataType::Data64, DataType::Boolean,
+    DataType::Bitmap8, DataType::Bitmap16, DataType::Bitmap24, DataType::Bitmap32,
+    DataType::Bitmap40, DataType::Bitmap48, DataType::Bitmap56, DataType::Bitmap64,
+    DataType::Uint8, DataType::Uint16, DataType::Uint24, DataType::Uint32, DataType::Uint40,
+    DataType::Uint48, DataType::Uint56, DataType::Uint64, DataType::Int8, DataType::Int16,
+    DataType::Int24, DataType::Int32, DataType::Int40, DataType::Int48, DataType::Int56,
+    DataType::Int64, DataType::Enum8, DataType::Enum16, DataType::Semi, DataType::Single,
+    DataType::Double, DataType::OctetString, DataType::CharacterString,
+    DataType::LongOctetString, DataType::LongCharacterString, DataType::Array,
+    DataType::Structure, DataType::Set, DataType::Bag, DataType::TimeOfDay, DataType::Date,
+    DataType::UtcTime, DataType::ClusterId, DataType::AttributeId, DataType::BacnetOid,
+    DataType::IeeeAddress, DataType::SecurityKey, DataType::Unknown,
+];
+
+impl DataType {
+    pub fn from_u8(value: u8) -> Result<Self, DecodeError> {
+        DATA_TYPES.iter()
+            .find(|&&data_type| data_type as u8 == value)
+            .cloned()
+            .ok_or(DecodeError::InvalidValue("data type"))
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        DataType::from_u8(reader.read_u8()?)
+    }
+
+    /// The number of octets of the values of the type, `None` if it varies.
+    pub fn fixed_length(self) -> Option<usize> {
+        let code = self as u8;
+        match self {
+            DataType::NoData => Some(0),
+            DataType::Boolean | DataType::Enum8 => Some(1),
+            DataType::Enum16 | DataType::Semi | DataType::ClusterId | DataType::AttributeId => Some(2),
+            DataType::Single | DataType::TimeOfDay | DataType::Date | DataType::UtcTime
+                | DataType::BacnetOid => Some(4),
+            DataType::Double | DataType::IeeeAddress => Some(8),
+            DataType::SecurityKey => Some(16),
+            // The data, bitmap and integer types of 1 to 8 octets.
+            _ if (0x08..=0x2F).contains(&code) && code != 0x10 => Some((code & 0x07) as usize + 1),
+            _ => None,
+        }
+    }
+
+    /// Whether the values of the type are analog: the integers, the floating
+    /// point numbers and the times. The report of a change of an analog
+    /// attribute depends on the reportable change configured for it;
+    /// any change of a discrete attribute is reported.
+    pub fn is_analog(self) -> bool {
+        let code = self as u8;
+        (0x20..=0x2F).contains(&code) || (0x38..=0x3A).contains(&code)
+            || (0xE0..=0xE2).contains(&code)
+    }
+}
+
+/// The time of day. A field that is 0xFF is unspecified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeOfDay {
+    pub hours: u8,
+    pub minutes: u8,
+    pub seconds: u8,
+    pub hundredths: u8,
+}
+
+/// A date. A field that is 0xFF is unspecified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Date {
+    /// The years since 1900.
+    pub year: u8,
+    /// From 1 to 12.
+    pub month: u8,
+    /// From 1 to 31.
+    pub day_of_month: u8,
+    /// From 1, Monday, to 7, Sunday.
+    pub day_of_week: u8,
+}
+
+/// The value of an attribute, with its type.
+///
+/// The strings and the collections are `None` when invalid. The strings
+/// longer than their type allows are truncated when encoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AttributeValue {
+    NoData,
+    Data8(u8),
+    Data16(u16),
+    Data24(u32),
+    Data32(u32),
+    Data40(u64),
+    Data48(u64),
+    Data56(u64),
+    Data64(u64),
+    /// `None` is the invalid value.
+    Boolean(Option<bool>),
+    Bitmap8(u8),
+    Bitmap16(u16),
+    Bitmap24(u32),
+    Bitmap32(u32),
+    Bitmap40(u64),
+    Bitmap48(u64),
+    Bitmap56(u64),
+    Bitmap64(u64),
+    Uint8(u8),
+    Uint16(u16),
+    Uint24(u32),
+    Uint32(u32),
+    Uint40(u64),
+    Uint48(u64),
+    Uint56(u64),
+    Uint64(u64),
+    Int8(i8),
+    Int16(i16),
+    Int24(i32),
+    Int32(i32),
+    Int40(i64),
+    Int48(i64),
+    Int56(i64),
+    Int64(i64),
+    Enum8(u8),
+    Enum16(u16),
+    /// The bits of a half precision floating point number.
+    Semi(u16),
+    Single(f32),
+    Double(f64),
+    OctetString(Option<Vec<u8>>),
+    CharacterString(Option<String>),
+    LongOctetString(Option<Vec<u8>>),
+    LongCharacterString(Option<String>),
+    Array { element_type: DataType, elements: Option<Vec<AttributeValue>> },
+    Structure(Option<Vec<AttributeValue>>),
+    Set { element_type: DataType, elements: Option<Vec<AttributeValue>> },
+    Bag { element_type: DataType, elements: Option<Vec<AttributeValue>> },
+    TimeOfDay(TimeOfDay),
+    Date(Date),
+    /// The seconds since the 1st of January 2000, 00:00 UTC.
+    UtcTime(u32),
+    ClusterId(ClusterId),
+    AttributeId(AttributeId),
+    BacnetOid(u32),
+    IeeeAddress(IeeeAddress),
+    SecurityKey(Key),
+}
+
+const MAX_STRING_LENGTH: usize = 0xFE;
+const MAX_LONG_STRING_LENGTH: usize = 0xFFFE;
+const HALF_NAN: u16 = 0x7E00;
+
+fn read_uint(reader: &mut Reader, length: usize) -> Result<u64, DecodeError> {
+    Ok(reader.read_bytes(length)?.iter().rev().fold(0, |value, &byte| value << 8 | u64::from(byte)))
+}
+
+fn write_uint(out: &mut Vec<u8>, value: u64, length: usize) {
+    for i in 0..length {
+        out.push((value >> (8 * i)) as u8);
+    }
+}
+
+/// Extend the sign of a signed integer of `length` octets.
+fn sign_extend(value: u64, length: usize) -> i64 {
+    let shift = 64 - 8 * length;
+    ((value << shift) as i64) >> shift
+}
+
+fn read_string(reader: &mut Reader, long: bool) -> Result<Option<Vec<u8>>, DecodeError> {
+    let length = if long { reader.read_u16()? as usize } else { reader.read_u8()? as usize };
+    if (long && length == 0xFFFF) || (!long && length == 0xFF) {
+        return Ok(None);
+    }
+    Ok(Some(reader.read_bytes(length)?.to_vec()))
+}
+
+fn read_character_string(reader: &mut Reader, long: bool) -> Result<Option<String>, DecodeError> {
+    match read_string(reader, long)? {
+        Some(bytes) => String::from_utf8(bytes)
+            .map(Some)
+            .map_err(|_| DecodeError::InvalidValue("character string")),
+        None => Ok(None),
+    }
+}
+
+fn write_string(out: &mut Vec<u8>, string: Option<&[u8]>, long: bool) {
+    match string {
+        Some(bytes) => {
+            let length = min(bytes.len(), if long { MAX_LONG_STRING_LENGTH } else { MAX_STRING_LENGTH });
+            if long { write_u16(out, length as u16) } else { out.push(length as u8) }
+            out.extend_from_slice(&bytes[..length]);
+        }
+        None => if long { write_u16(out, 0xFFFF) } else { out.push(0xFF) },
+    }
+}
+
+fn read_elements(reader: &mut Reader, element_type: DataType)
+                 -> Result<Option<Vec<AttributeValue>>, DecodeError> {
+    let count = reader.read_u16()?;
+    if count == 0xFFFF {
+        return Ok(None);
+    }
+    (0..count).map(|_| AttributeValue::decode(element_type, reader)).collect::<Result<_, _>>().map(Some)
+}
+
+fn write_elements(out: &mut Vec<u8>, element_type: DataType, elements: &Option<Vec<AttributeValue>>) {
+    out.push(element_type as u8);
+    match *elements {
+        Some(ref elements) => {
+            write_u16(out, elements.len() as u16);
+            for element in elements {
+                element.encode(out);
+            }
+        }
+        None => write_u16(out, 0xFFFF),
+    }
+}
+
+/// Convert a half precision floating point number.
+fn half_to_f64(bits: u16) -> f64 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = i32::from((bits >> 10) & 0x1F);
+    let mantissa = f64::from(bits & 0x03FF);
+    sign * match exponent {
+        0 => mantissa * 2f64.powi(-24),
+        0x1F if mantissa == 0.0 => f64::INFINITY,
+        0x1F => f64::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f64.powi(exponent - 15),
+    }
+}
+
+/// The days from the 1st of January 1900 to a date.
+fn days_since_1900(year: i64, month: i64, day: i64) -> i64 {
+    // The days from civil algorithm, with the years starting in March.
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = if year >= 0 { year } else { year - 399 } / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 693_901
+}
+
+impl AttributeValue {
+    pub fn data_type(&self) -> DataType {
+        match *self {
+            AttributeValue::NoData => DataType::NoData,
+            AttributeValue::Data8(_) => DataType::Data8,
+            AttributeValue::Data16(_) => DataType::Data16,
+            AttributeValue::Data24(_) => DataType::Data24,
+            AttributeValue::Data32(_) => DataType::Data32,
+            AttributeValue::Data40(_) => DataType::Data40,
+            AttributeValue::Data48(_) => DataType::Data48,
+            AttributeValue::Data56(_) => DataType::Data56,
+            AttributeValue::Data64(_) => DataType::Data64,
+            AttributeValue::Boolean(_) => DataType::Boolean,
+            AttributeValue::Bitmap8(_) => DataType::Bitmap8,
+            AttributeValue::Bitmap16(_) => DataType::Bitmap16,
+            AttributeValue::Bitmap24(_) => DataType::Bitmap24,
+            AttributeValue::Bitmap32(_) => DataType::Bitmap32,
+            AttributeValue::Bitmap40(_) => DataType::Bitmap40,
+            AttributeValue::Bitmap48(_) => DataType::Bitmap48,
+            AttributeValue::Bitmap56(_) => DataType::Bitmap56,
+            AttributeValue::Bitmap64(_) => DataType::Bitmap64,
+            AttributeValue::Uint8(_) => DataType::Uint8,
+            AttributeValue::Uint16(_) => DataType::Uint16,
+            AttributeValue::Uint24(_) => DataType::Uint24,
+            AttributeValue::Uint32(_) => DataType::Uint32,
+            AttributeValue::Uint40(_) => DataType::Uint40,
+            AttributeValue::Uint48(_) => DataType::Uint48,
+            AttributeValue::Uint56(_) => DataType::Uint56,
+            AttributeValue::Uint64(_) => DataType::Uint64,
+            AttributeValue::Int8(_) => DataType::Int8,
+            AttributeValue::Int16(_) => DataType::Int16,
+            AttributeValue::Int24(_) => DataType::Int24,
+            AttributeValue::Int32(_) => DataType::Int32,
+            AttributeValue::Int40(_) => DataType::Int40,
+            AttributeValue::Int48(_) => DataType::Int48,
+            AttributeValue::Int56(_) => DataType::Int56,
+            AttributeValue::Int64(_) => DataType::Int64,
+            AttributeValue::Enum8(_) => DataType::Enum8,
+            AttributeValue::Enum16(_) => DataType::Enum16,
+            AttributeValue::Semi(_) => DataType::Semi,
+            AttributeValue::Single(_) => DataType::Single,
+            AttributeValue::Double(_) => DataType::Double,
+            AttributeValue::OctetString(_) => DataType::OctetString,
+            AttributeValue::CharacterString(_) => DataType::CharacterString,
+            AttributeValue::LongOctetString(_) => DataType::LongOctetString,
+            AttributeValue::LongCharacterString(_) => DataType::LongCharacterString,
+            AttributeValue::Array { .. } => DataType::Array,
+            AttributeValue::Structure(_) => DataType::Structure,
+            AttributeValue::Set { .. } => DataType::Set,
+            AttributeValue::Bag { .. } => DataType::Bag,
+            AttributeValue::TimeOfDay(_) => DataType::TimeOfDay,
+            AttributeValue::Date(_) => DataType::Date,
+            AttributeValue::UtcTime(_) => DataType::UtcTime,
+            AttributeValue::ClusterId(_) => DataType::ClusterId,
+            AttributeValue::AttributeId(_) => DataType::AttributeId,
+            AttributeValue::BacnetOid(_) => DataType::BacnetOid,
+            AttributeValue::IeeeAddress(_) => DataType::IeeeAddress,
+            AttributeValue::SecurityKey(_) => DataType::SecurityKey,
+        }
+    }
+
+    /// The bits of the values encoded as a little endian integer.
+    fn bits(&self) -> Option<u64> {
+        Some(match *self {
+            AttributeValue::Data8(value) | AttributeValue::Bitmap8(value)
+                | AttributeValue::Uint8(value) | AttributeValue::Enum8(value) => u64::from(value),
+            AttributeValue::Data16(value) | AttributeValue::Bitmap16(value)
+                | AttributeValue::Uint16(value) | AttributeValue::Enum16(value)
+                | AttributeValue::Semi(value) => u64::from(value),
+            AttributeValue::Data24(value) | AttributeValue::Bitmap24(value)
+                | AttributeValue::Uint24(value) | AttributeValue::Data32(value)
+                | AttributeValue::Bitmap32(value) | AttributeValue::Uint32(value)
+                | AttributeValue::UtcTime(value) | AttributeValue::BacnetOid(value) => u64::from(value),
+            AttributeValue::Data40(value) | AttributeValue::Bitmap40(value)
+                | AttributeValue::Uint40(value) | AttributeValue::Data48(value)
+                | AttributeValue::Bitmap48(value) | AttributeValue::Uint48(value)
+                | AttributeValue::Data56(value) | AttributeValue::Bitmap56(value)
+                | AttributeValue::Uint56(value) | AttributeValue::Data64(value)
+                | AttributeValue::Bitmap64(value) | AttributeValue::Uint64(value) => value,
+            AttributeValue::Int8(value) => value as u64,
+            AttributeValue::Int16(value) => value as u64,
+            AttributeValue::Int24(value) | AttributeValue::Int32(value) => value as u64,
+            AttributeValue::Int40(value) | AttributeValue::Int48(value)
+                | AttributeValue::Int56(value) | AttributeValue::Int64(value) => value as u64,
+            AttributeValue::Boolean(value) => value.map_or(0xFF, u64::from),
+            AttributeValue::Single(value) => u64::from(value.to_bits()),
+            AttributeValue::Double(value) => value.to_bits(),
+            AttributeValue::ClusterId(id) => u64::from(id.0),
+            AttributeValue::AttributeId(id) => u64::from(id.0),
+            AttributeValue::IeeeAddress(address) => address.0,
+            AttributeValue::TimeOfDay(time) => u64::from(u32::from(time.hours)
+                | u32::from(time.minutes) << 8 | u32::from(time.seconds) << 16
+                | u32::from(time.hundredths) << 24),
+            AttributeValue::Date(date) => u64::from(u32::from(date.year)
+                | u32::from(date.month) << 8 | u32::from(date.day_of_month) << 16
+                | u32::from(date.day_of_week) << 24),
+            _ => return None,
+        })
+    }
+
+    /// The value of the type encoded by `bits`, for the types of at most
+    /// 8 octets.
+    fn from_bits(data_type: DataType, bits: u64) -> Result<Self, DecodeError> {
+        let length = data_type.fixed_length().unwrap_or(0);
+        let octet = |i: usize| (bits >> (8 * i)) as u8;
+        Ok(match data_type {
+            DataType::NoData => AttributeValue::NoData,
+            DataType::Data8 => AttributeValue::Data8(bits as u8),
+            DataType::Data16 => AttributeValue::Data16(bits as u16),
+            DataType::Data24 => AttributeValue::Data24(bits as u32),
+            DataType::Data32 => AttributeValue::Data32(bits as u32),
+            DataType::Data40 => AttributeValue::Data40(bits),
+            DataType::Data48 => AttributeValue::Data48(bits),
+            DataType::Data56 => AttributeValue::Data56(bits),
+            DataType::Data64 => AttributeValue::Data64(bits),
+            DataType::Boolean => AttributeValue::Boolean(match bits {
+                0x00 => Some(false),
+                0x01 => Some(true),
+                0xFF => None,
+                _ => return Err(DecodeError::InvalidValue("boolean")),
+            }),
+            DataType::Bitmap8 => AttributeValue::Bitmap8(bits as u8),
+            DataType::Bitmap16 => AttributeValue::Bitmap16(bits as u16),
+            DataType::Bitmap24 => AttributeValue::Bitmap24(bits as u32),
+            DataType::Bitmap32 => AttributeValue::Bitmap32(bits as u32),
+            DataType::Bitmap40 => AttributeValue::Bitmap40(bits),
+            DataType::Bitmap48 => AttributeValue::Bitmap48(bits),
+            DataType::Bitmap56 => AttributeValue::Bitmap56(bits),
+            DataType::Bitmap64 => AttributeValue::Bitmap64(bits),
+            DataType::Uint8 => AttributeValue::Uint8(bits as u8),
+            DataType::Uint16 => AttributeValue::Uint16(bits as u16),
+            DataType::Uint24 => AttributeValue::Uint24(bits as u32),
+            DataType::Uint32 => AttributeValue::Uint32(bits as u32),
+            DataType::Uint40 => AttributeValue::Uint40(bits),
+            DataType::Uint48 => AttributeValue::Uint48(bits),
+            DataType::Uint56 => AttributeValue::Uint56(bits),
+            DataType::Uint64 => AttributeValue::Uint64(bits),
+            DataType::Int8 => AttributeValue::Int8(bits as i8),
+            DataType::Int16 => AttributeValue::Int16(bits as i16),
+            DataType::Int24 => AttributeValue::Int24(sign_extend(bits, length) as i32),
+            DataType::Int32 => AttributeValue::Int32(bits as i32),
+            DataType::Int40 => AttributeValue::Int40(sign_extend(bits, length)),
+            DataType::Int48 => AttributeValue::Int48(sign_extend(bits, length)),
+            DataType::Int56 => AttributeValue::Int56(sign_extend(bits, length)),
+            DataType::Int64 => AttributeValue::Int64(bits as i64),
+            DataType::Enum8 => AttributeValue::Enum8(bits as u8),
+            DataType::Enum16 => AttributeValue::Enum16(bits as u16),
+            DataType::Semi => AttributeValue::Semi(bits as u16),
+            DataType::Single => AttributeValue::Single(f32::from_bits(bits as u32)),
+            DataType::Double => AttributeValue::Double(f64::from_bits(bits)),
+            DataType::TimeOfDay => AttributeValue::TimeOfDay(TimeOfDay {
+                hours: octet(0), minutes: octet(1), seconds: octet(2), hundredths: octet(3)
+            }),
+            DataType::Date => AttributeValue::Date(Date {
+                year: octet(0), month: octet(1), day_of_month: octet(2), day_of_week: octet(3)
+            }),
+            DataType::UtcTime => AttributeValue::UtcTime(bits as u32),
+            DataType::ClusterId => AttributeValue::ClusterId(ClusterId(bits as u16)),
+            DataType::AttributeId => AttributeValue::AttributeId(AttributeId(bits as u16)),
+            DataType::BacnetOid => AttributeValue::BacnetOid(bits as u32),
+            DataType::IeeeAddress => AttributeValue::IeeeAddress(IeeeAddress(bits)),
+            _ => return Err(DecodeError::InvalidValue("data type")),
+        })
+    }
+
+    /// Encode the value, without its type.
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        if let Some(bits) = self.bits() {
+            write_uint(out, bits, self.data_type().fixed_length().unwrap_or(0));
+            return;
+        }
+        match *self {
+            AttributeValue::OctetString(ref string) => write_string(out, string.as_ref().map(|s| &s[..]), false),
+            AttributeValue::CharacterString(ref string) => write_string(out, string.as_ref().map(|s| s.as_bytes()), false),
+            AttributeValue::LongOctetString(ref string) => write_string(out, string.as_ref().map(|s| &s[..]), true),
+            AttributeValue::LongCharacterString(ref string) => write_string(out, string.as_ref().map(|s| s.as_bytes()), true),
+            AttributeValue::Array { element_type, ref elements }
+                | AttributeValue::Set { element_type, ref elements }
+                | AttributeValue::Bag { element_type, ref elements } => write_elements(out, element_type, elements),
+            AttributeValue::Structure(ref elements) => match *elements {
+                Some(ref elements) => {
+                    write_u16(out, elements.len() as u16);
+                    for element in elements {
+                        element.encode_typed(out);
+                    }
+                }
+                None => write_u16(out, 0xFFFF),
+            },
+            AttributeValue::SecurityKey(ref key) => out.extend_from_slice(key),
+            _ => {}
+        }
+    }
+
+    /// Decode a value of the given type.
+    pub fn decode(data_type: DataType, reader: &mut Reader) -> Result<Self, DecodeError> {
+        if let Some(length) = data_type.fixed_length() {
+            if data_type == DataType::SecurityKey {
+                let mut key = [0; KEY_SIZE];
+                key.copy_from_slice(reader.read_bytes(KEY_SIZE)?);
+                return Ok(AttributeValue::SecurityKey(key));
+            }
+            return AttributeValue::from_bits(data_type, read_uint(reader, length)?);
+        }
+        Ok(match data_type {
+            DataType::OctetString => AttributeValue::OctetString(read_string(reader, false)?),
+            DataType::CharacterString => AttributeValue::CharacterString(read_character_string(reader, false)?),
+            DataType::LongOctetString => AttributeValue::LongOctetString(read_string(reader, true)?),
+            DataType::LongCharacterString =>
+                AttributeValue::LongCharacterString(read_character_string(reader, true)?),
+            DataType::Array => {
+                let element_type = DataType::decode(reader)?;
+                AttributeValue::Array { element_type, elements: read_elements(reader, element_type)? }
+            }
+            DataType::Set => {
+                let element_type = DataType::decode(reader)?;
+                AttributeValue::Set { element_type, elements: read_elements(reader, element_type)? }
+            }
+            DataType::Bag => {
+                let element_type = DataType::decode(reader)?;
+                AttributeValue::Bag { element_type, elements: read_elements(reader, element_type)? }
+            }
+            DataType::Structure => {
+                let count = reader.read_u16()?;
+                AttributeValue::Structure(if count == 0xFFFF {
+                    None
+                } else {
+                    Some((0..count).map(|_| AttributeValue::decode_typed(reader)).collect::<Result<_, _>>()?)
+                })
+            }
+            _ => return Err(DecodeError::InvalidValue("data type")),
+        })
+    }
+
+    /// Encode the type followed by the value.
+    pub fn encode_typed(&self, out: &mut Vec<u8>) {
+        out.push(self.data_type() as u8);
+        self.encode(out);
+    }
+
+    pub fn decode_typed(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let data_type = DataType::decode(reader)?;
+        AttributeValue::decode(data_type, reader)
+    }
+
+    /// The value that means that an attribute of the type is invalid or
+    /// has no value, `None` if the type has none.
+    pub fn invalid(data_type: DataType) -> Option<Self> {
+        let length = data_type.fixed_length().unwrap_or(0);
+        let all_ones = if length >= 8 { u64::MAX } else { (1 << (8 * length)) - 1 };
+        let code = data_type as u8;
+        match data_type {
+            DataType::OctetString => Some(AttributeValue::OctetString(None)),
+            DataType::CharacterString => Some(AttributeValue::CharacterString(None)),
+            DataType::LongOctetString => Some(AttributeValue::LongOctetString(None)),
+            DataType::LongCharacterString => Some(AttributeValue::LongCharacterString(None)),
+            DataType::Array => Some(AttributeValue::Array { element_type: DataType::Unknown, elements: None }),
+            DataType::Set => Some(AttributeValue::Set { element_type: DataType::Unknown, elements: None }),
+            DataType::Bag => Some(AttributeValue::Bag { element_type: DataType::Unknown, elements: None }),
+            DataType::Structure => Some(AttributeValue::Structure(None)),
+            DataType::Semi => Some(AttributeValue::Semi(HALF_NAN)),
+            DataType::Single => Some(AttributeValue::Single(f32::NAN)),
+            DataType::Double => Some(AttributeValue::Double(f64::NAN)),
+            // The signed integers: the most negative value.
+            _ if (0x28..=0x2F).contains(&code) =>
+                AttributeValue::from_bits(data_type, 1 << (8 * length - 1)).ok(),
+            DataType::Boolean | DataType::Enum8 | DataType::Enum16 | DataType::TimeOfDay
+                | DataType::Date | DataType::UtcTime | DataType::ClusterId | DataType::AttributeId
+                | DataType::BacnetOid | DataType::IeeeAddress =>
+                AttributeValue::from_bits(data_type, all_ones).ok(),
+            // The unsigned integers.
+            _ if (0x20..=0x27).contains(&code) => AttributeValue::from_bits(data_type, all_ones).ok(),
+            _ => None,
+        }
+    }
+
+    /// Whether the value means that the attribute is invalid or has no
+    /// value.
+    pub fn is_invalid(&self) -> bool {
+        match *self {
+            AttributeValue::Semi(value) => half_to_f64(value).is_nan(),
+            AttributeValue::Single(value) => value.is_nan(),
+            AttributeValue::Double(value) => value.is_nan(),
+            AttributeValue::Array { ref elements, .. }
+                | AttributeValue::Set { ref elements, .. }
+                | AttributeValue::Bag { ref elements, .. } => elements.is_none(),
+            _ => match (AttributeValue::invalid(self.data_type()), self.bits()) {
+                (Some(invalid), Some(bits)) => invalid.bits() == Some(bits),
+                (Some(invalid), None) => invalid == *self,
+                (None, _) => false,
+            },
+        }
+    }
+
+    /// Whether the value is analog, see `DataType::is_analog`.
+    pub fn is_analog(&self) -> bool {
+        self.data_type().is_analog()
+    }
+
+    /// The value of an analog attribute as a number, used to compare it to
+    /// the reportable change: the times of day are counted in hundredths
+    /// of a second since midnight and the dates in days since the 1st of
+    /// January 1900, so that the reportable change of a date is itself a
+    /// date after the 1st of January 1900. `None` for the discrete and the
+    /// invalid values.
+    pub fn analog_value(&self) -> Option<f64> {
+        if self.is_invalid() {
+            return None;
+        }
+        Some(match *self {
+            AttributeValue::Uint8(value) => f64::from(value),
+            AttributeValue::Uint16(value) => f64::from(value),
+            AttributeValue::Uint24(value) | AttributeValue::Uint32(value)
+                | AttributeValue::UtcTime(value) => f64::from(value),
+            AttributeValue::Uint40(value) | AttributeValue::Uint48(value)
+                | AttributeValue::Uint56(value) | AttributeValue::Uint64(value) => value as f64,
+            AttributeValue::Int8(value) => f64::from(value),
+            AttributeValue::Int16(value) => f64::from(value),
+            AttributeValue::Int24(value) | AttributeValue::Int32(value) => f64::from(value),
+            AttributeValue::Int40(value) | AttributeValue::Int48(value)
+                | AttributeValue::Int56(value) | AttributeValue::Int64(value) => value as f64,
+            AttributeValue::Semi(value) => half_to_f64(value),
+            AttributeValue::Single(value) => f64::from(value),
+            AttributeValue::Double(value) => value,
+            AttributeValue::TimeOfDay(time) => {
+                if time.hours == 0xFF || time.minutes == 0xFF || time.seconds == 0xFF
+                    || time.hundredths == 0xFF {
+                    return None;
+                }
+                f64::from(((u32::from(time.hours) * 60 + u32::from(time.minutes)) * 60
+                           + u32::from(time.seconds)) * 100 + u32::from(time.hundredths))
+            }
+            AttributeValue::Date(date) => {
+                if date.year == 0xFF || date.month == 0xFF || date.day_of_month == 0xFF {
+                    return None;
+                }
+                days_since_1900(1900 + i64::from(date.year), i64::from(date.month),
+                                i64::from(date.day_of_month)) as f64
+            }
+            _ => return None,
+        })
+    }
+
+    /// Whether the change from `previous` to this value must be reported:
+    /// for an analog attribute, when it is at least `reportable_change`;
+    /// for a discrete attribute, or when either value is invalid, on any
+    /// change.
+    pub fn exceeds_reportable_change(&self, previous: &AttributeValue,
+                                     reportable_change: Option<&AttributeValue>) -> bool {
+        let analog = (self.analog_value(), previous.analog_value(),
+                      reportable_change.and_then(|change| change.analog_value()));
+        match analog {
+            (Some(current), Some(previous), Some(change)) if self.is_analog() =>
+                (current - previous).abs() >= change && current != previous,
+            _ => self != previous,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcl::data_type::AttributeValue as V;
+
+    /// Check that the value is encoded, type included, to `bytes` and
+    /// decoded back from them.
+    fn round_trip(value: V, bytes: &[u8]) {
+        let mut out = Vec::new();
+        value.encode_typed(&mut out);
+        assert_eq!(out, bytes);
+        let mut reader = Reader::new(bytes);
+        assert_eq!(V::decode_typed(&mut reader), Ok(value));
+        assert!(reader.is_empty());
+    }
+
+    fn decode(bytes: &[u8]) -> Result<V, DecodeError> {
+        V::decode_typed(&mut Reader::new(bytes))
+    }
+
+    #[test]
+    fn data_types() {
+        for &data_type in DATA_TYPES.iter() {
+            assert_eq!(DataType::from_u8(data_type as u8), Ok(data_type));
+        }
+        assert_eq!(DataType::from_u8(0x01), Err(DecodeError::InvalidValue("data type")));
+        assert_eq!(DataType::Data24.fixed_length(), Some(3));
+        assert_eq!(DataType::Int56.fixed_length(), Some(7));
+        assert_eq!(DataType::Boolean.fixed_length(), Some(1));
+        assert_eq!(DataType::SecurityKey.fixed_length(), Some(16));
+        assert_eq!(DataType::CharacterString.fixed_length(), None);
+        assert!(DataType::Uint8.is_analog());
+        assert!(DataType::Double.is_analog());
+        assert!(DataType::UtcTime.is_analog());
+        assert!(!DataType::Enum8.is_analog());
+        assert!(!DataType::Bitmap16.is_analog());
+    }
+
+    #[test]
+    fn integers() {
+        round_trip(V::NoData, &[0x00]);
+        round_trip(V::Boolean(Some(true)), &[0x10, 0x01]);
+        round_trip(V::Bitmap24(0x0A_0B0C), &[0x1A, 0x0C, 0x0B, 0x0A]);
+        round_trip(V::Uint40(0x01_0203_0405), &[0x24, 0x05, 0x04, 0x03, 0x02, 0x01]);
+        round_trip(V::Int8(-1), &[0x28, 0xFF]);
+        round_trip(V::Int24(-2), &[0x2A, 0xFE, 0xFF, 0xFF]);
+        round_trip(V::Int24(0x7F_FFFF), &[0x2A, 0xFF, 0xFF, 0x7F]);
+        round_trip(V::Int48(-0x10000), &[0x2D, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+        round_trip(V::Enum16(0x1234), &[0x31, 0x34, 0x12]);
+        round_trip(V::Single(1.5), &[0x39, 0x00, 0x00, 0xC0, 0x3F]);
+        round_trip(V::ClusterId(ClusterId(0x0006)), &[0xE8, 0x06, 0x00]);
+        round_trip(V::IeeeAddress(IeeeAddress(0x0011_2233_4455_6677)),
+                   &[0xF0, 0x77, 0x66, 0x55, 0x44, 0x33, 0x22, 0x11, 0x00]);
+    }
+
+    #[test]
+    fn times_and_keys() {
+        round_trip(V::TimeOfDay(TimeOfDay { hours: 13, minutes: 45, seconds: 30, hundredths: 0xFF }),
+                   &[0xE0, 13, 45, 30, 0xFF]);
+        round_trip(V::Date(Date { year: 124, month: 2, day_of_month: 29, day_of_week: 4 }),
+                   &[0xE1, 124, 2, 29, 4]);
+        let mut bytes = vec![0xF1];
+        bytes.extend(0..16);
+        let mut key = [0; KEY_SIZE];
+        key.copy_from_slice(&bytes[1..]);
+        round_trip(V::SecurityKey(key), &bytes);
+    }
+
+    #[test]
+    fn strings() {
+        round_trip(V::CharacterString(Some("abc".to_string())), &[0x42, 0x03, b'a', b'b', b'c']);
+        round_trip(V::CharacterString(None), &[0x42, 0xFF]);
+        round_trip(V::OctetString(Some(Vec::new())), &[0x41, 0x00]);
+        round_trip(V::LongOctetString(Some(vec![0x01, 0x02])), &[0x43, 0x02, 0x00, 0x01, 0x02]);
+        round_trip(V::LongCharacterString(None), &[0x44, 0xFF, 0xFF]);
+        // The strings too long for their type are truncated.
+        let mut out = Vec::new();
+        V::OctetString(Some(vec![0xAA; 300])).encode(&mut out);
+        assert_eq!(out.len(), 1 + MAX_STRING_LENGTH);
+        assert_eq!(out[0], MAX_STRING_LENGTH as u8);
+        assert_eq!(decode(&[0x42, 0x02, 0xC3, 0x28]), Err(DecodeError::InvalidValue("character string")));
+        assert_eq!(decode(&[0x42, 0x03, b'a']), Err(DecodeError::UnexpectedEnd));
+    }
+
+    #[test]
+    fn collections() {
+        round_trip(V::Array { element_type: DataType::Uint8, elements: Some(vec![V::Uint8(1), V::Uint8(2)]) },
+                   &[0x48, 0x20, 0x02, 0x00, 0x01, 0x02]);
+        round_trip(V::Set { element_type: DataType::Uint16, elements: None }, &[0x50, 0x21, 0xFF, 0xFF]);
+        round_trip(V::Structure(Some(vec![V::Boolean(Some(false)), V::Int16(-1)])),
+                   &[0x4C, 0x02, 0x00, 0x10, 0x00, 0x29, 0xFF, 0xFF]);
+        round_trip(V::Structure(None), &[0x4C, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn invalid_encodings() {
+        assert_eq!(decode(&[0x10, 0x02]), Err(DecodeError::InvalidValue("boolean")));
+        assert_eq!(decode(&[0x01, 0x00]), Err(DecodeError::InvalidValue("data type")));
+        assert_eq!(decode(&[0x23, 0x01, 0x02]), Err(DecodeError::UnexpectedEnd));
+        // The type of the elements of an array is not itself a value.
+        assert_eq!(decode(&[0x48, 0xFF, 0x01, 0x00, 0x00]), Err(DecodeError::InvalidValue("data type")));
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert_eq!(V::invalid(DataType::Uint8), Some(V::Uint8(0xFF)));
+        assert_eq!(V::invalid(DataType::Uint64), Some(V::Uint64(u64::MAX)));
+        assert_eq!(V::invalid(DataType::Int16), Some(V::Int16(i16::MIN)));
+        assert_eq!(V::invalid(DataType::Int24), Some(V::Int24(-0x80_0000)));
+        assert_eq!(V::invalid(DataType::Boolean), Some(V::Boolean(None)));
+        assert_eq!(V::invalid(DataType::CharacterString), Some(V::CharacterString(None)));
+        assert_eq!(V::invalid(DataType::Bitmap8), None);
+        assert_eq!(V::invalid(DataType::Data16), None);
+        for &data_type in DATA_TYPES.iter() {
+            if let Some(value) = V::invalid(data_type) {
+                assert!(value.is_invalid(), "{:?}", data_type);
+                assert_eq!(value.data_type(), data_type);
+            }
+        }
+        assert!(V::Semi(0x7C01).is_invalid());
+        assert!(!V::Uint8(0xFE).is_invalid());
+        assert!(!V::Int16(i16::MIN + 1).is_invalid());
+        assert!(!V::Bitmap8(0xFF).is_invalid());
+        assert!(!V::Single(0.0).is_invalid());
+    }
+
+    #[test]
+    fn analog_values() {
+        assert_eq!(V::Int24(-5).analog_value(), Some(-5.0));
+        assert_eq!(V::Semi(0x3C00).analog_value(), Some(1.0));
+        assert_eq!(V::Semi(0xC000).analog_value(), Some(-2.0));
+        assert_eq!(V::TimeOfDay(TimeOfDay { hours: 1, minutes: 0, seconds: 1, hundredths: 5 }).analog_value(),
+                   Some(360_105.0));
+        assert_eq!(V::Date(Date { year: 0, month: 1, day_of_month: 1, day_of_week: 0xFF }).analog_value(),
+                   Some(0.0));
+        assert_eq!(V::Date(Date { year: 100, month: 3, day_of_month: 1, day_of_week: 0xFF }).analog_value(),
+                   Some(36_584.0));
+        assert_eq!(V::Uint8(0xFF).analog_value(), None);
+        assert_eq!(V::Enum8(1).analog_value(), None);
+    }
+
+    #[test]
+    fn reportable_changes() {
+        let change = V::Uint16(10);
+        assert!(!V::Uint16(109).exceeds_reportable_change(&V::Uint16(100), Some(&change)));
+        assert!(V::Uint16(110).exceeds_reportable_change(&V::Uint16(100), Some(&change)));
+        assert!(V::Uint16(90).exceeds_reportable_change(&V::Uint16(100), Some(&change)));
+        // Without a reportable change, or from an invalid value, any change
+        // is reported.
+        assert!(V::Uint16(101).exceeds_reportable_change(&V::Uint16(100), None));
+        assert!(V::Uint16(100).exceeds_reportable_change(&V::Uint16(0xFFFF), Some(&change)));
+        assert!(!V::Uint16(100).exceeds_reportable_change(&V::Uint16(100), None));
+        // A discrete value is reported on any change.
+        assert!(V::Enum8(1).exceeds_reportable_change(&V::Enum8(0), Some(&V::Enum8(5))));
+    }
+}
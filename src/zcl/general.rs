@@ -0,0 +1,1045 @@
+//! The general commands, described in section 2.5 of the ZCL
+//! specification, that read, write, report and discover the attributes
+//! and the commands of any cluster.
+//!
+//! Each command knows how to encode and decode its payload, that is what
+//! follows the ZCL header. The lists of records extend to the end of the
+//! frame.
+
+use codec::{DecodeError, Reader, write_u16};
+use types::AttributeId;
+use zcl::{Direction, FrameType, ZclHeader, ZclStatus};
+use zcl::data_type::{AttributeValue, DataType};
+
+fn read_attribute_id(reader: &mut Reader) -> Result<AttributeId, DecodeError> {
+    Ok(AttributeId(reader.read_u16()?))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadAttributes {
+    pub attributes: Vec<AttributeId>,
+}
+
+impl ReadAttributes {
+    pub const ID: u8 = 0x00;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        for attribute_id in &self.attributes {
+            write_u16(out, attribute_id.0);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut attributes = Vec::new();
+        while !reader.is_empty() {
+            attributes.push(read_attribute_id(reader)?);
+        }
+        Ok(ReadAttributes { attributes })
+    }
+}
+
+/// The value of an attribute read, or the reason why it could not be.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadAttributeStatusRecord {
+    pub attribute_id: AttributeId,
+    pub result: Result<AttributeValue, ZclStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadAttributesResponse {
+    pub records: Vec<ReadAttributeStatusRecord>,
+}
+
+impl ReadAttributesResponse {
+    pub const ID: u8 = 0x01;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        for record in &self.records {
+            write_u16(out, record.attribute_id.0);
+            match record.result {
+                Ok(ref value) => {
+                    out.push(ZclStatus::Success as u8);
+                    value.encode_typed(out);
+                }
+                Err(status) => out.push(status as u8),
+            }
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            let attribute_id = read_attribute_id(reader)?;
+            let result = match ZclStatus::decode(reader)? {
+                ZclStatus::Success => Ok(AttributeValue::decode_typed(reader)?),
+                status => Err(status),
+            };
+            records.push(ReadAttributeStatusRecord { attribute_id, result });
+        }
+        Ok(ReadAttributesResponse { records })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteAttributeRecord {
+    pub attribute_id: AttributeId,
+    pub value: AttributeValue,
+}
+
+/// The payload of the Write Attributes, Write Attributes Undivided and
+/// Write Attributes No Response commands.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WriteAttributes {
+    pub records: Vec<WriteAttributeRecord>,
+}
+
+impl WriteAttributes {
+    pub const ID: u8 = 0x02;
+    /// Write all the attributes or none of them.
+    pub const UNDIVIDED_ID: u8 = 0x03;
+    /// Write the attributes without answering.
+    pub const NO_RESPONSE_ID: u8 = 0x05;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        for record in &self.records {
+            write_u16(out, record.attribute_id.0);
+            record.value.encode_typed(out);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            let attribute_id = read_attribute_id(reader)?;
+            records.push(WriteAttributeRecord { attribute_id, value: AttributeValue::decode_typed(reader)? });
+        }
+        Ok(WriteAttributes { records })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteAttributeStatusRecord {
+    pub status: ZclStatus,
+    pub attribute_id: AttributeId,
+}
+
+/// The attributes that could not be written. When all of them were, the
+/// list is empty and the response carries only the success status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WriteAttributesResponse {
+    pub records: Vec<WriteAttributeStatusRecord>,
+}
+
+impl WriteAttributesResponse {
+    pub const ID: u8 = 0x04;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        if self.records.is_empty() {
+            out.push(ZclStatus::Success as u8);
+        }
+        for record in &self.records {
+            out.push(record.status as u8);
+            write_u16(out, record.attribute_id.0);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        if reader.remaining() == [ZclStatus::Success as u8] {
+            reader.read_u8()?;
+            return Ok(WriteAttributesResponse { records: Vec::new() });
+        }
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            let status = ZclStatus::decode(reader)?;
+            records.push(WriteAttributeStatusRecord { status, attribute_id: read_attribute_id(reader)? });
+        }
+        Ok(WriteAttributesResponse { records })
+    }
+}
+
+/// Whether a reporting configuration concerns the reports sent or the
+/// reports received.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportingDirection {
+    /// The receiver of the configuration sends the reports of the
+    /// attribute.
+    Reported = 0x00,
+    /// The receiver of the configuration expects the reports of the
+    /// attribute.
+    Received = 0x01,
+}
+
+impl ReportingDirection {
+    fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        match reader.read_u8()? {
+            0x00 => Ok(ReportingDirection::Reported),
+            0x01 => Ok(ReportingDirection::Received),
+            _ => Err(DecodeError::InvalidValue("reporting direction")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReportingConfiguration {
+    Reported {
+        attribute_id: AttributeId,
+        data_type: DataType,
+        /// The minimum interval between two reports, in seconds.
+        min_interval: u16,
+        /// The maximum interval between two reports, in seconds. 0
+        /// disables the periodic reports and 0xFFFF all of them.
+        max_interval: u16,
+        /// The change of the value that triggers a report, a value of
+        /// `data_type`. Present only for the analog types: it is not
+        /// encoded for the discrete types, and encoded as 0 when missing
+        /// for the analog ones.
+        reportable_change: Option<AttributeValue>,
+    },
+    Received {
+        attribute_id: AttributeId,
+        /// The maximum expected interval between two reports, in seconds.
+        /// 0 disables the timeout.
+        timeout_period: u16,
+    },
+}
+
+impl ReportingConfiguration {
+    pub fn direction(&self) -> ReportingDirection {
+        match *self {
+            ReportingConfiguration::Reported { .. } => ReportingDirection::Reported,
+            ReportingConfiguration::Received { .. } => ReportingDirection::Received,
+        }
+    }
+
+    pub fn attribute_id(&self) -> AttributeId {
+        match *self {
+            ReportingConfiguration::Reported { attribute_id, .. }
+            | ReportingConfiguration::Received { attribute_id, .. } => attribute_id,
+        }
+    }
+
+    /// Encode the configuration, without the direction.
+    fn encode(&self, out: &mut Vec<u8>) {
+        match *self {
+            ReportingConfiguration::Reported { attribute_id, data_type, min_interval, max_interval,
+                                               ref reportable_change } => {
+                write_u16(out, attribute_id.0);
+                out.push(data_type as u8);
+                write_u16(out, min_interval);
+                write_u16(out, max_interval);
+                // The change is present if and only if the type is analog,
+                // as `decode` expects; a change of 0 reports any change.
+                if data_type.is_analog() {
+                    match *reportable_change {
+                        Some(ref change) if change.data_type() == data_type => change.encode(out),
+                        _ => out.resize(out.len() + data_type.fixed_length().unwrap_or(0), 0),
+                    }
+                }
+            }
+            ReportingConfiguration::Received { attribute_id, timeout_period } => {
+                write_u16(out, attribute_id.0);
+                write_u16(out, timeout_period);
+            }
+        }
+    }
+
+    /// Decode the configuration of an attribute whose direction and
+    /// identifier were already read.
+    fn decode(reader: &mut Reader, direction: ReportingDirection, attribute_id: AttributeId)
+              -> Result<Self, DecodeError> {
+        match direction {
+            ReportingDirection::Reported => {
+                let data_type = DataType::decode(reader)?;
+                let min_interval = reader.read_u16()?;
+                let max_interval = reader.read_u16()?;
+                let reportable_change = if data_type.is_analog() {
+                    Some(AttributeValue::decode(data_type, reader)?)
+                } else {
+                    None
+                };
+                Ok(ReportingConfiguration::Reported {
+                    attribute_id, data_type, min_interval, max_interval, reportable_change
+                })
+            }
+            ReportingDirection::Received => Ok(ReportingConfiguration::Received {
+                attribute_id,
+                timeout_period: reader.read_u16()?,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigureReporting {
+    pub records: Vec<ReportingConfiguration>,
+}
+
+impl ConfigureReporting {
+    pub const ID: u8 = 0x06;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        for record in &self.records {
+            out.push(record.direction() as u8);
+            record.encode(out);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            let direction = ReportingDirection::decode(reader)?;
+            let attribute_id = read_attribute_id(reader)?;
+            records.push(ReportingConfiguration::decode(reader, direction, attribute_id)?);
+        }
+        Ok(ConfigureReporting { records })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReportingStatusRecord {
+    pub status: ZclStatus,
+    pub direction: ReportingDirection,
+    pub attribute_id: AttributeId,
+}
+
+impl ReportingStatusRecord {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(self.status as u8);
+        out.push(self.direction as u8);
+        write_u16(out, self.attribute_id.0);
+    }
+}
+
+/// The attributes that could not be configured. When all of them were,
+/// the list is empty and the response carries only the success status.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigureReportingResponse {
+    pub records: Vec<ReportingStatusRecord>,
+}
+
+impl ConfigureReportingResponse {
+    pub const ID: u8 = 0x07;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        if self.records.is_empty() {
+            out.push(ZclStatus::Success as u8);
+        }
+        for record in &self.records {
+            record.encode(out);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        if reader.remaining() == [ZclStatus::Success as u8] {
+            reader.read_u8()?;
+            return Ok(ConfigureReportingResponse { records: Vec::new() });
+        }
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            records.push(ReportingStatusRecord {
+                status: ZclStatus::decode(reader)?,
+                direction: ReportingDirection::decode(reader)?,
+                attribute_id: read_attribute_id(reader)?,
+            });
+        }
+        Ok(ConfigureReportingResponse { records })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadReportingConfiguration {
+    pub records: Vec<(ReportingDirection, AttributeId)>,
+}
+
+impl ReadReportingConfiguration {
+    pub const ID: u8 = 0x08;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        for &(direction, attribute_id) in &self.records {
+            out.push(direction as u8);
+            write_u16(out, attribute_id.0);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            let direction = ReportingDirection::decode(reader)?;
+            records.push((direction, read_attribute_id(reader)?));
+        }
+        Ok(ReadReportingConfiguration { records })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReadReportingConfigurationResponse {
+    pub records: Vec<Result<ReportingConfiguration, ReportingStatusRecord>>,
+}
+
+impl ReadReportingConfigurationResponse {
+    pub const ID: u8 = 0x09;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        for record in &self.records {
+            match *record {
+                Ok(ref configuration) => {
+                    out.push(ZclStatus::Success as u8);
+                    out.push(configuration.direction() as u8);
+                    configuration.encode(out);
+                }
+                Err(ref status) => status.encode(out),
+            }
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let mut records = Vec::new();
+        while !reader.is_empty() {
+            let status = ZclStatus::decode(reader)?;
+            let direction = ReportingDirection::decode(reader)?;
+            let attribute_id = read_attribute_id(reader)?;
+            records.push(match status {
+                ZclStatus::Success => Ok(ReportingConfiguration::decode(reader, direction, attribute_id)?),
+                status => Err(ReportingStatusRecord { status, direction, attribute_id }),
+            });
+        }
+        Ok(ReadReportingConfigurationResponse { records })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ReportAttributes {
+    pub reports: Vec<WriteAttributeRecord>,
+}
+
+impl ReportAttributes {
+    pub const ID: u8 = 0x0A;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        WriteAttributes { records: self.reports.clone() }.encode_payload(out)
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(ReportAttributes { reports: WriteAttributes::decode_payload(reader)?.records })
+    }
+}
+
+/// The answer to a command that has no specific response, sent when the
+/// command failed or when the default response was not disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DefaultResponse {
+    pub command_id: u8,
+    pub status: ZclStatus,
+}
+
+impl DefaultResponse {
+    pub const ID: u8 = 0x0B;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.command_id);
+        out.push(self.status as u8);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let command_id = reader.read_u8()?;
+        Ok(DefaultResponse { command_id, status: ZclStatus::decode(reader)? })
+    }
+}
+
+/// The payload of the Discover Attributes and Discover Attributes Extended
+/// commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoverAttributes {
+    pub start_attribute_id: AttributeId,
+    pub max_attribute_ids: u8,
+}
+
+impl DiscoverAttributes {
+    pub const ID: u8 = 0x0C;
+    pub const EXTENDED_ID: u8 = 0x15;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        write_u16(out, self.start_attribute_id.0);
+        out.push(self.max_attribute_ids);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let start_attribute_id = read_attribute_id(reader)?;
+        Ok(DiscoverAttributes { start_attribute_id, max_attribute_ids: reader.read_u8()? })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoverAttributesResponse {
+    /// Whether there are no more attributes to discover.
+    pub discovery_complete: bool,
+    pub attributes: Vec<(AttributeId, DataType)>,
+}
+
+impl DiscoverAttributesResponse {
+    pub const ID: u8 = 0x0D;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.discovery_complete as u8);
+        for &(attribute_id, data_type) in &self.attributes {
+            write_u16(out, attribute_id.0);
+            out.push(data_type as u8);
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let discovery_complete = reader.read_u8()? != 0;
+        let mut attributes = Vec::new();
+        while !reader.is_empty() {
+            let attribute_id = read_attribute_id(reader)?;
+            attributes.push((attribute_id, DataType::decode(reader)?));
+        }
+        Ok(DiscoverAttributesResponse { discovery_complete, attributes })
+    }
+}
+
+/// The payload of the Discover Commands Received and Discover Commands
+/// Generated commands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoverCommands {
+    pub start_command_id: u8,
+    pub max_command_ids: u8,
+}
+
+impl DiscoverCommands {
+    pub const RECEIVED_ID: u8 = 0x11;
+    pub const GENERATED_ID: u8 = 0x13;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.start_command_id);
+        out.push(self.max_command_ids);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let start_command_id = reader.read_u8()?;
+        Ok(DiscoverCommands { start_command_id, max_command_ids: reader.read_u8()? })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoverCommandsResponse {
+    /// Whether there are no more commands to discover.
+    pub discovery_complete: bool,
+    pub commands: Vec<u8>,
+}
+
+impl DiscoverCommandsResponse {
+    pub const RECEIVED_ID: u8 = 0x12;
+    pub const GENERATED_ID: u8 = 0x14;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.discovery_complete as u8);
+        out.extend_from_slice(&self.commands);
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let discovery_complete = reader.read_u8()? != 0;
+        Ok(DiscoverCommandsResponse { discovery_complete, commands: reader.read_to_end().to_vec() })
+    }
+}
+
+/// How an attribute may be accessed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AccessControl {
+    pub readable: bool,
+    pub writable: bool,
+    pub reportable: bool,
+}
+
+impl AccessControl {
+    pub fn to_u8(self) -> u8 {
+        self.readable as u8 | (self.writable as u8) << 1 | (self.reportable as u8) << 2
+    }
+
+    pub fn from_u8(value: u8) -> Self {
+        AccessControl {
+            readable: value & 0x01 != 0,
+            writable: value & 0x02 != 0,
+            reportable: value & 0x04 != 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtendedAttributeInformation {
+    pub attribute_id: AttributeId,
+    pub data_type: DataType,
+    pub access_control: AccessControl,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoverAttributesExtendedResponse {
+    /// Whether there are no more attributes to discover.
+    pub discovery_complete: bool,
+    pub attributes: Vec<ExtendedAttributeInformation>,
+}
+
+impl DiscoverAttributesExtendedResponse {
+    pub const ID: u8 = 0x16;
+
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        out.push(self.discovery_complete as u8);
+        for attribute in &self.attributes {
+            write_u16(out, attribute.attribute_id.0);
+            out.push(attribute.data_type as u8);
+            out.push(attribute.access_control.to_u8());
+        }
+    }
+
+    pub fn decode_payload(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let discovery_complete = reader.read_u8()? != 0;
+        let mut attributes = Vec::new();
+        while !reader.is_empty() {
+            attributes.push(ExtendedAttributeInformation {
+                attribute_id: read_attribute_id(reader)?,
+                data_type: DataType::decode(reader)?,
+                access_control: AccessControl::from_u8(reader.read_u8()?),
+            });
+        }
+        Ok(DiscoverAttributesExtendedResponse { discovery_complete, attributes })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GeneralCommand {
+    ReadAttributes(ReadAttributes),
+    ReadAttributesResponse(ReadAttributesResponse),
+    WriteAttributes(WriteAttributes),
+    WriteAttributesUndivided(WriteAttributes),
+    WriteAttributesResponse(WriteAttributesResponse),
+    WriteAttributesNoResponse(WriteAttributes),
+    ConfigureReporting(ConfigureReporting),
+    ConfigureReportingResponse(ConfigureReportingResponse),
+    ReadReportingConfiguration(ReadReportingConfiguration),
+    ReadReportingConfigurationResponse(ReadReportingConfigurationResponse),
+    ReportAttributes(ReportAttributes),
+    DefaultResponse(DefaultResponse),
+    DiscoverAttributes(DiscoverAttributes),
+    DiscoverAttributesResponse(DiscoverAttributesResponse),
+    DiscoverCommandsReceived(DiscoverCommands),
+    DiscoverCommandsReceivedResponse(DiscoverCommandsResponse),
+    DiscoverCommandsGenerated(DiscoverCommands),
+    DiscoverCommandsGeneratedResponse(DiscoverCommandsResponse),
+    DiscoverAttributesExtended(DiscoverAttributes),
+    DiscoverAttributesExtendedResponse(DiscoverAttributesExtendedResponse),
+}
+
+impl GeneralCommand {
+    pub fn id(&self) -> u8 {
+        match *self {
+            GeneralCommand::ReadAttributes(_) => ReadAttributes::ID,
+            GeneralCommand::ReadAttributesResponse(_) => ReadAttributesResponse::ID,
+            GeneralCommand::WriteAttributes(_) => WriteAttributes::ID,
+            GeneralCommand::WriteAttributesUndivided(_) => WriteAttributes::UNDIVIDED_ID,
+            GeneralCommand::WriteAttributesResponse(_) => WriteAttributesResponse::ID,
+            GeneralCommand::WriteAttributesNoResponse(_) => WriteAttributes::NO_RESPONSE_ID,
+            GeneralCommand::ConfigureReporting(_) => ConfigureReporting::ID,
+            GeneralCommand::ConfigureReportingResponse(_) => ConfigureReportingResponse::ID,
+            GeneralCommand::ReadReportingConfiguration(_) => ReadReportingConfiguration::ID,
+            GeneralCommand::ReadReportingConfigurationResponse(_) => ReadReportingConfigurationResponse::ID,
+            GeneralCommand::ReportAttributes(_) => ReportAttributes::ID,
+            GeneralCommand::DefaultResponse(_) => DefaultResponse::ID,
+            GeneralCommand::DiscoverAttributes(_) => DiscoverAttributes::ID,
+            GeneralCommand::DiscoverAttributesResponse(_) => DiscoverAttributesResponse::ID,
+            GeneralCommand::DiscoverCommandsReceived(_) => DiscoverCommands::RECEIVED_ID,
+            GeneralCommand::DiscoverCommandsReceivedResponse(_) => DiscoverCommandsResponse::RECEIVED_ID,
+            GeneralCommand::DiscoverCommandsGenerated(_) => DiscoverCommands::GENERATED_ID,
+            GeneralCommand::DiscoverCommandsGeneratedResponse(_) => DiscoverCommandsResponse::GENERATED_ID,
+            GeneralCommand::DiscoverAttributesExtended(_) => DiscoverAttributes::EXTENDED_ID,
+            GeneralCommand::DiscoverAttributesExtendedResponse(_) => DiscoverAttributesExtendedResponse::ID,
+        }
+    }
+
+    /// The direction of the command, when it is defined: the responses go
+    /// from the server to the client, the requests the other way. The
+    /// reporting commands and the default response go both ways.
+    pub fn direction(&self) -> Option<Direction> {
+        match *self {
+            GeneralCommand::ReadAttributes(_)
+            | GeneralCommand::WriteAttributes(_)
+            | GeneralCommand::WriteAttributesUndivided(_)
+            | GeneralCommand::WriteAttributesNoResponse(_)
+            | GeneralCommand::DiscoverAttributes(_)
+            | GeneralCommand::DiscoverCommandsReceived(_)
+            | GeneralCommand::DiscoverCommandsGenerated(_)
+            | GeneralCommand::DiscoverAttributesExtended(_) => Some(Direction::ClientToServer),
+            GeneralCommand::ReadAttributesResponse(_)
+            | GeneralCommand::WriteAttributesResponse(_)
+            | GeneralCommand::DiscoverAttributesResponse(_)
+            | GeneralCommand::DiscoverCommandsReceivedResponse(_)
+            | GeneralCommand::DiscoverCommandsGeneratedResponse(_)
+            | GeneralCommand::DiscoverAttributesExtendedResponse(_) => Some(Direction::ServerToClient),
+            _ => None,
+        }
+    }
+
+    /// Encode the payload of the command, without the ZCL header.
+    pub fn encode_payload(&self, out: &mut Vec<u8>) {
+        match *self {
+            GeneralCommand::ReadAttributes(ref command) => command.encode_payload(out),
+            GeneralCommand::ReadAttributesResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::WriteAttributes(ref command)
+            | GeneralCommand::WriteAttributesUndivided(ref command)
+            | GeneralCommand::WriteAttributesNoResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::WriteAttributesResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::ConfigureReporting(ref command) => command.encode_payload(out),
+            GeneralCommand::ConfigureReportingResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::ReadReportingConfiguration(ref command) => command.encode_payload(out),
+            GeneralCommand::ReadReportingConfigurationResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::ReportAttributes(ref command) => command.encode_payload(out),
+            GeneralCommand::DefaultResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::DiscoverAttributes(ref command)
+            | GeneralCommand::DiscoverAttributesExtended(ref command) => command.encode_payload(out),
+            GeneralCommand::DiscoverAttributesResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::DiscoverCommandsReceived(ref command)
+            | GeneralCommand::DiscoverCommandsGenerated(ref command) => command.encode_payload(out),
+            GeneralCommand::DiscoverCommandsReceivedResponse(ref command)
+            | GeneralCommand::DiscoverCommandsGeneratedResponse(ref command) => command.encode_payload(out),
+            GeneralCommand::DiscoverAttributesExtendedResponse(ref command) => command.encode_payload(out),
+        }
+    }
+
+    /// Decode the payload of the general command `command_id`.
+    pub fn decode_payload(command_id: u8, reader: &mut Reader) -> Result<Self, DecodeError> {
+        Ok(match command_id {
+            ReadAttributes::ID => GeneralCommand::ReadAttributes(ReadAttributes::decode_payload(reader)?),
+            ReadAttributesResponse::ID =>
+                GeneralCommand::ReadAttributesResponse(ReadAttributesResponse::decode_payload(reader)?),
+            WriteAttributes::ID => GeneralCommand::WriteAttributes(WriteAttributes::decode_payload(reader)?),
+            WriteAttributes::UNDIVIDED_ID =>
+                GeneralCommand::WriteAttributesUndivided(WriteAttributes::decode_payload(reader)?),
+            WriteAttributesResponse::ID =>
+                GeneralCommand::WriteAttributesResponse(WriteAttributesResponse::decode_payload(reader)?),
+            WriteAttributes::NO_RESPONSE_ID =>
+                GeneralCommand::WriteAttributesNoResponse(WriteAttributes::decode_payload(reader)?),
+            ConfigureReporting::ID =>
+                GeneralCommand::ConfigureReporting(ConfigureReporting::decode_payload(reader)?),
+            ConfigureReportingResponse::ID =>
+                GeneralCommand::ConfigureReportingResponse(ConfigureReportingResponse::decode_payload(reader)?),
+            ReadReportingConfiguration::ID =>
+                GeneralCommand::ReadReportingConfiguration(ReadReportingConfiguration::decode_payload(reader)?),
+            ReadReportingConfigurationResponse::ID => GeneralCommand::ReadReportingConfigurationResponse(
+                ReadReportingConfigurationResponse::decode_payload(reader)?),
+            ReportAttributes::ID => GeneralCommand::ReportAttributes(ReportAttributes::decode_payload(reader)?),
+            DefaultResponse::ID => GeneralCommand::DefaultResponse(DefaultResponse::decode_payload(reader)?),
+            DiscoverAttributes::ID =>
+                GeneralCommand::DiscoverAttributes(DiscoverAttributes::decode_payload(reader)?),
+            DiscoverAttributesResponse::ID =>
+                GeneralCommand::DiscoverAttributesResponse(DiscoverAttributesResponse::decode_payload(reader)?),
+            DiscoverCommands::RECEIVED_ID =>
+                GeneralCommand::DiscoverCommandsReceived(DiscoverCommands::decode_payload(reader)?),
+            DiscoverCommandsResponse::RECEIVED_ID =>
+                GeneralCommand::DiscoverCommandsReceivedResponse(DiscoverCommandsResponse::decode_payload(reader)?),
+            DiscoverCommands::GENERATED_ID =>
+                GeneralCommand::DiscoverCommandsGenerated(DiscoverCommands::decode_payload(reader)?),
+            DiscoverCommandsResponse::GENERATED_ID =>
+                GeneralCommand::DiscoverCommandsGeneratedResponse(DiscoverCommandsResponse::decode_payload(reader)?),
+            DiscoverAttributes::EXTENDED_ID =>
+                GeneralCommand::DiscoverAttributesExtended(DiscoverAttributes::decode_payload(reader)?),
+            DiscoverAttributesExtendedResponse::ID => GeneralCommand::DiscoverAttributesExtendedResponse(
+                DiscoverAttributesExtendedResponse::decode_payload(reader)?),
+            _ => return Err(DecodeError::InvalidValue("general command")),
+        })
+    }
+
+    /// Encode a whole ZCL frame carrying the command. The manufacturer code
+    /// makes it a command on the manufacturer specific attributes.
+    pub fn encode(&self, direction: Direction, manufacturer_code: Option<u16>,
+                  disable_default_response: bool, sequence_number: u8, out: &mut Vec<u8>) {
+        ZclHeader {
+            frame_type: FrameType::Global,
+            manufacturer_code,
+            direction,
+            disable_default_response,
+            sequence_number,
+            command_id: self.id(),
+        }.encode(out);
+        self.encode_payload(out);
+    }
+
+    /// Decode a ZCL frame carrying a general command.
+    pub fn decode(frame: &[u8]) -> Result<(ZclHeader, Self), DecodeError> {
+        let mut reader = Reader::new(frame);
+        let header = ZclHeader::decode(&mut reader)?;
+        if header.frame_type != FrameType::Global {
+            return Err(DecodeError::InvalidValue("ZCL frame type"));
+        }
+        let command = GeneralCommand::decode_payload(header.command_id, &mut reader)?;
+        Ok((header, command))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcl::data_type::AttributeValue as V;
+
+    /// Check that the command is encoded, header included, to `bytes` and
+    /// decoded back from them.
+    fn round_trip(command: GeneralCommand, direction: Direction, bytes: &[u8]) {
+        let mut out = Vec::new();
+        command.encode(direction, None, false, 0x42, &mut out);
+        assert_eq!(out, bytes);
+        let (header, decoded) = GeneralCommand::decode(bytes).unwrap();
+        assert_eq!(header.direction, direction);
+        assert_eq!(header.sequence_number, 0x42);
+        assert_eq!(header.command_id, command.id());
+        assert_eq!(decoded, command);
+    }
+
+    #[test]
+    fn read_attributes() {
+        round_trip(GeneralCommand::ReadAttributes(ReadAttributes {
+            attributes: vec![AttributeId(0x0000), AttributeId(0x4001)],
+        }), Direction::ClientToServer, &[0x00, 0x42, 0x00, 0x00, 0x00, 0x01, 0x40]);
+    }
+
+    #[test]
+    fn read_attributes_response() {
+        round_trip(GeneralCommand::ReadAttributesResponse(ReadAttributesResponse {
+            records: vec![
+                ReadAttributeStatusRecord { attribute_id: AttributeId(0x0000), result: Ok(V::Uint16(0x1234)) },
+                ReadAttributeStatusRecord {
+                    attribute_id: AttributeId(0x0001),
+                    result: Ok(V::CharacterString(Some("ab".to_string()))),
+                },
+                ReadAttributeStatusRecord {
+                    attribute_id: AttributeId(0x0002),
+                    result: Err(ZclStatus::UnsupportedAttribute),
+                },
+            ],
+        }), Direction::ServerToClient, &[
+            0x08, 0x42, 0x01,
+            0x00, 0x00, 0x00, 0x21, 0x34, 0x12,
+            0x01, 0x00, 0x00, 0x42, 0x02, b'a', b'b',
+            0x02, 0x00, 0x86,
+        ]);
+    }
+
+    #[test]
+    fn write_attributes() {
+        let records = vec![WriteAttributeRecord { attribute_id: AttributeId(0x0010), value: V::Boolean(Some(true)) }];
+        round_trip(GeneralCommand::WriteAttributes(WriteAttributes { records: records.clone() }),
+                   Direction::ClientToServer, &[0x00, 0x42, 0x02, 0x10, 0x00, 0x10, 0x01]);
+        round_trip(GeneralCommand::WriteAttributesUndivided(WriteAttributes { records: records.clone() }),
+                   Direction::ClientToServer, &[0x00, 0x42, 0x03, 0x10, 0x00, 0x10, 0x01]);
+        round_trip(GeneralCommand::WriteAttributesNoResponse(WriteAttributes { records }),
+                   Direction::ClientToServer, &[0x00, 0x42, 0x05, 0x10, 0x00, 0x10, 0x01]);
+    }
+
+    #[test]
+    fn write_attributes_response() {
+        // When all the attributes were written, only the status is sent.
+        round_trip(GeneralCommand::WriteAttributesResponse(WriteAttributesResponse { records: Vec::new() }),
+                   Direction::ServerToClient, &[0x08, 0x42, 0x04, 0x00]);
+        round_trip(GeneralCommand::WriteAttributesResponse(WriteAttributesResponse {
+            records: vec![
+                WriteAttributeStatusRecord { status: ZclStatus::ReadOnly, attribute_id: AttributeId(0x0000) },
+                WriteAttributeStatusRecord { status: ZclStatus::InvalidDataType, attribute_id: AttributeId(0x0003) },
+            ],
+        }), Direction::ServerToClient, &[0x08, 0x42, 0x04, 0x88, 0x00, 0x00, 0x8D, 0x03, 0x00]);
+    }
+
+    #[test]
+    fn configure_reporting() {
+        round_trip(GeneralCommand::ConfigureReporting(ConfigureReporting {
+            records: vec![
+                // The reportable change is present only for the analog types.
+                ReportingConfiguration::Reported {
+                    attribute_id: AttributeId(0x0000),
+                    data_type: DataType::Int16,
+                    min_interval: 1,
+                    max_interval: 300,
+                    reportable_change: Some(V::Int16(50)),
+                },
+                ReportingConfiguration::Reported {
+                    attribute_id: AttributeId(0x0001),
+                    data_type: DataType::Boolean,
+                    min_interval: 0,
+                    max_interval: 0xFFFF,
+                    reportable_change: None,
+                },
+                ReportingConfiguration::Received { attribute_id: AttributeId(0x0002), timeout_period: 600 },
+            ],
+        }), Direction::ClientToServer, &[
+            0x00, 0x42, 0x06,
+            0x00, 0x00, 0x00, 0x29, 0x01, 0x00, 0x2C, 0x01, 0x32, 0x00,
+            0x00, 0x01, 0x00, 0x10, 0x00, 0x00, 0xFF, 0xFF,
+            0x01, 0x02, 0x00, 0x58, 0x02,
+        ]);
+    }
+
+    #[test]
+    fn configure_reporting_response() {
+        round_trip(GeneralCommand::ConfigureReportingResponse(ConfigureReportingResponse { records: Vec::new() }),
+                   Direction::ServerToClient, &[0x08, 0x42, 0x07, 0x00]);
+        round_trip(GeneralCommand::ConfigureReportingResponse(ConfigureReportingResponse {
+            records: vec![ReportingStatusRecord {
+                status: ZclStatus::UnreportableAttribute,
+                direction: ReportingDirection::Reported,
+                attribute_id: AttributeId(0x0005),
+            }],
+        }), Direction::ServerToClient, &[0x08, 0x42, 0x07, 0x8C, 0x00, 0x05, 0x00]);
+    }
+
+    #[test]
+    fn read_reporting_configuration() {
+        round_trip(GeneralCommand::ReadReportingConfiguration(ReadReportingConfiguration {
+            records: vec![
+                (ReportingDirection::Reported, AttributeId(0x0000)),
+                (ReportingDirection::Received, AttributeId(0x0001)),
+            ],
+        }), Direction::ClientToServer, &[0x00, 0x42, 0x08, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00]);
+        round_trip(GeneralCommand::ReadReportingConfigurationResponse(ReadReportingConfigurationResponse {
+            records: vec![
+                Ok(ReportingConfiguration::Reported {
+                    attribute_id: AttributeId(0x0000),
+                    data_type: DataType::Uint8,
+                    min_interval: 10,
+                    max_interval: 60,
+                    reportable_change: Some(V::Uint8(2)),
+                }),
+                Err(ReportingStatusRecord {
+                    status: ZclStatus::NotFound,
+                    direction: ReportingDirection::Received,
+                    attribute_id: AttributeId(0x0001),
+                }),
+            ],
+        }), Direction::ServerToClient, &[
+            0x08, 0x42, 0x09,
+            0x00, 0x00, 0x00, 0x00, 0x20, 0x0A, 0x00, 0x3C, 0x00, 0x02,
+            0x8B, 0x01, 0x01, 0x00,
+        ]);
+    }
+
+    #[test]
+    fn report_attributes_and_default_response() {
+        round_trip(GeneralCommand::ReportAttributes(ReportAttributes {
+            reports: vec![WriteAttributeRecord { attribute_id: AttributeId(0x0000), value: V::Enum8(3) }],
+        }), Direction::ServerToClient, &[0x08, 0x42, 0x0A, 0x00, 0x00, 0x30, 0x03]);
+        round_trip(GeneralCommand::DefaultResponse(DefaultResponse {
+            command_id: 0x02,
+            status: ZclStatus::MalformedCommand,
+        }), Direction::ServerToClient, &[0x08, 0x42, 0x0B, 0x02, 0x80]);
+    }
+
+    #[test]
+    fn discover_attributes() {
+        let discover = DiscoverAttributes { start_attribute_id: AttributeId(0x0010), max_attribute_ids: 5 };
+        round_trip(GeneralCommand::DiscoverAttributes(discover), Direction::ClientToServer,
+                   &[0x00, 0x42, 0x0C, 0x10, 0x00, 0x05]);
+        round_trip(GeneralCommand::DiscoverAttributesExtended(discover), Direction::ClientToServer,
+                   &[0x00, 0x42, 0x15, 0x10, 0x00, 0x05]);
+        round_trip(GeneralCommand::DiscoverAttributesResponse(DiscoverAttributesResponse {
+            discovery_complete: false,
+            attributes: vec![(AttributeId(0x0010), DataType::Uint8), (AttributeId(0x0011), DataType::Single)],
+        }), Direction::ServerToClient, &[0x08, 0x42, 0x0D, 0x00, 0x10, 0x00, 0x20, 0x11, 0x00, 0x39]);
+        round_trip(GeneralCommand::DiscoverAttributesExtendedResponse(DiscoverAttributesExtendedResponse {
+            discovery_complete: true,
+            attributes: vec![ExtendedAttributeInformation {
+                attribute_id: AttributeId(0x0010),
+                data_type: DataType::Boolean,
+                access_control: AccessControl { readable: true, writable: false, reportable: true },
+            }],
+        }), Direction::ServerToClient, &[0x08, 0x42, 0x16, 0x01, 0x10, 0x00, 0x10, 0x05]);
+    }
+
+    #[test]
+    fn discover_commands() {
+        let discover = DiscoverCommands { start_command_id: 0x00, max_command_ids: 0x10 };
+        round_trip(GeneralCommand::DiscoverCommandsReceived(discover), Direction::ClientToServer,
+                   &[0x00, 0x42, 0x11, 0x00, 0x10]);
+        round_trip(GeneralCommand::DiscoverCommandsGenerated(discover), Direction::ClientToServer,
+                   &[0x00, 0x42, 0x13, 0x00, 0x10]);
+        let response = DiscoverCommandsResponse { discovery_complete: true, commands: vec![0x00, 0x01, 0x02] };
+        round_trip(GeneralCommand::DiscoverCommandsReceivedResponse(response.clone()), Direction::ServerToClient,
+                   &[0x08, 0x42, 0x12, 0x01, 0x00, 0x01, 0x02]);
+        round_trip(GeneralCommand::DiscoverCommandsGeneratedResponse(response), Direction::ServerToClient,
+                   &[0x08, 0x42, 0x14, 0x01, 0x00, 0x01, 0x02]);
+    }
+
+    #[test]
+    fn manufacturer_specific_frame() {
+        let command = GeneralCommand::ReadAttributes(ReadAttributes { attributes: vec![AttributeId(0x0000)] });
+        let mut out = Vec::new();
+        command.encode(Direction::ClientToServer, Some(0x1037), true, 0x07, &mut out);
+        assert_eq!(out, [0x14, 0x37, 0x10, 0x07, 0x00, 0x00, 0x00]);
+        let (header, decoded) = GeneralCommand::decode(&out).unwrap();
+        assert_eq!(header.manufacturer_code, Some(0x1037));
+        assert!(header.disable_default_response);
+        assert_eq!(decoded, command);
+    }
+
+    #[test]
+    fn access_control() {
+        for value in 0..8 {
+            assert_eq!(AccessControl::from_u8(value).to_u8(), value);
+        }
+        assert_eq!(AccessControl::from_u8(0x02),
+                   AccessControl { readable: false, writable: true, reportable: false });
+    }
+
+    #[test]
+    fn directions() {
+        let read = GeneralCommand::ReadAttributes(ReadAttributes { attributes: Vec::new() });
+        assert_eq!(read.direction(), Some(Direction::ClientToServer));
+        let response = GeneralCommand::WriteAttributesResponse(WriteAttributesResponse { records: Vec::new() });
+        assert_eq!(response.direction(), Some(Direction::ServerToClient));
+        let report = GeneralCommand::ReportAttributes(ReportAttributes { reports: Vec::new() });
+        assert_eq!(report.direction(), None);
+    }
+
+    #[test]
+    fn invalid_frames() {
+        // A cluster specific frame.
+        assert_eq!(GeneralCommand::decode(&[0x01, 0x42, 0x00]), Err(DecodeError::InvalidValue("ZCL frame type")));
+        // An unknown general command.
+        assert_eq!(GeneralCommand::decode(&[0x00, 0x42, 0x20]), Err(DecodeError::InvalidValue("general command")));
+        // A truncated attribute identifier.
+        assert_eq!(GeneralCommand::decode(&[0x00, 0x42, 0x00, 0x00]), Err(DecodeError::UnexpectedEnd));
+        // A value shorter than its type.
+        assert_eq!(GeneralCommand::decode(&[0x00, 0x42, 0x02, 0x00, 0x00, 0x21, 0x01]),
+                   Err(DecodeError::UnexpectedEnd));
+        // A reserved reporting direction.
+        assert_eq!(GeneralCommand::decode(&[0x00, 0x42, 0x08, 0x02, 0x00, 0x00]),
+                   Err(DecodeError::InvalidValue("reporting direction")));
+        // A reserved status.
+        assert_eq!(GeneralCommand::decode(&[0x08, 0x42, 0x0B, 0x02, 0x50]),
+                   Err(DecodeError::InvalidValue("ZCL status")));
+    }
+
+    #[test]
+    fn reportable_change_follows_the_data_type() {
+        let configure = |data_type, reportable_change| GeneralCommand::ConfigureReporting(ConfigureReporting {
+            records: vec![ReportingConfiguration::Reported {
+                attribute_id: AttributeId(0x0000),
+                data_type,
+                min_interval: 1,
+                max_interval: 2,
+                reportable_change,
+            }],
+        });
+        let encode = |command: &GeneralCommand| {
+            let mut out = Vec::new();
+            command.encode(Direction::ClientToServer, None, false, 0x42, &mut out);
+            out
+        };
+
+        // A discrete attribute has no reportable change.
+        let frame = encode(&configure(DataType::Boolean, Some(V::Boolean(Some(true)))));
+        assert_eq!(frame, [0x00, 0x42, 0x06, 0x00, 0x00, 0x00, 0x10, 0x01, 0x00, 0x02, 0x00]);
+        assert_eq!(GeneralCommand::decode(&frame).unwrap().1, configure(DataType::Boolean, None));
+
+        // An analog one always has one, of its type.
+        for change in &[None, Some(V::Uint8(3))] {
+            let frame = encode(&configure(DataType::Uint16, change.clone()));
+            assert_eq!(frame, [0x00, 0x42, 0x06, 0x00, 0x00, 0x00, 0x21, 0x01, 0x00, 0x02, 0x00, 0x00, 0x00]);
+            assert_eq!(GeneralCommand::decode(&frame).unwrap().1, configure(DataType::Uint16, Some(V::Uint16(0))));
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! The Zigbee Cluster Library, that defines the frames carried in the ASDU
+//! of the APS data frames exchanged by the application objects.
+//!
+//! A ZCL frame is a header, described in section 2.4.1 of the ZCL
+//! specification, followed by the payload of a command. The commands are
+//! either specific to a cluster or general, the ones in `general` that
+//! act on the attributes of any cluster.
+pub mod data_type;
+pub mod general;
+pub mod cluster;
+pub mod reporting;
+
+use std::convert::TryFrom;
+use codec::{DecodeError, Reader, write_u16};
+
+status_codes! {
+    /// The status codes of the ZCL commands.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ZclStatus {
+        Success = 0x00,
+        Failure = 0x01,
+        NotAuthorized = 0x7E,
+        ReservedFieldNotZero = 0x7F,
+        MalformedCommand = 0x80,
+        UnsupClusterCommand = 0x81,
+        UnsupGeneralCommand = 0x82,
+        UnsupManufClusterCommand = 0x83,
+        UnsupManufGeneralCommand = 0x84,
+        InvalidField = 0x85,
+        UnsupportedAttribute = 0x86,
+        InvalidValue = 0x87,
+        ReadOnly = 0x88,
+        InsufficientSpace = 0x89,
+        DuplicateExists = 0x8A,
+        NotFound = 0x8B,
+        UnreportableAttribute = 0x8C,
+        InvalidDataType = 0x8D,
+        InvalidSelector = 0x8E,
+        WriteOnly = 0x8F,
+        InconsistentStartupState = 0x90,
+        DefinedOutOfBand = 0x91,
+        Inconsistent = 0x92,
+        ActionDenied = 0x93,
+        Timeout = 0x94,
+        Abort = 0x95,
+        InvalidImage = 0x96,
+        WaitForData = 0x97,
+        NoImageAvailable = 0x98,
+        RequireMoreImage = 0x99,
+        NotificationPending = 0x9A,
+        HardwareFailure = 0xC0,
+        SoftwareFailure = 0xC1,
+        CalibrationError = 0xC2,
+        UnsupportedCluster = 0xC3,
+        LimitReached = 0xC4,
+    }
+}
+
+impl ZclStatus {
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        ZclStatus::try_from(reader.read_u8()?).map_err(|_| DecodeError::InvalidValue("ZCL status"))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameType {
+    /// A command that acts across the clusters, defined in `general`.
+    Global = 0x00,
+    ClusterSpecific = 0x01,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    ClientToServer = 0x00,
+    ServerToClient = 0x01,
+}
+
+impl Direction {
+    /// The direction of the answer to a command.
+    pub fn reverse(self) -> Self {
+        match self {
+            Direction::ClientToServer => Direction::ServerToClient,
+            Direction::ServerToClient => Direction::ClientToServer,
+        }
+    }
+}
+
+const MANUFACTURER_SPECIFIC: u8 = 1 << 2;
+const DIRECTION: u8 = 1 << 3;
+const DISABLE_DEFAULT_RESPONSE: u8 = 1 << 4;
+
+/// The header of a ZCL frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZclHeader {
+    pub frame_type: FrameType,
+    /// The code of the manufacturer, for the manufacturer specific
+    /// extensions of a cluster.
+    pub manufacturer_code: Option<u16>,
+    pub direction: Direction,
+    /// Whether the Default Response command must not be sent when the
+    /// command succeeds.
+    pub disable_default_response: bool,
+    pub sequence_number: u8,
+    pub command_id: u8,
+}
+
+impl ZclHeader {
+    pub fn encode(&self, out: &mut Vec<u8>) {
+        let mut frame_control = self.frame_type as u8;
+        if self.manufacturer_code.is_some() { frame_control |= MANUFACTURER_SPECIFIC; }
+        if self.direction == Direction::ServerToClient { frame_control |= DIRECTION; }
+        if self.disable_default_response { frame_control |= DISABLE_DEFAULT_RESPONSE; }
+        out.push(frame_control);
+        if let Some(code) = self.manufacturer_code {
+            write_u16(out, code);
+        }
+        out.push(self.sequence_number);
+        out.push(self.command_id);
+    }
+
+    pub fn decode(reader: &mut Reader) -> Result<Self, DecodeError> {
+        let frame_control = reader.read_u8()?;
+        let frame_type = match frame_control & 0x03 {
+            0x00 => FrameType::Global,
+            0x01 => FrameType::ClusterSpecific,
+            _ => return Err(DecodeError::InvalidValue("ZCL frame type")),
+        };
+        let manufacturer_code = if frame_control & MANUFACTURER_SPECIFIC != 0 {
+            Some(reader.read_u16()?)
+        } else {
+            None
+        };
+        Ok(ZclHeader {
+            frame_type,
+            manufacturer_code,
+            direction: if frame_control & DIRECTION != 0 {
+                Direction::ServerToClient
+            } else {
+                Direction::ClientToServer
+            },
+            disable_default_response: frame_control & DISABLE_DEFAULT_RESPONSE != 0,
+            sequence_number: reader.read_u8()?,
+            command_id: reader.read_u8()?,
+        })
+    }
+}
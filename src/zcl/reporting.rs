@@ -0,0 +1,140 @@
+//! The reporting of the attributes, configured with the Configure Reporting
+//! command.
+//!
+//! An attribute is reported when its maximum interval expires, or when its
+//! value changed by at least its reportable change, but never sooner than
+//! its minimum interval after the previous report. An attribute that was
+//! never reported since it was configured is reported at once.
+//!
+//! The scheduling does not read a clock: the current time is given to each
+//! call, as a `Duration` since any fixed origin, so that the reports can be
+//! driven by the system clock or by a simulated one.
+
+use std::time::Duration;
+use zcl::data_type::AttributeValue;
+
+/// The configuration of the reports of an attribute, and its last report.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributeReporting {
+    /// The minimum interval between two reports, in seconds.
+    pub min_interval: u16,
+    /// The maximum interval between two reports, in seconds, 0 if the
+    /// attribute is reported only when it changes.
+    pub max_interval: u16,
+    pub reportable_change: Option<AttributeValue>,
+    last_report: Option<(Duration, AttributeValue)>,
+}
+
+impl AttributeReporting {
+    pub fn new(min_interval: u16, max_interval: u16, reportable_change: Option<AttributeValue>) -> Self {
+        AttributeReporting { min_interval, max_interval, reportable_change, last_report: None }
+    }
+
+    /// Whether the configuration stops the reports: a maximum interval of
+    /// 0xFFFF, or the request to restore the default configuration, since
+    /// the attributes have none.
+    pub fn is_disabled(min_interval: u16, max_interval: u16) -> bool {
+        max_interval == 0xFFFF || (max_interval == 0 && min_interval == 0xFFFF)
+    }
+
+    fn changed(&self, value: &AttributeValue) -> bool {
+        match self.last_report {
+            Some((_, ref last)) => value.exceeds_reportable_change(last, self.reportable_change.as_ref()),
+            None => true,
+        }
+    }
+
+    /// The time the attribute must be reported, with its current value.
+    pub fn next_report(&self, value: &AttributeValue) -> Option<Duration> {
+        let time = match self.last_report {
+            Some((time, _)) => time,
+            None => return Some(Duration::from_secs(0)),
+        };
+        let periodic = match self.max_interval {
+            0 => None,
+            max_interval => Some(time + Duration::from_secs(u64::from(max_interval))),
+        };
+        let change = if self.changed(value) {
+            Some(time + Duration::from_secs(u64::from(self.min_interval)))
+        } else {
+            None
+        };
+        match (periodic, change) {
+            (Some(periodic), Some(change)) => Some(periodic.min(change)),
+            (periodic, change) => periodic.or(change),
+        }
+    }
+
+    /// Whether the value must be reported at time `now`.
+    pub fn is_due(&self, now: Duration, value: &AttributeValue) -> bool {
+        self.next_report(value).is_some_and(|time| time <= now)
+    }
+
+    /// Record that the value was reported at time `now`.
+    pub fn reported(&mut self, now: Duration, value: AttributeValue) {
+        self.last_report = Some((now, value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use zcl::data_type::AttributeValue as V;
+
+    fn secs(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn first_report_is_immediate() {
+        let reporting = AttributeReporting::new(5, 60, Some(V::Int16(10)));
+        assert_eq!(reporting.next_report(&V::Int16(0)), Some(secs(0)));
+        assert!(reporting.is_due(secs(0), &V::Int16(0)));
+    }
+
+    #[test]
+    fn reportable_change() {
+        let mut reporting = AttributeReporting::new(5, 60, Some(V::Int16(10)));
+        reporting.reported(secs(100), V::Int16(0));
+        // Smaller changes wait for the maximum interval.
+        assert_eq!(reporting.next_report(&V::Int16(0)), Some(secs(160)));
+        assert_eq!(reporting.next_report(&V::Int16(9)), Some(secs(160)));
+        assert_eq!(reporting.next_report(&V::Int16(-9)), Some(secs(160)));
+        // Larger ones are reported after the minimum interval.
+        assert_eq!(reporting.next_report(&V::Int16(10)), Some(secs(105)));
+        assert_eq!(reporting.next_report(&V::Int16(-10)), Some(secs(105)));
+        assert!(!reporting.is_due(secs(104), &V::Int16(10)));
+        assert!(reporting.is_due(secs(105), &V::Int16(10)));
+        assert!(!reporting.is_due(secs(159), &V::Int16(9)));
+        assert!(reporting.is_due(secs(160), &V::Int16(9)));
+    }
+
+    #[test]
+    fn max_interval_zero_reports_only_changes() {
+        let mut reporting = AttributeReporting::new(1, 0, None);
+        reporting.reported(secs(10), V::Boolean(Some(false)));
+        assert_eq!(reporting.next_report(&V::Boolean(Some(false))), None);
+        assert!(!reporting.is_due(secs(1000), &V::Boolean(Some(false))));
+        // Any change of a discrete value is reported.
+        assert_eq!(reporting.next_report(&V::Boolean(Some(true))), Some(secs(11)));
+        reporting.reported(secs(11), V::Boolean(Some(true)));
+        assert_eq!(reporting.next_report(&V::Boolean(Some(true))), None);
+    }
+
+    #[test]
+    fn min_interval_longer_than_max_interval() {
+        // A change is never reported later than the periodic report.
+        let mut reporting = AttributeReporting::new(30, 10, Some(V::Uint8(1)));
+        reporting.reported(secs(0), V::Uint8(0));
+        assert_eq!(reporting.next_report(&V::Uint8(5)), Some(secs(10)));
+    }
+
+    #[test]
+    fn disabled_configurations() {
+        assert!(AttributeReporting::is_disabled(0, 0xFFFF));
+        assert!(AttributeReporting::is_disabled(10, 0xFFFF));
+        assert!(AttributeReporting::is_disabled(0xFFFF, 0));
+        assert!(!AttributeReporting::is_disabled(0, 0));
+        assert!(!AttributeReporting::is_disabled(0xFFFF, 0xFFFE));
+    }
+}